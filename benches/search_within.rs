@@ -0,0 +1,43 @@
+use std::hint::black_box;
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use vector_db::{GraphBuilder, NodeId};
+
+const DIMS: u16 = 16;
+
+fn vectors(count: usize) -> Vec<Vec<f32>> {
+    let mut state = 1u32;
+    (0..count)
+        .map(|_| {
+            (0..DIMS)
+                .map(|_| {
+                    state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                    (state >> 8) as f32 / (1 << 24) as f32 - 0.5
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// Top-k selection over a large candidate list: few results take the bounded
+// heap, many select in place.
+fn bench_search_within(c: &mut Criterion) {
+    let vecs = vectors(20_000);
+    let graph = GraphBuilder::new().dims(DIMS).build();
+    for vec in &vecs {
+        graph.index(vec, 16);
+    }
+    let candidates: Vec<NodeId> = (0..vecs.len() as u32).map(NodeId).collect();
+    let query = &vecs[0];
+
+    let mut group = c.benchmark_group("search_within");
+    for top_k in [10, 100, 1000] {
+        group.bench_function(format!("top_{top_k}"), |b| {
+            b.iter(|| black_box(graph.search_within(&candidates, query, top_k)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_search_within);
+criterion_main!(benches);
@@ -0,0 +1,35 @@
+use std::hint::black_box;
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use vector_db::VisitedSet;
+
+// Ids spread like node handles visited by a search over a large graph.
+fn ids(count: u32) -> Vec<u32> {
+    (0..count)
+        .map(|i| i.wrapping_mul(2_654_435_761) % 1_000_000)
+        .collect()
+}
+
+fn bench_visited(c: &mut Criterion) {
+    let ids = ids(2048);
+    let mut group = c.benchmark_group("visited");
+
+    let mut set = VisitedSet::with_capacity(1_000_000);
+    group.bench_function("visited_set", |b| {
+        b.iter(|| {
+            set.clear();
+            let mut hits = 0;
+            for &id in &ids {
+                if !set.insert(id) {
+                    hits += 1;
+                }
+            }
+            black_box(hits)
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_visited);
+criterion_main!(benches);
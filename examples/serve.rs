@@ -0,0 +1,140 @@
+// A small serving loop: load vectors from an .fvecs file, index them, and
+// answer queries read from stdin, one whitespace-separated vector per line.
+//
+//     cargo run --release --features std --example serve -- base.fvecs [graph.snapshot]
+//
+// With a snapshot path, the graph is loaded from it when it exists and written
+// to it after indexing otherwise. Each answer is one line of `node:score`
+// pairs, best first. A line `k <n>` changes the number of results.
+
+use std::{
+    env, fs,
+    io::{self, BufRead, Write},
+    process,
+};
+
+use vector_db::{
+    Graph, GraphBuilder, MAX_TOP_K, NodeId, SearchContext, SearchParams, SearchResult,
+};
+
+const EF_SEARCH: u16 = 64;
+
+// .fvecs: each vector is its dimension as a little-endian i32, followed by
+// that many little-endian f32 components.
+fn read_fvecs(path: &str) -> io::Result<Vec<Vec<f32>>> {
+    let bytes = fs::read(path)?;
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, format!("{path}: {msg}"));
+    let mut vecs = Vec::new();
+    let mut rest = &bytes[..];
+    while !rest.is_empty() {
+        let (dims, tail) = rest
+            .split_first_chunk::<4>()
+            .ok_or_else(|| invalid("truncated dimension"))?;
+        let dims = i32::from_le_bytes(*dims);
+        let len = usize::try_from(dims)
+            .ok()
+            .filter(|&dims| dims > 0)
+            .ok_or_else(|| invalid("bad dimension"))?;
+        let (components, tail) = tail
+            .split_at_checked(len * 4)
+            .ok_or_else(|| invalid("truncated vector"))?;
+        vecs.push(
+            components
+                .chunks_exact(4)
+                .map(|c| f32::from_le_bytes(c.try_into().unwrap()))
+                .collect(),
+        );
+        rest = tail;
+    }
+    Ok(vecs)
+}
+
+fn build(vecs: &[Vec<f32>]) -> Result<Graph, String> {
+    let dims = vecs.first().ok_or("no vectors to index")?.len();
+    let dims = u16::try_from(dims).map_err(|_| format!("{dims} dimensions are too many"))?;
+    let graph = GraphBuilder::new().dims(dims).ef_search(EF_SEARCH).build();
+    let vecs: Vec<&[f32]> = vecs.iter().map(Vec::as_slice).collect();
+    graph
+        .try_index_batch(&vecs, 64)
+        .map_err(|err| err.to_string())?;
+    Ok(graph)
+}
+
+fn load(base: &str, snapshot: Option<&str>) -> Result<Graph, String> {
+    if let Some(path) = snapshot
+        && let Ok(bytes) = fs::read(path)
+    {
+        return Graph::deserialize(&bytes).map_err(|err| format!("{path}: {err}"));
+    }
+
+    let vecs = read_fvecs(base).map_err(|err| err.to_string())?;
+    let graph = build(&vecs)?;
+    if let Some(path) = snapshot {
+        fs::write(path, graph.serialize()).map_err(|err| format!("{path}: {err}"))?;
+    }
+    Ok(graph)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let Some(base) = args.first() else {
+        eprintln!("usage: serve <base.fvecs> [graph.snapshot]");
+        process::exit(2);
+    };
+    let graph = load(base, args.get(1).map(String::as_str)).unwrap_or_else(|err| {
+        eprintln!("{err}");
+        process::exit(1);
+    });
+    eprintln!("serving {} vectors of {} dims", graph.len(), graph.dims());
+
+    // one context for the loop, so searches stop allocating once it has grown
+    let mut context = SearchContext::new();
+    let mut out = vec![
+        SearchResult {
+            node: NodeId(0),
+            score: 0.0,
+        };
+        10
+    ];
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    for line in io::stdin().lock().lines() {
+        let line = line.expect("stdin");
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(k) = line.strip_prefix("k ") {
+            match k.trim().parse::<u16>() {
+                Ok(k) if (1..=MAX_TOP_K).contains(&k) => out.resize(k as usize, out[0]),
+                _ => eprintln!("k must be in 1..={MAX_TOP_K}"),
+            }
+            continue;
+        }
+
+        let query: Result<Vec<f32>, _> = line.split_whitespace().map(str::parse).collect();
+        let query = match query {
+            Ok(query) if query.len() != graph.dims() as usize => {
+                eprintln!("expected {} components, got {}", graph.dims(), query.len());
+                continue;
+            }
+            Ok(query) if query.iter().any(|component| component.is_nan()) => {
+                eprintln!("NaN component");
+                continue;
+            }
+            Ok(query) => query,
+            Err(err) => {
+                eprintln!("{err}");
+                continue;
+            }
+        };
+
+        let params = SearchParams::new(EF_SEARCH.max(out.len() as u16), out.len() as u16);
+        let found = graph.search_with_context(&mut context, &query, &params, &mut out);
+        let answer: Vec<String> = out[..found]
+            .iter()
+            .map(|result| format!("{}:{}", result.node.0, result.score))
+            .collect();
+        writeln!(stdout, "{}", answer.join(" ")).expect("stdout");
+    }
+}
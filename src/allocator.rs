@@ -0,0 +1,68 @@
+// Where index memory comes from: the global allocator or, with the
+// `allocator_api` feature, one supplied through `GraphBuilder::allocator`.
+// Arena chunks, quantized queries and the root vector all go through it.
+
+use core::{alloc::Layout, ptr::NonNull};
+
+use alloc::alloc::{alloc, alloc_zeroed, dealloc, handle_alloc_error};
+#[cfg(feature = "allocator_api")]
+use alloc::sync::Arc;
+#[cfg(feature = "allocator_api")]
+use core::alloc::Allocator;
+
+#[derive(Clone, Default)]
+pub(crate) struct IndexAllocator {
+    #[cfg(feature = "allocator_api")]
+    allocator: Option<Arc<dyn Allocator + Send + Sync>>,
+}
+
+impl IndexAllocator {
+    #[cfg(feature = "allocator_api")]
+    pub(crate) fn new(allocator: impl Allocator + Send + Sync + 'static) -> Self {
+        Self {
+            allocator: Some(Arc::new(allocator)),
+        }
+    }
+
+    /// Allocate `layout`, which must not be zero-sized, aborting through
+    /// `handle_alloc_error` on failure
+    pub(crate) fn alloc(&self, layout: Layout, zeroed: bool) -> NonNull<u8> {
+        debug_assert_ne!(layout.size(), 0);
+
+        #[cfg(feature = "allocator_api")]
+        if let Some(allocator) = &self.allocator {
+            let result = if zeroed {
+                allocator.allocate_zeroed(layout)
+            } else {
+                allocator.allocate(layout)
+            };
+            return match result {
+                Ok(ptr) => ptr.cast(),
+                Err(_) => handle_alloc_error(layout),
+            };
+        }
+
+        let ptr = unsafe {
+            if zeroed {
+                alloc_zeroed(layout)
+            } else {
+                alloc(layout)
+            }
+        };
+
+        NonNull::new(ptr).unwrap_or_else(|| handle_alloc_error(layout))
+    }
+
+    /// # Safety
+    ///
+    /// `ptr` must come from `self.alloc` with the same `layout`.
+    pub(crate) unsafe fn dealloc(&self, ptr: NonNull<u8>, layout: Layout) {
+        #[cfg(feature = "allocator_api")]
+        if let Some(allocator) = &self.allocator {
+            unsafe { allocator.deallocate(ptr, layout) };
+            return;
+        }
+
+        unsafe { dealloc(ptr.as_ptr(), layout) }
+    }
+}
@@ -2,18 +2,17 @@ use core::{
     alloc::Layout,
     marker::PhantomData,
     mem,
-    ops::Index,
-    ptr::{self, NonNull, Pointee},
-    sync::atomic::{AtomicU32, Ordering},
+    ops::{Index, IndexMut},
+    ptr::{self, NonNull},
+    sync::atomic,
 };
 
-use alloc::{
-    alloc::{alloc, handle_alloc_error},
-    vec::Vec,
+use crate::{
+    allocator::IndexAllocator,
+    handle::{DoubleHandle, Handle, HandleA, HandleB},
+    sync::{AtomicU32, AtomicU64, Ordering, RwLock, RwLockWriteGuard},
 };
-use parking_lot::{RwLock, RwLockWriteGuard};
-
-use crate::handle::{DoubleHandle, Handle, HandleA, HandleB};
+use alloc::{boxed::Box, vec::Vec};
 
 struct Chunk<T: DynAlloc + ?Sized> {
     ptr: NonNull<u8>,
@@ -21,17 +20,27 @@ struct Chunk<T: DynAlloc + ?Sized> {
 }
 
 impl<T: DynAlloc + ?Sized> Chunk<T> {
-    unsafe fn new(item_size: usize, item_align: usize, chunk_size: usize) -> Self {
-        let layout =
-            unsafe { Layout::from_size_align_unchecked(item_size * chunk_size, item_align) };
-        let ptr = unsafe { alloc(layout) };
-
-        if ptr.is_null() {
-            handle_alloc_error(layout)
+    unsafe fn new(
+        item_size: usize,
+        item_align: usize,
+        chunk_size: usize,
+        zeroed: bool,
+        allocator: &IndexAllocator,
+    ) -> Self {
+        let layout = item_size
+            .checked_mul(chunk_size)
+            .and_then(|size| Layout::from_size_align(size, item_align).ok())
+            .expect("arena chunk too large");
+        // zero-sized items need no backing memory
+        if layout.size() == 0 {
+            return Self {
+                ptr: unsafe { NonNull::new_unchecked(ptr::without_provenance_mut(item_align)) },
+                _marker: PhantomData,
+            };
         }
 
         Self {
-            ptr: unsafe { NonNull::new_unchecked(ptr) },
+            ptr: allocator.alloc(layout, zeroed),
             _marker: PhantomData,
         }
     }
@@ -40,13 +49,17 @@ impl<T: DynAlloc + ?Sized> Chunk<T> {
         unsafe { self.ptr.as_ptr().add(item_size * index) }
     }
 
-    unsafe fn get_ref<'a>(
-        &self,
+    unsafe fn get_ref<'a>(&self, item_size: usize, index: usize, metadata: T::Metadata) -> &'a T {
+        unsafe { &*T::from_raw_parts(self.ptr.as_ptr().add(item_size * index), metadata) }
+    }
+
+    unsafe fn get_mut<'a>(
+        &mut self,
         item_size: usize,
         index: usize,
-        metadata: <T as Pointee>::Metadata,
-    ) -> &'a T {
-        unsafe { &*ptr::from_raw_parts(self.ptr.as_ptr().add(item_size * index), metadata) }
+        metadata: T::Metadata,
+    ) -> &'a mut T {
+        unsafe { &mut *T::from_raw_parts(self.ptr.as_ptr().add(item_size * index), metadata) }
     }
 
     unsafe fn init(&self, item_size: usize, index: usize, metadata: T::Metadata, args: T::Args) {
@@ -59,6 +72,10 @@ impl<T: DynAlloc + ?Sized> Chunk<T> {
 unsafe impl<T: Send + DynAlloc + ?Sized> Send for Chunk<T> {}
 unsafe impl<T: Sync + DynAlloc + ?Sized> Sync for Chunk<T> {}
 
+// Byte pattern written over freed chunks in debug builds.
+#[cfg(debug_assertions)]
+const POISON: u8 = 0xA5;
+
 fn align_up(size: usize, alignment: usize) -> usize {
     debug_assert!(alignment != 0, "Alignment must be non-zero");
     debug_assert!(
@@ -84,7 +101,9 @@ pub trait DynAlloc {
         align_up(size, Self::ALIGN)
     }
 
-    fn ptr_metadata(metadata: Self::Metadata) -> <Self as Pointee>::Metadata;
+    // A pointer to the value at `ptr`. Unsized types end in a slice and cast a
+    // slice pointer of their tail's length, which works without `ptr_metadata`.
+    fn from_raw_parts(ptr: *mut u8, metadata: Self::Metadata) -> *mut Self;
 
     unsafe fn new_at(ptr: *mut u8, metadata: Self::Metadata, args: Self::Args);
 }
@@ -93,11 +112,37 @@ pub struct ArenaWithoutIndex<T: DynAlloc + ?Sized> {
     chunks: RwLock<Vec<Chunk<T>>>,
     chunk_size: usize,
     metadata: T::Metadata,
+    zeroed: bool,
+    // The chunks point into memory the arena neither writes to nor frees.
+    borrowed: bool,
+    // The chunks, moved out of their lock while the arena is frozen.
+    frozen: Option<Box<[Chunk<T>]>>,
+    allocator: IndexAllocator,
 }
 
 pub struct Arena<T: DynAlloc + ?Sized> {
     arena: ArenaWithoutIndex<T>,
     next_index: AtomicU32,
+    free: FreeList,
+}
+
+/// A lock-free stack of freed slot indices, a Treiber stack whose links live
+/// in a zeroed arena at the index they follow
+///
+/// The head packs a counter, bumped by every push and pop, above the top
+/// index plus one, so a pop racing a pop and push of the same index fails its
+/// compare-exchange instead of linking a stale successor. Growing the links
+/// takes the arena's chunk lock, like any arena allocation.
+pub struct FreeList {
+    head: AtomicU64,
+    links: ArenaWithoutIndex<FreeLink>,
+}
+
+// The index below a freed slot on the stack, plus one, or 0 at the bottom.
+// Lives in arena memory, so its atomic stays on `core` even under loom.
+#[repr(C, align(4))]
+pub struct FreeLink {
+    next: atomic::AtomicU32,
 }
 
 pub struct DoubleArena<A: DynAlloc + ?Sized, B: DynAlloc + ?Sized> {
@@ -107,15 +152,60 @@ pub struct DoubleArena<A: DynAlloc + ?Sized, B: DynAlloc + ?Sized> {
 }
 
 impl<T: DynAlloc + ?Sized> ArenaWithoutIndex<T> {
-    pub fn new(chunk_size: usize, metadata: T::Metadata) -> Self {
+    pub fn new(chunk_size: usize, metadata: T::Metadata, allocator: IndexAllocator) -> Self {
         Self {
             chunks: RwLock::new(Vec::new()),
             chunk_size,
             metadata,
+            zeroed: false,
+            borrowed: false,
+            frozen: None,
+            allocator,
+        }
+    }
+
+    /// Create an arena over `len` items laid out back to back at `ptr`, whose
+    /// memory it never writes to or frees
+    ///
+    /// # Safety
+    /// `ptr` must be aligned to `T::ALIGN` and point to `len` initialized items
+    /// that stay valid and unchanged while the arena is in use. Nothing may be
+    /// allocated in or written through the arena.
+    #[cfg(feature = "mmap")]
+    pub unsafe fn borrowed(
+        chunk_size: usize,
+        metadata: T::Metadata,
+        ptr: *const u8,
+        len: usize,
+    ) -> Self {
+        let item_size = T::size_aligned(metadata);
+        let chunks = (0..len.div_ceil(chunk_size))
+            .map(|chunk| Chunk {
+                ptr: unsafe {
+                    NonNull::new_unchecked(ptr.add(chunk * chunk_size * item_size) as *mut u8)
+                },
+                _marker: PhantomData,
+            })
+            .collect();
+        Self {
+            chunks: RwLock::new(chunks),
+            borrowed: true,
+            ..Self::new(chunk_size, metadata, IndexAllocator::default())
+        }
+    }
+
+    /// Create an arena whose chunks are zero-filled when allocated, so slots that
+    /// were never initialized read as all-zero bytes
+    pub fn new_zeroed(chunk_size: usize, metadata: T::Metadata, allocator: IndexAllocator) -> Self {
+        Self {
+            zeroed: true,
+            ..Self::new(chunk_size, metadata, allocator)
         }
     }
 
     pub fn alloc(&self, index: u32, args: T::Args) -> Handle<T> {
+        debug_assert!(!self.borrowed, "allocating in a borrowed arena");
+        debug_assert!(self.frozen.is_none(), "allocating in a frozen arena");
         let chunk_index = index as usize / self.chunk_size;
         let offset = index as usize % self.chunk_size;
 
@@ -126,7 +216,13 @@ impl<T: DynAlloc + ?Sized> ArenaWithoutIndex<T> {
             let mut chunks_guard = self.chunks.write();
             while chunk_index >= chunks_guard.len() {
                 chunks_guard.push(unsafe {
-                    Chunk::new(T::size_aligned(self.metadata), T::ALIGN, self.chunk_size)
+                    Chunk::new(
+                        T::size_aligned(self.metadata),
+                        T::ALIGN,
+                        self.chunk_size,
+                        self.zeroed,
+                        &self.allocator,
+                    )
                 });
             }
             RwLockWriteGuard::downgrade(chunks_guard)
@@ -142,22 +238,111 @@ impl<T: DynAlloc + ?Sized> ArenaWithoutIndex<T> {
         Handle::new(index)
     }
 
+    /// Allocate the chunks holding the first `len` items, if they aren't yet
+    pub fn reserve(&self, len: usize) {
+        debug_assert!(self.frozen.is_none(), "allocating in a frozen arena");
+        let chunks = len.div_ceil(self.chunk_size);
+        if chunks <= self.chunks.read().len() {
+            return;
+        }
+        let mut chunks_guard = self.chunks.write();
+        while chunks > chunks_guard.len() {
+            chunks_guard.push(unsafe {
+                Chunk::new(
+                    T::size_aligned(self.metadata),
+                    T::ALIGN,
+                    self.chunk_size,
+                    self.zeroed,
+                    &self.allocator,
+                )
+            });
+        }
+    }
+
+    /// Bytes taken by the allocated chunks, none for a borrowed arena
+    pub fn allocated_bytes(&self) -> usize {
+        if self.borrowed {
+            return 0;
+        }
+        self.with_chunks(|chunks| chunks.len()) * self.chunk_size * T::size_aligned(self.metadata)
+    }
+
+    /// Number of allocated chunks, none for a borrowed arena
+    pub fn chunk_count(&self) -> usize {
+        if self.borrowed {
+            return 0;
+        }
+        self.with_chunks(|chunks| chunks.len())
+    }
+
+    /// Bytes per item
+    pub fn slot_bytes(&self) -> usize {
+        T::size_aligned(self.metadata)
+    }
+
+    /// Start of each allocated chunk, in order
+    pub fn chunk_ptrs(&self) -> Vec<*const u8> {
+        self.with_chunks(|chunks| {
+            chunks
+                .iter()
+                .map(|chunk| chunk.ptr.as_ptr() as *const u8)
+                .collect()
+        })
+    }
+
+    /// Move the chunks out of their lock, so reads skip it until `thaw`
+    ///
+    /// Nothing may be allocated in a frozen arena.
+    pub fn freeze(&mut self) {
+        if self.frozen.is_none() {
+            self.frozen = Some(mem::take(self.chunks.get_mut()).into_boxed_slice());
+        }
+    }
+
+    pub fn thaw(&mut self) {
+        if let Some(chunks) = self.frozen.take() {
+            *self.chunks.get_mut() = chunks.into_vec();
+        }
+    }
+
+    fn with_chunks<R>(&self, f: impl FnOnce(&[Chunk<T>]) -> R) -> R {
+        match &self.frozen {
+            Some(chunks) => f(chunks),
+            None => f(&self.chunks.read()),
+        }
+    }
+
     fn split_handle(&self, handle: Handle<T>) -> (usize, usize) {
         let index = *handle as usize;
         (index / self.chunk_size, index % self.chunk_size)
     }
 
-    pub fn clear(&self, len: u32) {
-        let mut chunks_guard = self.chunks.write();
-        let chunks = mem::take(&mut *chunks_guard); // Take ownership of the chunks
-
-        let len = len as usize;
+    /// Get the item at `handle`, or `None` if its chunk hasn't been allocated yet
+    ///
+    /// Only meaningful for zeroed arenas, where a slot in an allocated chunk is
+    /// readable even before it is initialized.
+    pub fn get(&self, handle: Handle<T>) -> Option<&T> {
+        let (chunk_index, offset) = self.split_handle(handle);
+        self.with_chunks(|chunks| {
+            let chunk = chunks.get(chunk_index)?;
+            Some(unsafe { chunk.get_ref(T::size_aligned(self.metadata), offset, self.metadata) })
+        })
+    }
 
-        if len == 0 {
-            return; // No objects allocated
-        }
+    /// Drop the first `len` items and free every chunk
+    ///
+    /// Takes `&mut self` so no reference handed out by the arena can outlive the
+    /// memory it points into. In debug builds the freed chunks are poisoned first.
+    pub fn clear(&mut self, len: u32) {
+        self.clear_with(len, |_| true);
+    }
 
-        if chunks.is_empty() {
+    // Like `clear`, dropping only the items `live` accepts, by index.
+    fn clear_with(&mut self, len: u32, live: impl Fn(usize) -> bool) {
+        self.thaw();
+        let chunks = mem::take(self.chunks.get_mut());
+        // the items belong to whoever lent the memory
+        if self.borrowed {
             return;
         }
 
@@ -165,13 +350,14 @@ impl<T: DynAlloc + ?Sized> ArenaWithoutIndex<T> {
         let item_align = T::ALIGN;
 
         // Drop each allocated object in reverse order (from last to first)
-        for i in (0..len).rev() {
+        for i in (0..len as usize).rev().filter(|&i| live(i)) {
             let chunk_index = i / self.chunk_size;
             let offset = i % self.chunk_size;
-            let chunk = &chunks[chunk_index];
+            let Some(chunk) = chunks.get(chunk_index) else {
+                continue;
+            };
             let ptr = unsafe { chunk.get_raw(item_size, offset) };
-            let ptr_to_t: *mut T =
-                ptr::from_raw_parts_mut(ptr as *mut (), T::ptr_metadata(self.metadata));
+            let ptr_to_t = T::from_raw_parts(ptr, self.metadata);
             unsafe {
                 ptr::drop_in_place(ptr_to_t);
             }
@@ -179,32 +365,181 @@ impl<T: DynAlloc + ?Sized> ArenaWithoutIndex<T> {
 
         // Deallocate each chunk
         for chunk in chunks {
+            if item_size == 0 {
+                continue;
+            }
+
+            #[cfg(debug_assertions)]
+            unsafe {
+                ptr::write_bytes(chunk.ptr.as_ptr(), POISON, item_size * self.chunk_size);
+            }
+
             let layout = Layout::from_size_align(item_size * self.chunk_size, item_align)
                 .expect("Invalid layout");
             unsafe {
-                alloc::alloc::dealloc(chunk.ptr.as_ptr(), layout);
+                self.allocator.dealloc(chunk.ptr, layout);
             }
         }
     }
 }
 
+impl FreeList {
+    pub fn new(chunk_size: usize, allocator: IndexAllocator) -> Self {
+        Self {
+            head: AtomicU64::new(0),
+            links: ArenaWithoutIndex::new_zeroed(chunk_size, (), allocator),
+        }
+    }
+
+    /// Queue `index` for reuse
+    ///
+    /// An index must not be pushed again before it is popped.
+    pub fn push(&self, index: u32) {
+        debug_assert!(index < u32::MAX, "index past the largest slot");
+        self.links.reserve(index as usize + 1);
+        let link = &self.links[Handle::new(index)];
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            link.next.store(head as u32, atomic::Ordering::Relaxed);
+            let new = (head >> 32).wrapping_add(1) << 32 | (index as u64 + 1);
+            match self
+                .head
+                .compare_exchange_weak(head, new, Ordering::Release, Ordering::Relaxed)
+            {
+                Ok(_) => return,
+                Err(current) => head = current,
+            }
+        }
+    }
+
+    /// Take the most recently pushed index
+    pub fn pop(&self) -> Option<u32> {
+        let mut head = self.head.load(Ordering::Acquire);
+        loop {
+            let top = (head as u32).checked_sub(1)?;
+            let next = self.links[Handle::new(top)]
+                .next
+                .load(atomic::Ordering::Relaxed);
+            let new = (head >> 32).wrapping_add(1) << 32 | next as u64;
+            match self
+                .head
+                .compare_exchange_weak(head, new, Ordering::Acquire, Ordering::Acquire)
+            {
+                Ok(_) => return Some(top),
+                Err(current) => head = current,
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Acquire) as u32 == 0
+    }
+
+    /// Bytes taken by the links
+    pub fn allocated_bytes(&self) -> usize {
+        self.links.allocated_bytes()
+    }
+
+    /// Empty the stack, returning the indices it held
+    pub fn drain(&mut self) -> Vec<u32> {
+        let mut indices = Vec::new();
+        while let Some(index) = self.pop() {
+            indices.push(index);
+        }
+        indices
+    }
+}
+
+impl Drop for FreeList {
+    fn drop(&mut self) {
+        self.links.clear(0);
+    }
+}
+
+impl DynAlloc for FreeLink {
+    type Metadata = ();
+    type Args = u32;
+
+    const ALIGN: usize = 4;
+
+    fn size(_metadata: ()) -> usize {
+        4
+    }
+
+    fn from_raw_parts(ptr: *mut u8, _metadata: ()) -> *mut Self {
+        ptr.cast()
+    }
+
+    // Links are only ever read from the zeroed arena, never allocated.
+    unsafe fn new_at(ptr: *mut u8, _metadata: (), next: Self::Args) {
+        let link = unsafe { &*(ptr as *const FreeLink) };
+        link.next.store(next, atomic::Ordering::Relaxed);
+    }
+}
+
 impl<T: DynAlloc + ?Sized> Arena<T> {
-    pub fn new(chunk_size: usize, metadata: T::Metadata) -> Self {
+    pub fn new(chunk_size: usize, metadata: T::Metadata, allocator: IndexAllocator) -> Self {
         Self {
-            arena: ArenaWithoutIndex::new(chunk_size, metadata),
+            arena: ArenaWithoutIndex::new(chunk_size, metadata, allocator.clone()),
             next_index: AtomicU32::new(0),
+            free: FreeList::new(chunk_size, allocator),
         }
     }
 
+    /// Allocate a slot, reusing one freed by `dealloc` if there is any
     pub fn alloc(&self, args: T::Args) -> Handle<T> {
-        let index = self.next_index.fetch_add(1, Ordering::Relaxed);
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => self.next_index.fetch_add(1, Ordering::Relaxed),
+        };
 
         self.arena.alloc(index, args);
 
         Handle::new(index)
     }
 
-    /// Get the number of allocated items
+    /// Drop the item at `handle` and queue its slot for reuse by `alloc`
+    ///
+    /// # Safety
+    /// The slot must be allocated, not already freed, and nothing may read it
+    /// again before `alloc` hands it out anew.
+    #[allow(unused)]
+    pub unsafe fn dealloc(&self, handle: Handle<T>) {
+        let (chunk_index, offset) = self.arena.split_handle(handle);
+        self.arena.with_chunks(|chunks| unsafe {
+            let ptr = chunks[chunk_index].get_raw(T::size_aligned(self.arena.metadata), offset);
+            ptr::drop_in_place(T::from_raw_parts(ptr, self.arena.metadata));
+        });
+        self.free.push(*handle);
+    }
+
+    /// Allocate the chunks holding the first `len` items, if they aren't yet
+    pub fn reserve(&self, len: usize) {
+        self.arena.reserve(len);
+    }
+
+    /// Bytes taken by the allocated chunks
+    pub fn allocated_bytes(&self) -> usize {
+        self.arena.allocated_bytes()
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.arena.chunk_count()
+    }
+
+    pub fn slot_bytes(&self) -> usize {
+        self.arena.slot_bytes()
+    }
+
+    pub fn freeze(&mut self) {
+        self.arena.freeze();
+    }
+
+    pub fn thaw(&mut self) {
+        self.arena.thaw();
+    }
+
+    /// Get the number of slots handed out, freed ones included
     #[allow(unused)]
     pub fn len(&self) -> usize {
         self.next_index.load(Ordering::Acquire) as usize
@@ -218,16 +553,25 @@ impl<T: DynAlloc + ?Sized> Arena<T> {
 
     pub fn clear(&mut self) {
         let len = self.next_index.load(Ordering::Acquire);
-        self.arena.clear(len);
+        let mut freed = alloc::vec![false; len as usize];
+        for index in self.free.drain() {
+            freed[index as usize] = true;
+        }
+        self.arena.clear_with(len, |index| !freed[index]);
         self.next_index.store(0, Ordering::Release);
     }
 }
 
 impl<A: DynAlloc + ?Sized, B: DynAlloc + ?Sized> DoubleArena<A, B> {
-    pub fn new(chunk_size: usize, metadata_a: A::Metadata, metadata_b: B::Metadata) -> Self {
+    pub fn new(
+        chunk_size: usize,
+        metadata_a: A::Metadata,
+        metadata_b: B::Metadata,
+        allocator: IndexAllocator,
+    ) -> Self {
         Self {
-            arena_a: ArenaWithoutIndex::new(chunk_size, metadata_a),
-            arena_b: ArenaWithoutIndex::new(chunk_size, metadata_b),
+            arena_a: ArenaWithoutIndex::new(chunk_size, metadata_a, allocator.clone()),
+            arena_b: ArenaWithoutIndex::new(chunk_size, metadata_b, allocator),
             next_index: AtomicU32::new(0),
         }
     }
@@ -241,6 +585,74 @@ impl<A: DynAlloc + ?Sized, B: DynAlloc + ?Sized> DoubleArena<A, B> {
         DoubleHandle::new(index)
     }
 
+    /// Create an arena over `len` slots laid out at `ptr_a` and `ptr_b`, see
+    /// `ArenaWithoutIndex::borrowed`
+    ///
+    /// # Safety
+    /// As for `ArenaWithoutIndex::borrowed`, for both pointers.
+    #[cfg(feature = "mmap")]
+    pub unsafe fn borrowed(
+        chunk_size: usize,
+        metadata_a: A::Metadata,
+        metadata_b: B::Metadata,
+        (ptr_a, ptr_b): (*const u8, *const u8),
+        len: u32,
+    ) -> Self {
+        unsafe {
+            Self {
+                arena_a: ArenaWithoutIndex::borrowed(chunk_size, metadata_a, ptr_a, len as usize),
+                arena_b: ArenaWithoutIndex::borrowed(chunk_size, metadata_b, ptr_b, len as usize),
+                next_index: AtomicU32::new(len),
+            }
+        }
+    }
+
+    /// Re-initialize an allocated slot in place
+    ///
+    /// # Safety
+    /// The previous items must not need dropping, and nothing may read the slot
+    /// until this returns.
+    pub unsafe fn reinit(&self, handle: DoubleHandle<A, B>, args_a: A::Args, args_b: B::Args) {
+        self.arena_a.alloc(*handle, args_a);
+        self.arena_b.alloc(*handle, args_b);
+    }
+
+    /// Allocate the chunks holding the first `len` items, if they aren't yet
+    pub fn reserve(&self, len: usize) {
+        self.arena_a.reserve(len);
+        self.arena_b.reserve(len);
+    }
+
+    /// Bytes taken by the allocated chunks of both arenas
+    pub fn allocated_bytes(&self) -> usize {
+        self.arena_a.allocated_bytes() + self.arena_b.allocated_bytes()
+    }
+
+    /// Number of allocated chunks, the same in both arenas
+    pub fn chunk_count(&self) -> usize {
+        self.arena_b.chunk_count()
+    }
+
+    /// Bytes per slot of both arenas
+    pub fn slot_bytes(&self) -> usize {
+        self.arena_a.slot_bytes() + self.arena_b.slot_bytes()
+    }
+
+    /// Start of each allocated chunk of the first and the second arena
+    pub fn chunk_ptrs(&self) -> (Vec<*const u8>, Vec<*const u8>) {
+        (self.arena_a.chunk_ptrs(), self.arena_b.chunk_ptrs())
+    }
+
+    pub fn freeze(&mut self) {
+        self.arena_a.freeze();
+        self.arena_b.freeze();
+    }
+
+    pub fn thaw(&mut self) {
+        self.arena_a.thaw();
+        self.arena_b.thaw();
+    }
+
     /// Get the number of allocated items
     #[allow(unused)]
     pub fn len(&self) -> usize {
@@ -278,15 +690,21 @@ impl<T: DynAlloc + ?Sized> Index<Handle<T>> for ArenaWithoutIndex<T> {
 
     fn index(&self, handle: Handle<T>) -> &Self::Output {
         let (chunk_index, offset) = self.split_handle(handle);
-        let chunks_guard = self.chunks.read();
-        let chunk = &chunks_guard[chunk_index];
-        unsafe {
-            chunk.get_ref(
-                T::size_aligned(self.metadata),
-                offset,
-                T::ptr_metadata(self.metadata),
-            )
-        }
+        self.with_chunks(|chunks| unsafe {
+            chunks[chunk_index].get_ref(T::size_aligned(self.metadata), offset, self.metadata)
+        })
+    }
+}
+
+impl<T: DynAlloc + ?Sized> IndexMut<Handle<T>> for ArenaWithoutIndex<T> {
+    fn index_mut(&mut self, handle: Handle<T>) -> &mut Self::Output {
+        let (chunk_index, offset) = self.split_handle(handle);
+        let item_size = T::size_aligned(self.metadata);
+        let chunks = match &mut self.frozen {
+            Some(chunks) => chunks,
+            None => self.chunks.get_mut().as_mut_slice(),
+        };
+        unsafe { chunks[chunk_index].get_mut(item_size, offset, self.metadata) }
     }
 }
 
@@ -298,6 +716,12 @@ impl<T: DynAlloc + ?Sized> Index<Handle<T>> for Arena<T> {
     }
 }
 
+impl<T: DynAlloc + ?Sized> IndexMut<Handle<T>> for Arena<T> {
+    fn index_mut(&mut self, handle: Handle<T>) -> &mut Self::Output {
+        &mut self.arena[handle]
+    }
+}
+
 impl<A: DynAlloc + ?Sized, B: DynAlloc + ?Sized> Index<HandleA<A>> for DoubleArena<A, B> {
     type Output = A;
 
@@ -314,6 +738,18 @@ impl<A: DynAlloc + ?Sized, B: DynAlloc + ?Sized> Index<HandleB<B>> for DoubleAre
     }
 }
 
+impl<A: DynAlloc + ?Sized, B: DynAlloc + ?Sized> IndexMut<HandleA<A>> for DoubleArena<A, B> {
+    fn index_mut(&mut self, handle: HandleA<A>) -> &mut Self::Output {
+        &mut self.arena_a[handle.cast()]
+    }
+}
+
+impl<A: DynAlloc + ?Sized, B: DynAlloc + ?Sized> IndexMut<HandleB<B>> for DoubleArena<A, B> {
+    fn index_mut(&mut self, handle: HandleB<B>) -> &mut Self::Output {
+        &mut self.arena_b[handle.cast()]
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -342,7 +778,9 @@ mod tests {
             size_of::<Self>()
         }
 
-        fn ptr_metadata(_metadata: Self::Metadata) -> <Self as Pointee>::Metadata {}
+        fn from_raw_parts(ptr: *mut u8, _metadata: Self::Metadata) -> *mut Self {
+            ptr.cast()
+        }
 
         unsafe fn new_at(ptr: *mut u8, _metadata: (), args: Self::Args) {
             unsafe {
@@ -387,7 +825,9 @@ mod tests {
             size_of::<Self>()
         }
 
-        fn ptr_metadata(_metadata: Self::Metadata) -> <Self as Pointee>::Metadata {}
+        fn from_raw_parts(ptr: *mut u8, _metadata: Self::Metadata) -> *mut Self {
+            ptr.cast()
+        }
 
         unsafe fn new_at(ptr: *mut u8, _metadata: (), args: Self::Args) {
             unsafe {
@@ -396,9 +836,16 @@ mod tests {
         }
     }
 
+    #[test]
+    #[should_panic(expected = "arena chunk too large")]
+    fn oversized_chunk_panics() {
+        let arena = Arena::<TestStruct>::new(usize::MAX / 2, (), IndexAllocator::default());
+        arena.alloc(10);
+    }
+
     #[test]
     fn basic_allocation() {
-        let arena = Arena::<TestStruct>::new(2, ());
+        let arena = Arena::<TestStruct>::new(2, (), IndexAllocator::default());
         let handle1 = arena.alloc(10);
         let handle2 = arena.alloc(20);
 
@@ -409,7 +856,7 @@ mod tests {
 
     #[test]
     fn chunk_expansion() {
-        let arena = Arena::<TestStruct>::new(1, ()); // Small chunk size
+        let arena = Arena::<TestStruct>::new(1, (), IndexAllocator::default()); // Small chunk size
         let handle1 = arena.alloc(1);
         let handle2 = arena.alloc(2); // Should trigger new chunk
 
@@ -420,7 +867,7 @@ mod tests {
 
     #[test]
     fn clear_operation_and_drop_arena() {
-        let mut arena = Arena::<DropTest>::new(2, ());
+        let mut arena = Arena::<DropTest>::new(2, (), IndexAllocator::default());
         let _ = arena.alloc(1);
         let _ = arena.alloc(2);
 
@@ -432,7 +879,7 @@ mod tests {
 
         DROP_COUNT.store(0, Ordering::SeqCst);
         {
-            let arena = Arena::<DropTest>::new(2, ());
+            let arena = Arena::<DropTest>::new(2, (), IndexAllocator::default());
             let _ = arena.alloc(1);
             let _ = arena.alloc(2);
         } // Arena dropped here
@@ -440,9 +887,116 @@ mod tests {
         assert_eq!(DROP_COUNT.load(Ordering::SeqCst), 2);
     }
 
+    #[test]
+    fn clear_then_reuse() {
+        let mut arena = Arena::<TestStruct>::new(2, (), IndexAllocator::default());
+        for i in 0..5 {
+            arena.alloc(i);
+        }
+        arena.clear();
+        assert!(arena.is_empty());
+
+        let handle1 = arena.alloc(7);
+        let handle2 = arena.alloc(8);
+        assert_eq!(*handle1, 0);
+        assert_eq!(arena[handle1].value, 7);
+        assert_eq!(arena[handle2].value, 8);
+    }
+
+    #[test]
+    fn clear_frees_zeroed_chunks() {
+        let mut arena =
+            ArenaWithoutIndex::<TestStruct>::new_zeroed(2, (), IndexAllocator::default());
+        let handle = arena.alloc(0, 3);
+        assert_eq!(arena.get(Handle::new(1)).map(|item| item.value), Some(0));
+
+        arena.clear(1);
+        assert!(arena.get(handle).is_none());
+
+        // `len` past the allocated chunks is ignored
+        arena.alloc(0, 4);
+        arena.clear(8);
+        assert!(arena.get(handle).is_none());
+    }
+
+    #[test]
+    fn clear_double_arena() {
+        let mut arena =
+            DoubleArena::<TestStruct, TestStruct>::new(2, (), (), IndexAllocator::default());
+        let handle = arena.alloc(1, 2);
+        arena.alloc(3, 4);
+        arena.alloc(5, 6);
+        arena.clear();
+        assert!(arena.is_empty());
+
+        let handle2 = arena.alloc(9, 10);
+        assert_eq!(*handle2, *handle);
+        assert_eq!(arena[handle2.handle_a()].value, 9);
+        assert_eq!(arena[handle2.handle_b()].value, 10);
+    }
+
+    #[test]
+    fn dealloc_reuses_slots() {
+        let mut arena = Arena::<DropTest>::new(2, (), IndexAllocator::default());
+        let handles: Vec<_> = (0..4).map(|i| arena.alloc(i)).collect();
+
+        DROP_COUNT.store(0, Ordering::SeqCst);
+        unsafe {
+            arena.dealloc(handles[1]);
+            arena.dealloc(handles[3]);
+        }
+        assert_eq!(DROP_COUNT.load(Ordering::SeqCst), 2);
+
+        // freed slots come back last in, first out, before the arena grows
+        assert_eq!(*arena.alloc(5), 3);
+        assert_eq!(*arena.alloc(6), 1);
+        assert_eq!(*arena.alloc(7), 4);
+        assert_eq!(arena.len(), 5);
+
+        unsafe { arena.dealloc(handles[0]) };
+        DROP_COUNT.store(0, Ordering::SeqCst);
+        arena.clear();
+        // the freed slot isn't dropped twice
+        assert_eq!(DROP_COUNT.load(Ordering::SeqCst), 4);
+        assert_eq!(*arena.alloc(8), 0);
+    }
+
+    #[test]
+    fn free_list_hands_out_each_index_once() {
+        extern crate std;
+
+        let free = FreeList::new(16, IndexAllocator::default());
+        for index in 0..256 {
+            free.push(index);
+        }
+        let mut popped: Vec<u32> = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..4)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut popped = Vec::new();
+                        for _ in 0..64 {
+                            // churn: give one back and take it again
+                            let index = free.pop().unwrap();
+                            free.push(index);
+                            popped.push(free.pop().unwrap());
+                        }
+                        popped
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap())
+                .collect()
+        });
+        popped.sort_unstable();
+        assert_eq!(popped, (0..256).collect::<Vec<_>>());
+        assert!(free.is_empty());
+    }
+
     #[test]
     fn large_allocation() {
-        let arena = Arena::<TestStruct>::new(100, ());
+        let arena = Arena::<TestStruct>::new(100, (), IndexAllocator::default());
         for i in 0..1000 {
             let handle = arena.alloc(i as u32);
             assert_eq!(arena[handle].value, i as u32);
@@ -450,3 +1004,34 @@ mod tests {
         assert_eq!(arena.len(), 1000);
     }
 }
+
+// Run with `RUSTFLAGS="--cfg loom" cargo test --release --lib loom`.
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use loom::{sync::Arc, thread};
+
+    use super::*;
+
+    #[test]
+    fn concurrent_free_list() {
+        loom::model(|| {
+            let free = Arc::new(FreeList::new(2, IndexAllocator::default()));
+            free.push(0);
+            free.push(1);
+
+            let popper = {
+                let free = free.clone();
+                thread::spawn(move || free.pop())
+            };
+            // returned at once, so the stack never runs dry for the popper
+            let first = free.pop().unwrap();
+            free.push(first);
+            let second = popper.join().unwrap().unwrap();
+
+            let mut rest = [second, free.pop().unwrap()];
+            rest.sort_unstable();
+            assert_eq!(rest, [0, 1]);
+            assert_eq!(free.pop(), None);
+        });
+    }
+}
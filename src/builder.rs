@@ -0,0 +1,386 @@
+use alloc::boxed::Box;
+#[cfg(feature = "allocator_api")]
+use core::alloc::Allocator;
+
+use crate::{
+    DistanceMetricKind, Graph, MemoryPressureHook, NeighborSelection, Quantization, Reranker,
+    allocator::IndexAllocator,
+    arena::DynAlloc,
+    graph::CHUNK_SIZE,
+    node::{Node, Node0},
+};
+
+// Neighbor positions are u16 and list lengths are compared in u16 arithmetic,
+// so keep a bit of headroom below `u16::MAX`.
+pub(crate) const MAX_NEIGHBORS: u16 = 32768;
+
+pub(crate) type CheckpointFn = Box<dyn Fn(&Graph, u64) + Send + Sync>;
+pub(crate) type ClockFn = Box<dyn Fn() -> u64 + Send + Sync>;
+
+pub struct GraphBuilder {
+    pub(crate) m: u16,
+    pub(crate) m0: u16,
+    pub(crate) dims: u16,
+    pub(crate) levels: u8,
+    pub(crate) quantization: Quantization,
+    pub(crate) metric: DistanceMetricKind,
+    pub(crate) assume_normalized: bool,
+    pub(crate) cache_budget: Option<u32>,
+    pub(crate) rerank: bool,
+    pub(crate) full_precision_linking: bool,
+    pub(crate) neighbor_selection: NeighborSelection,
+    pub(crate) result_cache: Option<usize>,
+    pub(crate) checkpoint: Option<(u64, CheckpointFn)>,
+    pub(crate) memory_pressure_hook: Option<Box<dyn MemoryPressureHook>>,
+    pub(crate) reranker: Option<Box<dyn Reranker>>,
+    pub(crate) clock: Option<ClockFn>,
+    pub(crate) record_ingest: bool,
+    pub(crate) priors: bool,
+    pub(crate) min_insert_distance: Option<f32>,
+    pub(crate) trace_sampling: Option<(f32, usize)>,
+    pub(crate) seed: u64,
+    pub(crate) chunk_size: usize,
+    pub(crate) ef_construction: u16,
+    pub(crate) ef_search: u16,
+    pub(crate) allocator: IndexAllocator,
+}
+
+impl GraphBuilder {
+    pub fn new() -> Self {
+        Self {
+            m: 16,
+            m0: 32,
+            dims: 0,
+            levels: 4,
+            quantization: Quantization::FullPrecisionFP,
+            metric: DistanceMetricKind::Cosine,
+            assume_normalized: false,
+            cache_budget: None,
+            rerank: true,
+            full_precision_linking: false,
+            neighbor_selection: NeighborSelection::Simple,
+            result_cache: None,
+            checkpoint: None,
+            memory_pressure_hook: None,
+            reranker: None,
+            clock: None,
+            record_ingest: false,
+            priors: false,
+            min_insert_distance: None,
+            trace_sampling: None,
+            seed: 42,
+            chunk_size: CHUNK_SIZE,
+            ef_construction: 64,
+            ef_search: 64,
+            allocator: IndexAllocator::default(),
+        }
+    }
+
+    pub fn m(mut self, m: u16) -> Self {
+        self.m = m;
+        self
+    }
+
+    pub fn m0(mut self, m0: u16) -> Self {
+        self.m0 = m0;
+        self
+    }
+
+    pub fn dims(mut self, dims: u16) -> Self {
+        self.dims = dims;
+        self
+    }
+
+    /// Number of levels above level 0 (default 4)
+    ///
+    /// With 0 the graph is flat: there is no hierarchy to descend, and searches
+    /// start from the first node plus a few pseudo-random ones. That is smaller
+    /// and often as fast for small to medium datasets.
+    pub fn levels(mut self, levels: u8) -> Self {
+        self.levels = levels;
+        self
+    }
+
+    pub fn quantization(mut self, quantization: Quantization) -> Self {
+        self.quantization = quantization;
+        self
+    }
+
+    pub fn metric(mut self, metric: DistanceMetricKind) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    /// Trust that every vector has unit norm and score Cosine as the plain dot
+    /// product, skipping the divide by the magnitudes (default false)
+    ///
+    /// Scores of vectors that aren't normalized come out scaled by their
+    /// magnitudes, so rankings drift from true cosine. Needs the Cosine
+    /// metric.
+    pub fn assume_normalized(mut self, enabled: bool) -> Self {
+        self.assume_normalized = enabled;
+        self
+    }
+
+    /// Turn the graph into an approximate LRU cache holding at most `budget` vectors
+    ///
+    /// Once the budget is reached, each insert evicts a node that hasn't been
+    /// returned by a search recently (sampled, not exact LRU) and reuses its slot,
+    /// so memory stays bounded. The evicted node's `NodeId` is handed to the new
+    /// vector.
+    pub fn cache_budget(mut self, budget: u32) -> Self {
+        self.cache_budget = Some(budget);
+        self
+    }
+
+    /// Rescore search results against full-precision copies of the vectors
+    /// (default true)
+    ///
+    /// Disabling it skips storing the copies. With `FullPrecisionFP` the stored
+    /// vectors are already full precision, so no copy is kept either way.
+    pub fn rerank(mut self, rerank: bool) -> Self {
+        self.rerank = rerank;
+        self
+    }
+
+    /// Choose neighbors while inserting by full-precision scores (default false)
+    ///
+    /// Candidates are still found with quantized scores, but a wider set of them
+    /// is rescored against the full-precision copies kept for reranking, which
+    /// improves link quality and so recall, at some cost per insert. Needs
+    /// `rerank`, and changes nothing with `FullPrecisionFP`.
+    pub fn full_precision_linking(mut self, enabled: bool) -> Self {
+        self.full_precision_linking = enabled;
+        self
+    }
+
+    /// How inserts choose neighbors (default `NeighborSelection::Simple`)
+    ///
+    /// The heuristic weighs up to `ef` candidates per level, so it costs more
+    /// per insert, and may leave lists shorter than `m`. It mostly pays off at
+    /// high dimensionality or on clustered data. Backlinks still replace a
+    /// node's weakest link by score.
+    pub fn neighbor_selection(mut self, selection: NeighborSelection) -> Self {
+        self.neighbor_selection = selection;
+        self
+    }
+
+    /// Cache the results of up to `capacity` recent queries
+    ///
+    /// Only exact repeats of a query with the same ef and top_k are served from
+    /// the cache, and every insert invalidates it. Searches with a post-filter
+    /// bypass it.
+    pub fn result_cache(mut self, capacity: usize) -> Self {
+        self.result_cache = Some(capacity);
+        self
+    }
+
+    /// Call `callback` after every `every` inserts, with the graph and the number
+    /// of inserts so far
+    ///
+    /// New inserts, deletes, updates and purges wait while the callback runs and
+    /// none are in flight, so it sees a consistent graph holding exactly that
+    /// many inserts, e.g. to persist it or record how far into the input a long
+    /// build got. With concurrent inserts the
+    /// count may have moved past the multiple of `every` by the time the callback
+    /// runs. The callback must not insert into the graph.
+    pub fn checkpoint(
+        mut self,
+        every: u64,
+        callback: impl Fn(&Graph, u64) + Send + Sync + 'static,
+    ) -> Self {
+        assert!(every > 0, "checkpoint interval must be positive");
+        self.checkpoint = Some((every, Box::new(callback)));
+        self
+    }
+
+    pub fn memory_pressure_hook(mut self, hook: impl MemoryPressureHook + 'static) -> Self {
+        self.memory_pressure_hook = Some(Box::new(hook));
+        self
+    }
+
+    /// Let `reranker` score search candidates instead of rescoring them against
+    /// the full-precision copies
+    ///
+    /// Called by every search that rescores, with the candidates of its
+    /// quantized traversal. Inserts aliasing near-duplicates, see
+    /// `min_insert_distance`, search through it too.
+    pub fn reranker(mut self, reranker: impl Reranker + 'static) -> Self {
+        self.reranker = Some(Box::new(reranker));
+        self
+    }
+
+    /// Read the current time from `now`, in ticks of any unit, enabling
+    /// `Graph::index_with_ttl`
+    ///
+    /// Expired nodes stop being returned by searches right away, and
+    /// `Graph::purge_expired` hands their slots and `NodeId`s to new vectors.
+    /// The result cache is bypassed, as results depend on the time.
+    pub fn clock(mut self, now: impl Fn() -> u64 + Send + Sync + 'static) -> Self {
+        self.clock = Some(Box::new(now));
+        self
+    }
+
+    /// Keep a record of every node's insert: the clock time, see `clock`, and
+    /// a source tag, see `Graph::index_with_source` and `Graph::ingest`
+    ///
+    /// Takes 8 bytes per node. Without a clock the time is 0, and clock times
+    /// past `u32::MAX` are recorded as `u32::MAX`.
+    pub fn record_ingest(mut self, enabled: bool) -> Self {
+        self.record_ingest = enabled;
+        self
+    }
+
+    /// Keep a query-independent prior per node, e.g. document popularity, for
+    /// searches to blend into their scores, see `Graph::set_prior` and
+    /// `SearchParams::prior_weight`
+    ///
+    /// Takes 4 bytes per node. Nodes start with a prior of 0.
+    pub fn priors(mut self, enabled: bool) -> Self {
+        self.priors = enabled;
+        self
+    }
+
+    /// Skip inserts of near-duplicates: a vector scoring at least as well as
+    /// `threshold` against its nearest node, i.e. within that distance for
+    /// the distance metrics or at least that similar for Cosine and
+    /// DotProduct, becomes an alias of that node
+    ///
+    /// No node is created for an alias, and `index` returns the nearest node's
+    /// `NodeId`. The nearest node is found by an ef-wide search, so aliasing is
+    /// approximate, and concurrent inserts of the same vector may both create
+    /// nodes. Inserts with a TTL always create a node.
+    pub fn min_insert_distance(mut self, threshold: f32) -> Self {
+        self.min_insert_distance = Some(threshold);
+        self
+    }
+
+    /// Seed the RNG that draws node levels
+    ///
+    /// Graphs built with the same seed from the same inserts, made one at a
+    /// time, are identical.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Number of nodes per arena chunk, the unit in which the graph grows
+    ///
+    /// Smaller chunks waste less memory in small graphs, larger ones allocate
+    /// less often. Defaults to 1024.
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// The ef `Graph::add` inserts with (default 64)
+    ///
+    /// Wider searches while inserting find better neighbors, for better recall
+    /// at some cost per insert. `Graph::index` takes its own ef instead.
+    pub fn ef_construction(mut self, ef: u16) -> Self {
+        self.ef_construction = ef;
+        self
+    }
+
+    /// The ef `Graph::nearest` searches with (default 64), raised to `top_k`
+    /// for larger result counts
+    pub fn ef_search(mut self, ef: u16) -> Self {
+        self.ef_search = ef;
+        self
+    }
+
+    /// Record the traversal of each search with the given `probability`,
+    /// keeping the last `capacity` traces for `Graph::sample_traces`
+    ///
+    /// A trace holds the upper-level path and the number of level 0 nodes
+    /// expanded. Sampled searches skip the result cache.
+    pub fn trace_sampling(mut self, probability: f32, capacity: usize) -> Self {
+        self.trace_sampling = Some((probability, capacity));
+        self
+    }
+
+    /// Allocate index memory (arena chunks, quantized queries) from `allocator`
+    /// instead of the global allocator
+    ///
+    /// Lets embedders keep the index on a dedicated heap or pool and account for
+    /// it separately. Short-lived bookkeeping of a search, such as candidate
+    /// queues, still uses the global allocator.
+    #[cfg(feature = "allocator_api")]
+    pub fn allocator(mut self, allocator: impl Allocator + Send + Sync + 'static) -> Self {
+        self.allocator = IndexAllocator::new(allocator);
+        self
+    }
+
+    pub fn build(self) -> Graph {
+        if let Err(msg) = self.check() {
+            panic!("{msg}");
+        }
+        Graph::from_builder(self)
+    }
+
+    // The reason `build` would reject these settings, if any.
+    pub(crate) fn check(&self) -> Result<(), &'static str> {
+        if self.dims == 0 {
+            return Err("dims must be set");
+        }
+        // an empty neighbor list would be full before anything is linked
+        if self.m == 0 {
+            return Err("m must be positive");
+        }
+        if self.m0 == 0 {
+            return Err("m0 must be positive");
+        }
+        if self.m > MAX_NEIGHBORS {
+            return Err("m must be at most 32768");
+        }
+        if self.m0 > MAX_NEIGHBORS {
+            return Err("m0 must be at most 32768");
+        }
+        if self.chunk_size == 0 {
+            return Err("chunk size must be positive");
+        }
+        if self.ef_construction == 0 {
+            return Err("ef_construction must be positive");
+        }
+        if self.ef_search == 0 {
+            return Err("ef_search must be positive");
+        }
+        let chunk_fits = |node_size: usize| {
+            node_size
+                .checked_mul(self.chunk_size)
+                .is_some_and(|size| size <= isize::MAX as usize)
+        };
+        if !chunk_fits(Node::size_aligned(self.m)) || !chunk_fits(Node0::size_aligned(self.m0)) {
+            return Err("neighbor lists too large for an arena chunk");
+        }
+        if self.full_precision_linking
+            && !self.rerank
+            && !matches!(self.quantization, Quantization::FullPrecisionFP)
+        {
+            return Err("full-precision linking needs the copies kept by rerank");
+        }
+        if matches!(self.quantization, Quantization::Binary)
+            != matches!(self.metric, DistanceMetricKind::Hamming)
+        {
+            return Err("the Hamming metric and binary quantization go together");
+        }
+        if self.assume_normalized && !matches!(self.metric, DistanceMetricKind::Cosine) {
+            return Err("assume_normalized needs the Cosine metric");
+        }
+        if let Some((probability, capacity)) = self.trace_sampling {
+            if !(0.0..=1.0).contains(&probability) {
+                return Err("trace sampling probability must be in [0, 1]");
+            }
+            if capacity == 0 {
+                return Err("trace sampling capacity must be positive");
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for GraphBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
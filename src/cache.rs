@@ -0,0 +1,91 @@
+use alloc::{boxed::Box, vec::Vec};
+use parking_lot::Mutex;
+
+use crate::params::{RescoreMode, ResultOrder};
+
+// Search parameters a cached result depends on: ef, max ef, top_k, rescoring and
+// result order.
+pub type CacheParams = (u16, u16, u16, RescoreMode, ResultOrder);
+
+struct CacheEntry {
+    hash: u64,
+    seq: u64,
+    params: CacheParams,
+    query: Box<[f32]>,
+    results: Box<[(u32, f32)]>,
+}
+
+// A small direct-mapped cache of search results.
+//
+// Entries are found by a hash of the quantized query, but only returned when the
+// query matches exactly and no insert or delete happened since they were stored,
+// as tracked by the graph's sequence number.
+pub struct ResultCache {
+    slots: Box<[Mutex<Option<CacheEntry>>]>,
+}
+
+impl ResultCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            slots: (0..capacity.max(1)).map(|_| Mutex::new(None)).collect(),
+        }
+    }
+
+    // Bytes a filled slot takes for queries of `dims` and `top_k` results.
+    pub fn entry_bytes(dims: u16, top_k: u16) -> usize {
+        size_of::<Mutex<Option<CacheEntry>>>()
+            + dims as usize * size_of::<f32>()
+            + top_k as usize * size_of::<(u32, f32)>()
+    }
+
+    fn slot(&self, hash: u64) -> &Mutex<Option<CacheEntry>> {
+        &self.slots[(hash % self.slots.len() as u64) as usize]
+    }
+
+    pub fn get(
+        &self,
+        hash: u64,
+        seq: u64,
+        params: CacheParams,
+        query: &[f32],
+    ) -> Option<Vec<(u32, f32)>> {
+        let slot = self.slot(hash).lock();
+        let entry = slot.as_ref()?;
+        let hit = entry.hash == hash
+            && entry.seq == seq
+            && entry.params == params
+            && same_bits(&entry.query, query);
+        hit.then(|| entry.results.to_vec())
+    }
+
+    pub fn insert(
+        &self,
+        hash: u64,
+        seq: u64,
+        params: CacheParams,
+        query: &[f32],
+        results: &[(u32, f32)],
+    ) {
+        *self.slot(hash).lock() = Some(CacheEntry {
+            hash,
+            seq,
+            params,
+            query: query.into(),
+            results: results.into(),
+        });
+    }
+}
+
+fn same_bits(a: &[f32], b: &[f32]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.to_bits() == b.to_bits())
+}
+
+// 64-bit FNV-1a.
+pub fn hash_bytes(bytes: &[u8]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
@@ -0,0 +1,26 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// A flag asking long operations to stop early, e.g. when a service shuts down
+///
+/// Share it between the task running the operation and the one cancelling it,
+/// e.g. in an `Arc`. Operations check it between units of work, an insert or a
+/// node, so the graph is left consistent when they stop. A cancelled token
+/// stays cancelled.
+#[derive(Debug, Default)]
+pub struct CancellationToken {
+    cancelled: AtomicBool,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
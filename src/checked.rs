@@ -0,0 +1,149 @@
+use core::mem;
+
+use alloc::{boxed::Box, collections::BTreeMap, vec::Vec};
+use parking_lot::Mutex;
+
+use crate::{Graph, NodeId, SearchResult, VectorDbError, metric::dot_product_f32, storage::RawVec};
+
+/// A graph that keeps a brute-force copy of its vectors and checks every
+/// search against the exact results, for differential tests
+///
+/// `search` panics when its recall, the share of the exact top_k it returns,
+/// falls below the bound given to `new`. Exact results are ranked by the
+/// graph's metric over the vectors as inserted. Only changes made through the
+/// wrapper are mirrored, so graphs that evict or expire nodes on their own
+/// (cache mode, a clock) drift from the copy.
+pub struct CheckedGraph {
+    graph: Graph,
+    min_recall: f32,
+    vecs: Mutex<BTreeMap<NodeId, Box<[f32]>>>,
+}
+
+impl CheckedGraph {
+    pub fn new(graph: Graph, min_recall: f32) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&min_recall),
+            "recall must be in [0, 1]"
+        );
+        Self {
+            graph,
+            min_recall,
+            vecs: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn graph(&self) -> &Graph {
+        &self.graph
+    }
+
+    pub fn index(&self, vec: &[f32], ef: u16) -> NodeId {
+        let id = self.graph.index(vec, ef);
+        // an aliased insert keeps the vector of the node it aliases
+        self.vecs.lock().entry(id).or_insert_with(|| vec.into());
+        id
+    }
+
+    pub fn delete(&self, id: NodeId) -> bool {
+        let deleted = self.graph.delete(id);
+        if deleted {
+            self.vecs.lock().remove(&id);
+        }
+        deleted
+    }
+
+    pub fn update(&self, id: NodeId, vec: &[f32], ef: u16) -> Result<(), VectorDbError> {
+        self.graph.update(id, vec, ef)?;
+        self.vecs.lock().insert(id, vec.into());
+        Ok(())
+    }
+
+    /// Like `Graph::search`, panicking when the recall is below the bound
+    pub fn search(&self, query: &[f32], ef: u16, top_k: u16) -> Box<[SearchResult]> {
+        let results = self.graph.search(query, ef, top_k);
+        let exact = self.exact(query, top_k as usize);
+        if exact.is_empty() {
+            return results;
+        }
+
+        let found = exact
+            .iter()
+            .filter(|id| results.iter().any(|result| result.node == **id))
+            .count();
+        let recall = found as f32 / exact.len() as f32;
+        assert!(
+            recall >= self.min_recall,
+            "recall {recall} below {} for {} results: got {:?}, expected {:?}",
+            self.min_recall,
+            top_k,
+            results.iter().map(|result| result.node).collect::<Vec<_>>(),
+            exact,
+        );
+        results
+    }
+
+    // The `top_k` stored vectors scoring best against `query`, best first.
+    fn exact(&self, query: &[f32], top_k: usize) -> Vec<NodeId> {
+        let metric = self.graph.distance_metric();
+        let mag_query = dot_product_f32(query, query);
+        let query = unsafe { mem::transmute::<&[f32], &RawVec>(query) };
+        let mut scored: Vec<(NodeId, f32)> = self
+            .vecs
+            .lock()
+            .iter()
+            .map(|(&id, vec)| {
+                let mag = dot_product_f32(vec, vec);
+                let vec = unsafe { mem::transmute::<&[f32], &RawVec>(vec) };
+                (id, metric.calculate_raw(query, mag_query, vec, mag))
+            })
+            .collect();
+        scored.sort_by(|a, b| metric.cmp_score(b.1, a.1));
+        scored.truncate(top_k);
+        scored.into_iter().map(|(id, _)| id).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GraphBuilder;
+
+    fn vectors(count: usize) -> Vec<Vec<f32>> {
+        let mut state = 1u32;
+        (0..count)
+            .map(|_| {
+                (0..16)
+                    .map(|_| {
+                        state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                        (state >> 8) as f32 / (1 << 24) as f32 - 0.5
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn checked(min_recall: f32) -> CheckedGraph {
+        let graph = GraphBuilder::new().m(8).m0(16).dims(16).build();
+        let checked = CheckedGraph::new(graph, min_recall);
+        for vec in vectors(256) {
+            checked.index(&vec, 32);
+        }
+        checked
+    }
+
+    #[test]
+    fn searches_meet_the_recall_bound() {
+        let graph = checked(0.75);
+        for query in vectors(300).iter().skip(256) {
+            assert_eq!(graph.search(query, 64, 8).len(), 8);
+        }
+        assert!(graph.delete(NodeId(0)));
+        graph.search(&vectors(1)[0], 64, 8);
+    }
+
+    #[test]
+    #[should_panic(expected = "below")]
+    fn low_recall_fails() {
+        // a single expanded node can't hold the top 8
+        checked(1.0).search(&vectors(1)[0], 1, 8);
+    }
+}
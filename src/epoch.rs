@@ -0,0 +1,57 @@
+use core::{
+    hint,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use parking_lot::Mutex;
+
+// A two-counter grace period tracker.
+//
+// Readers pin the current epoch for the duration of an operation. A writer that
+// wants to reuse memory first makes it unreachable for new readers, then calls
+// `synchronize`, which flips the epoch and waits until every reader pinned in the
+// previous epoch has finished. Writers are serialized so that a reader can never
+// be skipped by two flips in a row.
+pub struct Epoch {
+    epoch: AtomicU32,
+    readers: [AtomicU32; 2],
+    writer: Mutex<()>,
+}
+
+pub struct EpochPin<'a> {
+    readers: &'a AtomicU32,
+}
+
+impl Epoch {
+    pub const fn new() -> Self {
+        Self {
+            epoch: AtomicU32::new(0),
+            readers: [AtomicU32::new(0), AtomicU32::new(0)],
+            writer: Mutex::new(()),
+        }
+    }
+
+    #[inline]
+    pub fn pin(&self) -> EpochPin<'_> {
+        let epoch = self.epoch.load(Ordering::SeqCst);
+        let readers = &self.readers[(epoch & 1) as usize];
+        readers.fetch_add(1, Ordering::SeqCst);
+        EpochPin { readers }
+    }
+
+    pub fn synchronize(&self) {
+        let _guard = self.writer.lock();
+        let epoch = self.epoch.fetch_add(1, Ordering::SeqCst);
+        let readers = &self.readers[(epoch & 1) as usize];
+        while readers.load(Ordering::SeqCst) != 0 {
+            hint::spin_loop();
+        }
+    }
+}
+
+impl Drop for EpochPin<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        self.readers.fetch_sub(1, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,93 @@
+use core::{error::Error, fmt};
+
+use crate::params::MAX_TOP_K;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum VectorDbError {
+    /// The input vector's length doesn't match the graph's input dimensionality
+    DimensionMismatch { expected: u16, found: usize },
+    /// The input vector's component at `index` is NaN
+    NanComponent { index: usize },
+    /// The search or insert was given an ef of 0, leaving it nothing to expand
+    ZeroEf,
+    /// The search asked for more than `MAX_TOP_K` results
+    TopKOutOfRange { top_k: u16 },
+    /// No graph is registered for the requested model tag
+    UnknownModel,
+    /// The memory pressure hook refused the allocations an insert needed
+    OutOfBudget,
+    /// The operation needs a clock, but the graph was built without one
+    NoClock,
+    /// The operation needs node priors, but the graph was built without them
+    NoPriors,
+    /// The `NodeId` doesn't refer to an indexed node
+    UnknownNode,
+    /// The graph's vectors live in a read-only buffer, see `Graph::from_mapped`
+    ReadOnly,
+    /// The graph is frozen, see `Graph::freeze`
+    Frozen,
+    /// The operation stopped early because its `CancellationToken` was cancelled
+    Cancelled,
+}
+
+impl fmt::Display for VectorDbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DimensionMismatch { expected, found } => write!(
+                f,
+                "dimension mismatch: the graph expects {expected} dims, got {found}"
+            ),
+            Self::NanComponent { index } => write!(f, "component {index} of the vector is NaN"),
+            Self::ZeroEf => write!(f, "ef must be positive"),
+            Self::TopKOutOfRange { top_k } => {
+                write!(f, "top_k {top_k} is above the maximum of {MAX_TOP_K}")
+            }
+            Self::UnknownModel => write!(f, "no graph is registered for this model tag"),
+            Self::OutOfBudget => write!(f, "insert vetoed by the memory pressure hook"),
+            Self::NoClock => write!(f, "the graph has no clock, see GraphBuilder::clock"),
+            Self::NoPriors => write!(f, "the graph keeps no priors, see GraphBuilder::priors"),
+            Self::UnknownNode => write!(f, "no indexed node has this id"),
+            Self::ReadOnly => write!(f, "the graph is backed by a read-only buffer"),
+            Self::Frozen => write!(f, "the graph is frozen, see Graph::thaw"),
+            Self::Cancelled => write!(f, "the operation was cancelled"),
+        }
+    }
+}
+
+impl Error for VectorDbError {}
+
+/// Why `Graph::deserialize` rejected a buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DeserializeError {
+    /// The buffer doesn't start like a snapshot
+    NotASnapshot,
+    /// The snapshot was written in a format version this build can't read
+    UnsupportedVersion(u32),
+    /// The buffer ends before the snapshot does
+    Truncated,
+    /// The snapshot is inconsistent, e.g. a handle points past its arena
+    Corrupt,
+    /// A mapped buffer doesn't start at the alignment its vectors need
+    Misaligned,
+    /// A mapped buffer was written on a machine of the other byte order
+    ForeignByteOrder,
+}
+
+impl fmt::Display for DeserializeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotASnapshot => write!(f, "not a graph snapshot"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported snapshot format version {version}")
+            }
+            Self::Truncated => write!(f, "truncated snapshot"),
+            Self::Corrupt => write!(f, "corrupt snapshot"),
+            Self::Misaligned => write!(f, "misaligned mapped snapshot"),
+            Self::ForeignByteOrder => write!(f, "mapped snapshot of the other byte order"),
+        }
+    }
+}
+
+impl Error for DeserializeError {}
@@ -1,24 +1,86 @@
-use core::{alloc::Layout, cmp::Ordering, mem, ptr};
+use core::{
+    alloc::Layout,
+    cmp::Ordering,
+    mem,
+    ops::{Deref, Range},
+    ptr,
+};
 
 use alloc::{
-    alloc::{alloc, dealloc, handle_alloc_error},
     boxed::Box,
+    collections::{BTreeMap, BTreeSet, VecDeque},
     vec::Vec,
 };
-use binary_heap_plus::BinaryHeap;
+use binary_heap_plus::{BinaryHeap, FnComparator};
+use parking_lot::Mutex;
 
+#[cfg(feature = "mmap")]
+use crate::snapshot::{BYTE_ORDER, MAPPED_ALIGN, MAPPED_MAGIC};
+#[cfg(feature = "nightly")]
+use crate::util::widen_f16;
 use crate::{
-    NodeId,
-    arena::{Arena, DoubleArena, DynAlloc},
-    fixedset::FixedSet,
-    handle::{Handle, HandleA},
+    CancellationToken, DeserializeError, GraphBuilder, KERNEL_VERSION, NodeId, VectorDbError,
+    allocator::IndexAllocator,
+    arena::{Arena, ArenaWithoutIndex, DoubleArena, DynAlloc, FreeList},
+    builder::{CheckpointFn, ClockFn},
+    cache::{ResultCache, hash_bytes},
+    epoch::{Epoch, EpochPin},
+    handle::{Handle, HandleA, HandleB},
+    layout::{self, RawChunk, VecChunks},
     metric::{DistanceMetric, DistanceMetricKind, dot_product_f32},
-    node::{Neighbor, Neighbor0, Node, Node0, Node0Handle, NodeHandle, VecHandle},
-    random::{AtomicRng, exponential_random},
-    storage::{QuantVec, Quantization, RawVec},
-    util::map_boxed_slice,
+    node::{
+        Neighbor, Neighbor0, NeighborOrder, NeighborSelection, Node, Node0, Node0Handle,
+        NodeExpiry, NodeExpiryHandle, NodeHandle, NodeIngest, NodeIngestHandle, NodeMeta,
+        NodeMetaHandle, NodePrior, NodePriorHandle, VecHandle,
+    },
+    params::{FilterDecision, MAX_TOP_K, RescoreMode, SearchParams},
+    pressure::MemoryPressureHook,
+    random::{AtomicRng, ThreadSafeRng, exponential_random},
+    rerank::Reranker,
+    rwlock::{self, RwLockReadGuard},
+    snapshot::{FORMAT_VERSION, MAGIC, Reader, Writer},
+    storage::{QuantArgs, QuantVec, QuantVecBox, Quantization, RawVec},
+    sync::{AtomicU32, AtomicU64, Ordering as AtomicOrdering, RwLock},
+    util::{map_boxed_slice, widen_bf16},
+    visited::VisitedSet,
 };
 
+// Default number of items per arena chunk.
+#[cfg(not(loom))]
+pub(crate) const CHUNK_SIZE: usize = 1024;
+// Loom models only run a handful of inserts, which should still grow the arenas.
+#[cfg(loom)]
+pub(crate) const CHUNK_SIZE: usize = 2;
+// Number of random slots inspected per eviction in cache mode.
+const LRU_SAMPLES: usize = 8;
+// Number of sampling rounds before giving up and growing the graph instead.
+const LRU_ATTEMPTS: usize = 4;
+// Number of random level 0 entry points added to searches of a flat graph.
+const FLAT_ENTRY_POINTS: u64 = 4;
+// Probability of a node reaching each next level.
+pub(crate) const LEVEL_FACTOR: f64 = 0.4;
+// Inserts whose magnitudes make up the baseline of `drift_report`.
+const DRIFT_BASELINE: u64 = 1024;
+// Weight of each insert in the recent magnitude statistics.
+const DRIFT_WEIGHT: f32 = 1.0 / 256.0;
+// One search in this many counts towards the access counts of its results.
+const ACCESS_SAMPLE_RATE: u32 = 16;
+// Candidates per kept result from which top-k selection streams them through
+// a bounded heap instead of selecting in place. Below it select_nth and a sort
+// win, by 2x or more at ratios near 10; at 1000 the heap is slightly ahead and
+// needn't hold the candidates.
+const HEAP_SELECT_RATIO: usize = 1024;
+
+/// An HNSW index, built with `GraphBuilder`
+///
+/// Searches, inserts, updates and deletes all take `&self`, so threads share a
+/// graph by reference and may run any of them at once. An insert fills in its
+/// vectors and nodes before linking them into any neighbor list, and publishes
+/// its metadata last, so searches only reach complete nodes, through lists
+/// read under their locks, and scans skip slots that aren't published yet. A
+/// deleted slot is only rewritten once every search that may have reached it
+/// has finished, which searches signal through epoch pins. `freeze` drops
+/// these guards for a graph that stops changing.
 pub struct Graph {
     m: u16,
     m0: u16,
@@ -29,10 +91,155 @@ pub struct Graph {
     nodes_arena: Arena<Node>,
     nodes0_arena: Arena<Node0>,
     vec_arena: DoubleArena<RawVec, QuantVec>,
+    meta_arena: ArenaWithoutIndex<NodeMeta>,
     top_level_root_node: NodeHandle,
     rng: AtomicRng,
+    // Number of items per arena chunk, `CHUNK_SIZE` unless the builder set one.
+    chunk_size: usize,
+    ef_construction: u16,
+    ef_search: u16,
+    cache_budget: Option<u32>,
+    // false when the raw arena holds zero-length vectors
+    store_raw: bool,
+    // only set when there are raw copies to link by
+    link_full_precision: bool,
+    // set when the vectors are borrowed, see `from_mapped`
+    read_only: bool,
+    // set by `freeze`: no writers, so searches skip the locks and epoch pins
+    frozen: bool,
+    neighbor_selection: NeighborSelection,
+    result_cache: Option<ResultCache>,
+    checkpoint: Option<Checkpoint>,
+    memory_pressure_hook: Option<Box<dyn MemoryPressureHook>>,
+    reranker: Option<Box<dyn Reranker>>,
+    now: Option<ClockFn>,
+    min_insert_distance: Option<f32>,
+    trace_sampler: Option<TraceSampler>,
+    // present iff there is a clock
+    expiry_arena: Option<ArenaWithoutIndex<NodeExpiry>>,
+    ingest_arena: Option<ArenaWithoutIndex<NodeIngest>>,
+    prior_arena: Option<ArenaWithoutIndex<NodePrior>>,
+    // Purged and deleted slots waiting to be reused, all tombstoned.
+    free_slots: FreeList,
+    // Nonzero once `delete` or `update` ran, or a loaded snapshot had free slots.
+    freed: AtomicU64,
+    magnitude_stats: Mutex<MagnitudeStats>,
+    // Visited sets of finished searches, one per concurrent search at most.
+    visited_pool: Mutex<Vec<VisitedSet>>,
+    // Bumped after every insert, invalidating cached results.
+    seq: AtomicU64,
+    clipped_vectors: AtomicU64,
+    clipped_components: AtomicU64,
+    aliased_inserts: AtomicU64,
+    clock: AtomicU32,
+    // Searches that returned results, to sample the ones counted as accesses.
+    searches: AtomicU32,
+    epoch: Epoch,
+    // Shared with the arenas, also used for quantized queries.
+    allocator: IndexAllocator,
+}
+
+// Magnitudes of inserted vectors: a fixed baseline over the first inserts and
+// exponentially weighted moments of the recent ones.
+#[derive(Default)]
+struct MagnitudeStats {
+    inserts: u64,
+    baseline_mean: f32,
+    // sum of squared deviations from the mean, Welford style
+    baseline_m2: f32,
+    recent_mean: f32,
+    recent_mean_square: f32,
+}
+
+impl MagnitudeStats {
+    fn record(&mut self, mag: f32) {
+        self.inserts += 1;
+        if self.inserts <= DRIFT_BASELINE {
+            let delta = mag - self.baseline_mean;
+            self.baseline_mean += delta / self.inserts as f32;
+            self.baseline_m2 += delta * (mag - self.baseline_mean);
+        }
+        if self.inserts == 1 {
+            self.recent_mean = mag;
+            self.recent_mean_square = mag * mag;
+        } else {
+            self.recent_mean += DRIFT_WEIGHT * (mag - self.recent_mean);
+            self.recent_mean_square += DRIFT_WEIGHT * (mag * mag - self.recent_mean_square);
+        }
+    }
+}
+
+struct Checkpoint {
+    every: u64,
+    callback: CheckpointFn,
+    // Held shared by inserts, deletes, updates and purges, and exclusively
+    // while the callback runs.
+    writers: RwLock<()>,
+    indexed: AtomicU64,
+    // Count passed to the last callback, to skip checkpoints that found nothing new.
+    reported: AtomicU64,
+}
+
+// Recent searches recorded by `GraphBuilder::trace_sampling`.
+struct TraceSampler {
+    // a search is traced when a draw is below this
+    threshold: u64,
+    // separate from the level rng, so that sampling leaves the graph unchanged
+    rng: AtomicRng,
+    capacity: usize,
+    traces: Mutex<VecDeque<SearchTrace>>,
+}
+
+// What a search recorded about its traversal.
+#[derive(Default)]
+struct Trace {
+    path: Vec<NodeId>,
+    nodes_visited: u32,
+}
+
+// What an insert writes into a vector slot: the full-precision copy, only read
+// when the graph keeps copies, and the source of the quantized vector.
+#[derive(Clone, Copy)]
+struct SlotArgs {
+    raw: *const f32,
+    quantized: QuantArgs,
+}
+
+impl SlotArgs {
+    fn new(vec: &[f32]) -> Self {
+        Self {
+            raw: vec.as_ptr(),
+            quantized: QuantArgs::Raw(vec.as_ptr()),
+        }
+    }
+}
+
+// What an insert records about its node besides the vector.
+#[derive(Clone, Copy, Default)]
+struct Stamp {
+    // Clock tick the node expires at, 0 for never.
+    expires_at: u64,
+    source: u32,
+}
+
+// A neighbor list read by a search, see `Graph::read_list`.
+enum ListRef<'a, T: ?Sized> {
+    Locked(RwLockReadGuard<'a, T>),
+    Frozen(&'a T),
+}
+
+impl<T: ?Sized> Deref for ListRef<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        match self {
+            Self::Locked(guard) => guard,
+            Self::Frozen(list) => list,
+        }
+    }
 }
 
+/// Laid out like `SearchResult`, with a handle in place of the id
 #[repr(C, align(4))]
 pub struct InternalSearchResult<T: ?Sized> {
     pub node: Handle<T>,
@@ -47,6 +254,8 @@ impl<T: ?Sized> Clone for InternalSearchResult<T> {
 
 impl<T: ?Sized> Copy for InternalSearchResult<T> {}
 
+/// A u32 node id at offset 0 followed by an f32 score at offset 4, 8 bytes
+/// aligned to 4, which FFI callers may rely on
 #[repr(C, align(4))]
 #[derive(Debug, Clone, Copy)]
 pub struct SearchResult {
@@ -54,6 +263,249 @@ pub struct SearchResult {
     pub score: f32,
 }
 
+// Searches transmute boxed results between `SearchResult`, `(u32, f32)` and,
+// through `map_boxed_slice`, `InternalSearchResult`, so their layouts must match.
+const _: () = {
+    assert!(mem::size_of::<SearchResult>() == 8 && mem::align_of::<SearchResult>() == 4);
+    assert!(mem::offset_of!(SearchResult, node) == 0);
+    assert!(mem::offset_of!(SearchResult, score) == 4);
+    assert!(mem::size_of::<(u32, f32)>() == 8 && mem::align_of::<(u32, f32)>() == 4);
+    assert!(mem::offset_of!((u32, f32), 0) == 0 && mem::offset_of!((u32, f32), 1) == 4);
+    assert!(mem::size_of::<InternalSearchResult<Node0>>() == 8);
+    assert!(mem::align_of::<InternalSearchResult<Node0>>() == 4);
+    assert!(mem::offset_of!(InternalSearchResult<Node0>, score) == 4);
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ClippingStats {
+    /// Inserted vectors with at least one clamped component
+    pub clipped_vectors: u64,
+    pub clipped_components: u64,
+}
+
+/// Shape and size of a graph, see `Graph::stats`
+#[derive(Debug, Clone, PartialEq)]
+pub struct GraphStats {
+    /// Live nodes on each level, level 0 first
+    pub nodes_per_level: Box<[u64]>,
+    /// Mean number of neighbors of the live nodes on each level, level 0 first
+    pub mean_neighbors: Box<[f32]>,
+    /// Bytes of the arena chunks holding vectors, node metadata and neighbor
+    /// lists, including slots not used yet
+    pub arena_bytes: u64,
+}
+
+/// Rough cost of a search, see `Graph::estimate_cost`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CostEstimate {
+    /// Quantized and full-precision scores computed
+    pub distance_computations: u64,
+    /// Vector and neighbor list bytes read
+    pub bytes_touched: u64,
+}
+
+/// Statistics of the magnitudes (squared norms) of inserted vectors, see
+/// `Graph::drift_report`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DriftReport {
+    pub inserts: u64,
+    /// Mean magnitude over the first 1024 inserts
+    pub baseline_mean: f32,
+    pub baseline_variance: f32,
+    /// Mean magnitude weighted towards roughly the last 256 inserts
+    pub recent_mean: f32,
+    pub recent_variance: f32,
+}
+
+impl DriftReport {
+    /// Whether the recent mean is more than `max_shift` baseline standard
+    /// deviations away from the baseline mean
+    ///
+    /// The deviation counts as at least 0.1% of the baseline mean, so that
+    /// vectors normalized upstream aren't flagged for rounding noise.
+    pub fn is_drifted(&self, max_shift: f32) -> bool {
+        let shift = self.recent_mean - self.baseline_mean;
+        let floor = 1e-3 * self.baseline_mean;
+        let variance = self.baseline_variance.max(floor * floor);
+        shift * shift > max_shift * max_shift * variance
+    }
+}
+
+/// Live nodes split by how often searches return them, see `Graph::tier_report`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TierReport {
+    pub hot_nodes: u64,
+    pub cold_nodes: u64,
+    /// Bytes of full-precision copies kept for cold nodes
+    pub cold_raw_bytes: u64,
+}
+
+/// Space held by a graph beyond its live nodes, see
+/// `Graph::fragmentation_report`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FragmentationReport {
+    /// Vectors and their full-precision copies
+    pub vectors: ArenaUsage,
+    pub metadata: ArenaUsage,
+    /// Expiry times, only kept with a clock
+    pub expiry: Option<ArenaUsage>,
+    /// Ingest times and sources, only kept with `GraphBuilder::record_ingest`
+    pub ingest: Option<ArenaUsage>,
+    /// Priors, only kept with `GraphBuilder::priors`
+    pub priors: Option<ArenaUsage>,
+    pub upper_nodes: ArenaUsage,
+    pub level0_nodes: ArenaUsage,
+}
+
+impl FragmentationReport {
+    /// Bytes that later inserts can fill without allocating, across arenas
+    pub fn reusable_bytes(&self) -> u64 {
+        [
+            self.vectors,
+            self.metadata,
+            self.upper_nodes,
+            self.level0_nodes,
+        ]
+        .iter()
+        .chain(&self.expiry)
+        .chain(&self.ingest)
+        .chain(&self.priors)
+        .map(ArenaUsage::reusable_bytes)
+        .sum()
+    }
+}
+
+/// Slot usage of one arena, see `FragmentationReport`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ArenaUsage {
+    pub slot_bytes: u64,
+    /// Allocated chunks, none for vectors read from a mapped buffer
+    pub chunks: u64,
+    /// Chunks with slots not handed out yet
+    pub partial_chunks: u64,
+    /// Slots in allocated chunks not handed out yet
+    pub unused_slots: u64,
+    /// Slots of deleted, purged or evicted nodes, waiting to be reused
+    pub tombstoned_slots: u64,
+}
+
+impl ArenaUsage {
+    fn new(chunks: usize, chunk_size: usize, slot_bytes: usize, len: usize) -> Self {
+        Self {
+            slot_bytes: slot_bytes as u64,
+            chunks: chunks as u64,
+            partial_chunks: chunks.saturating_sub(len / chunk_size) as u64,
+            unused_slots: (chunks * chunk_size).saturating_sub(len) as u64,
+            tombstoned_slots: 0,
+        }
+    }
+
+    pub fn reusable_bytes(&self) -> u64 {
+        (self.unused_slots + self.tombstoned_slots) * self.slot_bytes
+    }
+}
+
+/// Results of `Graph::search_with_outcome`
+#[derive(Debug, Clone)]
+pub struct SearchOutcome {
+    pub results: Box<[SearchResult]>,
+    /// Fewer than `top_k` results were found after considering every node,
+    /// because the graph has fewer live nodes
+    pub exhausted: bool,
+}
+
+/// When a node was inserted and where from, see `Graph::ingest`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ingest {
+    /// Clock time of the insert, 0 in a graph without a clock
+    pub at: u32,
+    pub source: u32,
+}
+
+/// A vector borrowed from a graph, see `Graph::get_vector`
+///
+/// Holds an epoch pin, so inserts and updates that would rewrite the slot of a
+/// deleted node wait until it's dropped. Don't keep one across such a write on
+/// the same thread, which would wait for itself.
+pub struct VectorRef<'a, T: ?Sized> {
+    vec: &'a T,
+    _pin: Option<EpochPin<'a>>,
+}
+
+impl<T: ?Sized> Deref for VectorRef<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.vec
+    }
+}
+
+/// Results of `Graph::search_banded`
+#[derive(Debug, Clone)]
+pub struct BandedResults {
+    pub results: Box<[SearchResult]>,
+    /// Candidates per score band: `counts[i]` scored at least as well as
+    /// `bands[i]` but worse than `bands[i - 1]`, and the last entry counts the
+    /// ones worse than every band
+    pub counts: Box<[u32]>,
+}
+
+/// How `Graph::classify` counts the votes of the nearest neighbors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoteKind {
+    /// One vote per neighbor
+    Majority,
+    /// Votes weighted by similarity: the score itself for Cosine and
+    /// DotProduct, floored at 0, and `1 / (1 + distance)` for the distance
+    /// metrics
+    Weighted,
+}
+
+/// The label picked by `Graph::classify`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LabelScore {
+    pub label: u64,
+    /// Share of the votes cast for the label, in (0, 1]
+    pub score: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchProvenance {
+    pub results: Box<[SearchResult]>,
+    /// Upper-level nodes the search descended through, top level first
+    pub path: Box<[NodeId]>,
+}
+
+/// Working memory kept between searches, see `Graph::search_with_context`
+///
+/// Holds the visited set, candidate queues and quantized query of a search,
+/// grown to what the largest search so far needed. Create one per thread; a
+/// context may serve several graphs.
+#[derive(Default)]
+pub struct SearchContext {
+    visited: VisitedSet,
+    queue: Vec<InternalSearchResult<Node0>>,
+    results: Vec<InternalSearchResult<Node0>>,
+    upper_queue: Vec<InternalSearchResult<Node>>,
+    upper_results: Vec<InternalSearchResult<Node>>,
+    query: Option<QuantVecBox>,
+}
+
+impl SearchContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// A search recorded by `GraphBuilder::trace_sampling`
+#[derive(Debug, Clone)]
+pub struct SearchTrace {
+    /// Upper-level nodes the search descended through, top level first
+    pub path: Box<[NodeId]>,
+    /// Level 0 nodes expanded, over every round of a refilling search
+    pub nodes_visited: u32,
+}
+
 impl Graph {
     pub fn new(
         m: u16,
@@ -63,14 +515,81 @@ impl Graph {
         quantization: Quantization,
         metric: DistanceMetricKind,
     ) -> Self {
-        let nodes_arena = Arena::new(1024, m);
-        let nodes0_arena = Arena::new(1024, m0);
-        let vec_arena = DoubleArena::new(1024, dims, (quantization, dims));
+        GraphBuilder::new()
+            .m(m)
+            .m0(m0)
+            .dims(dims)
+            .levels(levels)
+            .quantization(quantization)
+            .metric(metric)
+            .build()
+    }
+
+    pub(crate) fn from_builder(builder: GraphBuilder) -> Self {
+        let GraphBuilder {
+            m,
+            m0,
+            dims,
+            levels,
+            quantization,
+            metric,
+            assume_normalized,
+            cache_budget,
+            rerank,
+            full_precision_linking,
+            neighbor_selection,
+            result_cache,
+            checkpoint,
+            memory_pressure_hook,
+            reranker,
+            clock,
+            record_ingest,
+            priors,
+            min_insert_distance,
+            trace_sampling,
+            seed,
+            chunk_size,
+            ef_construction,
+            ef_search,
+            allocator,
+        } = builder;
+
+        let store_raw = rerank && !matches!(quantization, Quantization::FullPrecisionFP);
 
-        let root_vec_raw: Box<[f32]> =
-            unsafe { Box::new_zeroed_slice(dims as usize).assume_init() };
+        let nodes_arena = Arena::new(chunk_size, m, allocator.clone());
+        let nodes0_arena = Arena::new(chunk_size, m0, allocator.clone());
+        let raw_dims = if store_raw { dims } else { 0 };
+        let vec_arena = DoubleArena::new(
+            chunk_size,
+            raw_dims,
+            (quantization, dims),
+            allocator.clone(),
+        );
+        let meta_arena = ArenaWithoutIndex::new_zeroed(chunk_size, (), allocator.clone());
+
+        // all-zero bytes read as 0.0, and dims is positive so the layout isn't empty
+        let root_layout = Layout::array::<f32>(dims as usize).expect("dims fit in memory");
+        let root_vec_raw = allocator.alloc(root_layout, true);
 
-        let vec_handle = vec_arena.alloc(root_vec_raw.as_ptr(), root_vec_raw.as_ptr());
+        let root_ptr = root_vec_raw.as_ptr() as *const f32;
+        let vec_handle = vec_arena.alloc(root_ptr, QuantArgs::Raw(root_ptr));
+        unsafe { allocator.dealloc(root_vec_raw, root_layout) };
+        meta_arena.alloc(*vec_handle, 0);
+        let expiry_arena = clock.as_ref().map(|_| {
+            let expiry_arena = ArenaWithoutIndex::new_zeroed(chunk_size, (), allocator.clone());
+            expiry_arena.alloc(*vec_handle, 0);
+            expiry_arena
+        });
+        let ingest_arena = record_ingest.then(|| {
+            let ingest_arena = ArenaWithoutIndex::new_zeroed(chunk_size, (), allocator.clone());
+            ingest_arena.alloc(*vec_handle, (0, 0));
+            ingest_arena
+        });
+        let prior_arena = priors.then(|| {
+            let prior_arena = ArenaWithoutIndex::new_zeroed(chunk_size, (), allocator.clone());
+            prior_arena.alloc(*vec_handle, 0.0);
+            prior_arena
+        });
 
         let node0_handle = nodes0_arena.alloc(vec_handle);
 
@@ -81,377 +600,7345 @@ impl Graph {
             prev_node = node_handle;
         }
 
+        meta_arena[NodeMetaHandle::new(*vec_handle)].publish(prev_node, levels, 0);
+
         Self {
             m,
             m0,
             dims,
             levels,
             quantization,
-            distance_metric: DistanceMetric::new(metric, quantization),
+            distance_metric: DistanceMetric::new(metric, quantization)
+                .assume_normalized(assume_normalized),
             nodes_arena,
             nodes0_arena,
             vec_arena,
+            meta_arena,
             top_level_root_node: prev_node,
-            rng: AtomicRng::new(42),
+            rng: AtomicRng::new(seed),
+            chunk_size,
+            ef_construction,
+            ef_search,
+            cache_budget,
+            store_raw,
+            link_full_precision: full_precision_linking && store_raw,
+            read_only: false,
+            frozen: false,
+            neighbor_selection,
+            result_cache: result_cache.map(ResultCache::new),
+            checkpoint: checkpoint.map(|(every, callback)| Checkpoint {
+                every,
+                callback,
+                writers: RwLock::new(()),
+                indexed: AtomicU64::new(0),
+                reported: AtomicU64::new(0),
+            }),
+            memory_pressure_hook,
+            reranker,
+            now: clock,
+            min_insert_distance,
+            trace_sampler: trace_sampling.map(|(probability, capacity)| TraceSampler {
+                threshold: (probability as f64 * u64::MAX as f64) as u64,
+                rng: AtomicRng::new(42),
+                capacity,
+                traces: Mutex::new(VecDeque::with_capacity(capacity)),
+            }),
+            expiry_arena,
+            ingest_arena,
+            prior_arena,
+            free_slots: FreeList::new(chunk_size, allocator.clone()),
+            freed: AtomicU64::new(0),
+            visited_pool: Mutex::new(Vec::new()),
+            magnitude_stats: Mutex::new(MagnitudeStats::default()),
+            seq: AtomicU64::new(0),
+            clipped_vectors: AtomicU64::new(0),
+            clipped_components: AtomicU64::new(0),
+            aliased_inserts: AtomicU64::new(0),
+            clock: AtomicU32::new(0),
+            searches: AtomicU32::new(0),
+            epoch: Epoch::new(),
+            allocator,
         }
     }
 
-    pub fn index(&self, vec: &[f32], ef: u16) -> NodeId {
-        let vec_handle = self.vec_arena.alloc(vec.as_ptr(), vec.as_ptr());
-        let vec = &self.vec_arena[vec_handle.handle_b()];
+    /// Number of node slots, which is one more than the largest `NodeId` handed
+    /// out so far
+    ///
+    /// Slots taken over by a new vector are counted once.
+    pub fn len(&self) -> usize {
+        // minus the synthetic root
+        self.vec_arena.len() - 1
+    }
 
-        let max_level = exponential_random(&self.rng, 0.4, self.levels);
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 
-        self.index_level(
-            vec_handle,
-            vec,
-            self.top_level_root_node,
-            self.levels,
-            max_level,
-            ef,
-        );
+    pub fn dims(&self) -> u16 {
+        self.dims
+    }
 
-        NodeId(*vec_handle - 1)
+    /// Number of levels above level 0
+    pub fn levels(&self) -> u8 {
+        self.levels
     }
 
-    fn index_level(
-        &self,
-        vec_handle: VecHandle,
-        vec: &QuantVec,
-        entry_node: NodeHandle,
-        current_level: u8,
-        max_level: u8,
-        ef: u16,
-    ) -> NodeHandle {
-        if current_level > max_level {
-            let results = self.search_level(entry_node, vec, ef, 1, true);
-            let child = self.nodes_arena[results[0].node].child;
+    #[cfg(feature = "checked")]
+    pub(crate) fn distance_metric(&self) -> &DistanceMetric {
+        &self.distance_metric
+    }
 
-            self.index_level(vec_handle, vec, child, current_level - 1, max_level, ef)
-        } else if current_level == 0 {
-            self.index_level0(vec_handle, vec, entry_node.cast(), ef)
-                .cast()
-        } else {
-            let results = self.search_level(entry_node, vec, ef, self.m, true);
-            let child = self.nodes_arena[results[0].node].child;
+    /// Pointers to the vector chunks, for copying them wholesale, e.g. to a GPU
+    ///
+    /// See the `layout` module for what the chunks hold. Slots are counted up
+    /// to the number taken when this is called.
+    ///
+    /// # Safety
+    /// The pointers are valid for reads of `len * stride` bytes until the graph
+    /// is dropped. Inserts, updates and slot reuse write slots in place, and a
+    /// slot whose insert is still running may be uninitialized, so read through
+    /// them with writers paused, e.g. from a checkpoint callback.
+    pub unsafe fn chunks_raw(&self) -> VecChunks {
+        let slots = self.vec_arena.len();
+        let (raw_ptrs, quantized_ptrs) = self.vec_arena.chunk_ptrs();
+        let chunks = |ptrs: Vec<*const u8>, stride| {
+            ptrs.into_iter()
+                .enumerate()
+                .map(|(i, ptr)| RawChunk {
+                    ptr,
+                    len: slots
+                        .saturating_sub(i * self.chunk_size)
+                        .min(self.chunk_size),
+                    stride,
+                })
+                .filter(|chunk| chunk.len > 0)
+                .collect()
+        };
+        VecChunks {
+            quantized: chunks(
+                quantized_ptrs,
+                layout::quantized_stride(self.quantization, self.dims),
+            ),
+            full_precision: if self.store_raw {
+                chunks(raw_ptrs, layout::full_precision_stride(self.dims))
+            } else {
+                Box::new([])
+            },
+        }
+    }
 
-            let child = self.index_level(vec_handle, vec, child, current_level - 1, max_level, ef);
+    /// Count the live nodes and their neighbors on every level, and the memory
+    /// the arenas take up
+    ///
+    /// Walks every node, so it takes time linear in the size of the graph.
+    pub fn stats(&self) -> GraphStats {
+        let _pin = self.pin();
+        let levels = self.levels as usize + 1;
+        let mut nodes = alloc::vec![0u64; levels];
+        let mut neighbors = alloc::vec![0u64; levels];
 
-            self.create_node(vec_handle, results, child)
+        for id in 0..self.len() as u32 {
+            let Some(vec) = self.live_vec(NodeId(id)) else {
+                continue;
+            };
+            let meta = self.meta(vec);
+            let mut node = meta.top();
+            for level in (1..=meta.level() as usize).rev() {
+                let node_ref = &self.nodes_arena[node];
+                nodes[level] += 1;
+                neighbors[level] += node_ref.neighbors.read().neighbors().len() as u64;
+                node = node_ref.child;
+            }
+            let node0 = &self.nodes0_arena[node.cast::<Node0>()];
+            nodes[0] += 1;
+            neighbors[0] += node0.neighbors.read().neighbors().len() as u64;
+        }
+
+        let arena_bytes = self.vec_arena.allocated_bytes()
+            + self.meta_arena.allocated_bytes()
+            + self
+                .expiry_arena
+                .as_ref()
+                .map_or(0, ArenaWithoutIndex::allocated_bytes)
+            + self
+                .ingest_arena
+                .as_ref()
+                .map_or(0, ArenaWithoutIndex::allocated_bytes)
+            + self
+                .prior_arena
+                .as_ref()
+                .map_or(0, ArenaWithoutIndex::allocated_bytes)
+            + self.free_slots.allocated_bytes()
+            + self.nodes_arena.allocated_bytes()
+            + self.nodes0_arena.allocated_bytes();
+
+        GraphStats {
+            mean_neighbors: nodes
+                .iter()
+                .zip(&neighbors)
+                .map(|(&nodes, &neighbors)| {
+                    if nodes == 0 {
+                        0.0
+                    } else {
+                        neighbors as f32 / nodes as f32
+                    }
+                })
+                .collect(),
+            nodes_per_level: nodes.into_boxed_slice(),
+            arena_bytes: arena_bytes as u64,
         }
     }
 
-    fn index_level0(
-        &self,
-        vec_handle: VecHandle,
-        vec: &QuantVec,
-        entry_node: Node0Handle,
-        ef: u16,
-    ) -> Node0Handle {
-        let results = self.search_level0(entry_node, vec, ef, self.m0, true);
-        self.create_node0(vec_handle, results)
+    /// Insert `vec` with the builder's `ef_construction`, panicking where
+    /// `try_add` would return an error
+    pub fn add(&self, vec: &[f32]) -> NodeId {
+        self.index(vec, self.ef_construction)
     }
 
-    fn create_node(
-        &self,
-        vec_handle: VecHandle,
-        results: Box<[InternalSearchResult<Node>]>,
-        child: NodeHandle,
-    ) -> NodeHandle {
-        let node_handle = self.nodes_arena.alloc((vec_handle, child));
-        let node = &self.nodes_arena[node_handle];
-        let mut neighbors_guard = node.neighbors.write();
+    /// Insert `vec` with the builder's `ef_construction`, see `try_index`
+    pub fn try_add(&self, vec: &[f32]) -> Result<NodeId, VectorDbError> {
+        self.try_index(vec, self.ef_construction)
+    }
 
-        unsafe {
-            ptr::copy_nonoverlapping(
-                results.as_ptr() as *const Neighbor,
-                neighbors_guard.neighbors.as_mut_ptr(),
-                results.len(),
-            );
+    /// Insert `vec`, panicking where `try_index` would return an error
+    pub fn index(&self, vec: &[f32], ef: u16) -> NodeId {
+        match self.try_index(vec, ef) {
+            Ok(id) => id,
+            Err(err) => panic!("{err}"),
         }
+    }
 
-        if results.len() as u16 == self.m {
-            neighbors_guard.neighbors_full = true;
-            let mut lowest_index = 0;
-            let mut lowest_score = self.distance_metric.max_value();
-
-            for i in 0..self.m {
-                let neighbor = &neighbors_guard.neighbors[i as usize];
-                if self.distance_metric.cmp_score(neighbor.score, lowest_score) == Ordering::Less {
-                    lowest_score = neighbor.score;
-                    lowest_index = i;
-                }
-            }
+    /// Insert `vec`, failing on a dimension mismatch, a NaN component or a
+    /// zero `ef`, in a read-only graph, or when the memory pressure hook vetoes
+    /// the chunks the insert would allocate
+    ///
+    /// `ef` overrides the builder's `ef_construction` for this insert.
+    pub fn try_index(&self, vec: &[f32], ef: u16) -> Result<NodeId, VectorDbError> {
+        self.try_index_stamped(vec, ef, Stamp::default())
+    }
 
-            neighbors_guard.lowest_index = lowest_index;
-            neighbors_guard.lowest_score = lowest_score;
-        } else {
-            neighbors_guard.lowest_index = results.len() as u16;
+    /// Like `index`, without validating `vec` and `ef`
+    ///
+    /// Saves a pass over the vector for callers that already validate their
+    /// input. A NaN component leaves the node with meaningless scores, and a
+    /// zero `ef` panics.
+    ///
+    /// # Safety
+    ///
+    /// `vec` must have the graph's input dimensionality.
+    pub unsafe fn index_unchecked(&self, vec: &[f32], ef: u16) -> NodeId {
+        match self.try_index_validated(vec, ef, Stamp::default()) {
+            Ok(id) => id,
+            Err(err) => panic!("{err}"),
         }
+    }
 
-        for result in results {
-            let neighbor = &self.nodes_arena[result.node];
-            neighbor.neighbors.write().insert_neighbor(
-                &self.distance_metric,
-                node_handle,
-                result.score,
-            );
-        }
+    /// Insert an f16 vector, converted to f32 internally, panicking where
+    /// `try_index_f16` would return an error
+    #[cfg(feature = "nightly")]
+    pub fn index_f16(&self, vec: &[f16], ef: u16) -> NodeId {
+        self.index(&widen_f16(vec), ef)
+    }
 
-        node_handle
+    /// Like `try_index`, for an f16 vector
+    #[cfg(feature = "nightly")]
+    pub fn try_index_f16(&self, vec: &[f16], ef: u16) -> Result<NodeId, VectorDbError> {
+        self.try_index(&widen_f16(vec), ef)
     }
 
-    fn create_node0(
-        &self,
-        vec_handle: VecHandle,
-        results: Box<[InternalSearchResult<Node0>]>,
-    ) -> Node0Handle {
-        let node_handle = self.nodes0_arena.alloc(vec_handle);
-        let node = &self.nodes0_arena[node_handle];
-        let mut neighbors_guard = node.neighbors.write();
+    /// Insert a bf16 vector, given as the bits of each component as inference
+    /// runtimes emit them, panicking where `try_index_bf16` would return an
+    /// error
+    ///
+    /// A convenience over widening the vector yourself: it's copied to f32
+    /// first, as the f32 paths store and score it, so it costs the same.
+    pub fn index_bf16(&self, vec: &[u16], ef: u16) -> NodeId {
+        self.index(&widen_bf16(vec), ef)
+    }
 
-        unsafe {
-            ptr::copy_nonoverlapping(
-                results.as_ptr() as *const Neighbor0,
-                neighbors_guard.neighbors.as_mut_ptr(),
-                results.len(),
-            );
-        }
+    /// Like `try_index`, for a bf16 vector given as bits
+    pub fn try_index_bf16(&self, vec: &[u16], ef: u16) -> Result<NodeId, VectorDbError> {
+        self.try_index(&widen_bf16(vec), ef)
+    }
 
-        if results.len() as u16 == self.m0 {
-            neighbors_guard.neighbors_full = true;
-            let mut lowest_index = 0;
-            let mut lowest_score = self.distance_metric.max_value();
+    /// Insert a vector given as bytes, such as an image descriptor, panicking
+    /// where `try_index_bytes` would return an error
+    pub fn index_bytes(&self, bytes: &[u8], norm: Option<f32>, ef: u16) -> NodeId {
+        match self.try_index_bytes(bytes, norm, ef) {
+            Ok(id) => id,
+            Err(err) => panic!("{err}"),
+        }
+    }
 
-            for i in 0..self.m0 {
-                let neighbor = &neighbors_guard.neighbors[i as usize];
-                if self.distance_metric.cmp_score(neighbor.score, lowest_score) == Ordering::Less {
-                    lowest_score = neighbor.score;
-                    lowest_index = i;
-                }
-            }
+    /// Insert a vector given as bytes, such as an image descriptor
+    ///
+    /// A `Binary` graph takes one bit per dimension, packed eight to a byte with
+    /// the lowest bit first, as binary descriptors like ORB and BRIEF come.
+    /// Other graphs take one byte per dimension, standing for that byte divided
+    /// by 255, as for quantized VLAD descriptors. With `UnsignedByte`
+    /// quantization the bytes are stored as they are, and `norm`, the L2 norm
+    /// of the bytes taken as integers, saves computing it. It is ignored
+    /// otherwise. The bytes are only widened to f32 for a full-precision copy
+    /// (see `GraphBuilder::rerank`), for the `min_insert_distance` check, or
+    /// under other quantizations. Fails where `try_index` would.
+    pub fn try_index_bytes(
+        &self,
+        bytes: &[u8],
+        norm: Option<f32>,
+        ef: u16,
+    ) -> Result<NodeId, VectorDbError> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("index_bytes", ef).entered();
 
-            neighbors_guard.lowest_index = lowest_index;
-            neighbors_guard.lowest_score = lowest_score;
+        self.check_writable()?;
+        self.check_ef(ef)?;
+        let binary = matches!(self.quantization, Quantization::Binary);
+        let expected = if binary {
+            (self.dims as usize).div_ceil(8)
         } else {
-            neighbors_guard.lowest_index = results.len() as u16;
+            self.dims as usize
+        };
+        if bytes.len() != expected {
+            return Err(VectorDbError::DimensionMismatch {
+                expected: self.dims,
+                found: if binary { bytes.len() * 8 } else { bytes.len() },
+            });
         }
+        let widened = || -> Vec<f32> {
+            (0..self.dims as usize)
+                .map(|i| {
+                    if binary {
+                        (bytes[i / 8] >> (i % 8) & 1) as f32
+                    } else {
+                        bytes[i] as f32 / 255.0
+                    }
+                })
+                .collect()
+        };
 
-        for result in results {
-            let neighbor = &self.nodes0_arena[result.node];
-            neighbor.neighbors.write().insert_neighbor(
-                &self.distance_metric,
-                node_handle,
-                result.score,
-            );
+        let (encoded, mag) = match self.quantization {
+            Quantization::UnsignedByte => {
+                let mag = match norm {
+                    Some(norm) => norm * norm,
+                    None => bytes.iter().map(|&byte| (byte as f32).powi(2)).sum(),
+                };
+                (bytes.to_vec(), mag / (255.0 * 255.0))
+            }
+            Quantization::Binary => (pack_bits(bytes, self.dims), widened().iter().sum()),
+            _ => return self.try_index(&widened(), ef),
+        };
+
+        let raw = (self.store_raw || self.min_insert_distance.is_some()).then(widened);
+        if let Some(vec) = &raw
+            && let Some(id) = self.near_duplicate(vec, ef)
+        {
+            self.aliased_inserts.fetch_add(1, AtomicOrdering::Relaxed);
+            return Ok(id);
         }
+        let slot = SlotArgs {
+            // never read without copies, which only need an aligned pointer
+            raw: raw.as_deref().map_or(ptr::dangling(), <[f32]>::as_ptr),
+            quantized: QuantArgs::Encoded(encoded.as_ptr(), mag),
+        };
+        self.index_checkpointed(|max_level| {
+            self.insert_slot(slot, mag, 0, ef, max_level, Stamp::default())
+        })
+    }
 
-        node_handle
+    /// Insert `vec`, to be returned by searches for the next `ttl` clock ticks,
+    /// panicking where `try_index_with_ttl` would return an error
+    pub fn index_with_ttl(&self, vec: &[f32], ttl: u64, ef: u16) -> NodeId {
+        match self.try_index_with_ttl(vec, ttl, ef) {
+            Ok(id) => id,
+            Err(err) => panic!("{err}"),
+        }
     }
 
-    pub fn search_quantized(&self, query: &[f32], ef: u16, top_k: u16) -> Box<[SearchResult]> {
-        let (query, ptr, layout): (&QuantVec, *mut u8, Layout) = unsafe {
-            let metadata = (self.quantization, self.dims);
-            let size = QuantVec::size_aligned(metadata);
-            let layout = Layout::from_size_align_unchecked(size, QuantVec::ALIGN);
-            let ptr = alloc(layout);
-            if ptr.is_null() {
-                handle_alloc_error(layout);
-            }
-            QuantVec::new_at(ptr, metadata, query.as_ptr());
-            let query = &*ptr::from_raw_parts(ptr, QuantVec::ptr_metadata(metadata));
-            (query, ptr, layout)
+    /// Insert `vec`, to be returned by searches for the next `ttl` clock ticks
+    ///
+    /// Fails if the graph was built without a clock, or where `try_index` would.
+    pub fn try_index_with_ttl(
+        &self,
+        vec: &[f32],
+        ttl: u64,
+        ef: u16,
+    ) -> Result<NodeId, VectorDbError> {
+        let now = self.now().ok_or(VectorDbError::NoClock)?;
+        // 0 means never
+        let stamp = Stamp {
+            expires_at: now.saturating_add(ttl).max(1),
+            ..Stamp::default()
         };
-        let mut entry_node = self.top_level_root_node;
+        self.try_index_stamped(vec, ef, stamp)
+    }
 
-        // ignore the `0..self.range`, the actual search range in (0, self.levels]
-        for _ in 0..self.levels {
-            let results = self.search_level(entry_node, query, ef, top_k, true);
-            let child = self.nodes_arena[results[0].node].child;
-            entry_node = child;
+    /// Insert `vec` tagged with `source`, panicking where
+    /// `try_index_with_source` would return an error
+    pub fn index_with_source(&self, vec: &[f32], source: u32, ef: u16) -> NodeId {
+        match self.try_index_with_source(vec, source, ef) {
+            Ok(id) => id,
+            Err(err) => panic!("{err}"),
         }
+    }
 
-        let entry_node = entry_node.cast();
+    /// Insert `vec` tagged with `source`, which `ingest` returns for the node
+    ///
+    /// Other inserts tag their nodes with 0. An insert aliased to a
+    /// near-duplicate keeps the tag of the node it aliases. The tag is dropped
+    /// unless the graph records ingests, see `GraphBuilder::record_ingest`.
+    pub fn try_index_with_source(
+        &self,
+        vec: &[f32],
+        source: u32,
+        ef: u16,
+    ) -> Result<NodeId, VectorDbError> {
+        self.try_index_stamped(
+            vec,
+            ef,
+            Stamp {
+                source,
+                ..Stamp::default()
+            },
+        )
+    }
 
-        let results = self.search_level0(entry_node, query, ef, top_k, false);
+    /// When the node `id` was inserted and the source it was tagged with, or
+    /// `None` if `id` isn't a live node or the graph doesn't record ingests
+    ///
+    /// See `GraphBuilder::record_ingest`. Look up the nodes of search results
+    /// to audit where they came from.
+    pub fn ingest(&self, id: NodeId) -> Option<Ingest> {
+        let ingest_arena = self.ingest_arena.as_ref()?;
+        let _pin = self.pin();
+        let vec_handle = self.live_vec(id)?;
+        let ingest = &ingest_arena[NodeIngestHandle::new(*vec_handle)];
+        Some(Ingest {
+            at: ingest.at.load(AtomicOrdering::Relaxed),
+            source: ingest.source.load(AtomicOrdering::Relaxed),
+        })
+    }
 
-        unsafe {
-            dealloc(ptr, layout);
-        }
+    /// Set the query-independent prior of the node `id`, e.g. its popularity,
+    /// which searches blend into its score, see `SearchParams::prior_weight`
+    pub fn set_prior(&self, id: NodeId, prior: f32) -> Result<(), VectorDbError> {
+        self.check_writable()?;
+        let prior_arena = self.prior_arena.as_ref().ok_or(VectorDbError::NoPriors)?;
+        let _pin = self.pin();
+        let vec_handle = self.live_vec(id).ok_or(VectorDbError::UnknownNode)?;
+        prior_arena[NodePriorHandle::new(*vec_handle)].set(prior);
+        Ok(())
+    }
 
-        unsafe {
-            map_boxed_slice(results, |result| SearchResult {
-                node: NodeId(*self.nodes0_arena[result.node].vec - 1),
-                score: result.score,
-            })
-        }
+    /// The prior of the node `id`, or `None` if `id` isn't a live node or the
+    /// graph keeps no priors
+    pub fn prior(&self, id: NodeId) -> Option<f32> {
+        let prior_arena = self.prior_arena.as_ref()?;
+        let _pin = self.pin();
+        let vec_handle = self.live_vec(id)?;
+        Some(prior_arena[NodePriorHandle::new(*vec_handle)].get())
     }
 
-    pub fn search(&self, query: &[f32], ef: u16, top_k: u16) -> Box<[SearchResult]> {
-        debug_assert!((0..8192).contains(&top_k));
-        let mag_query = dot_product_f32(query, query);
-        let results_quantized = self.search_quantized(query, ef, top_k * 8);
-        let results_quantized =
-            unsafe { mem::transmute::<Box<[SearchResult]>, Box<[(u32, f32)]>>(results_quantized) };
-        let query = unsafe { mem::transmute::<&[f32], &RawVec>(query) };
-        let mut results = Vec::with_capacity(results_quantized.len());
-        for (handle, _) in results_quantized {
-            let handle_a = HandleA::new(handle + 1);
-            let vec = &self.vec_arena[handle_a];
-            let mag_vec = dot_product_f32(&vec.vec, &vec.vec);
-            let score = self
-                .distance_metric
-                .calculate_raw(query, mag_query, vec, mag_vec);
-            results.push((handle, score));
-        }
+    fn try_index_stamped(
+        &self,
+        vec: &[f32],
+        ef: u16,
+        stamp: Stamp,
+    ) -> Result<NodeId, VectorDbError> {
+        self.check_vec(vec)?;
+        self.check_ef(ef)?;
+        self.try_index_validated(vec, ef, stamp)
+    }
 
-        let top_k = top_k as usize;
+    // `try_index_stamped` for a `vec` and `ef` known to be valid.
+    fn try_index_validated(
+        &self,
+        vec: &[f32],
+        ef: u16,
+        stamp: Stamp,
+    ) -> Result<NodeId, VectorDbError> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("index", ef).entered();
 
-        if results.len() > top_k {
-            results.select_nth_unstable_by(top_k, |a, b| self.distance_metric.cmp_score(a.1, b.1));
-            results.truncate(top_k);
+        self.check_writable()?;
+        if stamp.expires_at == 0
+            && let Some(id) = self.near_duplicate(vec, ef)
+        {
+            self.aliased_inserts.fetch_add(1, AtomicOrdering::Relaxed);
+            return Ok(id);
         }
+        self.index_checkpointed(|max_level| self.insert(vec, ef, max_level, stamp))
+    }
 
-        results.sort_unstable_by(|a, b| self.distance_metric.cmp_score(a.1, b.1));
+    // Draw a level and `insert` at it, counting the insert towards the
+    // checkpoint.
+    fn index_checkpointed(
+        &self,
+        insert: impl FnOnce(u8) -> NodeId,
+    ) -> Result<NodeId, VectorDbError> {
+        let max_level = self.draw_level()?;
 
-        unsafe {
-            mem::transmute::<Box<[(u32, f32)]>, Box<[SearchResult]>>(results.into_boxed_slice())
+        let Some(checkpoint) = &self.checkpoint else {
+            return Ok(insert(max_level));
+        };
+
+        let (id, indexed) = {
+            let _writer = checkpoint.writers.read();
+            let id = insert(max_level);
+            // counted before the lock is released, so a quiesced count is exact
+            let indexed = checkpoint.indexed.fetch_add(1, AtomicOrdering::Relaxed) + 1;
+            (id, indexed)
+        };
+
+        if indexed % checkpoint.every == 0 {
+            self.run_checkpoint(checkpoint);
+        }
+
+        Ok(id)
+    }
+
+    /// Insert every vector of `vecs`, panicking where `try_index_batch` would
+    /// return an error
+    pub fn index_batch(&self, vecs: &[&[f32]], ef: u16) -> Box<[NodeId]> {
+        match self.try_index_batch(vecs, ef) {
+            Ok(ids) => ids,
+            Err(err) => panic!("{err}"),
         }
     }
 
-    fn search_level(
+    /// Insert every vector of `vecs` in order, returning their ids
+    ///
+    /// Cheaper than a `try_index` call per vector for bulk loads: the arena
+    /// chunks the batch needs are allocated up front, and the checkpoint lock
+    /// is taken once, so the checkpoint callback runs after the batch rather
+    /// than in the middle of it. Every vector's dimensions are checked before
+    /// any is inserted. If the memory pressure hook vetoes an insert, the
+    /// vectors before it stay inserted.
+    pub fn try_index_batch(
         &self,
-        entry_node: NodeHandle,
-        query: &QuantVec,
+        vecs: &[&[f32]],
         ef: u16,
-        top_k: u16,
-        include_root: bool,
-    ) -> Box<[InternalSearchResult<Node>]> {
-        let mut candidate_queue = BinaryHeap::new_by(|a: &InternalSearchResult<Node>, b| {
-            self.distance_metric.cmp_score(a.score, b.score)
-        });
-        let mut results = Vec::new();
-        let mut set = FixedSet::new(self.m);
+    ) -> Result<Box<[NodeId]>, VectorDbError> {
+        self.try_index_batch_cancellable(vecs, ef, &CancellationToken::new())
+    }
 
-        let node = &self.nodes_arena[entry_node];
-        let vec = &self.vec_arena[node.vec.handle_b()];
+    /// Like `try_index_batch`, stopping with `VectorDbError::Cancelled` before
+    /// the next insert once `cancel` is cancelled
+    ///
+    /// The vectors inserted before stay inserted, as after a veto.
+    pub fn try_index_batch_cancellable(
+        &self,
+        vecs: &[&[f32]],
+        ef: u16,
+        cancel: &CancellationToken,
+    ) -> Result<Box<[NodeId]>, VectorDbError> {
+        self.check_writable()?;
+        self.check_ef(ef)?;
+        for vec in vecs {
+            self.check_vec(vec)?;
+        }
+        self.reserve_batch(vecs.len());
 
-        let score = self.distance_metric.calculate(query, vec);
+        let writer = self
+            .checkpoint
+            .as_ref()
+            .map(|checkpoint| checkpoint.writers.read());
+        let mut ids = Vec::with_capacity(vecs.len());
+        let mut inserted = 0;
+        let mut error = None;
+        for vec in vecs {
+            if cancel.is_cancelled() {
+                error = Some(VectorDbError::Cancelled);
+                break;
+            }
+            if let Some(id) = self.near_duplicate(vec, ef) {
+                self.aliased_inserts.fetch_add(1, AtomicOrdering::Relaxed);
+                ids.push(id);
+                continue;
+            }
+            match self.draw_level() {
+                Ok(max_level) => {
+                    ids.push(self.insert(vec, ef, max_level, Stamp::default()));
+                    inserted += 1;
+                }
+                Err(err) => {
+                    error = Some(err);
+                    break;
+                }
+            }
+        }
 
-        set.insert(*entry_node);
-        candidate_queue.push(InternalSearchResult {
-            node: entry_node,
-            score,
-        });
+        if let Some(checkpoint) = &self.checkpoint {
+            let indexed = checkpoint
+                .indexed
+                .fetch_add(inserted, AtomicOrdering::Relaxed)
+                + inserted;
+            drop(writer);
+            if indexed / checkpoint.every != (indexed - inserted) / checkpoint.every {
+                self.run_checkpoint(checkpoint);
+            }
+        }
 
-        let mut nodes_visisted = 0;
+        match error {
+            Some(err) => Err(err),
+            None => Ok(ids.into_boxed_slice()),
+        }
+    }
 
-        while let Some(entry) = candidate_queue.pop() {
-            if nodes_visisted >= ef {
-                break;
+    /// Insert every vector of `vecs` on rayon's thread pool, panicking where
+    /// `try_index` would return an error
+    ///
+    /// The id of each vector is at its position in the result, but as the
+    /// inserts run concurrently, the ids don't increase along `vecs`. Arena
+    /// chunks are allocated up front as in `try_index_batch`.
+    #[cfg(feature = "rayon")]
+    pub fn par_index_batch(&self, vecs: &[&[f32]], ef: u16) -> Box<[NodeId]> {
+        use rayon::prelude::*;
+
+        if let Err(err) = self.check_writable().and_then(|()| self.check_ef(ef)) {
+            panic!("{err}");
+        }
+        for vec in vecs {
+            if let Err(err) = self.check_vec(vec) {
+                panic!("{err}");
             }
+        }
+        self.reserve_batch(vecs.len());
+        vecs.par_iter().map(|vec| self.index(vec, ef)).collect()
+    }
 
-            nodes_visisted += 1;
-            if include_root || *entry.node != 0 {
-                results.push(entry);
+    // Draw the level of a new node, and ask the memory pressure hook about the
+    // chunks inserting it would allocate.
+    fn draw_level(&self) -> Result<u8, VectorDbError> {
+        let max_level = exponential_random(&self.rng, LEVEL_FACTOR, self.levels);
+        self.check_memory_pressure(max_level)?;
+        Ok(max_level)
+    }
+
+    /// Allocate up front the arena chunks `additional` more nodes need, so
+    /// inserts don't stop to grow the arenas as they cross chunk boundaries
+    ///
+    /// Upper-level nodes are reserved for the expected share of nodes drawing
+    /// a level above 0, so a few inserts may still allocate. With a memory
+    /// pressure hook, the hook is asked about all the chunks at once and may
+    /// veto them with `VectorDbError::OutOfBudget`.
+    pub fn reserve(&self, additional: usize) -> Result<(), VectorDbError> {
+        self.check_writable()?;
+        let (vecs, nodes) = self.reserve_targets(additional);
+        if let Some(hook) = &self.memory_pressure_hook {
+            let bytes = self.chunk_bytes(vecs, nodes);
+            if bytes > 0 && !hook.allow_alloc(bytes) {
+                return Err(VectorDbError::OutOfBudget);
             }
+        }
+        self.reserve_chunks(vecs, nodes);
+        Ok(())
+    }
 
-            let node = &self.nodes_arena[entry_node];
+    // Reserve ahead of a batch of `additional` inserts, under one lock per
+    // arena. Skipped where the memory pressure hook approves each insert's
+    // chunks instead, or inserts may reuse slots.
+    fn reserve_batch(&self, additional: usize) {
+        if self.memory_pressure_hook.is_some() || self.reuses_slots() {
+            return;
+        }
+        let (vecs, nodes) = self.reserve_targets(additional);
+        self.reserve_chunks(vecs, nodes);
+    }
 
-            for neighbor in node.neighbors.read().neighbors() {
-                if !set.is_member(*neighbor.node) {
-                    let neighbor_node = &self.nodes_arena[neighbor.node];
-                    let neighbor_vec = &self.vec_arena[neighbor_node.vec.handle_b()];
-                    let score = self.distance_metric.calculate(query, neighbor_vec);
+    // Vector slots and upper-level nodes after `additional` more inserts, the
+    // latter as expected from the level distribution.
+    fn reserve_targets(&self, additional: usize) -> (usize, usize) {
+        let upper = (additional as f64 * LEVEL_FACTOR / (1.0 - LEVEL_FACTOR)).ceil() as usize;
+        (
+            self.vec_arena.len() + additional,
+            self.nodes_arena.len() + upper,
+        )
+    }
 
-                    set.insert(*neighbor.node);
-                    candidate_queue.push(InternalSearchResult {
-                        node: neighbor.node,
-                        score,
-                    });
-                }
+    // Allocate the chunks holding `vecs` vector slots, with their metadata and
+    // level 0 nodes, and `nodes` upper-level nodes.
+    fn reserve_chunks(&self, vecs: usize, nodes: usize) {
+        self.vec_arena.reserve(vecs);
+        self.meta_arena.reserve(vecs);
+        if let Some(expiry_arena) = &self.expiry_arena {
+            expiry_arena.reserve(vecs);
+        }
+        if let Some(ingest_arena) = &self.ingest_arena {
+            ingest_arena.reserve(vecs);
+        }
+        if let Some(prior_arena) = &self.prior_arena {
+            prior_arena.reserve(vecs);
+        }
+        self.nodes0_arena.reserve(vecs);
+        self.nodes_arena.reserve(nodes);
+    }
+
+    // Bytes of the chunks `reserve_chunks(vecs, nodes)` would allocate.
+    fn chunk_bytes(&self, vecs: usize, nodes: usize) -> usize {
+        let new_chunks =
+            |len: usize, chunks: usize| len.div_ceil(self.chunk_size).saturating_sub(chunks);
+        let raw_dims = if self.store_raw { self.dims } else { 0 };
+        let slot_size = RawVec::size_aligned(raw_dims)
+            + QuantVec::size_aligned((self.quantization, self.dims))
+            + NodeMeta::size_aligned(())
+            + if self.expiry_arena.is_some() {
+                NodeExpiry::size_aligned(())
+            } else {
+                0
             }
+            + if self.ingest_arena.is_some() {
+                NodeIngest::size_aligned(())
+            } else {
+                0
+            }
+            + if self.prior_arena.is_some() {
+                NodePrior::size_aligned(())
+            } else {
+                0
+            };
+
+        let chunk_bytes = |slot_size: usize| self.chunk_size * slot_size;
+        new_chunks(vecs, self.vec_arena.chunk_count()) * chunk_bytes(slot_size)
+            + new_chunks(vecs, self.nodes0_arena.chunk_count())
+                * chunk_bytes(Node0::size_aligned(self.m0))
+            + new_chunks(nodes, self.nodes_arena.chunk_count())
+                * chunk_bytes(Node::size_aligned(self.m))
+    }
+
+    fn run_checkpoint(&self, checkpoint: &Checkpoint) {
+        let _quiesced = checkpoint.writers.write();
+        // inserts that finished since are part of the graph the callback sees
+        let indexed = checkpoint.indexed.load(AtomicOrdering::Relaxed);
+        if checkpoint.reported.swap(indexed, AtomicOrdering::Relaxed) != indexed {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(indexed, "checkpoint");
+            (checkpoint.callback)(self, indexed);
         }
+    }
 
-        let top_k = top_k as usize;
+    // The node `vec` would be an alias of, see `GraphBuilder::min_insert_distance`.
+    fn near_duplicate(&self, vec: &[f32], ef: u16) -> Option<NodeId> {
+        let threshold = self.min_insert_distance?;
+        let _pin = self.pin();
+        let exact = RescoreMode::FullPrecision { oversample: 1 };
+        let results = self.search_rescored(vec, ef, ef, 1, exact, None);
+        let &(id, score) = results.first()?;
+        (self.distance_metric.cmp_score(score, threshold) != Ordering::Less).then_some(NodeId(id))
+    }
 
-        if results.len() > top_k {
-            results.select_nth_unstable_by(top_k, |a, b| {
-                self.distance_metric.cmp_score(a.score, b.score)
-            });
-            results.truncate(top_k);
+    /// Number of inserts that became aliases of an existing node instead of
+    /// creating one, see `GraphBuilder::min_insert_distance`
+    pub fn aliased_inserts(&self) -> u64 {
+        self.aliased_inserts.load(AtomicOrdering::Relaxed)
+    }
+
+    /// The searches recorded by `GraphBuilder::trace_sampling`, oldest first
+    ///
+    /// Empty unless sampling is enabled.
+    pub fn sample_traces(&self) -> Box<[SearchTrace]> {
+        let Some(sampler) = &self.trace_sampler else {
+            return Box::new([]);
+        };
+        sampler.traces.lock().iter().cloned().collect()
+    }
+
+    // An empty trace for a search that sampling picked.
+    fn sampled_trace(&self) -> Option<Trace> {
+        let sampler = self.trace_sampler.as_ref()?;
+        (sampler.rng.next_u64() < sampler.threshold).then(Trace::default)
+    }
+
+    // Add a sampled trace, dropping the oldest one when the buffer is full.
+    fn record_trace(&self, trace: Trace) {
+        let Some(sampler) = &self.trace_sampler else {
+            return;
+        };
+        let mut traces = sampler.traces.lock();
+        if traces.len() == sampler.capacity {
+            traces.pop_front();
         }
+        traces.push_back(SearchTrace {
+            path: trace.path.into_boxed_slice(),
+            nodes_visited: trace.nodes_visited,
+        });
+    }
 
-        results.sort_unstable_by(|a, b| self.distance_metric.cmp_score(a.score, b.score));
+    // Count the components of `vec` that quantization clamps, returning the
+    // per-node value stored in its metadata.
+    fn record_clipping(&self, vec: &[f32]) -> u16 {
+        let clipped = self.quantization.clipped_components(vec);
+        if clipped > 0 {
+            self.clipped_vectors.fetch_add(1, AtomicOrdering::Relaxed);
+            self.clipped_components
+                .fetch_add(clipped as u64, AtomicOrdering::Relaxed);
 
-        results.into_boxed_slice()
+            #[cfg(feature = "tracing")]
+            tracing::debug!(clipped, "components clamped during quantization");
+        }
+        clipped.min(u16::MAX as u32) as u16
     }
 
-    fn search_level0(
+    /// Totals of components clamped during quantization, across all inserts
+    pub fn clipping_stats(&self) -> ClippingStats {
+        ClippingStats {
+            clipped_vectors: self.clipped_vectors.load(AtomicOrdering::Relaxed),
+            clipped_components: self.clipped_components.load(AtomicOrdering::Relaxed),
+        }
+    }
+
+    /// Estimated number of times searches returned the node, saturating at
+    /// `u32::MAX`
+    ///
+    /// Only one search in 16 is counted, so estimates move in steps of 16.
+    /// Returns `None` if `id` doesn't refer to an indexed node.
+    pub fn accesses(&self, id: NodeId) -> Option<u32> {
+        let _pin = self.pin();
+        let vec = self.live_vec(id)?;
+        let sampled = self.meta(vec).accesses.load(AtomicOrdering::Relaxed);
+        Some(sampled.saturating_mul(ACCESS_SAMPLE_RATE))
+    }
+
+    /// Split the live nodes into hot ones, with at least `hot_accesses`
+    /// estimated accesses (see `accesses`), and cold ones
+    ///
+    /// Meant for sizing tiered deployments: `cold_raw_bytes` is the memory the
+    /// cold nodes' full-precision copies take up, while their quantized codes
+    /// are what searches need resident.
+    pub fn tier_report(&self, hot_accesses: u32) -> TierReport {
+        let _pin = self.pin();
+        let raw_size = if self.store_raw {
+            RawVec::size_aligned(self.dims) as u64
+        } else {
+            0
+        };
+
+        let mut report = TierReport::default();
+        for id in 0..self.len() as u32 {
+            let Some(vec) = self.live_vec(NodeId(id)) else {
+                continue;
+            };
+            let sampled = self.meta(vec).accesses.load(AtomicOrdering::Relaxed);
+            if sampled.saturating_mul(ACCESS_SAMPLE_RATE) >= hot_accesses {
+                report.hot_nodes += 1;
+            } else {
+                report.cold_nodes += 1;
+                report.cold_raw_bytes += raw_size;
+            }
+        }
+        report
+    }
+
+    /// How much of the allocated space holds live nodes, per arena
+    ///
+    /// Tombstoned slots are taken over by later inserts, and unused slots are
+    /// the tail of the last chunk. Both are only given back to the allocator
+    /// by rebuilding the graph, which is worth it once `reusable_bytes` is a
+    /// large share of `GraphStats::arena_bytes` and few inserts are expected.
+    pub fn fragmentation_report(&self) -> FragmentationReport {
+        let chunk_size = self.chunk_size;
+        let usage = |chunks, slot_bytes, len| ArenaUsage::new(chunks, chunk_size, slot_bytes, len);
+        let vecs = self.vec_arena.len();
+        let mut report = FragmentationReport {
+            vectors: usage(
+                self.vec_arena.chunk_count(),
+                self.vec_arena.slot_bytes(),
+                vecs,
+            ),
+            metadata: usage(
+                self.meta_arena.chunk_count(),
+                self.meta_arena.slot_bytes(),
+                vecs,
+            ),
+            expiry: self
+                .expiry_arena
+                .as_ref()
+                .map(|arena| usage(arena.chunk_count(), arena.slot_bytes(), vecs)),
+            ingest: self
+                .ingest_arena
+                .as_ref()
+                .map(|arena| usage(arena.chunk_count(), arena.slot_bytes(), vecs)),
+            priors: self
+                .prior_arena
+                .as_ref()
+                .map(|arena| usage(arena.chunk_count(), arena.slot_bytes(), vecs)),
+            upper_nodes: usage(
+                self.nodes_arena.chunk_count(),
+                self.nodes_arena.slot_bytes(),
+                self.nodes_arena.len(),
+            ),
+            level0_nodes: usage(
+                self.nodes0_arena.chunk_count(),
+                self.nodes0_arena.slot_bytes(),
+                self.nodes0_arena.len(),
+            ),
+        };
+
+        // slot 0 holds the synthetic root
+        for index in 1..vecs as u32 {
+            let Some(meta) = self.meta_arena.get(NodeMetaHandle::new(index)) else {
+                continue;
+            };
+            if !meta.is_tombstoned() {
+                continue;
+            }
+            report.vectors.tombstoned_slots += 1;
+            report.metadata.tombstoned_slots += 1;
+            if let Some(expiry) = &mut report.expiry {
+                expiry.tombstoned_slots += 1;
+            }
+            if let Some(ingest) = &mut report.ingest {
+                ingest.tombstoned_slots += 1;
+            }
+            if let Some(priors) = &mut report.priors {
+                priors.tombstoned_slots += 1;
+            }
+            // the node keeps its levels, and reuse relinks them
+            report.upper_nodes.tombstoned_slots += meta.level() as u64;
+            report.level0_nodes.tombstoned_slots += 1;
+        }
+        report
+    }
+
+    /// Magnitude statistics of the inserted vectors, comparing the first
+    /// inserts with the recent ones to catch embedding model drift
+    pub fn drift_report(&self) -> DriftReport {
+        let stats = self.magnitude_stats.lock();
+        let baseline_count = stats.inserts.min(DRIFT_BASELINE);
+        DriftReport {
+            inserts: stats.inserts,
+            baseline_mean: stats.baseline_mean,
+            baseline_variance: if baseline_count > 1 {
+                stats.baseline_m2 / (baseline_count - 1) as f32
+            } else {
+                0.0
+            },
+            recent_mean: stats.recent_mean,
+            recent_variance: (stats.recent_mean_square - stats.recent_mean * stats.recent_mean)
+                .max(0.0),
+        }
+    }
+
+    /// Number of components of a stored vector that were clamped during
+    /// quantization, saturating at `u16::MAX`
+    ///
+    /// Returns `None` if `id` doesn't refer to an indexed node.
+    pub fn clipped_components(&self, id: NodeId) -> Option<u16> {
+        let _pin = self.pin();
+        let vec = self.live_vec(id)?;
+        Some(self.meta(vec).clipped())
+    }
+
+    // Ask the memory pressure hook about the chunks an insert reaching
+    // `max_level` would allocate. Approximate under concurrent inserts, which may
+    // cross a chunk boundary first.
+    fn check_memory_pressure(&self, max_level: u8) -> Result<(), VectorDbError> {
+        let Some(hook) = &self.memory_pressure_hook else {
+            return Ok(());
+        };
+
+        // slot reuse allocates nothing
+        if !self.free_slots.is_empty()
+            || self
+                .cache_budget
+                .is_some_and(|budget| self.vec_arena.len() > budget as usize)
+        {
+            return Ok(());
+        }
+
+        let bytes = self.chunk_bytes(
+            self.vec_arena.len() + 1,
+            self.nodes_arena.len() + max_level as usize,
+        );
+        if bytes > 0 && !hook.allow_alloc(bytes) {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(bytes, "insert vetoed by memory pressure hook");
+            return Err(VectorDbError::OutOfBudget);
+        }
+        Ok(())
+    }
+
+    fn insert(&self, vec: &[f32], ef: u16, max_level: u8, stamp: Stamp) -> NodeId {
+        let clipped = self.record_clipping(vec);
+        let mag = dot_product_f32(vec, vec);
+        self.insert_slot(SlotArgs::new(vec), mag, clipped, ef, max_level, stamp)
+    }
+
+    // Insert the vector described by `slot`, of squared magnitude `mag`.
+    fn insert_slot(
         &self,
-        entry_node: Node0Handle,
-        query: &QuantVec,
+        slot: SlotArgs,
+        mag: f32,
+        clipped: u16,
         ef: u16,
-        top_k: u16,
-        include_root: bool,
-    ) -> Box<[InternalSearchResult<Node0>]> {
-        let mut candidate_queue = BinaryHeap::new_by(|a: &InternalSearchResult<Node0>, b| {
-            self.distance_metric.cmp_score(a.score, b.score)
-        });
-        let mut results = Vec::new();
-        let mut set = FixedSet::new(self.m0);
+        max_level: u8,
+        stamp: Stamp,
+    ) -> NodeId {
+        self.magnitude_stats.lock().record(mag);
+        let ingested_at = self
+            .now()
+            .map_or(0, |now| u32::try_from(now).unwrap_or(u32::MAX));
 
-        let node = &self.nodes0_arena[entry_node];
-        let vec = &self.vec_arena[node.vec.handle_b()];
+        if let Some(victim) = self.reusable_slot() {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(node = NodeId::from_vec_handle(victim).0, "reusing slot");
+            if let Some(expiry_arena) = &self.expiry_arena {
+                expiry_arena[NodeExpiryHandle::new(*victim)]
+                    .at
+                    .store(stamp.expires_at, AtomicOrdering::Relaxed);
+            }
+            if let Some(ingest_arena) = &self.ingest_arena {
+                ingest_arena[NodeIngestHandle::new(*victim)].set(ingested_at, stamp.source);
+            }
+            if let Some(prior_arena) = &self.prior_arena {
+                prior_arena[NodePriorHandle::new(*victim)].set(0.0);
+            }
+            self.reindex(victim, slot, ef);
+            self.meta(victim).set_clipped(clipped);
+            return NodeId::from_vec_handle(victim);
+        }
 
-        let score = self.distance_metric.calculate(query, vec);
+        let _pin = self.pin();
+        let vec_handle = self.vec_arena.alloc(slot.raw, slot.quantized);
+        self.meta_arena
+            .alloc(*vec_handle, self.clock.load(AtomicOrdering::Relaxed));
+        if let Some(expiry_arena) = &self.expiry_arena {
+            expiry_arena.alloc(*vec_handle, stamp.expires_at);
+        }
+        if let Some(ingest_arena) = &self.ingest_arena {
+            ingest_arena.alloc(*vec_handle, (ingested_at, stamp.source));
+        }
+        if let Some(prior_arena) = &self.prior_arena {
+            prior_arena.alloc(*vec_handle, 0.0);
+        }
+        let vec = &self.vec_arena[vec_handle.handle_b()];
 
-        set.insert(*entry_node);
-        candidate_queue.push(InternalSearchResult {
-            node: entry_node,
-            score,
-        });
+        let top = self.index_level(
+            vec_handle,
+            vec,
+            &[self.top_level_root_node],
+            self.levels,
+            max_level,
+            ef,
+        );
 
-        let mut nodes_visisted = 0;
+        self.meta(vec_handle).publish(top, max_level, clipped);
+        self.seq.fetch_add(1, AtomicOrdering::Release);
+        #[cfg(all(feature = "debug-checks", debug_assertions))]
+        self.check_link_scores(vec_handle);
 
-        while let Some(entry) = candidate_queue.pop() {
-            if nodes_visisted >= ef {
+        #[cfg(feature = "tracing")]
+        tracing::trace!(
+            node = NodeId::from_vec_handle(vec_handle).0,
+            level = max_level,
+            "indexed"
+        );
+
+        NodeId::from_vec_handle(vec_handle)
+    }
+
+    #[inline]
+    fn meta(&self, vec_handle: VecHandle) -> &NodeMeta {
+        &self.meta_arena[NodeMetaHandle::new(*vec_handle)]
+    }
+
+    // The vector slot of a fully indexed node that isn't being evicted. Callers
+    // must hold an epoch pin while they use the slot.
+    fn live_vec(&self, id: NodeId) -> Option<VecHandle> {
+        if id.0 as usize >= self.len() {
+            return None;
+        }
+        let vec_handle = id.to_vec_handle();
+        let meta = self.meta_arena.get(NodeMetaHandle::new(*vec_handle))?;
+        (meta.is_ready() && !meta.is_tombstoned()).then_some(vec_handle)
+    }
+
+    // Whether inserts may take over existing slots: in cache mode, with a clock,
+    // or after deletes.
+    #[inline]
+    fn reuses_slots(&self) -> bool {
+        self.cache_budget.is_some()
+            || self.now.is_some()
+            || self.freed.load(AtomicOrdering::Relaxed) > 0
+    }
+
+    // In a graph that reuses slots a tombstoned slot may be in the middle of
+    // being rewritten, so traversal must not read its vectors.
+    #[inline]
+    fn is_reclaiming(&self, vec_handle: VecHandle) -> bool {
+        self.reuses_slots() && self.meta(vec_handle).is_tombstoned()
+    }
+
+    #[inline]
+    fn now(&self) -> Option<u64> {
+        self.now.as_ref().map(|now| now())
+    }
+
+    #[inline]
+    fn is_expired(&self, vec_handle: VecHandle, now: Option<u64>) -> bool {
+        match (now, &self.expiry_arena) {
+            (Some(now), Some(expiry_arena)) => {
+                expiry_arena[NodeExpiryHandle::new(*vec_handle)].is_expired(now)
+            }
+            _ => false,
+        }
+    }
+
+    // A purged or deleted slot or, in cache mode once over budget, an evicted
+    // one. Either comes back tombstoned.
+    fn reusable_slot(&self) -> Option<VecHandle> {
+        if let Some(index) = self.free_slots.pop() {
+            return Some(VecHandle::new(index));
+        }
+        match self.cache_budget {
+            Some(budget) if self.vec_arena.len() > budget as usize => self.evict_lru(),
+            _ => None,
+        }
+    }
+
+    /// Tombstone every expired node and queue its slot for reuse, returning how
+    /// many were purged
+    ///
+    /// Purged nodes are skipped by searches, and later inserts take over their
+    /// slots and `NodeId`s. Does nothing without a clock.
+    pub fn purge_expired(&self) -> usize {
+        self.purge_expired_cancellable(&CancellationToken::new())
+    }
+
+    /// Like `purge_expired`, stopping before the next node once `cancel` is
+    /// cancelled
+    ///
+    /// The nodes purged so far stay purged and are counted. A later call picks
+    /// up the rest.
+    pub fn purge_expired_cancellable(&self, cancel: &CancellationToken) -> usize {
+        let (Some(now), Some(expiry_arena)) = (self.now(), &self.expiry_arena) else {
+            return 0;
+        };
+
+        let _writer = self
+            .checkpoint
+            .as_ref()
+            .map(|checkpoint| checkpoint.writers.read());
+        let mut purged = 0;
+        // slot 0 holds the synthetic root, which never expires
+        for index in 1..self.vec_arena.len() as u32 {
+            if cancel.is_cancelled() {
                 break;
             }
+            let Some(meta) = self.meta_arena.get(NodeMetaHandle::new(index)) else {
+                continue;
+            };
+            let Some(expiry) = expiry_arena.get(NodeExpiryHandle::new(index)) else {
+                continue;
+            };
+            if meta.is_ready() && expiry.is_expired(now) && meta.try_tombstone() {
+                self.free_slots.push(index);
+                purged += 1;
+            }
+        }
 
-            nodes_visisted += 1;
-            if include_root || *entry.node != 0 {
-                results.push(entry);
+        if purged > 0 {
+            self.seq.fetch_add(1, AtomicOrdering::Release);
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(purged, "purged expired nodes");
+
+        purged
+    }
+
+    /// Remove the node `id` from every level, returning `false` if it isn't an
+    /// indexed node or the graph is frozen
+    ///
+    /// Each neighbor that linked back to the node gets its other neighbors as
+    /// replacements. Links from nodes it didn't link to are left in place and
+    /// skipped by searches. The next insert takes over the slot and `NodeId`,
+    /// rescoring those links against its vector.
+    pub fn delete(&self, id: NodeId) -> bool {
+        if self.frozen {
+            return false;
+        }
+        let _writer = self
+            .checkpoint
+            .as_ref()
+            .map(|checkpoint| checkpoint.writers.read());
+        let _pin = self.pin();
+        let Some(vec_handle) = self.live_vec(id) else {
+            return false;
+        };
+        // counted first, so searches that see the tombstone skip the node
+        self.freed.fetch_add(1, AtomicOrdering::Relaxed);
+        if !self.meta(vec_handle).try_tombstone() {
+            return false;
+        }
+        self.unlink(vec_handle);
+
+        self.free_slots.push(*vec_handle);
+        self.seq.fetch_add(1, AtomicOrdering::Release);
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(node = id.0, "deleted");
+
+        true
+    }
+
+    /// Replace the vector of node `id` and relink the node at the levels it
+    /// already occupies, keeping its `NodeId`
+    ///
+    /// Fails where `try_index` would, or if `id` isn't an indexed node.
+    pub fn update(&self, id: NodeId, vec: &[f32], ef: u16) -> Result<(), VectorDbError> {
+        self.check_writable()?;
+        self.check_vec(vec)?;
+        self.check_ef(ef)?;
+        let _writer = self
+            .checkpoint
+            .as_ref()
+            .map(|checkpoint| checkpoint.writers.read());
+        let vec_handle = {
+            let _pin = self.pin();
+            self.live_vec(id).ok_or(VectorDbError::UnknownNode)?
+        };
+        // counted first, so searches that see the tombstone skip the node
+        self.freed.fetch_add(1, AtomicOrdering::Relaxed);
+        if !self.meta(vec_handle).try_tombstone() {
+            return Err(VectorDbError::UnknownNode);
+        }
+        {
+            let _pin = self.pin();
+            self.unlink(vec_handle);
+        }
+
+        let clipped = self.record_clipping(vec);
+        self.magnitude_stats
+            .lock()
+            .record(dot_product_f32(vec, vec));
+        self.reindex(vec_handle, SlotArgs::new(vec), ef);
+        self.meta(vec_handle).set_clipped(clipped);
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(node = id.0, "updated");
+
+        Ok(())
+    }
+
+    // Empty the neighbor lists of a tombstoned node. Each neighbor that linked
+    // back to it gets its other neighbors as replacements.
+    fn unlink(&self, vec_handle: VecHandle) {
+        let meta = self.meta(vec_handle);
+        let mut node = meta.top();
+        for _ in 0..meta.level() {
+            let node_ref = &self.nodes_arena[node];
+            let neighbors: Vec<NodeHandle> = {
+                let mut guard = node_ref.neighbors.write();
+                let neighbors = guard.neighbors().iter().map(Neighbor::node).collect();
+                guard.neighbors_full = false;
+                guard.lowest_index = 0;
+                neighbors
+            };
+            for &neighbor in &neighbors {
+                let neighbor_ref = &self.nodes_arena[neighbor];
+                let mut guard = neighbor_ref.neighbors.write();
+                if !guard.remove_neighbor(node) {
+                    continue;
+                }
+                for &other in &neighbors {
+                    let other_vec = self.nodes_arena[other].vec;
+                    if other != neighbor && !self.meta(other_vec).is_tombstoned() {
+                        let score = self.link_score(neighbor_ref.vec, other_vec);
+                        guard.upsert_neighbor(&self.distance_metric, other, score);
+                    }
+                }
+            }
+            node = node_ref.child;
+        }
+
+        let node = node.cast::<Node0>();
+        let node_ref = &self.nodes0_arena[node];
+        let neighbors: Vec<Node0Handle> = {
+            let mut guard = node_ref.neighbors.write();
+            let neighbors = guard.neighbors().iter().map(Neighbor0::node).collect();
+            guard.neighbors_full = false;
+            guard.lowest_index = 0;
+            neighbors
+        };
+        for &neighbor in &neighbors {
+            let neighbor_ref = &self.nodes0_arena[neighbor];
+            let mut guard = neighbor_ref.neighbors.write();
+            if !guard.remove_neighbor(node) {
+                continue;
+            }
+            for &other in &neighbors {
+                let other_vec = self.nodes0_arena[other].vec;
+                if other != neighbor && !self.meta(other_vec).is_tombstoned() {
+                    let score = self.link_score(neighbor_ref.vec, other_vec);
+                    guard.upsert_neighbor(&self.distance_metric, other, score);
+                }
             }
+        }
+    }
 
-            let node = &self.nodes0_arena[entry_node];
+    // Pick an approximately least-recently-returned node and tombstone it.
+    fn evict_lru(&self) -> Option<VecHandle> {
+        let len = self.vec_arena.len() as u64;
+        // slot 0 holds the synthetic root, which is never evicted
+        if len <= 1 {
+            return None;
+        }
 
-            for neighbor in node.neighbors.read().neighbors() {
-                if !set.is_member(*neighbor.node) {
-                    let neighbor_node = &self.nodes0_arena[neighbor.node];
-                    let neighbor_vec = &self.vec_arena[neighbor_node.vec.handle_b()];
-                    let score = self.distance_metric.calculate(query, neighbor_vec);
+        let now = self.clock.load(AtomicOrdering::Relaxed);
 
-                    set.insert(*neighbor.node);
-                    candidate_queue.push(InternalSearchResult {
-                        node: neighbor.node,
-                        score,
-                    });
+        for _ in 0..LRU_ATTEMPTS {
+            let mut victim = None;
+            let mut oldest = 0;
+
+            for _ in 0..LRU_SAMPLES {
+                let index = 1 + (self.rng.next_u64() % (len - 1)) as u32;
+                let Some(meta) = self.meta_arena.get(NodeMetaHandle::new(index)) else {
+                    continue;
+                };
+                if !meta.is_ready() || meta.is_tombstoned() {
+                    continue;
+                }
+                let age = now.wrapping_sub(meta.last_access.load(AtomicOrdering::Relaxed));
+                if victim.is_none() || age > oldest {
+                    victim = Some(index);
+                    oldest = age;
                 }
             }
+
+            if let Some(index) = victim
+                && self.meta_arena[NodeMetaHandle::new(index)].try_tombstone()
+            {
+                return Some(VecHandle::new(index));
+            }
         }
 
-        let top_k = top_k as usize;
+        None
+    }
 
-        if results.len() > top_k {
-            results.select_nth_unstable_by(top_k, |a, b| {
-                self.distance_metric.cmp_score(a.score, b.score)
-            });
-            results.truncate(top_k);
+    // Overwrite a tombstoned slot with `vec` and relink its nodes at the levels
+    // they already occupy.
+    fn reindex(&self, vec_handle: VecHandle, slot: SlotArgs, ef: u16) {
+        // wait for searches that may have reached the slot before it was
+        // tombstoned, and inserts that may have linked to it
+        self.epoch.synchronize();
+        unsafe {
+            self.vec_arena.reinit(vec_handle, slot.raw, slot.quantized);
         }
 
-        results.sort_unstable_by(|a, b| self.distance_metric.cmp_score(a.score, b.score));
+        let _pin = self.pin();
+        let meta = self.meta(vec_handle);
+        self.rescore_links_into(vec_handle);
 
-        results.into_boxed_slice()
+        self.relink_level(
+            vec_handle,
+            &[self.top_level_root_node],
+            self.levels,
+            meta.level(),
+            meta.top(),
+            ef,
+        );
+
+        meta.last_access.store(
+            self.clock.load(AtomicOrdering::Relaxed),
+            AtomicOrdering::Relaxed,
+        );
+        meta.accesses.store(0, AtomicOrdering::Relaxed);
+        meta.clear_tombstone();
+        self.seq.fetch_add(1, AtomicOrdering::Release);
+        #[cfg(all(feature = "debug-checks", debug_assertions))]
+        self.check_link_scores(vec_handle);
+    }
+
+    // Rescore the links other nodes hold into the nodes of an overwritten slot,
+    // which still carry scores against its previous vector: links from nodes
+    // it didn't link back to survive a delete, and purged or evicted nodes
+    // aren't unlinked at all. Costs a pass over every node.
+    fn rescore_links_into(&self, vec_handle: VecHandle) {
+        let meta = self.meta(vec_handle);
+        // the slot's node at each upper level, top level first
+        let mut targets = Vec::with_capacity(meta.level() as usize);
+        let mut node = meta.top();
+        for _ in 0..meta.level() {
+            targets.push(node);
+            node = self.nodes_arena[node].child;
+        }
+        let target0 = node.cast::<Node0>();
+
+        for index in 0..self.vec_arena.len() as u32 {
+            let other = VecHandle::new(index);
+            let Some(other_meta) = self.meta_arena.get(NodeMetaHandle::new(index)) else {
+                continue;
+            };
+            // tombstoned slots get relinked from scratch when they're reused
+            if other == vec_handle || !other_meta.is_ready() || other_meta.is_tombstoned() {
+                continue;
+            }
+            let mut score = None;
+            let mut score = || *score.get_or_insert_with(|| self.link_score(other, vec_handle));
+
+            let mut node = other_meta.top();
+            for level in (1..=other_meta.level()).rev() {
+                let node_ref = &self.nodes_arena[node];
+                if level <= meta.level() {
+                    let target = targets[(meta.level() - level) as usize];
+                    if node_ref
+                        .neighbors
+                        .read()
+                        .neighbors()
+                        .iter()
+                        .any(|n| n.node() == target)
+                    {
+                        node_ref.neighbors.write().refresh_neighbor(
+                            &self.distance_metric,
+                            target,
+                            score(),
+                        );
+                    }
+                }
+                node = node_ref.child;
+            }
+            let node0 = &self.nodes0_arena[node.cast::<Node0>()];
+            if node0
+                .neighbors
+                .read()
+                .neighbors()
+                .iter()
+                .any(|n| n.node() == target0)
+            {
+                node0
+                    .neighbors
+                    .write()
+                    .refresh_neighbor(&self.distance_metric, target0, score());
+            }
+        }
+    }
+
+    fn relink_level(
+        &self,
+        vec_handle: VecHandle,
+        entry_nodes: &[NodeHandle],
+        current_level: u8,
+        max_level: u8,
+        node: NodeHandle,
+        ef: u16,
+    ) {
+        if current_level > max_level {
+            let vec = &self.vec_arena[vec_handle.handle_b()];
+            let results = self.search_level(entry_nodes, vec, ef, 1, true, false);
+            let child = self.nodes_arena[results[0].node].child;
+
+            self.relink_level(vec_handle, &[child], current_level - 1, max_level, node, ef);
+        } else if current_level == 0 {
+            let entry_nodes: Vec<Node0Handle> =
+                entry_nodes.iter().map(|node| node.cast()).collect();
+            let results = self.link_candidates0(vec_handle, &entry_nodes, ef);
+            self.link_node0(node.cast(), results, true);
+        } else {
+            let (children, results) = self.link_candidates(vec_handle, entry_nodes, ef);
+            let node_child = self.nodes_arena[node].child;
+
+            self.link_node(node, results, true);
+            self.relink_level(
+                vec_handle,
+                &children,
+                current_level - 1,
+                max_level,
+                node_child,
+                ef,
+            );
+        }
+    }
+
+    // Link the vector at `vec_handle` into the levels from `max_level` down,
+    // following the construction of the HNSW paper: above `max_level` the
+    // descent keeps only the best node, from there on every level keeps up to
+    // ef candidates, links to up to m (m0 at level 0) of them and hands all of
+    // them down as the entry points of the level below.
+    fn index_level(
+        &self,
+        vec_handle: VecHandle,
+        vec: &QuantVec,
+        entry_nodes: &[NodeHandle],
+        current_level: u8,
+        max_level: u8,
+        ef: u16,
+    ) -> NodeHandle {
+        if current_level > max_level {
+            let results = self.search_level(entry_nodes, vec, ef, 1, true, false);
+            let child = self.nodes_arena[results[0].node].child;
+
+            self.index_level(vec_handle, vec, &[child], current_level - 1, max_level, ef)
+        } else if current_level == 0 {
+            let entry_nodes: Vec<Node0Handle> =
+                entry_nodes.iter().map(|node| node.cast()).collect();
+            self.index_level0(vec_handle, &entry_nodes, ef).cast()
+        } else {
+            let (children, results) = self.link_candidates(vec_handle, entry_nodes, ef);
+            let child =
+                self.index_level(vec_handle, vec, &children, current_level - 1, max_level, ef);
+
+            self.create_node(vec_handle, results, child)
+        }
+    }
+
+    fn index_level0(
+        &self,
+        vec_handle: VecHandle,
+        entry_nodes: &[Node0Handle],
+        ef: u16,
+    ) -> Node0Handle {
+        let results = self.link_candidates0(vec_handle, entry_nodes, ef);
+        self.create_node0(vec_handle, results)
+    }
+
+    // The children of the up to `ef` best nodes for the vector at `vec_handle`
+    // at an upper level, best first, to enter the level below from, and the
+    // neighbors to link to there. With full-precision linking the candidates
+    // are rescored first, and the heuristic selection picks from all of them.
+    fn link_candidates(
+        &self,
+        vec_handle: VecHandle,
+        entry_nodes: &[NodeHandle],
+        ef: u16,
+    ) -> (Vec<NodeHandle>, Box<[InternalSearchResult<Node>]>) {
+        let vec = &self.vec_arena[vec_handle.handle_b()];
+        let vec_of = |node| self.nodes_arena[node].vec;
+        let results = self.search_level(entry_nodes, vec, ef, ef.max(self.m), true, false);
+        let children = results
+            .iter()
+            .map(|result| self.nodes_arena[result.node].child)
+            .collect();
+        let results = self.link_pool_of(vec_handle, results, self.m, ef, vec_of);
+        (children, self.select_neighbors(results, self.m, vec_of))
+    }
+
+    fn link_candidates0(
+        &self,
+        vec_handle: VecHandle,
+        entry_nodes: &[Node0Handle],
+        ef: u16,
+    ) -> Box<[InternalSearchResult<Node0>]> {
+        let vec = &self.vec_arena[vec_handle.handle_b()];
+        let vec_of = |node| self.nodes0_arena[node].vec;
+        let results = self.search_level0(entry_nodes, vec, ef, ef.max(self.m0), true);
+        let results = self.link_pool_of(vec_handle, results, self.m0, ef, vec_of);
+        self.select_neighbors(results, self.m0, vec_of)
+    }
+
+    // The best-first candidates to choose `m` neighbors from out of the
+    // `results` of a level search, rescored at full precision when linking
+    // uses it.
+    fn link_pool_of<T: ?Sized>(
+        &self,
+        vec_handle: VecHandle,
+        results: Box<[InternalSearchResult<T>]>,
+        m: u16,
+        ef: u16,
+        vec_of: impl Fn(Handle<T>) -> VecHandle,
+    ) -> Box<[InternalSearchResult<T>]> {
+        let pool = self.link_pool(ef, m);
+        if self.link_full_precision {
+            return self.rescore_links(vec_handle, results, pool, vec_of);
+        }
+        let mut results = results.into_vec();
+        results.truncate(pool as usize);
+        results.into_boxed_slice()
+    }
+
+    // Number of candidates to choose `m` neighbors from.
+    fn link_pool(&self, ef: u16, m: u16) -> u16 {
+        match self.neighbor_selection {
+            NeighborSelection::Simple => m,
+            NeighborSelection::Heuristic => ef.max(m),
+        }
+    }
+
+    // Up to `m` of the best-first `candidates`. The heuristic keeps a candidate
+    // only if it scores better against the new vector than against every
+    // neighbor kept before it.
+    fn select_neighbors<T: ?Sized>(
+        &self,
+        candidates: Box<[InternalSearchResult<T>]>,
+        m: u16,
+        vec_of: impl Fn(Handle<T>) -> VecHandle,
+    ) -> Box<[InternalSearchResult<T>]> {
+        if self.neighbor_selection == NeighborSelection::Simple {
+            return candidates;
+        }
+
+        let mut selected: Vec<InternalSearchResult<T>> = Vec::with_capacity(m as usize);
+        for candidate in candidates {
+            if selected.len() == m as usize {
+                break;
+            }
+            let vec = vec_of(candidate.node);
+            let diverse = selected.iter().all(|kept| {
+                let between = self.link_score(vec, vec_of(kept.node));
+                self.distance_metric.cmp_score(candidate.score, between) == Ordering::Greater
+            });
+            if diverse {
+                selected.push(candidate);
+            }
+        }
+        selected.into_boxed_slice()
+    }
+
+    // Rescore candidates against the raw copy of the vector at `vec_handle` and
+    // keep the best `top_k`, best first.
+    fn rescore_links<T: ?Sized>(
+        &self,
+        vec_handle: VecHandle,
+        results: Box<[InternalSearchResult<T>]>,
+        top_k: u16,
+        vec_of: impl Fn(Handle<T>) -> VecHandle,
+    ) -> Box<[InternalSearchResult<T>]> {
+        let vec = &self.vec_arena[vec_handle.handle_a()];
+        let mag_vec = dot_product_f32(&vec.vec, &vec.vec);
+
+        let mut results = results.into_vec();
+        for result in &mut results {
+            let other = &self.vec_arena[vec_of(result.node).handle_a()];
+            let mag_other = dot_product_f32(&other.vec, &other.vec);
+            result.score = self
+                .distance_metric
+                .calculate_raw(vec, mag_vec, other, mag_other);
+        }
+
+        results.sort_unstable_by(|a, b| self.distance_metric.cmp_score(b.score, a.score));
+        results.truncate(top_k as usize);
+        results.into_boxed_slice()
+    }
+
+    fn create_node(
+        &self,
+        vec_handle: VecHandle,
+        results: Box<[InternalSearchResult<Node>]>,
+        child: NodeHandle,
+    ) -> NodeHandle {
+        let node_handle = self.nodes_arena.alloc((vec_handle, child));
+        self.link_node(node_handle, results, false);
+        node_handle
+    }
+
+    // Replace the node's neighbor list with `results` and add backlinks. When
+    // `relink` is set the neighbors may already hold a (stale) link to the node,
+    // which is updated rather than duplicated.
+    fn link_node(
+        &self,
+        node_handle: NodeHandle,
+        results: Box<[InternalSearchResult<Node>]>,
+        relink: bool,
+    ) {
+        let node = &self.nodes_arena[node_handle];
+        let mut neighbors_guard = node.neighbors.write();
+
+        // a longer list would be cut short by the zip below
+        let len = u16::try_from(results.len())
+            .ok()
+            .filter(|&len| len <= self.m)
+            .expect("more results than neighbor slots");
+        for (slot, result) in neighbors_guard.neighbors.iter_mut().zip(&results) {
+            *slot = Neighbor::new(&self.distance_metric, result.node, result.score);
+        }
+
+        if len == self.m {
+            neighbors_guard.neighbors_full = true;
+            let mut lowest_index = 0;
+            let mut lowest_score = self.distance_metric.max_value();
+
+            for i in 0..self.m {
+                let neighbor = &neighbors_guard.neighbors[i as usize];
+                if self
+                    .distance_metric
+                    .cmp_score(neighbor.score(&self.distance_metric), lowest_score)
+                    == Ordering::Less
+                {
+                    lowest_score = neighbor.score(&self.distance_metric);
+                    lowest_index = i;
+                }
+            }
+
+            neighbors_guard.lowest_index = lowest_index;
+            neighbors_guard.lowest_score = lowest_score;
+        } else {
+            neighbors_guard.neighbors_full = false;
+            neighbors_guard.lowest_index = len;
+        }
+
+        drop(neighbors_guard);
+
+        for result in results {
+            let neighbor = &self.nodes_arena[result.node];
+            let mut neighbors_guard = neighbor.neighbors.write();
+            if relink {
+                neighbors_guard.upsert_neighbor(&self.distance_metric, node_handle, result.score);
+            } else {
+                neighbors_guard.insert_neighbor(&self.distance_metric, node_handle, result.score);
+            }
+        }
+    }
+
+    fn create_node0(
+        &self,
+        vec_handle: VecHandle,
+        results: Box<[InternalSearchResult<Node0>]>,
+    ) -> Node0Handle {
+        let node_handle = self.nodes0_arena.alloc(vec_handle);
+        self.link_node0(node_handle, results, false);
+        node_handle
+    }
+
+    fn link_node0(
+        &self,
+        node_handle: Node0Handle,
+        results: Box<[InternalSearchResult<Node0>]>,
+        relink: bool,
+    ) {
+        let node = &self.nodes0_arena[node_handle];
+        let mut neighbors_guard = node.neighbors.write();
+
+        // a longer list would be cut short by the zip below
+        let len = u16::try_from(results.len())
+            .ok()
+            .filter(|&len| len <= self.m0)
+            .expect("more results than neighbor slots");
+        for (slot, result) in neighbors_guard.neighbors.iter_mut().zip(&results) {
+            *slot = Neighbor0::new(&self.distance_metric, result.node, result.score);
+        }
+
+        if len == self.m0 {
+            neighbors_guard.neighbors_full = true;
+            let mut lowest_index = 0;
+            let mut lowest_score = self.distance_metric.max_value();
+
+            for i in 0..self.m0 {
+                let neighbor = &neighbors_guard.neighbors[i as usize];
+                if self
+                    .distance_metric
+                    .cmp_score(neighbor.score(&self.distance_metric), lowest_score)
+                    == Ordering::Less
+                {
+                    lowest_score = neighbor.score(&self.distance_metric);
+                    lowest_index = i;
+                }
+            }
+
+            neighbors_guard.lowest_index = lowest_index;
+            neighbors_guard.lowest_score = lowest_score;
+        } else {
+            neighbors_guard.neighbors_full = false;
+            neighbors_guard.lowest_index = len;
+        }
+
+        drop(neighbors_guard);
+
+        for result in results {
+            let neighbor = &self.nodes0_arena[result.node];
+            let mut neighbors_guard = neighbor.neighbors.write();
+            if relink {
+                neighbors_guard.upsert_neighbor(&self.distance_metric, node_handle, result.score);
+            } else {
+                neighbors_guard.insert_neighbor(&self.distance_metric, node_handle, result.score);
+            }
+        }
+    }
+
+    pub fn search_quantized(&self, query: &[f32], ef: u16, top_k: u16) -> Box<[SearchResult]> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("search_quantized", ef, top_k).entered();
+
+        assert_eq!(query.len(), self.dims as usize, "query dimension mismatch");
+        let _pin = self.pin();
+        let results = self.search_quantized_unpinned(query, ef, ef, top_k, None);
+        self.touch(&results);
+        results
+    }
+
+    /// Approximate the `top_k` nodes least similar to `query`, furthest first
+    ///
+    /// Runs the usual traversal with every comparison reversed, so it's as
+    /// approximate as `search_quantized` and reports the same quantized scores.
+    /// Useful for diverse sampling and for mining outliers.
+    pub fn search_furthest(&self, query: &[f32], ef: u16, top_k: u16) -> Box<[SearchResult]> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("search_furthest", ef, top_k).entered();
+
+        assert_eq!(query.len(), self.dims as usize, "query dimension mismatch");
+        let _pin = self.pin();
+        let query = QuantVecBox::new(self.quantization, self.dims, query, &self.allocator);
+        let entry_node = self.descend(&query, ef, top_k, None, true);
+        let results = self.search_level0_adaptive(
+            &[entry_node],
+            &query,
+            ef,
+            ef,
+            top_k,
+            false,
+            true,
+            None,
+            None,
+        );
+        let results = unsafe {
+            map_boxed_slice(results, |result| SearchResult {
+                node: NodeId::from_vec_handle(self.nodes0_arena[result.node].vec),
+                score: -result.score,
+            })
+        };
+        self.touch(&results);
+        results
+    }
+
+    // Record that `results` were returned: in the access counts of one search in
+    // `ACCESS_SAMPLE_RATE`, and for LRU eviction in cache mode.
+    fn touch(&self, results: &[SearchResult]) {
+        if self
+            .searches
+            .fetch_add(1, AtomicOrdering::Relaxed)
+            .is_multiple_of(ACCESS_SAMPLE_RATE)
+        {
+            for result in results {
+                self.meta(result.node.to_vec_handle()).record_access();
+            }
+        }
+
+        if self.cache_budget.is_none() {
+            return;
+        }
+
+        let now = self.clock.fetch_add(1, AtomicOrdering::Relaxed) + 1;
+        for result in results {
+            let meta = self.meta(result.node.to_vec_handle());
+            meta.last_access.store(now, AtomicOrdering::Relaxed);
+        }
+    }
+
+    // Records the descent and the level 0 nodes expanded in `trace`, if given.
+    fn search_quantized_unpinned(
+        &self,
+        query: &[f32],
+        ef: u16,
+        max_ef: u16,
+        top_k: u16,
+        mut trace: Option<&mut Trace>,
+    ) -> Box<[SearchResult]> {
+        let query = QuantVecBox::new(self.quantization, self.dims, query, &self.allocator);
+        let path = trace.as_deref_mut().map(|trace| &mut trace.path);
+        let entry_node = self.descend(&query, ef, top_k, path, false);
+        let nodes_visited = trace.map(|trace| &mut trace.nodes_visited);
+        self.search_level0_ids(entry_node, &query, ef, max_ef, top_k, nodes_visited)
+    }
+
+    /// Estimate what a search with `params` would cost without running it, e.g.
+    /// to reject or queue expensive queries
+    ///
+    /// Derived from the number of nodes, the expected number of them on each
+    /// level, m, m0 and ef, taking neighbor lists as full. Searches that refill
+    /// (post-filter, group limit) are costed as if every round up to the work
+    /// budget ran. Result cache hits cost less than estimated.
+    pub fn estimate_cost(&self, params: &SearchParams) -> CostEstimate {
+        let mut cost = CostEstimate::default();
+        if params.refills() {
+            let mut ef = self.start_ef(params);
+            let max_ef = params
+                .work_budget
+                .unwrap_or(params.ef.saturating_mul(8))
+                .max(ef)
+                .min(self.scratch_ef(params));
+            loop {
+                let rescored = if params.rescore == RescoreMode::None {
+                    0
+                } else {
+                    ef
+                };
+                self.add_search_cost(&mut cost, ef, ef, rescored);
+                if ef >= max_ef {
+                    break;
+                }
+                ef = ef.saturating_mul(2).min(max_ef);
+            }
+        } else {
+            let ef = self.start_ef(params);
+            let max_ef = self.max_ef(params, ef);
+            let rescored = if params.rescore == RescoreMode::None {
+                0
+            } else {
+                params.candidates()
+            };
+            self.add_search_cost(&mut cost, ef, max_ef, rescored);
+        }
+        cost
+    }
+
+    // Cost of one `search_rescored` call.
+    fn add_search_cost(&self, cost: &mut CostEstimate, ef: u16, max_ef: u16, rescored: u16) {
+        let live = self.len() as u64;
+        let quant_size = QuantVec::size_aligned((self.quantization, self.dims)) as u64;
+        // a level search expands up to ef nodes, scoring their unvisited neighbors
+        let mut level_cost = |nodes: u64, ef: u16, degree: u16, list_size: usize| {
+            let expanded = nodes.min(ef as u64);
+            let scored = (expanded * degree as u64 + 1).min(nodes);
+            cost.distance_computations += scored;
+            cost.bytes_touched += scored * quant_size + expanded * list_size as u64;
+        };
+
+        for level in 1..=self.levels {
+            // plus the root, which is on every level
+            let nodes = (live as f64 * LEVEL_FACTOR.powi(level as i32)).ceil() as u64 + 1;
+            level_cost(nodes, ef, self.m, Node::size_aligned(self.m));
+        }
+        level_cost(live + 1, max_ef, self.m0, Node0::size_aligned(self.m0));
+
+        if self.store_raw || matches!(self.quantization, Quantization::FullPrecisionFP) {
+            let rescored = (rescored as u64).min(max_ef as u64).min(live);
+            cost.distance_computations += rescored;
+            cost.bytes_touched += rescored * RawVec::size_aligned(self.dims) as u64;
+        }
+    }
+
+    // Search the upper levels for the level 0 entry point closest to `query`.
+    // Pushes the upper-level nodes the descent goes through onto `path`, if given.
+    fn descend(
+        &self,
+        query: &QuantVec,
+        ef: u16,
+        top_k: u16,
+        path: Option<&mut Vec<NodeId>>,
+        furthest: bool,
+    ) -> Node0Handle {
+        let mut context = self.take_context();
+        let entry_node = self.descend_in(&mut context, query, ef, top_k, path, furthest);
+        self.return_context(context);
+        entry_node
+    }
+
+    // `descend` with the working memory of `context`.
+    fn descend_in(
+        &self,
+        context: &mut SearchContext,
+        query: &QuantVec,
+        ef: u16,
+        top_k: u16,
+        mut path: Option<&mut Vec<NodeId>>,
+        furthest: bool,
+    ) -> Node0Handle {
+        let mut entry_node = self.top_level_root_node;
+
+        // ignore the `0..self.range`, the actual search range in (0, self.levels]
+        for _ in 0..self.levels {
+            self.search_level_in(context, &[entry_node], query, ef, top_k, true, furthest);
+            let node = &self.nodes_arena[context.upper_results[0].node];
+            if let Some(path) = path.as_deref_mut()
+                && *node.vec != 0
+            {
+                let id = NodeId::from_vec_handle(node.vec);
+                if path.last() != Some(&id) {
+                    path.push(id);
+                }
+            }
+            entry_node = node.child;
+        }
+
+        entry_node.cast()
+    }
+
+    fn search_level0_ids(
+        &self,
+        entry_node: Node0Handle,
+        query: &QuantVec,
+        ef: u16,
+        max_ef: u16,
+        top_k: u16,
+        nodes_visited: Option<&mut u32>,
+    ) -> Box<[SearchResult]> {
+        // unless the scratch budget capped ef below the number of slots
+        if top_k as usize >= self.len() && max_ef as usize > self.len() {
+            if let Some(nodes_visited) = nodes_visited {
+                *nodes_visited += self.len() as u32;
+            }
+            return self.scan_level0(query, top_k);
+        }
+        let results = self.search_level0_adaptive(
+            &[entry_node],
+            query,
+            ef,
+            max_ef,
+            top_k,
+            false,
+            false,
+            None,
+            nodes_visited,
+        );
+
+        unsafe {
+            map_boxed_slice(results, |result| SearchResult {
+                node: NodeId::from_vec_handle(self.nodes0_arena[result.node].vec),
+                score: result.score,
+            })
+        }
+    }
+
+    /// Approximate the `top_k` nodes most similar to `query`, best first
+    ///
+    /// Like every search, returns nothing rather than an error while the graph
+    /// holds no live nodes, e.g. before the first insert or after deleting
+    /// them all.
+    pub fn search(&self, query: &[f32], ef: u16, top_k: u16) -> Box<[SearchResult]> {
+        self.search_with_params(query, &SearchParams::new(ef, top_k))
+    }
+
+    /// Like `search`, with the builder's `ef_search`, raised to `top_k` so the
+    /// search expands at least as many nodes as it returns
+    pub fn nearest(&self, query: &[f32], top_k: u16) -> Box<[SearchResult]> {
+        self.search(query, self.ef_search.max(top_k), top_k)
+    }
+
+    pub fn search_with_params(&self, query: &[f32], params: &SearchParams) -> Box<[SearchResult]> {
+        assert_eq!(query.len(), self.dims as usize, "query dimension mismatch");
+        unsafe { self.search_unchecked(query, params) }
+    }
+
+    /// Like `search_with_params`, without checking the query's length
+    ///
+    /// # Safety
+    ///
+    /// `query` must have the graph's input dimensionality.
+    pub unsafe fn search_unchecked(
+        &self,
+        query: &[f32],
+        params: &SearchParams,
+    ) -> Box<[SearchResult]> {
+        let _pin = self.pin();
+        let results = self.search_params_unpinned(query, params, None);
+        let results = unsafe {
+            mem::transmute::<Box<[(u32, f32)]>, Box<[SearchResult]>>(results.into_boxed_slice())
+        };
+        self.touch(&results);
+        results
+    }
+
+    /// Like `search`, for an f16 query
+    #[cfg(feature = "nightly")]
+    pub fn search_f16(&self, query: &[f16], ef: u16, top_k: u16) -> Box<[SearchResult]> {
+        self.search(&widen_f16(query), ef, top_k)
+    }
+
+    /// Like `search_with_params`, for an f16 query
+    #[cfg(feature = "nightly")]
+    pub fn search_with_params_f16(
+        &self,
+        query: &[f16],
+        params: &SearchParams,
+    ) -> Box<[SearchResult]> {
+        self.search_with_params(&widen_f16(query), params)
+    }
+
+    /// Like `search`, for a bf16 query given as bits
+    ///
+    /// The query is copied to f32 first, see `index_bf16`.
+    pub fn search_bf16(&self, query: &[u16], ef: u16, top_k: u16) -> Box<[SearchResult]> {
+        self.search(&widen_bf16(query), ef, top_k)
+    }
+
+    /// Like `search_with_params`, for a bf16 query given as bits
+    pub fn search_with_params_bf16(
+        &self,
+        query: &[u16],
+        params: &SearchParams,
+    ) -> Box<[SearchResult]> {
+        self.search_with_params(&widen_bf16(query), params)
+    }
+
+    /// Like `search_with_params`, but also returns the upper-level nodes the
+    /// search descended through, top level first
+    ///
+    /// The descent is greedy, so every result is reached through the same chain.
+    /// When upper levels encode coarse categories, the chain names the category
+    /// of the matches.
+    pub fn search_with_provenance(&self, query: &[f32], params: &SearchParams) -> SearchProvenance {
+        assert_eq!(query.len(), self.dims as usize, "query dimension mismatch");
+        let _pin = self.pin();
+        let mut trace = Trace::default();
+        let results = self.search_params_unpinned(query, params, Some(&mut trace));
+        let results = unsafe {
+            mem::transmute::<Box<[(u32, f32)]>, Box<[SearchResult]>>(results.into_boxed_slice())
+        };
+        self.touch(&results);
+        SearchProvenance {
+            results,
+            path: trace.path.into_boxed_slice(),
+        }
+    }
+
+    /// Like `search_with_params`, but also reports whether the graph ran out of
+    /// nodes before `top_k` results were found
+    ///
+    /// When `top_k` is at least `len()`, every node is scored instead of
+    /// traversing the graph, so all live nodes (that pass the filters) are
+    /// returned, unless the scratch budget doesn't allow for the scan. Results
+    /// are never padded.
+    pub fn search_with_outcome(&self, query: &[f32], params: &SearchParams) -> SearchOutcome {
+        let results = self.search_with_params(query, params);
+        SearchOutcome {
+            exhausted: results.len() < params.top_k as usize
+                && params.top_k as usize >= self.len()
+                && self.start_ef(params) as usize > self.len(),
+            results,
+        }
+    }
+
+    /// Like `search_with_params`, but also counts the candidates the search
+    /// scored into the score bands bounded by `bands`, best first
+    ///
+    /// Counts cover every candidate the search rescored, at least ef of them,
+    /// not only the `top_k` returned, without materializing them as results,
+    /// e.g. for relevance facets. Scores are the ones results would get, so a
+    /// returned result always falls in the band it's counted in. Post-filters
+    /// and group limits aren't supported.
+    pub fn search_banded(
+        &self,
+        query: &[f32],
+        params: &SearchParams,
+        bands: &[f32],
+    ) -> BandedResults {
+        assert_eq!(query.len(), self.dims as usize, "query dimension mismatch");
+        assert!(!params.refills(), "banded searches can't refill");
+        assert!(
+            bands
+                .windows(2)
+                .all(|pair| self.distance_metric.cmp_score(pair[0], pair[1]) != Ordering::Less),
+            "bands must be ordered best first"
+        );
+
+        let _pin = self.pin();
+        let ef = self.start_ef(params);
+        let max_ef = self.max_ef(params, ef);
+        let candidates = params.candidates().max(ef);
+        let mut results = self.search_rescored(query, ef, max_ef, candidates, params.rescore, None);
+        self.blend_priors(&mut results, params.prior_weight);
+
+        let mut counts = alloc::vec![0; bands.len() + 1].into_boxed_slice();
+        for &(_, score) in &results {
+            let band = bands
+                .iter()
+                .position(|&bound| self.distance_metric.cmp_score(score, bound) != Ordering::Less)
+                .unwrap_or(bands.len());
+            counts[band] += 1;
+        }
+
+        self.select_results(&mut results, params);
+        let results = unsafe {
+            mem::transmute::<Box<[(u32, f32)]>, Box<[SearchResult]>>(results.into_boxed_slice())
+        };
+        self.touch(&results);
+        BandedResults { results, counts }
+    }
+
+    /// Every node the search finds scoring at least as well as `threshold`,
+    /// best first: within that distance for the distance metrics, or at least
+    /// that similar for Cosine and DotProduct
+    ///
+    /// Only the ef candidates the search keeps are checked, so a loose
+    /// threshold returns at most ef results; raise ef to find more. Scores are
+    /// rescored at full precision where the graph keeps copies, as `search`
+    /// does.
+    pub fn search_range(&self, query: &[f32], ef: u16, threshold: f32) -> Box<[SearchResult]> {
+        assert_eq!(query.len(), self.dims as usize, "query dimension mismatch");
+        let _pin = self.pin();
+        let params = SearchParams::new(ef, ef);
+        let ef = self.start_ef(&params);
+        let mut results = self.search_rescored(query, ef, ef, ef, params.rescore, None);
+        results.retain(|&(_, score)| {
+            self.distance_metric.cmp_score(score, threshold) != Ordering::Less
+        });
+        results.sort_unstable_by(|a, b| self.distance_metric.cmp_score(b.1, a.1));
+        let results = unsafe {
+            mem::transmute::<Box<[(u32, f32)]>, Box<[SearchResult]>>(results.into_boxed_slice())
+        };
+        self.touch(&results);
+        results
+    }
+
+    /// Search for the `top_k` best nodes that pass `filter`
+    ///
+    /// Nodes failing the filter are still traversed, leading the search to the
+    /// ones that pass, but never returned. Level 0 expands nodes until `ef` of
+    /// them pass or no reachable ones are left, so the more selective the
+    /// filter, the more of the graph a search visits.
+    pub fn search_filtered(
+        &self,
+        query: &[f32],
+        ef: u16,
+        top_k: u16,
+        filter: impl Fn(NodeId) -> bool,
+    ) -> Box<[SearchResult]> {
+        assert_eq!(query.len(), self.dims as usize, "query dimension mismatch");
+        let _pin = self.pin();
+        let quantized = QuantVecBox::new(self.quantization, self.dims, query, &self.allocator);
+        let candidates = top_k.saturating_mul(8);
+        let entry_node = self.descend(&quantized, ef, candidates, None, false);
+        let results = self.search_level0_adaptive(
+            &[entry_node],
+            &quantized,
+            ef,
+            ef,
+            candidates,
+            false,
+            false,
+            Some(&filter),
+            None,
+        );
+        let results = unsafe {
+            map_boxed_slice(results, |result| SearchResult {
+                node: NodeId::from_vec_handle(self.nodes0_arena[result.node].vec),
+                score: result.score,
+            })
+        };
+
+        let mut results = self.rescore(query, results);
+        self.select_top_k(&mut results, top_k as usize);
+        let results = unsafe {
+            mem::transmute::<Box<[(u32, f32)]>, Box<[SearchResult]>>(results.into_boxed_slice())
+        };
+        self.touch(&results);
+        results
+    }
+
+    /// Score only the nodes in `candidates` against `query` and return the best
+    /// `top_k` of them, best first
+    ///
+    /// A brute-force pass over the given ids, for when an upstream filter has
+    /// already narrowed the candidates down and traversing the graph isn't
+    /// needed. Scores come from the full-precision copies where the graph keeps
+    /// them. Ids that aren't indexed or have expired are skipped, and repeated
+    /// ids count once.
+    pub fn search_within(
+        &self,
+        candidates: &[NodeId],
+        query: &[f32],
+        top_k: u16,
+    ) -> Box<[SearchResult]> {
+        assert_eq!(query.len(), self.dims as usize, "query dimension mismatch");
+        let _pin = self.pin();
+        let quantized = QuantVecBox::new(self.quantization, self.dims, query, &self.allocator);
+        let mag_query = dot_product_f32(query, query);
+        let raw_query = unsafe { mem::transmute::<&[f32], &RawVec>(query) };
+        let now = self.now();
+
+        let mut seen = self.take_visited();
+        let scored = candidates.iter().filter_map(|&id| {
+            let vec_handle = self.live_vec(id)?;
+            if self.is_expired(vec_handle, now) || !seen.insert(id.0) {
+                return None;
+            }
+            let score = match self.raw_vec(vec_handle.handle_a()) {
+                Some(vec) => {
+                    let mag_vec = dot_product_f32(&vec.vec, &vec.vec);
+                    self.distance_metric
+                        .calculate_raw(raw_query, mag_query, vec, mag_vec)
+                }
+                None => self
+                    .distance_metric
+                    .calculate(&quantized, &self.vec_arena[vec_handle.handle_b()]),
+            };
+            Some((id.0, score))
+        });
+        let results = self.collect_top_k(scored, top_k as usize);
+        self.return_visited(seen);
+        let results = unsafe {
+            mem::transmute::<Box<[(u32, f32)]>, Box<[SearchResult]>>(results.into_boxed_slice())
+        };
+        self.touch(&results);
+        results
+    }
+
+    /// Classify `query` by a vote among its `k` nearest labeled neighbors
+    ///
+    /// `label` gives the label of a node, or `None` for nodes that don't take
+    /// part. Ties go to the label of the better ranked neighbor. Returns `None`
+    /// if no labeled node was found.
+    pub fn classify(
+        &self,
+        query: &[f32],
+        ef: u16,
+        k: u16,
+        vote: VoteKind,
+        label: impl Fn(NodeId) -> Option<u64>,
+    ) -> Option<LabelScore> {
+        let results = self.search_filtered(query, ef, k, |node| label(node).is_some());
+
+        // label -> votes and the rank of its best neighbor
+        let mut votes = BTreeMap::<u64, (f32, usize)>::new();
+        let mut total = 0.0;
+        for (rank, result) in results.iter().enumerate() {
+            let Some(label) = label(result.node) else {
+                continue;
+            };
+            let weight = match vote {
+                VoteKind::Majority => 1.0,
+                VoteKind::Weighted => match self.distance_metric.kind() {
+                    DistanceMetricKind::Cosine | DistanceMetricKind::DotProduct => {
+                        result.score.max(0.0)
+                    }
+                    DistanceMetricKind::Euclidean
+                    | DistanceMetricKind::Hamming
+                    | DistanceMetricKind::Manhattan
+                    | DistanceMetricKind::Chebyshev => 1.0 / (1.0 + result.score),
+                },
+            };
+            votes.entry(label).or_insert((0.0, rank)).0 += weight;
+            total += weight;
+        }
+
+        let (label, (weight, _)) = votes
+            .into_iter()
+            .max_by(|(_, (a, a_rank)), (_, (b, b_rank))| a.total_cmp(b).then(b_rank.cmp(a_rank)))?;
+        Some(LabelScore {
+            label,
+            // every vote weighed nothing
+            score: if total > 0.0 { weight / total } else { 0.0 },
+        })
+    }
+
+    /// Like `search_with_params`, but returns an error when the query's length
+    /// doesn't match the graph's input dimensionality or a component is NaN,
+    /// when ef is 0 or when top_k is above `MAX_TOP_K`
+    pub fn try_search(
+        &self,
+        query: &[f32],
+        params: &SearchParams,
+    ) -> Result<Box<[SearchResult]>, VectorDbError> {
+        self.check_vec(query)?;
+        self.check_ef(params.ef)?;
+        if params.top_k > MAX_TOP_K {
+            return Err(VectorDbError::TopKOutOfRange {
+                top_k: params.top_k,
+            });
+        }
+        Ok(unsafe { self.search_unchecked(query, params) })
+    }
+
+    // Validate an input vector against the dimensionality callers provide, which
+    // is what any preprocessing sees before vectors reach the graph.
+    fn check_dims(&self, vec: &[f32]) -> Result<(), VectorDbError> {
+        if vec.len() != self.dims as usize {
+            return Err(VectorDbError::DimensionMismatch {
+                expected: self.dims,
+                found: vec.len(),
+            });
+        }
+        Ok(())
+    }
+
+    // `check_dims`, and reject NaN components, which no score can rank.
+    fn check_vec(&self, vec: &[f32]) -> Result<(), VectorDbError> {
+        self.check_dims(vec)?;
+        match vec.iter().position(|component| component.is_nan()) {
+            Some(index) => Err(VectorDbError::NanComponent { index }),
+            None => Ok(()),
+        }
+    }
+
+    fn check_ef(&self, ef: u16) -> Result<(), VectorDbError> {
+        if ef == 0 {
+            return Err(VectorDbError::ZeroEf);
+        }
+        Ok(())
+    }
+
+    fn check_writable(&self) -> Result<(), VectorDbError> {
+        if self.read_only {
+            return Err(VectorDbError::ReadOnly);
+        }
+        if self.frozen {
+            return Err(VectorDbError::Frozen);
+        }
+        Ok(())
+    }
+
+    // Pin the epoch for a read, unless the graph is frozen and has no writers
+    // to wait for it.
+    #[inline]
+    fn pin(&self) -> Option<EpochPin<'_>> {
+        (!self.frozen).then(|| self.epoch.pin())
+    }
+
+    // The neighbor list behind `lock`, read in place in a frozen graph.
+    #[inline]
+    fn read_list<'a, T: ?Sized>(&self, lock: &'a rwlock::RwLock<T>) -> ListRef<'a, T> {
+        if self.frozen {
+            ListRef::Frozen(unsafe { &*lock.data_ptr() })
+        } else {
+            ListRef::Locked(lock.read())
+        }
+    }
+
+    /// Search each of `queries`, returning their results in order
+    ///
+    /// The batch is pinned once and its searches take turns with the same
+    /// visited sets from the graph's pool. Every query descends the upper levels
+    /// on its own, so recall matches `search`; for queries that lie close
+    /// together `search_batch_shared` also shares the descent.
+    pub fn search_batch(
+        &self,
+        queries: &[&[f32]],
+        ef: u16,
+        top_k: u16,
+    ) -> Box<[Box<[SearchResult]>]> {
+        for query in queries {
+            assert_eq!(query.len(), self.dims as usize, "query dimension mismatch");
+        }
+        let params = SearchParams::new(ef, top_k);
+        let _pin = self.pin();
+        queries
+            .iter()
+            .map(|query| {
+                let results = self.search_params_unpinned(query, &params, None);
+                let results = unsafe {
+                    mem::transmute::<Box<[(u32, f32)]>, Box<[SearchResult]>>(
+                        results.into_boxed_slice(),
+                    )
+                };
+                self.touch(&results);
+                results
+            })
+            .collect()
+    }
+
+    /// Search a batch of queries that lie close together, e.g. crops of the same
+    /// image, descending the upper levels only once, for the batch centroid
+    ///
+    /// Each query still runs its own level 0 search from the shared entry point,
+    /// so queries far from the centroid may lose recall. With a post-filter or group
+    /// limit every query is searched separately.
+    pub fn search_batch_shared(
+        &self,
+        queries: &[&[f32]],
+        params: &SearchParams,
+    ) -> Vec<Box<[SearchResult]>> {
+        if params.refills() || queries.is_empty() {
+            return queries
+                .iter()
+                .map(|query| self.search_with_params(query, params))
+                .collect();
+        }
+
+        let dims = self.dims as usize;
+        let mut centroid = alloc::vec![0.0; dims];
+        for query in queries {
+            assert_eq!(query.len(), dims, "query dimension mismatch");
+            for (sum, value) in centroid.iter_mut().zip(*query) {
+                *sum += value;
+            }
+        }
+        for sum in &mut centroid {
+            *sum /= queries.len() as f32;
+        }
+
+        let _pin = self.pin();
+        let candidates = params.candidates();
+        let ef = self.start_ef(params);
+        let max_ef = self.max_ef(params, ef);
+        let centroid = QuantVecBox::new(self.quantization, self.dims, &centroid, &self.allocator);
+        let entry_node = self.descend(&centroid, ef, candidates, None, false);
+
+        queries
+            .iter()
+            .map(|query| {
+                let quantized =
+                    QuantVecBox::new(self.quantization, self.dims, query, &self.allocator);
+                let results =
+                    self.search_level0_ids(entry_node, &quantized, ef, max_ef, candidates, None);
+                let mut results = self.rescore_with(query, results, params.rescore);
+                self.blend_priors(&mut results, params.prior_weight);
+                self.select_results(&mut results, params);
+                let results = unsafe {
+                    mem::transmute::<Box<[(u32, f32)]>, Box<[SearchResult]>>(
+                        results.into_boxed_slice(),
+                    )
+                };
+                self.touch(&results);
+                results
+            })
+            .collect()
+    }
+
+    /// Like `search_into`, but keeps the working memory of the search in
+    /// `context` instead of allocating it
+    ///
+    /// Once the context has grown to fit, the search allocates nothing. Searches
+    /// that `search_into` runs through `search_with_params`, and those whose
+    /// `top_k` covers every node, use the context only for the query.
+    pub fn search_with_context(
+        &self,
+        context: &mut SearchContext,
+        query: &[f32],
+        params: &SearchParams,
+        out: &mut [SearchResult],
+    ) -> usize {
+        assert_eq!(query.len(), self.dims as usize, "query dimension mismatch");
+        let ef = self.start_ef(params);
+        let max_ef = self.max_ef(params, ef);
+        let candidates = params.candidates();
+        if params.refills()
+            || params.mmr_lambda.is_some()
+            || params.prior_weight.is_some()
+            || self.result_cache.is_some()
+            || self.reranker.is_some()
+            || (candidates as usize >= self.len() && max_ef as usize > self.len())
+        {
+            return self.search_into(query, params, out);
+        }
+
+        let _pin = self.pin();
+        let limit = out.len().min(params.top_k as usize);
+        let out = &mut out[..limit];
+        let quantized = match context.query.take() {
+            Some(mut quantized) if quantized.fits(self.quantization, self.dims) => {
+                quantized.set(query);
+                quantized
+            }
+            _ => QuantVecBox::new(self.quantization, self.dims, query, &self.allocator),
+        };
+
+        let entry_node = self.descend_in(context, &quantized, ef, candidates, None, false);
+        self.search_level0_in(
+            context,
+            &[entry_node],
+            &quantized,
+            ef,
+            max_ef,
+            candidates,
+            false,
+            false,
+            None,
+            None,
+        );
+        let candidates = context.results.iter().map(|result| SearchResult {
+            node: NodeId::from_vec_handle(self.nodes0_arena[result.node].vec),
+            score: result.score,
+        });
+        let len = self.rank_into(query, candidates, params.rescore, out);
+        params.order.apply(&mut out[..len], |result| result.score);
+        context.query = Some(quantized);
+
+        self.touch(&out[..len]);
+        len
+    }
+
+    /// Like `search_with_params`, but writes the results into `out`, in the
+    /// order `params` asks for
+    ///
+    /// At most `out.len()` results are written. Returns the number written.
+    /// Results are ranked straight into `out`, so no result list is allocated,
+    /// though the search itself still allocates its working memory. Searches
+    /// with a post-filter, group limit, MMR or prior weight, and graphs with a
+    /// result cache or a reranker, go through `search_with_params` and copy its
+    /// results.
+    pub fn search_into(
+        &self,
+        query: &[f32],
+        params: &SearchParams,
+        out: &mut [SearchResult],
+    ) -> usize {
+        assert_eq!(query.len(), self.dims as usize, "query dimension mismatch");
+        let _pin = self.pin();
+        let limit = out.len().min(params.top_k as usize);
+        let out = &mut out[..limit];
+
+        let len = if params.refills()
+            || params.mmr_lambda.is_some()
+            || params.prior_weight.is_some()
+            || self.result_cache.is_some()
+            || self.reranker.is_some()
+        {
+            let results = self.search_params_unpinned(query, params, None);
+            for (slot, (handle, score)) in out.iter_mut().zip(&results) {
+                *slot = SearchResult {
+                    node: NodeId(*handle),
+                    score: *score,
+                };
+            }
+            results.len().min(out.len())
+        } else {
+            let ef = self.start_ef(params);
+            let max_ef = self.max_ef(params, ef);
+            let mut sampled = self.sampled_trace();
+            let candidates = self.search_quantized_unpinned(
+                query,
+                ef,
+                max_ef,
+                params.candidates(),
+                sampled.as_mut(),
+            );
+            if let Some(sampled) = sampled {
+                self.record_trace(sampled);
+            }
+            let len = self.rank_into(query, candidates.iter().copied(), params.rescore, out);
+            params.order.apply(&mut out[..len], |result| result.score);
+            len
+        };
+
+        self.touch(&out[..len]);
+        len
+    }
+
+    // Rescore quantized results and keep the best `out.len()` of them in `out`,
+    // best first. Returns the number kept.
+    fn rank_into(
+        &self,
+        query: &[f32],
+        candidates: impl IntoIterator<Item = SearchResult>,
+        rescore: RescoreMode,
+        out: &mut [SearchResult],
+    ) -> usize {
+        let mag_query = dot_product_f32(query, query);
+        let query = unsafe { mem::transmute::<&[f32], &RawVec>(query) };
+        let mut len = 0;
+
+        for candidate in candidates {
+            let score = match rescore {
+                RescoreMode::None => candidate.score,
+                RescoreMode::FullPrecision { .. } => {
+                    self.rescore_one(query, mag_query, candidate.node.0, candidate.score)
+                }
+            };
+            // after any results that are at least as good
+            let pos = out[..len].partition_point(|result| {
+                self.distance_metric.cmp_score(result.score, score) != Ordering::Less
+            });
+            if pos == out.len() {
+                continue;
+            }
+            if len < out.len() {
+                len += 1;
+            }
+            out.copy_within(pos..len - 1, pos + 1);
+            out[pos] = SearchResult {
+                node: candidate.node,
+                score,
+            };
+        }
+
+        len
+    }
+
+    fn search_params_unpinned(
+        &self,
+        query: &[f32],
+        params: &SearchParams,
+        trace: Option<&mut Trace>,
+    ) -> Vec<(u32, f32)> {
+        debug_assert!(params.top_k <= MAX_TOP_K);
+
+        if trace.is_none()
+            && let Some(mut sampled) = self.sampled_trace()
+        {
+            let results = self.search_params_unpinned(query, params, Some(&mut sampled));
+            self.record_trace(sampled);
+            return results;
+        }
+
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            "search",
+            ef = params.ef,
+            top_k = params.top_k,
+            filtered = params.refills(),
+        )
+        .entered();
+
+        if params.refills() {
+            return self.search_post_filtered(query, params, trace);
+        }
+
+        let ef = self.start_ef(params);
+        let max_ef = self.max_ef(params, ef);
+        let cache = self.result_cache.as_ref().filter(|_| {
+            trace.is_none()
+                && self.now.is_none()
+                && params.mmr_lambda.is_none()
+                && params.prior_weight.is_none()
+        });
+        let cache_key = cache.map(|_| {
+            let quantized = QuantVecBox::new(self.quantization, self.dims, query, &self.allocator);
+            let hash = hash_bytes(quantized.as_unsigned_byte());
+            let seq = self.seq.load(AtomicOrdering::Acquire);
+            (
+                hash,
+                seq,
+                (ef, max_ef, params.top_k, params.rescore, params.order),
+            )
+        });
+
+        if let (Some(cache), Some((hash, seq, key))) = (cache, cache_key)
+            && let Some(results) = cache.get(hash, seq, key, query)
+        {
+            #[cfg(feature = "tracing")]
+            tracing::trace!("result cache hit");
+            return results;
+        }
+
+        let mut results = self.search_rescored(
+            query,
+            ef,
+            max_ef,
+            params.candidates(),
+            params.rescore,
+            trace,
+        );
+        self.blend_priors(&mut results, params.prior_weight);
+        self.select_results(&mut results, params);
+
+        if let (Some(cache), Some((hash, seq, key))) = (cache, cache_key) {
+            cache.insert(hash, seq, key, query, &results);
+        }
+        results
+    }
+
+    // The ef a search with `params` starts with. When `top_k` covers every node
+    // slot it's raised past the number of slots, so that refilling searches ask
+    // for enough candidates to scan every node, see `search_level0_ids`.
+    fn start_ef(&self, params: &SearchParams) -> u16 {
+        let ef = if params.top_k as usize >= self.len() {
+            // plus the synthetic root
+            let nodes = u16::try_from(self.len() + 1).unwrap_or(u16::MAX);
+            params.ef.max(nodes)
+        } else {
+            params.ef
+        };
+        ef.min(self.scratch_ef(params))
+    }
+
+    // The ef adaptive searches with `params` may grow to from `ef`.
+    fn max_ef(&self, params: &SearchParams, ef: u16) -> u16 {
+        params
+            .adaptive_max_ef
+            .unwrap_or(ef)
+            .max(ef)
+            .min(self.scratch_ef(params))
+    }
+
+    // The largest ef whose level 0 working memory fits the scratch budget of
+    // `params`. See `SearchParams::max_scratch_bytes`.
+    fn scratch_ef(&self, params: &SearchParams) -> u16 {
+        let Some(bytes) = params.max_scratch_bytes else {
+            return u16::MAX;
+        };
+        let entry = mem::size_of::<InternalSearchResult<Node0>>();
+        let per_node = entry + self.m0 as usize * (entry + mem::size_of::<u32>());
+        u16::try_from(bytes / per_node).unwrap_or(u16::MAX).max(1)
+    }
+
+    // Quantized search for `candidates` results, rescored as `rescore` says.
+    // The results are unordered.
+    fn search_rescored(
+        &self,
+        query: &[f32],
+        ef: u16,
+        max_ef: u16,
+        candidates: u16,
+        rescore: RescoreMode,
+        trace: Option<&mut Trace>,
+    ) -> Vec<(u32, f32)> {
+        let results_quantized =
+            self.search_quantized_unpinned(query, ef, max_ef, candidates, trace);
+        self.rescore_with(query, results_quantized, rescore)
+    }
+
+    // Like `rescore`, but keeps the quantized scores for `RescoreMode::None`.
+    fn rescore_with(
+        &self,
+        query: &[f32],
+        results_quantized: Box<[SearchResult]>,
+        rescore: RescoreMode,
+    ) -> Vec<(u32, f32)> {
+        match rescore {
+            RescoreMode::None => unsafe {
+                mem::transmute::<Box<[SearchResult]>, Box<[(u32, f32)]>>(results_quantized)
+            }
+            .into_vec(),
+            RescoreMode::FullPrecision { .. } => self.rescore(query, results_quantized),
+        }
+    }
+
+    // Rescore quantized results at full precision where a full-precision copy is
+    // kept. The results are unordered.
+    fn rescore(&self, query: &[f32], results_quantized: Box<[SearchResult]>) -> Vec<(u32, f32)> {
+        if let Some(reranker) = &self.reranker {
+            let candidates: Vec<NodeId> = results_quantized.iter().map(|r| r.node).collect();
+            let mut scores: Vec<f32> = results_quantized.iter().map(|r| r.score).collect();
+            reranker.rerank(query, &candidates, &mut scores);
+            return candidates.into_iter().map(|id| id.0).zip(scores).collect();
+        }
+
+        let mag_query = dot_product_f32(query, query);
+        let mut results =
+            unsafe { mem::transmute::<Box<[SearchResult]>, Box<[(u32, f32)]>>(results_quantized) };
+        let query = unsafe { mem::transmute::<&[f32], &RawVec>(query) };
+        // in place, so the candidates are only held once
+        for (handle, score) in &mut results {
+            *score = self.rescore_one(query, mag_query, *handle, *score);
+        }
+        results.into_vec()
+    }
+
+    // Full-precision score of the vector behind `handle`, or its quantized
+    // `score` if no full-precision copy is kept.
+    fn rescore_one(&self, query: &RawVec, mag_query: f32, handle: u32, score: f32) -> f32 {
+        let Some(vec) = self.raw_vec(NodeId(handle).to_vec_handle().handle_a()) else {
+            return score;
+        };
+        let mag_vec = dot_product_f32(&vec.vec, &vec.vec);
+        self.distance_metric
+            .calculate_raw(query, mag_query, vec, mag_vec)
+    }
+
+    // Full-precision copy of a stored vector, if the graph has one.
+    fn raw_vec(&self, handle: HandleA<RawVec>) -> Option<&RawVec> {
+        if self.store_raw {
+            return Some(&self.vec_arena[handle]);
+        }
+
+        match self.quantization {
+            Quantization::FullPrecisionFP => {
+                let vec = self.vec_arena[HandleB::new(*handle)].as_full_precision_fp();
+                Some(unsafe { mem::transmute::<&[f32], &RawVec>(vec) })
+            }
+            _ => None,
+        }
+    }
+
+    // Add `weight` times each result's prior to its score.
+    fn blend_priors(&self, results: &mut [(u32, f32)], weight: Option<f32>) {
+        let (Some(weight), Some(prior_arena)) = (weight, &self.prior_arena) else {
+            return;
+        };
+        for (handle, score) in results {
+            let prior = prior_arena[NodePriorHandle::new(*NodeId(*handle).to_vec_handle())].get();
+            *score += weight * prior;
+        }
+    }
+
+    // Keep the `top_k` results to return: the best ones, or those picked by MMR,
+    // in the order `params` asks for.
+    fn select_results(&self, results: &mut Vec<(u32, f32)>, params: &SearchParams) {
+        match params.mmr_lambda {
+            Some(lambda) => self.select_mmr(results, params.top_k as usize, lambda),
+            None => self.select_top_k(results, params.top_k as usize),
+        }
+        params.order.apply(results, |&(_, score)| score);
+    }
+
+    // Greedy maximal marginal relevance over the candidates in `results`.
+    fn select_mmr(&self, results: &mut Vec<(u32, f32)>, top_k: usize, lambda: f32) {
+        // both terms as similarities, greater meaning closer
+        let sign = match self.distance_metric.cmp_score(1.0, 0.0) {
+            Ordering::Greater => 1.0,
+            _ => -1.0,
+        };
+        let candidates = mem::take(results);
+        let vecs: Vec<(VecHandle, f32)> = candidates
+            .iter()
+            .map(|&(handle, _)| {
+                let vec = NodeId(handle).to_vec_handle();
+                let mag = self
+                    .raw_vec(vec.handle_a())
+                    .map_or(0.0, |raw| dot_product_f32(&raw.vec, &raw.vec));
+                (vec, mag)
+            })
+            .collect();
+        let similarity = |(a, mag_a): (VecHandle, f32), (b, mag_b): (VecHandle, f32)| {
+            let score = match (self.raw_vec(a.handle_a()), self.raw_vec(b.handle_a())) {
+                (Some(raw_a), Some(raw_b)) => self
+                    .distance_metric
+                    .calculate_raw(raw_a, mag_a, raw_b, mag_b),
+                _ => self
+                    .distance_metric
+                    .calculate(&self.vec_arena[a.handle_b()], &self.vec_arena[b.handle_b()]),
+            };
+            sign * score
+        };
+
+        // similarity of each candidate to the closest one picked, None once picked
+        let mut closest = alloc::vec![Some(f32::NEG_INFINITY); candidates.len()];
+        while results.len() < top_k.min(candidates.len()) {
+            let marginal = |i: usize, closest: f32| {
+                let redundancy = if results.is_empty() { 0.0 } else { closest };
+                lambda * sign * candidates[i].1 - (1.0 - lambda) * redundancy
+            };
+            let Some((picked, _)) = closest
+                .iter()
+                .enumerate()
+                .filter_map(|(i, closest)| Some((i, marginal(i, (*closest)?))))
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+            else {
+                break;
+            };
+
+            closest[picked] = None;
+            results.push(candidates[picked]);
+            for (i, closest) in closest.iter_mut().enumerate() {
+                if let Some(closest) = closest {
+                    *closest = closest.max(similarity(vecs[picked], vecs[i]));
+                }
+            }
+        }
+    }
+
+    // Keep the best `top_k` results, best first.
+    fn select_top_k(&self, results: &mut Vec<(u32, f32)>, top_k: usize) {
+        if results.len() / HEAP_SELECT_RATIO >= top_k.max(1) {
+            *results = self.stream_top_k(mem::take(results), top_k);
+            return;
+        }
+
+        if results.len() > top_k {
+            results.select_nth_unstable_by(top_k, |a, b| self.distance_metric.cmp_score(b.1, a.1));
+            results.truncate(top_k);
+        }
+
+        results.sort_unstable_by(|a, b| self.distance_metric.cmp_score(b.1, a.1));
+    }
+
+    // The best `top_k` of `results`, best first, collected or streamed through
+    // a bounded heap by how many there may be, see `HEAP_SELECT_RATIO`.
+    fn collect_top_k(
+        &self,
+        results: impl Iterator<Item = (u32, f32)>,
+        top_k: usize,
+    ) -> Vec<(u32, f32)> {
+        match results.size_hint() {
+            (_, Some(len)) if len / HEAP_SELECT_RATIO < top_k.max(1) => {
+                let mut results = results.collect();
+                self.select_top_k(&mut results, top_k);
+                results
+            }
+            _ => self.stream_top_k(results, top_k),
+        }
+    }
+
+    fn stream_top_k(
+        &self,
+        results: impl IntoIterator<Item = (u32, f32)>,
+        top_k: usize,
+    ) -> Vec<(u32, f32)> {
+        if top_k == 0 {
+            return Vec::new();
+        }
+        // worst on top, to be replaced by anything better
+        let mut heap = BinaryHeap::with_capacity_by(top_k, |a: &(u32, f32), b: &(u32, f32)| {
+            self.distance_metric.cmp_score(b.1, a.1)
+        });
+        for result in results {
+            if heap.len() < top_k {
+                heap.push(result);
+            } else if self
+                .distance_metric
+                .cmp_score(result.1, heap.peek().unwrap().1)
+                == Ordering::Greater
+            {
+                heap.pop();
+                heap.push(result);
+            }
+        }
+        heap.into_sorted_vec()
+    }
+
+    fn search_post_filtered(
+        &self,
+        query: &[f32],
+        params: &SearchParams,
+        mut trace: Option<&mut Trace>,
+    ) -> Vec<(u32, f32)> {
+        let top_k = params.top_k as usize;
+        let mut ef = self.start_ef(params);
+        let max_ef = params
+            .work_budget
+            .unwrap_or(params.ef.saturating_mul(8))
+            .max(ef)
+            .min(self.scratch_ef(params));
+        let mut offered = BTreeSet::new();
+        let mut accepted = Vec::new();
+        let mut groups = BTreeMap::<u64, u16>::new();
+
+        'refill: loop {
+            // keep the path of the last round only, and count every round's nodes
+            if let Some(trace) = trace.as_deref_mut() {
+                trace.path.clear();
+            }
+            let mut candidates =
+                self.search_rescored(query, ef, ef, ef, params.rescore, trace.as_deref_mut());
+            self.blend_priors(&mut candidates, params.prior_weight);
+            let len = candidates.len();
+            self.select_top_k(&mut candidates, len);
+
+            let mut new_candidates = false;
+            for (handle, score) in candidates {
+                if accepted.len() >= top_k {
+                    break;
+                }
+                if !offered.insert(handle) {
+                    continue;
+                }
+                new_candidates = true;
+                let decision = match &params.post_filter {
+                    Some(filter) => filter(NodeId(handle), score),
+                    None => FilterDecision::Accept,
+                };
+                match decision {
+                    FilterDecision::Accept => {}
+                    FilterDecision::Reject => continue,
+                    FilterDecision::Stop => break 'refill,
+                }
+                // candidates come best first, so each group keeps its best results
+                if let Some((extractor, k_per_group)) = &params.group_limit {
+                    let count = groups.entry(extractor(NodeId(handle))).or_default();
+                    if *count >= *k_per_group {
+                        continue;
+                    }
+                    *count += 1;
+                }
+                accepted.push((handle, score));
+            }
+
+            if accepted.len() >= top_k || !new_candidates || ef >= max_ef {
+                break;
+            }
+            ef = ef.saturating_mul(2).min(max_ef);
+        }
+
+        self.select_top_k(&mut accepted, top_k);
+        params.order.apply(&mut accepted, |&(_, score)| score);
+        accepted
+    }
+
+    /// Sort every neighbor list, e.g. once bulk indexing is done
+    ///
+    /// Lists that gain neighbors afterwards fall back to `NeighborOrder::Unordered`.
+    pub fn reorder_neighbors(&mut self, order: NeighborOrder) {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("reorder_neighbors", ?order).entered();
+
+        for index in 0..self.nodes_arena.len() as u32 {
+            let node = &self.nodes_arena[NodeHandle::new(index)];
+            node.neighbors.write().sort(&self.distance_metric, order);
+        }
+
+        for index in 0..self.nodes0_arena.len() as u32 {
+            let node = &self.nodes0_arena[Node0Handle::new(index)];
+            node.neighbors.write().sort(&self.distance_metric, order);
+        }
+    }
+
+    /// Make the graph immutable until `thaw`, so searches read it without
+    /// taking a lock
+    ///
+    /// Searches of a frozen graph skip the neighbor list and arena locks and
+    /// the epoch pins that make them safe alongside inserts, for the most
+    /// throughput once a graph is done changing. Inserts and updates fail with
+    /// `VectorDbError::Frozen` and deletes remove nothing. Searches made
+    /// without a `SearchContext` still borrow a visited set from a shared pool.
+    pub fn freeze(&mut self) {
+        self.frozen = true;
+        self.nodes_arena.freeze();
+        self.nodes0_arena.freeze();
+        self.vec_arena.freeze();
+        self.meta_arena.freeze();
+        if let Some(expiry_arena) = &mut self.expiry_arena {
+            expiry_arena.freeze();
+        }
+        if let Some(ingest_arena) = &mut self.ingest_arena {
+            ingest_arena.freeze();
+        }
+        if let Some(prior_arena) = &mut self.prior_arena {
+            prior_arena.freeze();
+        }
+    }
+
+    /// Undo `freeze`, letting the graph change again
+    pub fn thaw(&mut self) {
+        self.frozen = false;
+        self.nodes_arena.thaw();
+        self.nodes0_arena.thaw();
+        self.vec_arena.thaw();
+        self.meta_arena.thaw();
+        if let Some(expiry_arena) = &mut self.expiry_arena {
+            expiry_arena.thaw();
+        }
+        if let Some(ingest_arena) = &mut self.ingest_arena {
+            ingest_arena.thaw();
+        }
+        if let Some(prior_arena) = &mut self.prior_arena {
+            prior_arena.thaw();
+        }
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    /// Score between two stored vectors, using the graph's metric and quantization
+    ///
+    /// Returns NaN if either id doesn't refer to an indexed node, see
+    /// `checked_distance`.
+    pub fn distance(&self, a: NodeId, b: NodeId) -> f32 {
+        self.checked_distance(a, b).unwrap_or(f32::NAN)
+    }
+
+    /// Like `distance`, returning `None` if either id doesn't refer to an
+    /// indexed node
+    pub fn checked_distance(&self, a: NodeId, b: NodeId) -> Option<f32> {
+        let _pin = self.pin();
+        let (a, b) = (self.live_vec(a)?, self.live_vec(b)?);
+        Some(
+            self.distance_metric
+                .calculate(&self.vec_arena[a.handle_b()], &self.vec_arena[b.handle_b()]),
+        )
+    }
+
+    /// Fill `out` with the scores between every pair of `ids`, row-major, so
+    /// that `out[i * ids.len() + j]` is `distance(ids[i], ids[j])`
+    ///
+    /// Each pair is scored once and mirrored. Rows and columns of ids that
+    /// don't refer to an indexed node are NaN. Panics unless `out` holds
+    /// `ids.len()` squared scores.
+    pub fn pairwise(&self, ids: &[NodeId], out: &mut [f32]) {
+        let n = ids.len();
+        assert_eq!(out.len(), n * n, "out must hold ids.len() squared scores");
+        let _pin = self.pin();
+        let vecs: Vec<Option<&QuantVec>> = ids
+            .iter()
+            .map(|&id| Some(&self.vec_arena[self.live_vec(id)?.handle_b()]))
+            .collect();
+
+        for (i, a) in vecs.iter().enumerate() {
+            for (j, b) in vecs.iter().enumerate().skip(i) {
+                let score = match (a, b) {
+                    (Some(a), Some(b)) => self.distance_metric.calculate(a, b),
+                    _ => f32::NAN,
+                };
+                out[i * n + j] = score;
+                out[j * n + i] = score;
+            }
+        }
+    }
+
+    /// Score between a stored vector and `query`, quantized the same way as
+    /// stored vectors
+    ///
+    /// Returns NaN if `id` doesn't refer to an indexed node, see
+    /// `distance_to_query`.
+    pub fn distance_to(&self, id: NodeId, query: &[f32]) -> f32 {
+        self.distance_to_query(id, query).unwrap_or(f32::NAN)
+    }
+
+    /// Like `distance_to`, returning `None` if `id` doesn't refer to an
+    /// indexed node
+    pub fn distance_to_query(&self, id: NodeId, query: &[f32]) -> Option<f32> {
+        assert_eq!(query.len(), self.dims as usize, "query dimension mismatch");
+        let _pin = self.pin();
+        let vec = self.live_vec(id)?;
+        let query = QuantVecBox::new(self.quantization, self.dims, query, &self.allocator);
+        Some(
+            self.distance_metric
+                .calculate(&query, &self.vec_arena[vec.handle_b()]),
+        )
+    }
+
+    /// The full-precision copy of the vector of `id`, or `None` if `id` isn't
+    /// a live node or the graph keeps no full-precision copies
+    ///
+    /// See `export_vectors` for dequantized vectors.
+    pub fn get_vector(&self, id: NodeId) -> Option<VectorRef<'_, [f32]>> {
+        let pin = self.pin();
+        let vec = self.raw_vec(self.live_vec(id)?.handle_a())?;
+        Some(VectorRef {
+            vec: &vec.vec,
+            _pin: pin,
+        })
+    }
+
+    /// The quantized vector of `id`, or `None` if `id` isn't a live node
+    pub fn get_quantized(&self, id: NodeId) -> Option<VectorRef<'_, QuantVec>> {
+        let pin = self.pin();
+        let vec = &self.vec_arena[self.live_vec(id)?.handle_b()];
+        Some(VectorRef { vec, _pin: pin })
+    }
+
+    /// Copy the vectors with ids in `range` into `out`, `dims` values each and in
+    /// `NodeId` order, returning how many were copied
+    ///
+    /// Full-precision copies are exported where they are kept, and dequantized
+    /// vectors otherwise. Stops at the end of the graph or once `out` is full.
+    /// Slots without a live vector, e.g. evicted ones, are zero-filled so that
+    /// positions in `out` keep matching ids.
+    pub fn export_vectors(&self, range: Range<u32>, out: &mut [f32]) -> usize {
+        let end = range.end.min(self.len() as u32);
+        let _pin = self.pin();
+
+        let mut copied = 0;
+        for (id, out) in (range.start..end).zip(out.chunks_exact_mut(self.dims as usize)) {
+            match self.live_vec(NodeId(id)) {
+                Some(vec) => match self.raw_vec(vec.handle_a()) {
+                    Some(raw) => out.copy_from_slice(&raw.vec),
+                    None => self.vec_arena[vec.handle_b()].dequantize_into(self.quantization, out),
+                },
+                None => out.fill(0.0),
+            }
+            copied += 1;
+        }
+        copied
+    }
+
+    /// Recompute the scores cached in every neighbor list with the current
+    /// kernels, e.g. after loading a graph built under another `KERNEL_VERSION`
+    pub fn recompute_scores(&mut self) {
+        for i in 0..self.nodes_arena.len() as u32 {
+            let node = &self.nodes_arena[NodeHandle::new(i)];
+            node.neighbors
+                .write()
+                .rescore(&self.distance_metric, |neighbor| {
+                    self.link_score(node.vec, self.nodes_arena[neighbor].vec)
+                });
+        }
+        for i in 0..self.nodes0_arena.len() as u32 {
+            let node = &self.nodes0_arena[Node0Handle::new(i)];
+            node.neighbors
+                .write()
+                .rescore(&self.distance_metric, |neighbor| {
+                    self.link_score(node.vec, self.nodes0_arena[neighbor].vec)
+                });
+        }
+        self.seq.fetch_add(1, AtomicOrdering::Release);
+    }
+
+    /// Encode the graph into a buffer that `Graph::deserialize` loads back
+    ///
+    /// Captures the parameters, every vector and full-precision copy, the neighbor
+    /// lists and the RNG state, so searches after a reload return what they
+    /// would have here and inserts draw the same levels. The clock, node expiry
+    /// times, ingest records, priors, result cache, checkpoint, memory pressure
+    /// hook, `assume_normalized`, alias threshold, trace sampling, chunk size,
+    /// neighbor selection, default efs and allocator are not captured. Inserts
+    /// running alongside may be caught halfway, which `deserialize` rejects, so
+    /// serialize from a checkpoint callback or with inserts paused.
+    pub fn serialize(&self) -> Box<[u8]> {
+        self.serialize_cancellable(&CancellationToken::new())
+            .expect("the token is never cancelled")
+    }
+
+    /// Like `serialize`, failing with `VectorDbError::Cancelled` once `cancel`
+    /// is cancelled, checked between nodes
+    pub fn serialize_cancellable(
+        &self,
+        cancel: &CancellationToken,
+    ) -> Result<Box<[u8]>, VectorDbError> {
+        let _pin = self.pin();
+        let mut out = Writer::new();
+        self.write_snapshot(&mut out, true, cancel)?;
+        Ok(out.finish())
+    }
+
+    /// Encode the graph into a buffer that `Graph::from_mapped` serves without
+    /// copying the vectors
+    ///
+    /// Captures what `serialize` does, with the vector slots in their native
+    /// layout, see `layout`, so only machines of the same byte order can map
+    /// the buffer.
+    #[cfg(feature = "mmap")]
+    pub fn serialize_mapped(&self) -> Box<[u8]> {
+        let _pin = self.pin();
+        let mut snapshot = Writer::new();
+        self.write_snapshot(&mut snapshot, false, &CancellationToken::new())
+            .expect("the token is never cancelled");
+        let snapshot = snapshot.finish();
+
+        let mut out = Writer::new();
+        out.bytes(&MAPPED_MAGIC);
+        out.u32(FORMAT_VERSION);
+        out.bytes(&BYTE_ORDER.to_ne_bytes());
+        out.u64(snapshot.len() as u64);
+        out.bytes(&snapshot);
+        let header = MAPPED_MAGIC.len() + 16 + snapshot.len();
+        out.bytes(&alloc::vec![0; header.next_multiple_of(MAPPED_ALIGN) - header]);
+
+        let len = self.vec_arena.len();
+        let (raw, quantized) = self.vec_arena.chunk_ptrs();
+        let strides = [
+            layout::quantized_stride(self.quantization, self.dims),
+            layout::full_precision_stride(if self.store_raw { self.dims } else { 0 }),
+        ];
+        for (chunks, stride) in [quantized, raw].into_iter().zip(strides) {
+            for (i, ptr) in chunks.into_iter().enumerate() {
+                let slots = (len - i * self.chunk_size).min(self.chunk_size);
+                out.bytes(unsafe { core::slice::from_raw_parts(ptr, slots * stride) });
+            }
+        }
+        out.finish()
+    }
+
+    // Everything `serialize` captures, leaving the vectors out unless `vectors`
+    // is set.
+    fn write_snapshot(
+        &self,
+        out: &mut Writer,
+        vectors: bool,
+        cancel: &CancellationToken,
+    ) -> Result<(), VectorDbError> {
+        let check = || {
+            if cancel.is_cancelled() {
+                return Err(VectorDbError::Cancelled);
+            }
+            Ok(())
+        };
+
+        out.bytes(&MAGIC);
+        out.u32(FORMAT_VERSION);
+        out.u32(KERNEL_VERSION);
+
+        out.u16(self.m);
+        out.u16(self.m0);
+        out.u16(self.dims);
+        out.u8(self.levels);
+        out.u8(self.quantization as u8);
+        out.u8(self.distance_metric.kind() as u8);
+        out.u8(self.store_raw as u8);
+        out.u8(self.link_full_precision as u8);
+        out.u8(self.cache_budget.is_some() as u8);
+        out.u32(self.cache_budget.unwrap_or(0));
+
+        out.u64(self.rng.state());
+        out.u32(self.clock.load(AtomicOrdering::Relaxed));
+        out.u64(self.clipped_vectors.load(AtomicOrdering::Relaxed));
+        out.u64(self.clipped_components.load(AtomicOrdering::Relaxed));
+        {
+            let stats = self.magnitude_stats.lock();
+            out.u64(stats.inserts);
+            out.f32(stats.baseline_mean);
+            out.f32(stats.baseline_m2);
+            out.f32(stats.recent_mean);
+            out.f32(stats.recent_mean_square);
+        }
+
+        let vecs = self.vec_arena.len() as u32;
+        let nodes = self.nodes_arena.len() as u32;
+        let nodes0 = self.nodes0_arena.len() as u32;
+        out.u32(vecs);
+        out.u32(nodes);
+        out.u32(nodes0);
+
+        for i in 0..vecs {
+            check()?;
+            let meta = &self.meta_arena[NodeMetaHandle::new(i)];
+            out.u32(meta.flags.load(AtomicOrdering::Acquire));
+            out.u32(meta.top.load(AtomicOrdering::Acquire));
+            out.u32(meta.last_access.load(AtomicOrdering::Relaxed));
+            out.u32(meta.accesses.load(AtomicOrdering::Relaxed));
+            if !vectors {
+                continue;
+            }
+
+            let vec_handle = VecHandle::new(i);
+            for dim in &self.vec_arena[vec_handle.handle_a()].vec {
+                out.f32(*dim);
+            }
+            let vec = &self.vec_arena[vec_handle.handle_b()];
+            out.f32(vec.mag);
+            out.components(vec.as_unsigned_byte(), self.quantization.size());
+        }
+
+        for i in 0..nodes {
+            check()?;
+            let node = &self.nodes_arena[NodeHandle::new(i)];
+            out.u32(*node.vec);
+            out.u32(*node.child);
+            let neighbors = node.neighbors.read();
+            out.u8(neighbors.neighbors_full as u8);
+            out.u8(neighbors.order as u8);
+            out.u16(neighbors.lowest_index);
+            out.f32(neighbors.lowest_score);
+            for neighbor in neighbors.neighbors() {
+                out.u32(*neighbor.node());
+                out.f32(neighbor.score(&self.distance_metric));
+            }
+        }
+
+        for i in 0..nodes0 {
+            check()?;
+            let node = &self.nodes0_arena[Node0Handle::new(i)];
+            out.u32(*node.vec);
+            let neighbors = node.neighbors.read();
+            out.u8(neighbors.neighbors_full as u8);
+            out.u8(neighbors.order as u8);
+            out.u16(neighbors.lowest_index);
+            out.f32(neighbors.lowest_score);
+            for neighbor in neighbors.neighbors() {
+                out.u32(*neighbor.node());
+                out.f32(neighbor.score(&self.distance_metric));
+            }
+        }
+        Ok(())
+    }
+
+    /// Load a graph written by `Graph::serialize`
+    ///
+    /// The graph uses the global allocator and has none of the settings
+    /// `serialize` leaves out. Snapshots written under another `KERNEL_VERSION`
+    /// get their neighbor scores recomputed. Every handle is checked, so a
+    /// damaged buffer is rejected rather than read out of bounds later.
+    pub fn deserialize(bytes: &[u8]) -> Result<Graph, DeserializeError> {
+        Self::read_snapshot(&mut Reader::new(bytes), None)
+    }
+
+    /// Load a graph written by `Graph::serialize_mapped`, reading its vectors
+    /// in place from `bytes`, e.g. a memory-mapped file
+    ///
+    /// Only the neighbor lists, which carry locks, and per-node bookkeeping are
+    /// copied, so a large index loads without a second copy of its vectors.
+    /// `bytes` must be aligned to `layout::ALIGN`, which a mapping always is.
+    /// The graph is read-only: inserts and updates fail with
+    /// `VectorDbError::ReadOnly`, while searches, deletes and everything else
+    /// behave as after `deserialize`.
+    #[cfg(feature = "mmap")]
+    pub fn from_mapped(bytes: &'static [u8]) -> Result<Graph, DeserializeError> {
+        if !(bytes.as_ptr() as usize).is_multiple_of(layout::ALIGN) {
+            return Err(DeserializeError::Misaligned);
+        }
+        let mut input = Reader::new(bytes);
+        if input.bytes(MAPPED_MAGIC.len()).ok() != Some(&MAPPED_MAGIC[..]) {
+            return Err(DeserializeError::NotASnapshot);
+        }
+        let version = input.u32()?;
+        if version != FORMAT_VERSION {
+            return Err(DeserializeError::UnsupportedVersion(version));
+        }
+        let byte_order = input.bytes(4)?;
+        if byte_order != BYTE_ORDER.to_ne_bytes() {
+            return Err(DeserializeError::ForeignByteOrder);
+        }
+        let len = usize::try_from(input.u64()?).map_err(|_| DeserializeError::Truncated)?;
+        let snapshot = input.bytes(len)?;
+
+        let header = MAPPED_MAGIC.len() + 16 + len;
+        let vecs = bytes
+            .get(header.next_multiple_of(MAPPED_ALIGN)..)
+            .ok_or(DeserializeError::Truncated)?;
+        Self::read_snapshot(&mut Reader::new(snapshot), Some(vecs))
+    }
+
+    // The inverse of `write_snapshot`. Without `mapped` the vectors are read
+    // from `input`, otherwise the graph borrows them from `mapped`, quantized
+    // slots first.
+    fn read_snapshot(
+        input: &mut Reader,
+        mapped: Option<&'static [u8]>,
+    ) -> Result<Graph, DeserializeError> {
+        if input.bytes(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+            return Err(DeserializeError::NotASnapshot);
+        }
+        let version = input.u32()?;
+        if version != FORMAT_VERSION {
+            return Err(DeserializeError::UnsupportedVersion(version));
+        }
+        let kernel_version = input.u32()?;
+
+        let mut builder = GraphBuilder::new()
+            .m(input.u16()?)
+            .m0(input.u16()?)
+            .dims(input.u16()?)
+            .levels(input.u8()?);
+        let quantization = match input.u8()? {
+            0 => Quantization::SignedByte,
+            1 => Quantization::UnsignedByte,
+            2 => Quantization::HalfPrecisionFP,
+            3 => Quantization::FullPrecisionFP,
+            4 => Quantization::Binary,
+            _ => return Err(DeserializeError::Corrupt),
+        };
+        let metric = match input.u8()? {
+            0 => DistanceMetricKind::Cosine,
+            1 => DistanceMetricKind::Euclidean,
+            2 => DistanceMetricKind::Hamming,
+            3 => DistanceMetricKind::DotProduct,
+            4 => DistanceMetricKind::Manhattan,
+            5 => DistanceMetricKind::Chebyshev,
+            _ => return Err(DeserializeError::Corrupt),
+        };
+        let store_raw = input.bool()?;
+        // no copy is kept next to full-precision vectors
+        if store_raw && matches!(quantization, Quantization::FullPrecisionFP) {
+            return Err(DeserializeError::Corrupt);
+        }
+        builder = builder
+            .quantization(quantization)
+            .metric(metric)
+            .rerank(store_raw)
+            .full_precision_linking(input.bool()?);
+        let has_cache_budget = input.bool()?;
+        let cache_budget = input.u32()?;
+        if has_cache_budget {
+            builder = builder.cache_budget(cache_budget);
+        }
+        builder.check().map_err(|_| DeserializeError::Corrupt)?;
+
+        let mut graph = Graph::from_builder(builder);
+        graph.rng = AtomicRng::new(input.u64()?);
+        graph.clock.store(input.u32()?, AtomicOrdering::Relaxed);
+        graph
+            .clipped_vectors
+            .store(input.u64()?, AtomicOrdering::Relaxed);
+        graph
+            .clipped_components
+            .store(input.u64()?, AtomicOrdering::Relaxed);
+        let stats = graph.magnitude_stats.get_mut();
+        stats.inserts = input.u64()?;
+        stats.baseline_mean = input.f32()?;
+        stats.baseline_m2 = input.f32()?;
+        stats.recent_mean = input.f32()?;
+        stats.recent_mean_square = input.f32()?;
+
+        let vecs = input.u32()?;
+        let nodes = input.u32()?;
+        let nodes0 = input.u32()?;
+        // the root's slot and nodes are already there, and u32::MAX is no handle
+        if vecs == 0 || nodes < graph.levels as u32 || nodes0 == 0 {
+            return Err(DeserializeError::Corrupt);
+        }
+        if [vecs, nodes, nodes0].contains(&u32::MAX) {
+            return Err(DeserializeError::Corrupt);
+        }
+
+        #[cfg(feature = "mmap")]
+        if let Some(mapped) = mapped {
+            graph.map_vecs(mapped, vecs)?;
+        }
+        graph.restore_vecs(input, vecs, mapped.is_none())?;
+        graph.restore_nodes(input, nodes)?;
+        graph.restore_nodes0(input, nodes0)?;
+        if !input.is_empty() {
+            return Err(DeserializeError::Corrupt);
+        }
+        graph.check_restored()?;
+
+        // deleted and purged slots, waiting for reuse
+        for i in 1..vecs {
+            if graph.meta_arena[NodeMetaHandle::new(i)].is_tombstoned() {
+                graph.free_slots.push(i);
+                graph.freed.fetch_add(1, AtomicOrdering::Relaxed);
+            }
+        }
+
+        if kernel_version != KERNEL_VERSION {
+            graph.recompute_scores();
+        }
+        Ok(graph)
+    }
+
+    // Point the vector arena at the `vecs` slots laid out in `mapped`.
+    #[cfg(feature = "mmap")]
+    fn map_vecs(&mut self, mapped: &'static [u8], vecs: u32) -> Result<(), DeserializeError> {
+        let raw_dims = if self.store_raw { self.dims } else { 0 };
+        let quantized_len = layout::quantized_stride(self.quantization, self.dims)
+            .checked_mul(vecs as usize)
+            .ok_or(DeserializeError::Corrupt)?;
+        let raw_len = layout::full_precision_stride(raw_dims)
+            .checked_mul(vecs as usize)
+            .ok_or(DeserializeError::Corrupt)?;
+        match mapped.len().cmp(&(quantized_len + raw_len)) {
+            Ordering::Less => return Err(DeserializeError::Truncated),
+            Ordering::Greater => return Err(DeserializeError::Corrupt),
+            Ordering::Equal => {}
+        }
+
+        let ptrs = (mapped[quantized_len..].as_ptr(), mapped.as_ptr());
+        // the buffer is aligned, and every stride is a multiple of the alignment
+        self.vec_arena = unsafe {
+            DoubleArena::borrowed(
+                self.chunk_size,
+                raw_dims,
+                (self.quantization, self.dims),
+                ptrs,
+                vecs,
+            )
+        };
+        self.read_only = true;
+        Ok(())
+    }
+
+    // The bookkeeping of `vecs` slots, and their vectors when `vectors` is set.
+    fn restore_vecs(
+        &mut self,
+        input: &mut Reader,
+        vecs: u32,
+        vectors: bool,
+    ) -> Result<(), DeserializeError> {
+        let zeros = alloc::vec![0.0; self.dims as usize];
+        for i in 0..vecs {
+            // slot 0 is the root's
+            if i > 0 {
+                if vectors {
+                    self.vec_arena
+                        .alloc(zeros.as_ptr(), QuantArgs::Raw(zeros.as_ptr()));
+                }
+                self.meta_arena.alloc(i, 0);
+            }
+
+            let meta = &self.meta_arena[NodeMetaHandle::new(i)];
+            meta.flags.store(input.u32()?, AtomicOrdering::Relaxed);
+            meta.top.store(input.u32()?, AtomicOrdering::Relaxed);
+            meta.last_access
+                .store(input.u32()?, AtomicOrdering::Relaxed);
+            meta.accesses.store(input.u32()?, AtomicOrdering::Relaxed);
+            if !vectors {
+                continue;
+            }
+
+            let vec_handle = VecHandle::new(i);
+            for dim in &mut self.vec_arena[vec_handle.handle_a()].vec {
+                *dim = input.f32()?;
+            }
+            let vec = &mut self.vec_arena[vec_handle.handle_b()];
+            vec.mag = input.f32()?;
+            input.components(vec.bytes_mut(), self.quantization.size())?;
+        }
+        Ok(())
+    }
+
+    fn restore_nodes(&mut self, input: &mut Reader, nodes: u32) -> Result<(), DeserializeError> {
+        for i in 0..nodes {
+            let vec = VecHandle::new(input.u32()?);
+            let child = NodeHandle::new(input.u32()?);
+            // the root's nodes are the first ones
+            if i >= self.levels as u32 {
+                self.nodes_arena.alloc((vec, child));
+            }
+
+            let node = &mut self.nodes_arena[NodeHandle::new(i)];
+            node.vec = vec;
+            node.child = child;
+            let neighbors = node.neighbors.get_mut();
+            neighbors.neighbors_full = input.bool()?;
+            neighbors.order = read_neighbor_order(input)?;
+            neighbors.lowest_index = input.u16()?;
+            neighbors.lowest_score = input.f32()?;
+            let len = restored_len(neighbors.neighbors_full, neighbors.lowest_index, self.m)?;
+            for slot in &mut neighbors.neighbors[..len] {
+                *slot = Neighbor::new(
+                    &self.distance_metric,
+                    NodeHandle::new(input.u32()?),
+                    input.f32()?,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn restore_nodes0(&mut self, input: &mut Reader, nodes0: u32) -> Result<(), DeserializeError> {
+        for i in 0..nodes0 {
+            let vec = VecHandle::new(input.u32()?);
+            // the root's node is the first one
+            if i > 0 {
+                self.nodes0_arena.alloc(vec);
+            }
+
+            let node = &mut self.nodes0_arena[Node0Handle::new(i)];
+            node.vec = vec;
+            let neighbors = node.neighbors.get_mut();
+            neighbors.neighbors_full = input.bool()?;
+            neighbors.order = read_neighbor_order(input)?;
+            neighbors.lowest_index = input.u16()?;
+            neighbors.lowest_score = input.f32()?;
+            let len = restored_len(neighbors.neighbors_full, neighbors.lowest_index, self.m0)?;
+            for slot in &mut neighbors.neighbors[..len] {
+                *slot = Neighbor0::new(
+                    &self.distance_metric,
+                    Node0Handle::new(input.u32()?),
+                    input.f32()?,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    // Check that the nodes of a restored graph form one chain per vector slot,
+    // from its top level down to level 0, and that every neighbor is a node of
+    // the same level, so searches on it stay within the arenas.
+    fn check_restored(&self) -> Result<(), DeserializeError> {
+        const UNSEEN: u8 = u8::MAX;
+        let corrupt = DeserializeError::Corrupt;
+        let mut node_levels = alloc::vec![UNSEEN; self.nodes_arena.len()];
+        let mut seen0 = alloc::vec![false; self.nodes0_arena.len()];
+
+        for i in 0..self.vec_arena.len() as u32 {
+            let meta = &self.meta_arena[NodeMetaHandle::new(i)];
+            let level = meta.level();
+            if !meta.is_ready() || level > self.levels {
+                return Err(corrupt);
+            }
+            if i == 0 && (level != self.levels || meta.top() != self.top_level_root_node) {
+                return Err(corrupt);
+            }
+
+            let mut node = meta.top();
+            for current_level in (1..=level).rev() {
+                let seen = node_levels
+                    .get_mut(*node as usize)
+                    .filter(|seen| **seen == UNSEEN)
+                    .ok_or(corrupt)?;
+                *seen = current_level;
+                let node_ref = &self.nodes_arena[node];
+                if *node_ref.vec != i {
+                    return Err(corrupt);
+                }
+                node = node_ref.child;
+            }
+            let seen = seen0
+                .get_mut(*node as usize)
+                .filter(|seen| !**seen)
+                .ok_or(corrupt)?;
+            *seen = true;
+            if *self.nodes0_arena[node.cast::<Node0>()].vec != i {
+                return Err(corrupt);
+            }
+        }
+        if node_levels.contains(&UNSEEN) || seen0.contains(&false) {
+            return Err(corrupt);
+        }
+
+        for (i, level) in node_levels.iter().enumerate() {
+            let node = &self.nodes_arena[NodeHandle::new(i as u32)];
+            for neighbor in node.neighbors.read().neighbors() {
+                if node_levels.get(*neighbor.node() as usize) != Some(level) {
+                    return Err(corrupt);
+                }
+            }
+        }
+        for i in 0..seen0.len() as u32 {
+            let node = &self.nodes0_arena[Node0Handle::new(i)];
+            for neighbor in node.neighbors.read().neighbors() {
+                if *neighbor.node() as usize >= seen0.len() {
+                    return Err(corrupt);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // The score neighbor lists store between two vectors.
+    fn link_score(&self, a: VecHandle, b: VecHandle) -> f32 {
+        if !self.link_full_precision {
+            return self
+                .distance_metric
+                .calculate(&self.vec_arena[a.handle_b()], &self.vec_arena[b.handle_b()]);
+        }
+        let (a, b) = (&self.vec_arena[a.handle_a()], &self.vec_arena[b.handle_a()]);
+        self.distance_metric.calculate_raw(
+            a,
+            dot_product_f32(&a.vec, &a.vec),
+            b,
+            dot_product_f32(&b.vec, &b.vec),
+        )
+    }
+
+    // With the `debug-checks` feature, recompute the scores in a freshly
+    // linked node's neighbor lists from the full-precision vectors and assert
+    // they agree with the stored ones, so encoder and kernel mismatches show up
+    // at the insert that exposes them rather than as lost recall.
+    #[cfg(all(feature = "debug-checks", debug_assertions))]
+    fn check_link_scores(&self, vec_handle: VecHandle) {
+        let _pin = self.pin();
+        let meta = self.meta(vec_handle);
+        let mut node = meta.top();
+        for _ in 0..meta.level() {
+            let node_ref = &self.nodes_arena[node];
+            for neighbor in node_ref.neighbors.read().neighbors() {
+                let other = self.nodes_arena[neighbor.node()].vec;
+                self.check_link_score(vec_handle, other, neighbor.score(&self.distance_metric));
+            }
+            node = node_ref.child;
+        }
+        let node0 = &self.nodes0_arena[node.cast::<Node0>()];
+        for neighbor in node0.neighbors.read().neighbors() {
+            let other = self.nodes0_arena[neighbor.node()].vec;
+            self.check_link_score(vec_handle, other, neighbor.score(&self.distance_metric));
+        }
+    }
+
+    #[cfg(all(feature = "debug-checks", debug_assertions))]
+    fn check_link_score(&self, a: VecHandle, b: VecHandle, stored: f32) {
+        // the root's zero vector has no meaningful score, clamped components
+        // fall outside the quantization error bound, a node still being
+        // inserted hasn't recorded its clamping yet, and a tombstoned slot may
+        // be in the middle of being rewritten
+        let meta_b = self.meta(b);
+        if *b == 0
+            || !meta_b.is_ready()
+            || meta_b.is_tombstoned()
+            || self.meta(a).clipped() > 0
+            || meta_b.clipped() > 0
+        {
+            return;
+        }
+        let (Some(raw_a), Some(raw_b)) = (self.raw_vec(a.handle_a()), self.raw_vec(b.handle_a()))
+        else {
+            return;
+        };
+        let mag_a = dot_product_f32(&raw_a.vec, &raw_a.vec);
+        let mag_b = dot_product_f32(&raw_b.vec, &raw_b.vec);
+        let raw = self
+            .distance_metric
+            .calculate_raw(raw_a, mag_a, raw_b, mag_b);
+        let error = if self.link_full_precision {
+            Some((1.0, 0.0))
+        } else {
+            self.distance_metric
+                .quantization_error(raw_a, mag_a, raw_b, mag_b)
+        };
+        let Some((scale, bound)) = error else {
+            return;
+        };
+        // clamped to the range of f16
+        #[cfg(feature = "f16-scores")]
+        if raw.abs() >= crate::node::MAX_STORED_SCORE {
+            return;
+        }
+        let stored = stored / scale;
+        // rounding in the kernels, and in f16 with the `f16-scores` feature
+        let slack = 1e-3 * (raw.abs() + stored.abs()) + 1e-5;
+        debug_assert!(
+            (stored - raw).abs() <= bound + slack,
+            "link score between nodes {} and {} is {stored}, full precision gives {raw}",
+            NodeId::from_vec_handle(a).0,
+            NodeId::from_vec_handle(b).0,
+        );
+    }
+
+    /// Break the full-precision score between `query` and a stored vector down
+    /// into one term per dimension, e.g. the scaled elementwise products for
+    /// cosine
+    ///
+    /// The terms sum to the score used when reranking. Returns `None` if `id`
+    /// doesn't refer to an indexed node, its full-precision copy isn't kept, or
+    /// the metric doesn't break down per dimension (the distance metrics).
+    pub fn explain_pair(&self, query: &[f32], id: NodeId) -> Option<Box<[f32]>> {
+        assert_eq!(query.len(), self.dims as usize, "query dimension mismatch");
+        let _pin = self.pin();
+        let vec = self.raw_vec(self.live_vec(id)?.handle_a())?;
+        let mag_query = dot_product_f32(query, query);
+        let mag_vec = dot_product_f32(&vec.vec, &vec.vec);
+        let query = unsafe { mem::transmute::<&[f32], &RawVec>(query) };
+        self.distance_metric
+            .contributions_raw(query, mag_query, vec, mag_vec)
+    }
+
+    fn search_level(
+        &self,
+        entry_nodes: &[NodeHandle],
+        query: &QuantVec,
+        ef: u16,
+        top_k: u16,
+        include_root: bool,
+        furthest: bool,
+    ) -> Box<[InternalSearchResult<Node>]> {
+        let mut context = self.take_context();
+        self.search_level_in(
+            &mut context,
+            entry_nodes,
+            query,
+            ef,
+            top_k,
+            include_root,
+            furthest,
+        );
+        let results = mem::take(&mut context.upper_results).into_boxed_slice();
+        self.return_context(context);
+        results
+    }
+
+    // `search_level` with the working memory of `context`, leaving the results
+    // in `context.upper_results`.
+    #[allow(clippy::too_many_arguments)]
+    fn search_level_in(
+        &self,
+        context: &mut SearchContext,
+        entry_nodes: &[NodeHandle],
+        query: &QuantVec,
+        ef: u16,
+        top_k: u16,
+        include_root: bool,
+        furthest: bool,
+    ) {
+        let SearchContext {
+            visited: set,
+            upper_queue: queue,
+            upper_results: results,
+            ..
+        } = context;
+        results.clear();
+        let mut candidate_queue = BinaryHeap::from_vec_cmp(
+            mem::take(queue),
+            FnComparator(
+                |a: &InternalSearchResult<Node>, b: &InternalSearchResult<Node>| {
+                    self.distance_metric.cmp_score(a.score, b.score)
+                },
+            ),
+        );
+
+        for &entry_node in entry_nodes {
+            if set.insert(*entry_node) {
+                let vec = &self.vec_arena[self.nodes_arena[entry_node].vec.handle_b()];
+                candidate_queue.push(InternalSearchResult {
+                    node: entry_node,
+                    score: self.search_score(query, vec, furthest),
+                });
+            }
+        }
+
+        let mut nodes_visisted = 0;
+
+        while let Some(entry) = candidate_queue.pop() {
+            if nodes_visisted >= ef {
+                break;
+            }
+
+            nodes_visisted += 1;
+            if include_root || *entry.node != 0 {
+                results.push(entry);
+            }
+
+            let node = &self.nodes_arena[entry.node];
+
+            for neighbor in self.read_list(&node.neighbors).neighbors() {
+                if !set.contains(*neighbor.node()) {
+                    let neighbor_node = &self.nodes_arena[neighbor.node()];
+                    if self.is_reclaiming(neighbor_node.vec) {
+                        continue;
+                    }
+                    let neighbor_vec = &self.vec_arena[neighbor_node.vec.handle_b()];
+                    let score = self.search_score(query, neighbor_vec, furthest);
+
+                    set.insert(*neighbor.node());
+                    candidate_queue.push(InternalSearchResult {
+                        node: neighbor.node(),
+                        score,
+                    });
+                }
+            }
+        }
+        set.clear();
+        *queue = candidate_queue.into_vec();
+        queue.clear();
+
+        let top_k = top_k as usize;
+
+        if results.len() > top_k {
+            results.select_nth_unstable_by(top_k, |a, b| {
+                self.distance_metric.cmp_score(b.score, a.score)
+            });
+            results.truncate(top_k);
+        }
+
+        // best first
+        results.sort_unstable_by(|a, b| self.distance_metric.cmp_score(b.score, a.score));
+    }
+
+    fn search_level0(
+        &self,
+        entry_nodes: &[Node0Handle],
+        query: &QuantVec,
+        ef: u16,
+        top_k: u16,
+        include_root: bool,
+    ) -> Box<[InternalSearchResult<Node0>]> {
+        self.search_level0_adaptive(
+            entry_nodes,
+            query,
+            ef,
+            ef,
+            top_k,
+            include_root,
+            false,
+            None,
+            None,
+        )
+    }
+
+    // Starts with `ef` and doubles it, resuming from the previous round's visited
+    // set and candidates, until a round leaves the top-k set unchanged or `max_ef`
+    // is reached. Nodes failing `filter` are traversed but not returned, and
+    // with a filter a round expands nodes until `ef` of them pass. Adds the
+    // number of expanded nodes to `nodes_visited`, if given.
+    #[allow(clippy::too_many_arguments)]
+    fn search_level0_adaptive(
+        &self,
+        entry_nodes: &[Node0Handle],
+        query: &QuantVec,
+        ef: u16,
+        max_ef: u16,
+        top_k: u16,
+        include_root: bool,
+        furthest: bool,
+        filter: Option<&dyn Fn(NodeId) -> bool>,
+        nodes_visited: Option<&mut u32>,
+    ) -> Box<[InternalSearchResult<Node0>]> {
+        let mut context = self.take_context();
+        self.search_level0_in(
+            &mut context,
+            entry_nodes,
+            query,
+            ef,
+            max_ef,
+            top_k,
+            include_root,
+            furthest,
+            filter,
+            nodes_visited,
+        );
+        let results = mem::take(&mut context.results).into_boxed_slice();
+        self.return_context(context);
+        results
+    }
+
+    // `search_level0_adaptive` with the working memory of `context`, leaving
+    // the results in `context.results`.
+    #[allow(clippy::too_many_arguments)]
+    fn search_level0_in(
+        &self,
+        context: &mut SearchContext,
+        entry_nodes: &[Node0Handle],
+        query: &QuantVec,
+        ef: u16,
+        max_ef: u16,
+        top_k: u16,
+        include_root: bool,
+        furthest: bool,
+        filter: Option<&dyn Fn(NodeId) -> bool>,
+        nodes_visited: Option<&mut u32>,
+    ) {
+        let SearchContext {
+            visited: set,
+            queue,
+            results,
+            ..
+        } = context;
+        results.clear();
+        let mut candidate_queue = BinaryHeap::from_vec_cmp(
+            mem::take(queue),
+            FnComparator(
+                |a: &InternalSearchResult<Node0>, b: &InternalSearchResult<Node0>| {
+                    self.distance_metric.cmp_score(a.score, b.score)
+                },
+            ),
+        );
+
+        let mut enter = |node: Node0Handle| {
+            if set.insert(*node) {
+                let vec = &self.vec_arena[self.nodes0_arena[node].vec.handle_b()];
+                candidate_queue.push(InternalSearchResult {
+                    node,
+                    score: self.search_score(query, vec, furthest),
+                });
+            }
+        };
+        for &node in entry_nodes {
+            enter(node);
+        }
+        if self.levels == 0 {
+            for node in self.flat_entry_points(query) {
+                enter(node);
+            }
+        }
+
+        let mut nodes_visisted = 0;
+        let mut ef = ef;
+        let mut previous_top_k = Vec::new();
+        // expired nodes are still traversed, and only returned to inserts
+        let now = if include_root { None } else { self.now() };
+
+        loop {
+            loop {
+                let expanded = if filter.is_some() {
+                    results.len()
+                } else {
+                    nodes_visisted
+                };
+                if expanded >= ef as usize {
+                    break;
+                }
+                let Some(entry) = candidate_queue.pop() else {
+                    break;
+                };
+
+                nodes_visisted += 1;
+                let node = &self.nodes0_arena[entry.node];
+                if include_root
+                    || (*entry.node != 0
+                        && !self.is_expired(node.vec, now)
+                        && filter.is_none_or(|filter| filter(NodeId::from_vec_handle(node.vec))))
+                {
+                    results.push(entry);
+                }
+
+                for neighbor in self.read_list(&node.neighbors).neighbors() {
+                    if !set.contains(*neighbor.node()) {
+                        let neighbor_node = &self.nodes0_arena[neighbor.node()];
+                        if self.is_reclaiming(neighbor_node.vec) {
+                            continue;
+                        }
+                        let neighbor_vec = &self.vec_arena[neighbor_node.vec.handle_b()];
+                        let score = self.search_score(query, neighbor_vec, furthest);
+
+                        set.insert(*neighbor.node());
+                        candidate_queue.push(InternalSearchResult {
+                            node: neighbor.node(),
+                            score,
+                        });
+                    }
+                }
+            }
+
+            if ef >= max_ef || candidate_queue.is_empty() {
+                break;
+            }
+
+            #[cfg(feature = "tracing")]
+            tracing::trace!(ef, nodes_visited = nodes_visisted, "adaptive round");
+
+            let current_top_k = self.top_k_handles(results, top_k as usize);
+            if current_top_k == previous_top_k {
+                break;
+            }
+            previous_top_k = current_top_k;
+            ef = ef.saturating_mul(2).min(max_ef);
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::trace!(ef, nodes_visited = nodes_visisted, "searched level 0");
+        set.clear();
+        *queue = candidate_queue.into_vec();
+        queue.clear();
+        if let Some(nodes_visited) = nodes_visited {
+            *nodes_visited += nodes_visisted as u32;
+        }
+
+        let top_k = top_k as usize;
+
+        if results.len() > top_k {
+            results.select_nth_unstable_by(top_k, |a, b| {
+                self.distance_metric.cmp_score(b.score, a.score)
+            });
+            results.truncate(top_k);
+        }
+
+        // best first
+        results.sort_unstable_by(|a, b| self.distance_metric.cmp_score(b.score, a.score));
+    }
+
+    // Score every live node, best first, for searches that ask for at least as
+    // many results as there are slots: traversal may miss nodes no list links
+    // to, and the scan costs no more than visiting every node.
+    fn scan_level0(&self, query: &QuantVec, top_k: u16) -> Box<[SearchResult]> {
+        let now = self.now();
+        let mut results = Vec::new();
+        // slot 0 holds the synthetic root
+        for index in 1..self.vec_arena.len() as u32 {
+            let vec_handle = VecHandle::new(index);
+            let Some(meta) = self.meta_arena.get(NodeMetaHandle::new(index)) else {
+                continue;
+            };
+            if !meta.is_ready() || meta.is_tombstoned() || self.is_expired(vec_handle, now) {
+                continue;
+            }
+            results.push(SearchResult {
+                node: NodeId::from_vec_handle(vec_handle),
+                score: self
+                    .distance_metric
+                    .calculate(query, &self.vec_arena[vec_handle.handle_b()]),
+            });
+        }
+
+        results.sort_unstable_by(|a, b| self.distance_metric.cmp_score(b.score, a.score));
+        results.truncate(top_k as usize);
+        results.into_boxed_slice()
+    }
+
+    // The score level searches rank by: the metric's, or its negation when
+    // looking for the furthest nodes, which reverses every comparison.
+    #[inline]
+    fn search_score(&self, query: &QuantVec, vec: &QuantVec, furthest: bool) -> f32 {
+        let score = self.distance_metric.calculate(query, vec);
+        if furthest { -score } else { score }
+    }
+
+    // Extra level 0 entry points for a flat graph, which has no upper levels to
+    // find one close to the query. Picked pseudo-randomly from the query, so that
+    // repeated queries search alike.
+    fn flat_entry_points(&self, query: &QuantVec) -> impl Iterator<Item = Node0Handle> + '_ {
+        let len = self.vec_arena.len() as u64;
+        let seed = hash_bytes(query.as_unsigned_byte());
+        (0..FLAT_ENTRY_POINTS).filter_map(move |i| {
+            // slot 0 holds the synthetic root, the usual entry point
+            if len <= 1 {
+                return None;
+            }
+            let mixed = seed.wrapping_add(i).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+            let index = 1 + ((mixed ^ (mixed >> 32)) % (len - 1)) as u32;
+            let meta = self.meta_arena.get(NodeMetaHandle::new(index))?;
+            (meta.is_ready() && !meta.is_tombstoned()).then(|| meta.top().cast())
+        })
+    }
+
+    // A cleared visited set from the pool, so searches don't allocate and zero
+    // one sized for the whole graph each time.
+    fn take_visited(&self) -> VisitedSet {
+        self.visited_pool.lock().pop().unwrap_or_default()
+    }
+
+    fn return_visited(&self, mut set: VisitedSet) {
+        set.clear();
+        self.visited_pool.lock().push(set);
+    }
+
+    // Working memory for a search made without a caller's context, around a
+    // visited set from the pool.
+    fn take_context(&self) -> SearchContext {
+        SearchContext {
+            visited: self.take_visited(),
+            ..SearchContext::new()
+        }
+    }
+
+    fn return_context(&self, context: SearchContext) {
+        self.return_visited(context.visited);
+    }
+
+    // Handles of the best `top_k` results, sorted by handle.
+    fn top_k_handles(&self, results: &[InternalSearchResult<Node0>], top_k: usize) -> Vec<u32> {
+        let mut results = results.to_vec();
+        if results.len() > top_k {
+            results.select_nth_unstable_by(top_k, |a, b| {
+                self.distance_metric.cmp_score(b.score, a.score)
+            });
+            results.truncate(top_k);
+        }
+
+        let mut handles: Vec<u32> = results.iter().map(|result| *result.node).collect();
+        handles.sort_unstable();
+        handles
+    }
+}
+
+impl Drop for Graph {
+    fn drop(&mut self) {
+        self.meta_arena.clear(self.vec_arena.len() as u32);
+        if let Some(expiry_arena) = &mut self.expiry_arena {
+            expiry_arena.clear(self.vec_arena.len() as u32);
+        }
+        if let Some(ingest_arena) = &mut self.ingest_arena {
+            ingest_arena.clear(self.vec_arena.len() as u32);
+        }
+        if let Some(prior_arena) = &mut self.prior_arena {
+            prior_arena.clear(self.vec_arena.len() as u32);
+        }
+    }
+}
+
+// The bits of `dims` components, lowest bit of each byte first, as the u64
+// words of a `Binary` vector in native byte order.
+fn pack_bits(bytes: &[u8], dims: u16) -> Vec<u8> {
+    let mut words: Vec<u64> = bytes
+        .chunks(8)
+        .map(|chunk| {
+            let mut word = [0; 8];
+            word[..chunk.len()].copy_from_slice(chunk);
+            u64::from_le_bytes(word)
+        })
+        .collect();
+    // bits past the last dimension stay clear, as when quantizing
+    if let Some(last) = words.last_mut()
+        && !dims.is_multiple_of(64)
+    {
+        *last &= (1 << (dims % 64)) - 1;
+    }
+    words.iter().flat_map(|word| word.to_ne_bytes()).collect()
+}
+
+fn read_neighbor_order(input: &mut Reader) -> Result<NeighborOrder, DeserializeError> {
+    match input.u8()? {
+        0 => Ok(NeighborOrder::Unordered),
+        1 => Ok(NeighborOrder::ByScore),
+        2 => Ok(NeighborOrder::ByHandle),
+        _ => Err(DeserializeError::Corrupt),
+    }
+}
+
+// Number of neighbors a list with this header holds, like `Neighbors::neighbors`.
+fn restored_len(full: bool, lowest_index: u16, capacity: u16) -> Result<usize, DeserializeError> {
+    match (full, lowest_index) {
+        (true, lowest_index) if lowest_index < capacity => Ok(capacity as usize),
+        (false, len) if len < capacity => Ok(len as usize),
+        _ => Err(DeserializeError::Corrupt),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{builder::MAX_NEIGHBORS, params::ResultOrder};
+
+    fn unit_vec(dims: u16, i: u32) -> Vec<f32> {
+        let mut vec = alloc::vec![0.0; dims as usize];
+        vec[i as usize % dims as usize] = 1.0;
+        vec[(i as usize + 1) % dims as usize] = (i % 7) as f32 / 7.0;
+        vec
+    }
+
+    #[test]
+    fn distance_matches_metric() {
+        let graph = GraphBuilder::new().m(4).m0(8).dims(16).levels(2).build();
+
+        let a = graph.index(&unit_vec(16, 0), 16);
+        let b = graph.index(&unit_vec(16, 1), 16);
+        let c = graph.index(&unit_vec(16, 8), 16);
+
+        assert_eq!(graph.distance(a, b), graph.distance(b, a));
+        assert_eq!(graph.distance(a, c), 0.0);
+        assert_eq!(graph.distance_to(b, &unit_vec(16, 1)), graph.distance(b, b));
+        assert_eq!(graph.checked_distance(a, b), Some(graph.distance(a, b)));
+        assert_eq!(
+            graph.distance_to_query(b, &unit_vec(16, 1)),
+            Some(graph.distance(b, b))
+        );
+        assert_eq!(graph.checked_distance(a, NodeId(3)), None);
+        assert_eq!(graph.distance_to_query(NodeId(3), &unit_vec(16, 1)), None);
+        assert!(graph.distance(a, NodeId(3)).is_nan());
+    }
+
+    #[test]
+    fn largest_neighbor_lists_build() {
+        let graph = GraphBuilder::new()
+            .dims(4)
+            .m(MAX_NEIGHBORS)
+            .m0(MAX_NEIGHBORS)
+            .levels(1)
+            .build();
+        let id = graph.index(&[1.0, 0.5, 0.0, 0.0], 16);
+        assert_eq!(graph.search(&[1.0, 0.5, 0.0, 0.0], 16, 1)[0].node, id);
+    }
+
+    #[test]
+    #[should_panic(expected = "m0 must be at most 32768")]
+    fn oversized_neighbor_lists_are_rejected() {
+        GraphBuilder::new().dims(4).m0(MAX_NEIGHBORS + 1).build();
+    }
+
+    #[test]
+    #[should_panic(expected = "m0 must be positive")]
+    fn empty_neighbor_lists_are_rejected() {
+        GraphBuilder::new().dims(16).m0(0).build();
+    }
+
+    #[test]
+    fn post_filter_refills_until_top_k() {
+        let graph = GraphBuilder::new().m(4).m0(8).dims(16).levels(2).build();
+        for i in 0..64 {
+            graph.index(&unit_vec(16, i), 16);
+        }
+
+        let query = unit_vec(16, 0);
+        let params = SearchParams::new(4, 4).post_filter(|id, _| {
+            if id.0 % 8 == 0 {
+                FilterDecision::Accept
+            } else {
+                FilterDecision::Reject
+            }
+        });
+        let results = graph.search_with_params(&query, &params);
+        assert_eq!(results.len(), 4);
+        assert!(results.iter().all(|result| result.node.0 % 8 == 0));
+
+        let offered = core::cell::Cell::new(0);
+        let params = SearchParams::new(16, 4).post_filter(|_, _| {
+            offered.set(offered.get() + 1);
+            FilterDecision::Stop
+        });
+        assert!(graph.search_with_params(&query, &params).is_empty());
+        assert_eq!(offered.get(), 1);
+    }
+
+    #[test]
+    fn max_per_group_refills() {
+        let graph = GraphBuilder::new().m(4).m0(8).dims(16).levels(2).build();
+        for i in 0..64 {
+            graph.index(&unit_vec(16, i % 16), 16);
+        }
+
+        // all copies of a vector share a group, so only one per group survives
+        let query = unit_vec(16, 3);
+        let params = SearchParams::new(4, 6).max_per_group(|id| (id.0 % 16) as u64, 1);
+        let results = graph.search_with_params(&query, &params);
+        assert_eq!(results.len(), 6);
+        assert_eq!(results[0].node.0 % 16, 3);
+        let mut groups: Vec<_> = results.iter().map(|result| result.node.0 % 16).collect();
+        groups.sort();
+        groups.dedup();
+        assert_eq!(groups.len(), 6);
+    }
+
+    #[test]
+    fn expired_nodes_are_hidden_then_purged() {
+        use alloc::sync::Arc;
+
+        let time = Arc::new(AtomicU64::new(0));
+        let graph = {
+            let time = time.clone();
+            GraphBuilder::new()
+                .dims(16)
+                .clock(move || time.load(AtomicOrdering::Relaxed))
+                .build()
+        };
+
+        for i in 0..8 {
+            graph.index(&unit_vec(16, i), 16);
+        }
+        let stale = graph.index_with_ttl(&unit_vec(16, 14), 10, 16);
+        let query = unit_vec(16, 14);
+        assert_eq!(graph.search(&query, 16, 1)[0].node, stale);
+
+        time.store(10, AtomicOrdering::Relaxed);
+        assert!(graph.search(&query, 16, 9).iter().all(|r| r.node != stale));
+        assert_eq!(
+            graph.distance_to(stale, &query),
+            graph.distance(stale, stale)
+        );
+
+        assert_eq!(graph.purge_expired(), 1);
+        assert_eq!(graph.purge_expired(), 0);
+        assert!(graph.distance_to(stale, &query).is_nan());
+
+        // the purged slot is reused
+        let fresh = graph.index_with_ttl(&unit_vec(16, 21), 10, 16);
+        assert_eq!(fresh, stale);
+        assert_eq!(graph.search(&unit_vec(16, 21), 16, 1)[0].node, fresh);
+        assert_eq!(graph.vec_arena.len(), 10);
+    }
+
+    #[test]
+    fn cancelled_operations_stop_cleanly() {
+        use alloc::sync::Arc;
+
+        let time = Arc::new(AtomicU64::new(0));
+        let graph = {
+            let time = time.clone();
+            GraphBuilder::new()
+                .dims(16)
+                .clock(move || time.load(AtomicOrdering::Relaxed))
+                .build()
+        };
+        let vecs: Vec<Vec<f32>> = (0..8).map(|i| unit_vec(16, i)).collect();
+        let vecs: Vec<&[f32]> = vecs.iter().map(Vec::as_slice).collect();
+        for vec in &vecs {
+            graph.index_with_ttl(vec, 5, 16);
+        }
+        time.store(5, AtomicOrdering::Relaxed);
+
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        assert_eq!(
+            graph.try_index_batch_cancellable(&vecs, 16, &cancel),
+            Err(VectorDbError::Cancelled)
+        );
+        assert_eq!(graph.len(), 8);
+        assert_eq!(
+            graph.serialize_cancellable(&cancel).unwrap_err(),
+            VectorDbError::Cancelled
+        );
+        assert_eq!(graph.purge_expired_cancellable(&cancel), 0);
+
+        // the graph is untouched and the work can be redone
+        assert_eq!(graph.purge_expired(), 8);
+        assert!(Graph::deserialize(&graph.serialize()).is_ok());
+    }
+
+    #[test]
+    fn ingests_are_recorded() {
+        use alloc::sync::Arc;
+
+        let time = Arc::new(AtomicU64::new(5));
+        let graph = {
+            let time = time.clone();
+            GraphBuilder::new()
+                .dims(16)
+                .record_ingest(true)
+                .clock(move || time.load(AtomicOrdering::Relaxed))
+                .build()
+        };
+
+        let untagged = graph.index(&unit_vec(16, 0), 16);
+        time.store(u64::MAX, AtomicOrdering::Relaxed);
+        let tagged = graph.index_with_source(&unit_vec(16, 1), 7, 16);
+        assert_eq!(graph.ingest(untagged), Some(Ingest { at: 5, source: 0 }));
+        assert_eq!(
+            graph.ingest(tagged),
+            Some(Ingest {
+                at: u32::MAX,
+                source: 7
+            })
+        );
+        assert!(graph.delete(tagged));
+        assert_eq!(graph.ingest(tagged), None);
+
+        let graph = GraphBuilder::new().dims(16).build();
+        let id = graph.index_with_source(&unit_vec(16, 0), 7, 16);
+        assert_eq!(graph.ingest(id), None);
+    }
+
+    #[test]
+    fn explain_pair_sums_to_score() {
+        let graph = GraphBuilder::new()
+            .dims(16)
+            .quantization(Quantization::SignedByte)
+            .build();
+        for i in 0..16 {
+            graph.index(&unit_vec(16, i), 16);
+        }
+
+        let query = unit_vec(16, 3);
+        let result = graph.search(&query, 16, 1)[0];
+        let terms = graph.explain_pair(&query, result.node).unwrap();
+        assert_eq!(terms.len(), 16);
+        assert!((terms.iter().sum::<f32>() - result.score).abs() < 1e-6);
+        assert!(
+            terms
+                .iter()
+                .enumerate()
+                .all(|(i, term)| (i == 3 || i == 4) || *term == 0.0)
+        );
+        assert!(graph.explain_pair(&query, NodeId(16)).is_none());
+
+        let graph = GraphBuilder::new()
+            .dims(16)
+            .quantization(Quantization::SignedByte)
+            .rerank(false)
+            .build();
+        let id = graph.index(&query, 16);
+        assert!(graph.explain_pair(&query, id).is_none());
+    }
+
+    #[test]
+    fn empty_graph_searches_return_nothing() {
+        for levels in [0, 4] {
+            let graph = GraphBuilder::new()
+                .dims(16)
+                .levels(levels)
+                .quantization(Quantization::SignedByte)
+                .build();
+            let query = unit_vec(16, 3);
+            assert!(graph.search(&query, 16, 4).is_empty());
+            assert!(graph.search_quantized(&query, 16, 4).is_empty());
+            assert!(graph.search_furthest(&query, 16, 4).is_empty());
+            assert!(
+                graph
+                    .search_with_params(&query, &SearchParams::new(16, 4))
+                    .is_empty()
+            );
+            assert!(graph.search_batch(&[&query], 16, 4)[0].is_empty());
+            let mut out = Vec::new();
+            assert_eq!(
+                graph.search_into(&query, &SearchParams::new(16, 4), &mut out),
+                0
+            );
+            let mut context = SearchContext::new();
+            assert_eq!(
+                graph.search_with_context(
+                    &mut context,
+                    &query,
+                    &SearchParams::new(16, 4),
+                    &mut out
+                ),
+                0
+            );
+            let outcome = graph.search_with_outcome(&query, &SearchParams::new(16, 4));
+            assert!(outcome.results.is_empty());
+            assert!(graph.search_filtered(&query, 16, 4, |_| true).is_empty());
+            let params = SearchParams::new(16, 4);
+            assert!(graph.search_batch_shared(&[&query], &params)[0].is_empty());
+
+            let id = graph.index(&query, 16);
+            assert_eq!(graph.search(&query, 16, 4)[0].node, id);
+            assert!(graph.delete(id));
+            assert!(graph.search(&query, 16, 4).is_empty());
+        }
+    }
+
+    #[test]
+    fn ids_follow_insertion_order() {
+        extern crate std;
+
+        let graph = GraphBuilder::new().dims(16).build();
+        assert!(graph.is_empty());
+        for i in 0..32 {
+            assert_eq!(graph.index(&unit_vec(16, i), 16), NodeId(i));
+            assert_eq!(graph.len(), i as usize + 1);
+        }
+
+        let mut ids: Vec<_> = std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..4)
+                .map(|t| {
+                    let graph = &graph;
+                    scope.spawn(move || {
+                        (0..16)
+                            .map(|i| graph.index(&unit_vec(16, t * 16 + i), 16))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            threads
+                .into_iter()
+                .flat_map(|thread| {
+                    // each thread sees its own ids increase
+                    let ids = thread.join().unwrap();
+                    assert!(ids.is_sorted());
+                    ids
+                })
+                .collect()
+        });
+        ids.sort();
+        assert!(ids.iter().zip(32..).all(|(id, i)| *id == NodeId(i)));
+        assert_eq!(graph.len(), 96);
+    }
+
+    #[test]
+    fn full_precision_linking_stores_raw_scores() {
+        let graph = GraphBuilder::new()
+            .m(4)
+            .m0(8)
+            .dims(16)
+            .levels(2)
+            .quantization(Quantization::SignedByte)
+            .full_precision_linking(true)
+            .build();
+        for i in 0..64 {
+            graph.index(&unit_vec(16, i), 16);
+        }
+
+        let raw_score = |a: VecHandle, b: VecHandle| {
+            let (a, b) = (
+                &graph.vec_arena[a.handle_a()],
+                &graph.vec_arena[b.handle_a()],
+            );
+            let score = graph.distance_metric.calculate_raw(
+                a,
+                dot_product_f32(&a.vec, &a.vec),
+                b,
+                dot_product_f32(&b.vec, &b.vec),
+            );
+            // as rounded by the neighbor list
+            Neighbor0::new(&graph.distance_metric, Node0Handle::new(0), score)
+                .score(&graph.distance_metric)
+        };
+        for i in 0..graph.nodes0_arena.len() as u32 {
+            let node = &graph.nodes0_arena[Node0Handle::new(i)];
+            for neighbor in node.neighbors.read().neighbors() {
+                let other = graph.nodes0_arena[neighbor.node()].vec;
+                assert_eq!(
+                    neighbor.score(&graph.distance_metric),
+                    raw_score(node.vec, other)
+                );
+            }
+        }
+
+        let query = unit_vec(16, 14);
+        assert_eq!(graph.search(&query, 16, 1)[0].node, NodeId(14));
+    }
+
+    #[test]
+    fn heuristic_selection_links_across_clusters() {
+        let linked = |selection| {
+            let graph = GraphBuilder::new()
+                .m0(4)
+                .dims(2)
+                .levels(0)
+                .metric(DistanceMetricKind::Euclidean)
+                .neighbor_selection(selection)
+                .build();
+            let far = graph.index(&[0.0, 10.0], 32);
+            for i in 0..8 {
+                graph.index(&[10.0, 1.0 + i as f32 * 0.01], 32);
+            }
+            let id = graph.index(&[5.0, 4.0], 32);
+
+            let node: Node0Handle = graph.meta(id.to_vec_handle()).top().cast();
+            let neighbors = graph.nodes0_arena[node].neighbors.read();
+            neighbors
+                .neighbors()
+                .iter()
+                .any(|neighbor| graph.nodes0_arena[neighbor.node()].vec == far.to_vec_handle())
+        };
+
+        // the cluster is nearer, and fills every slot of a plain list
+        assert!(!linked(NeighborSelection::Simple));
+        assert!(linked(NeighborSelection::Heuristic));
+    }
+
+    #[test]
+    fn recompute_scores_restores_neighbor_lists() {
+        let mut graph = GraphBuilder::new().m(4).m0(8).dims(16).levels(2).build();
+        for i in 0..64 {
+            graph.index(&unit_vec(16, i), 16);
+        }
+        graph.reorder_neighbors(NeighborOrder::ByScore);
+
+        let snapshot = |graph: &Graph| -> Vec<(u32, f32, u16)> {
+            (0..graph.nodes0_arena.len() as u32)
+                .flat_map(|i| {
+                    let neighbors = graph.nodes0_arena[Node0Handle::new(i)].neighbors.read();
+                    let lowest = neighbors.lowest_index;
+                    neighbors
+                        .neighbors()
+                        .iter()
+                        .map(|neighbor| {
+                            (
+                                *neighbor.node(),
+                                neighbor.score(&graph.distance_metric),
+                                lowest,
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .collect()
+        };
+        let before = snapshot(&graph);
+
+        // as if the scores were computed by other kernels
+        for i in 0..graph.nodes0_arena.len() as u32 {
+            let node = &graph.nodes0_arena[Node0Handle::new(i)];
+            let mut neighbors = node.neighbors.write();
+            let len = neighbors.neighbors().len();
+            for (j, neighbor) in neighbors.neighbors[..len].iter_mut().enumerate() {
+                *neighbor = Neighbor0::new(&graph.distance_metric, neighbor.node(), -(j as f32));
+            }
+        }
+
+        graph.recompute_scores();
+        assert_eq!(snapshot(&graph), before);
+    }
+
+    #[test]
+    fn stored_vectors_read_back() {
+        let graph = GraphBuilder::new()
+            .dims(16)
+            .quantization(Quantization::SignedByte)
+            .build();
+        for i in 0..8 {
+            graph.index(&unit_vec(16, i), 16);
+        }
+
+        let vec = unit_vec(16, 3);
+        assert_eq!(&*graph.get_vector(NodeId(3)).unwrap(), &vec[..]);
+        let quantized = graph.get_quantized(NodeId(3)).unwrap();
+        assert_eq!(quantized.magnitude(), dot_product_f32(&vec, &vec));
+        let mut dequantized = alloc::vec![0.0; 16];
+        quantized.dequantize_into(Quantization::SignedByte, &mut dequantized);
+        assert!(
+            dequantized
+                .iter()
+                .zip(&vec)
+                .all(|(a, b)| (a - b).abs() < 0.01)
+        );
+        drop(quantized);
+
+        assert!(graph.get_vector(NodeId(8)).is_none());
+        assert!(graph.delete(NodeId(3)));
+        assert!(graph.get_vector(NodeId(3)).is_none());
+        assert!(graph.get_quantized(NodeId(3)).is_none());
+
+        // without full-precision copies only the quantized vector is kept
+        let graph = GraphBuilder::new()
+            .dims(16)
+            .quantization(Quantization::SignedByte)
+            .rerank(false)
+            .build();
+        let id = graph.index(&vec, 16);
+        assert!(graph.get_vector(id).is_none());
+        assert!(graph.get_quantized(id).is_some());
+    }
+
+    #[test]
+    fn export_vectors_in_id_order() {
+        let graph = GraphBuilder::new()
+            .dims(16)
+            .quantization(Quantization::SignedByte)
+            .build();
+        for i in 0..8 {
+            graph.index(&unit_vec(16, i), 16);
+        }
+
+        let mut out = alloc::vec![f32::NAN; 16 * 4];
+        assert_eq!(graph.export_vectors(2..6, &mut out), 4);
+        assert_eq!(&out[..16], &unit_vec(16, 2)[..]);
+        assert_eq!(&out[48..], &unit_vec(16, 5)[..]);
+
+        // clamped to the graph and to the buffer
+        assert_eq!(graph.export_vectors(6..100, &mut out), 2);
+        assert_eq!(graph.export_vectors(0..8, &mut out[..40]), 2);
+
+        // without full-precision copies the vectors are dequantized
+        let graph = GraphBuilder::new()
+            .dims(16)
+            .quantization(Quantization::SignedByte)
+            .rerank(false)
+            .build();
+        graph.index(&unit_vec(16, 3), 16);
+        assert_eq!(graph.export_vectors(0..1, &mut out), 1);
+        let expected = unit_vec(16, 3);
+        assert!(
+            out[..16]
+                .iter()
+                .zip(&expected)
+                .all(|(a, b)| (a - b).abs() < 1.0 / 127.0)
+        );
+    }
+
+    #[test]
+    fn search_into_matches_search() {
+        let graph = GraphBuilder::new().dims(16).build();
+        for i in 0..64 {
+            graph.index(&unit_vec(16, i), 32);
+        }
+
+        let query = unit_vec(16, 5);
+        let params = SearchParams::new(32, 8);
+        let expected = graph.search_with_params(&query, &params);
+
+        let mut out = [SearchResult {
+            node: NodeId(u32::MAX),
+            score: 0.0,
+        }; 4];
+        let written = graph.search_into(&query, &params, &mut out);
+        assert_eq!(written, 4);
+        for (result, expected) in out.iter().zip(&expected[..]) {
+            assert_eq!(result.node, expected.node);
+            assert_eq!(result.score, expected.score);
+        }
+
+        // a larger buffer gets top_k results
+        let mut out = [out[0]; 16];
+        assert_eq!(graph.search_into(&query, &params, &mut out), 8);
+        for (result, expected) in out.iter().zip(&expected[..]) {
+            assert_eq!(result.node, expected.node);
+        }
+    }
+
+    #[test]
+    fn provenance_path_is_upper_level_chain() {
+        let graph = GraphBuilder::new().dims(16).build();
+        for i in 0..256 {
+            graph.index(&unit_vec(16, i), 32);
+        }
+
+        let query = unit_vec(16, 3);
+        let params = SearchParams::new(32, 4);
+        let traced = graph.search_with_provenance(&query, &params);
+        let plain = graph.search_with_params(&query, &params);
+
+        assert_eq!(traced.results.len(), plain.len());
+        assert!(!traced.path.is_empty());
+        assert!(traced.path.len() <= 4);
+        for id in &traced.path {
+            assert!(id.0 < 256);
+        }
+    }
+
+    #[test]
+    fn sampled_traces_keep_the_latest_searches() {
+        let graph = GraphBuilder::new().dims(16).trace_sampling(1.0, 4).build();
+        for i in 0..256 {
+            graph.index(&unit_vec(16, i), 32);
+        }
+
+        let params = SearchParams::new(32, 4);
+        for i in 0..6 {
+            graph.search_with_params(&unit_vec(16, i), &params);
+        }
+        let provenance = graph.search_with_provenance(&unit_vec(16, 3), &params);
+        let mut out = [SearchResult {
+            node: NodeId(0),
+            score: 0.0,
+        }; 4];
+        graph.search_into(&unit_vec(16, 3), &params, &mut out);
+
+        let traces = graph.sample_traces();
+        assert_eq!(traces.len(), 4);
+        for trace in &traces[..] {
+            assert!(!trace.path.is_empty());
+            assert!(trace.nodes_visited >= 32);
+        }
+        // the provenance search isn't sampled, the one through `search_into` is
+        assert_eq!(traces[3].path, provenance.path);
+
+        let unsampled = GraphBuilder::new().dims(16).trace_sampling(0.0, 4).build();
+        unsampled.index(&unit_vec(16, 0), 32);
+        unsampled.search(&unit_vec(16, 0), 32, 1);
+        assert!(unsampled.sample_traces().is_empty());
+    }
+
+    #[test]
+    fn full_precision_skips_raw_copy() {
+        let graph = GraphBuilder::new().dims(16).build();
+        let quantized = GraphBuilder::new()
+            .dims(16)
+            .quantization(Quantization::SignedByte)
+            .build();
+        let unranked = GraphBuilder::new()
+            .dims(16)
+            .quantization(Quantization::SignedByte)
+            .rerank(false)
+            .build();
+        assert!(!graph.store_raw);
+        assert!(quantized.store_raw);
+        assert!(!unranked.store_raw);
+
+        for i in 0..32 {
+            let vec = unit_vec(16, i);
+            graph.index(&vec, 32);
+            unranked.index(&vec, 32);
+        }
+
+        let raw = graph.raw_vec(NodeId(5).to_vec_handle().handle_a()).unwrap();
+        assert_eq!(&raw.vec, &unit_vec(16, 5)[..]);
+        assert!(
+            unranked
+                .raw_vec(NodeId(5).to_vec_handle().handle_a())
+                .is_none()
+        );
+
+        let query = unit_vec(16, 7);
+        assert_eq!(graph.search(&query, 32, 1)[0].node, NodeId(7));
+        assert_eq!(unranked.search(&query, 32, 1)[0].node, NodeId(7));
+    }
+
+    #[test]
+    fn adaptive_ef_finds_exact_match() {
+        let graph = GraphBuilder::new().dims(16).build();
+        for i in 0..100 {
+            graph.index(&unit_vec(16, i), 32);
+        }
+
+        let query = unit_vec(16, 7);
+        let params = SearchParams::new(2, 1).adaptive(64);
+        let results = graph.search_with_params(&query, &params);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].node, NodeId(7));
+    }
+
+    #[test]
+    fn result_cache_invalidated_by_insert() {
+        let graph = GraphBuilder::new().dims(16).result_cache(4).build();
+        for i in 0..32 {
+            graph.index(&unit_vec(16, i), 32);
+        }
+
+        let mut query = alloc::vec![0.0; 16];
+        query[3] = 0.6;
+        query[11] = 0.8;
+        let first = graph.search(&query, 32, 1);
+        let cached = graph.search(&query, 32, 1);
+        assert_eq!(first[0].node, cached[0].node);
+        assert_eq!(graph.seq.load(AtomicOrdering::Relaxed), 32);
+
+        let exact = graph.index(&query, 32);
+        let after = graph.search(&query, 32, 1);
+        assert_eq!(after[0].node, exact);
+        assert_ne!(first[0].node, exact);
+    }
+
+    #[test]
+    fn checkpoint_sees_quiesced_graph() {
+        use alloc::sync::Arc;
+        use parking_lot::Mutex;
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let graph = GraphBuilder::new()
+            .dims(16)
+            .checkpoint(10, {
+                let seen = seen.clone();
+                move |graph, indexed| {
+                    // the root occupies the first vector slot
+                    assert_eq!(graph.vec_arena.len() as u64, indexed + 1);
+                    seen.lock().push(indexed);
+                }
+            })
+            .build();
+
+        for i in 0..35 {
+            graph.index(&unit_vec(16, i), 32);
+        }
+
+        assert_eq!(*seen.lock(), [10, 20, 30]);
+    }
+
+    #[test]
+    fn batch_insert_checkpoints_after_the_batch() {
+        use alloc::sync::Arc;
+        use parking_lot::Mutex;
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let graph = GraphBuilder::new()
+            .m(4)
+            .m0(8)
+            .dims(16)
+            .levels(2)
+            .checkpoint(16, {
+                let seen = seen.clone();
+                move |graph, indexed| {
+                    assert_eq!(graph.len() as u64, indexed);
+                    seen.lock().push(indexed);
+                }
+            })
+            .build();
+
+        let vecs: Vec<Vec<f32>> = (0..40).map(|i| unit_vec(16, i)).collect();
+        let mut batch: Vec<&[f32]> = vecs.iter().map(Vec::as_slice).collect();
+        let short = [0.0; 8];
+        batch.push(&short);
+        assert!(matches!(
+            graph.try_index_batch(&batch, 16),
+            Err(VectorDbError::DimensionMismatch { .. })
+        ));
+        assert!(graph.is_empty());
+
+        batch.pop();
+        let ids = graph.index_batch(&batch, 16);
+        assert_eq!(ids[..], (0..40).map(NodeId).collect::<Vec<_>>()[..]);
+        assert_eq!(*seen.lock(), [40]);
+        let results = graph.search(&unit_vec(16, 21), 32, 1);
+        assert_eq!(results[0].node, NodeId(21));
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel_batch_insert_returns_each_vectors_id() {
+        let graph = GraphBuilder::new().m(4).m0(8).dims(16).levels(2).build();
+        let vecs: Vec<Vec<f32>> = (0..64).map(|i| unit_vec(16, i)).collect();
+        let batch: Vec<&[f32]> = vecs.iter().map(Vec::as_slice).collect();
+        let ids = graph.par_index_batch(&batch, 16);
+        assert_eq!(graph.len(), 64);
+
+        let mut exported = [0.0; 16];
+        for (vec, id) in vecs.iter().zip(&ids) {
+            assert_eq!(graph.export_vectors(id.0..id.0 + 1, &mut exported), 1);
+            assert_eq!(exported[..], vec[..]);
+        }
+    }
+
+    #[test]
+    fn searches_run_alongside_inserts() {
+        extern crate std;
+
+        let graph = GraphBuilder::new().m(4).m0(8).dims(16).levels(2).build();
+        for i in 0..16 {
+            graph.index(&unit_vec(16, i), 16);
+        }
+
+        let done = core::sync::atomic::AtomicBool::new(false);
+        std::thread::scope(|scope| {
+            for t in 0..3 {
+                let (graph, done) = (&graph, &done);
+                scope.spawn(move || {
+                    let mut i = t;
+                    while !done.load(AtomicOrdering::Relaxed) {
+                        let len = graph.len() as u32;
+                        // a top_k past the node count scans every slot
+                        for top_k in [4, 512] {
+                            let results = graph.search(&unit_vec(16, i), 16, top_k);
+                            assert!(!results.is_empty());
+                            assert!(results.iter().all(|result| {
+                                result.node.0 < graph.len() as u32 && result.score.is_finite()
+                            }));
+                        }
+                        assert!(graph.len() as u32 >= len);
+                        i += 1;
+                    }
+                });
+            }
+
+            for i in 16..256 {
+                graph.index(&unit_vec(16, i), 16);
+            }
+            done.store(true, AtomicOrdering::Relaxed);
+        });
+        assert_eq!(graph.search(&unit_vec(16, 0), 16, 512).len(), 256);
+    }
+
+    #[test]
+    fn frozen_graph_searches_without_changing() {
+        let mut graph = GraphBuilder::new().m(4).m0(8).dims(16).levels(2).build();
+        for i in 0..64 {
+            graph.index(&unit_vec(16, i), 16);
+        }
+        let ids = |results: Box<[SearchResult]>| -> Vec<(u32, u32)> {
+            results
+                .iter()
+                .map(|result| (result.node.0, result.score.to_bits()))
+                .collect()
+        };
+        let expected: Vec<_> = (0..16)
+            .map(|i| ids(graph.search(&unit_vec(16, i), 16, 5)))
+            .collect();
+
+        graph.freeze();
+        assert!(graph.is_frozen());
+        for (i, expected) in (0..16).zip(&expected) {
+            assert_eq!(&ids(graph.search(&unit_vec(16, i), 16, 5)), expected);
+        }
+        assert_eq!(graph.search(&unit_vec(16, 0), 16, 100).len(), 64);
+        assert_eq!(
+            graph.try_index(&unit_vec(16, 64), 16),
+            Err(VectorDbError::Frozen)
+        );
+        assert_eq!(
+            graph.update(NodeId(0), &unit_vec(16, 1), 16),
+            Err(VectorDbError::Frozen)
+        );
+        assert!(!graph.delete(NodeId(0)));
+        assert_eq!(graph.len(), 64);
+
+        graph.thaw();
+        assert!(!graph.is_frozen());
+        assert_eq!(graph.index(&unit_vec(16, 64), 16), NodeId(64));
+        assert!(graph.delete(NodeId(0)));
+    }
+
+    #[test]
+    fn checkpoint_count_matches_graph_under_concurrent_inserts() {
+        extern crate std;
+
+        let graph = GraphBuilder::new()
+            .dims(16)
+            .checkpoint(7, |graph, indexed| {
+                assert_eq!(graph.len() as u64, indexed);
+            })
+            .build();
+
+        std::thread::scope(|scope| {
+            for t in 0..4 {
+                let graph = &graph;
+                scope.spawn(move || {
+                    for i in 0..32 {
+                        graph.index(&unit_vec(16, t * 32 + i), 16);
+                    }
+                });
+            }
+        });
+        assert_eq!(graph.len(), 128);
+    }
+
+    #[test]
+    fn deletes_and_updates_wait_for_checkpoints() {
+        extern crate std;
+        use alloc::sync::Arc;
+        use core::{sync::atomic::AtomicBool, time::Duration};
+
+        let running = Arc::new(AtomicBool::new(false));
+        let graph = GraphBuilder::new()
+            .dims(16)
+            .checkpoint(16, {
+                let running = running.clone();
+                move |_, _| {
+                    running.store(true, AtomicOrdering::SeqCst);
+                    std::thread::sleep(Duration::from_millis(50));
+                    running.store(false, AtomicOrdering::SeqCst);
+                }
+            })
+            .build();
+        for i in 0..15 {
+            graph.index(&unit_vec(16, i), 16);
+        }
+
+        let changes: [&(dyn Fn() + Sync); 2] = [&|| assert!(graph.delete(NodeId(0))), &|| {
+            graph.update(NodeId(1), &unit_vec(16, 40), 16).unwrap()
+        }];
+        for (round, change) in changes.into_iter().enumerate() {
+            std::thread::scope(|scope| {
+                // the 16th insert of the round runs the checkpoint
+                scope.spawn(|| graph.index(&unit_vec(16, 15 + round as u32 * 16), 16));
+                while !running.load(AtomicOrdering::SeqCst) {
+                    std::thread::yield_now();
+                }
+                change();
+                assert!(!running.load(AtomicOrdering::SeqCst));
+            });
+            for i in 0..15 {
+                graph.index(&unit_vec(16, 16 + round as u32 * 16 + i), 16);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "query dimension mismatch")]
+    fn search_into_rejects_wrong_dims() {
+        let graph = GraphBuilder::new().dims(16).build();
+        graph.index(&unit_vec(16, 0), 32);
+
+        let mut out = [SearchResult {
+            node: NodeId(0),
+            score: 0.0,
+        }; 4];
+        graph.search_into(&[0.0; 8], &SearchParams::new(32, 4), &mut out);
+    }
+
+    #[test]
+    #[should_panic(expected = "query dimension mismatch")]
+    fn search_quantized_rejects_wrong_dims() {
+        let graph = GraphBuilder::new().dims(16).build();
+        graph.search_quantized(&[0.0; 32], 32, 1);
+    }
+
+    #[test]
+    fn ttl_needs_a_clock() {
+        let graph = GraphBuilder::new().dims(16).build();
+        assert_eq!(
+            graph.try_index_with_ttl(&unit_vec(16, 0), 10, 16),
+            Err(VectorDbError::NoClock)
+        );
+        assert!(graph.is_empty());
+    }
+
+    #[test]
+    fn try_search_rejects_wrong_dims() {
+        let graph = GraphBuilder::new().dims(16).build();
+        graph.index(&unit_vec(16, 0), 32);
+
+        let params = SearchParams::new(32, 1);
+        assert_eq!(
+            graph.try_search(&[0.0; 8], &params).unwrap_err(),
+            VectorDbError::DimensionMismatch {
+                expected: 16,
+                found: 8
+            }
+        );
+        assert_eq!(
+            graph.try_search(&unit_vec(16, 0), &params).unwrap()[0].node,
+            NodeId(0)
+        );
+    }
+
+    #[test]
+    fn invalid_inputs_are_rejected() {
+        let graph = GraphBuilder::new().dims(16).build();
+        let mut nan = unit_vec(16, 0);
+        nan[3] = f32::NAN;
+        assert_eq!(
+            graph.try_index(&nan, 32),
+            Err(VectorDbError::NanComponent { index: 3 })
+        );
+        assert_eq!(
+            graph.try_index(&unit_vec(16, 0), 0),
+            Err(VectorDbError::ZeroEf)
+        );
+        assert!(graph.is_empty());
+
+        let id = unsafe { graph.index_unchecked(&unit_vec(16, 0), 32) };
+        let query = unit_vec(16, 0);
+        assert_eq!(
+            graph
+                .try_search(&nan, &SearchParams::new(32, 1))
+                .unwrap_err(),
+            VectorDbError::NanComponent { index: 3 }
+        );
+        assert_eq!(
+            graph
+                .try_search(&query, &SearchParams::new(0, 1))
+                .unwrap_err(),
+            VectorDbError::ZeroEf
+        );
+        assert_eq!(
+            graph
+                .try_search(&query, &SearchParams::new(32, MAX_TOP_K + 1))
+                .unwrap_err(),
+            VectorDbError::TopKOutOfRange {
+                top_k: MAX_TOP_K + 1
+            }
+        );
+        let results = unsafe { graph.search_unchecked(&query, &SearchParams::new(32, 1)) };
+        assert_eq!(results[0].node, id);
+    }
+
+    #[test]
+    fn memory_pressure_vetoes_new_chunks() {
+        struct Budget(AtomicU64);
+
+        impl MemoryPressureHook for Budget {
+            fn allow_alloc(&self, bytes: usize) -> bool {
+                self.0
+                    .fetch_update(AtomicOrdering::Relaxed, AtomicOrdering::Relaxed, |left| {
+                        left.checked_sub(bytes as u64)
+                    })
+                    .is_ok()
+            }
+        }
+
+        // enough for the first chunk of every arena but not a second vector chunk
+        let graph = GraphBuilder::new()
+            .dims(4)
+            .m0(4)
+            .memory_pressure_hook(Budget(AtomicU64::new(64 * 1024)))
+            .build();
+        for i in 0..(CHUNK_SIZE as u32 - 1) {
+            assert!(graph.try_index(&unit_vec(4, i), 8).is_ok());
+        }
+
+        assert_eq!(
+            graph.try_index(&unit_vec(4, 0), 8),
+            Err(VectorDbError::OutOfBudget)
+        );
+        assert_eq!(graph.vec_arena.len(), CHUNK_SIZE);
+        assert_eq!(graph.search(&unit_vec(4, 1), 8, 1).len(), 1);
+    }
+
+    #[test]
+    fn reserve_allocates_chunks_up_front() {
+        let graph = GraphBuilder::new().dims(4).chunk_size(16).build();
+        graph.reserve(100).unwrap();
+        // 101 slots with the root
+        assert_eq!(graph.vec_arena.chunk_count(), 7);
+        assert_eq!(graph.nodes0_arena.chunk_count(), 7);
+        assert_eq!(graph.nodes_arena.chunk_count(), 5);
+        for i in 0..100 {
+            graph.index(&unit_vec(4, i), 8);
+        }
+        assert_eq!(graph.vec_arena.chunk_count(), 7);
+        assert_eq!(graph.nodes0_arena.chunk_count(), 7);
+
+        struct Veto;
+
+        impl MemoryPressureHook for Veto {
+            fn allow_alloc(&self, _bytes: usize) -> bool {
+                false
+            }
+        }
+
+        let graph = GraphBuilder::new()
+            .dims(4)
+            .chunk_size(16)
+            .memory_pressure_hook(Veto)
+            .build();
+        assert_eq!(graph.reserve(100), Err(VectorDbError::OutOfBudget));
+        assert_eq!(graph.vec_arena.chunk_count(), 1);
+        // the root's chunks are there already
+        assert_eq!(graph.reserve(15), Ok(()));
+    }
+
+    #[test]
+    fn context_search_matches_search_into() {
+        let graph = GraphBuilder::new()
+            .dims(16)
+            .quantization(Quantization::SignedByte)
+            .build();
+        for i in 0..128 {
+            graph.index(&unit_vec(16, i), 32);
+        }
+        let empty = SearchResult {
+            node: NodeId(0),
+            score: 0.0,
+        };
+
+        let mut context = SearchContext::new();
+        let params = SearchParams::new(32, 4);
+        for i in 0..8 {
+            let query = unit_vec(16, i * 13);
+            let mut out = [empty; 4];
+            let len = graph.search_with_context(&mut context, &query, &params, &mut out);
+            let mut expected = [empty; 4];
+            assert_eq!(len, graph.search_into(&query, &params, &mut expected));
+            for (result, expected) in out.iter().zip(&expected) {
+                assert_eq!((result.node, result.score), (expected.node, expected.score));
+            }
+        }
+
+        // the context adapts to a graph of another shape
+        let other = GraphBuilder::new().dims(4).build();
+        for i in 0..64 {
+            other.index(&unit_vec(4, i), 32);
+        }
+        let params = SearchParams::new(32, 1);
+        let mut out = [empty; 1];
+        let len = other.search_with_context(&mut context, &unit_vec(4, 1), &params, &mut out);
+        let mut expected = [empty; 1];
+        assert_eq!(
+            len,
+            other.search_into(&unit_vec(4, 1), &params, &mut expected)
+        );
+        assert_eq!(out[0].score, expected[0].score);
+    }
+
+    #[test]
+    fn batch_search_matches_single_queries() {
+        let graph = GraphBuilder::new().dims(16).build();
+        for i in 0..128 {
+            graph.index(&unit_vec(16, i), 32);
+        }
+
+        let queries: Vec<Vec<f32>> = (0..8).map(|i| unit_vec(16, i * 13)).collect();
+        let queries: Vec<&[f32]> = queries.iter().map(Vec::as_slice).collect();
+        let batch = graph.search_batch(&queries, 32, 4);
+        assert_eq!(batch.len(), queries.len());
+        for (results, query) in batch.iter().zip(&queries) {
+            let single = graph.search(query, 32, 4);
+            assert_eq!(results.len(), single.len());
+            for (result, expected) in results.iter().zip(&single) {
+                assert_eq!((result.node, result.score), (expected.node, expected.score));
+            }
+        }
+    }
+
+    #[test]
+    fn batch_shared_descent_matches_single_queries() {
+        let graph = GraphBuilder::new().dims(16).build();
+        for i in 0..100 {
+            graph.index(&unit_vec(16, i), 32);
+        }
+
+        let queries: Vec<Vec<f32>> = (0..4).map(|i| unit_vec(16, 7 + i * 16)).collect();
+        let queries: Vec<&[f32]> = queries.iter().map(|query| &query[..]).collect();
+        let params = SearchParams::new(64, 2);
+        let batch = graph.search_batch_shared(&queries, &params);
+
+        assert_eq!(batch.len(), 4);
+        for (query, results) in queries.iter().zip(&batch) {
+            let single = graph.search_with_params(query, &params);
+            assert_eq!(results[0].node, single[0].node);
+        }
+    }
+
+    #[test]
+    fn clipping_is_counted() {
+        let graph = GraphBuilder::new()
+            .dims(4)
+            .quantization(Quantization::SignedByte)
+            .build();
+        let a = graph.index(&[0.5, 0.5, 0.0, 0.0], 16);
+        let b = graph.index(&[2.0, -3.0, 0.5, 1.0], 16);
+        let c = graph.index(&[0.0, 0.0, 4.0, 0.0], 16);
+
+        assert_eq!(graph.clipped_components(a), Some(0));
+        assert_eq!(graph.clipped_components(b), Some(2));
+        assert_eq!(graph.clipped_components(c), Some(1));
+        assert_eq!(graph.clipped_components(NodeId(9)), None);
+        assert_eq!(
+            graph.clipping_stats(),
+            ClippingStats {
+                clipped_vectors: 2,
+                clipped_components: 3,
+            }
+        );
+    }
+
+    #[test]
+    fn cache_budget_bounds_slots() {
+        let graph = GraphBuilder::new()
+            .m(4)
+            .m0(8)
+            .dims(16)
+            .levels(2)
+            .cache_budget(32)
+            .build();
+
+        for i in 0..200 {
+            let id = graph.index(&unit_vec(16, i), 16);
+            assert!(id.0 < 32);
+        }
+
+        // the synthetic root plus at most `budget` vectors
+        assert_eq!(graph.vec_arena.len(), 33);
+    }
+
+    #[test]
+    fn search_keeps_the_best_results_best_first() {
+        let graph = Graph::new(
+            4,
+            8,
+            16,
+            2,
+            Quantization::FullPrecisionFP,
+            DistanceMetricKind::Cosine,
+        );
+        for i in 0..8 {
+            graph.index(&unit_vec(16, i), 16);
+        }
+
+        let results = graph.search(&unit_vec(16, 5), 16, 3);
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].node, NodeId(5));
+        assert!(results.windows(2).all(|pair| {
+            graph
+                .distance_metric
+                .cmp_score(pair[0].score, pair[1].score)
+                != Ordering::Less
+        }));
+    }
+
+    #[test]
+    fn construction_keeps_recall_high() {
+        let graph = GraphBuilder::new()
+            .m(4)
+            .m0(8)
+            .dims(16)
+            .levels(2)
+            .quantization(Quantization::FullPrecisionFP)
+            .build();
+        let mut state = 7u32;
+        let mut next = move || {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (state >> 8) as f32 / (1 << 24) as f32 - 0.5
+        };
+        for _ in 0..512 {
+            let vec: Vec<f32> = (0..16).map(|_| next()).collect();
+            graph.index(&vec, 32);
+        }
+
+        // every list fills up to m0 once the level holds enough nodes
+        for index in 1..graph.nodes0_arena.len() as u32 {
+            let neighbors = graph.nodes0_arena[Node0Handle::new(index)]
+                .neighbors
+                .read()
+                .neighbors()
+                .len();
+            assert_eq!(neighbors, 8);
+        }
+
+        let mut found = 0;
+        for _ in 0..16 {
+            let query: Vec<f32> = (0..16).map(|_| next()).collect();
+            // a top_k covering the graph scans every node
+            let exact = graph.search(&query, 32, 512);
+            let results = graph.search(&query, 32, 10);
+            found += results
+                .iter()
+                .filter(|result| exact[..10].iter().any(|e| e.node == result.node))
+                .count();
+        }
+        assert!(found >= 16 * 10 * 9 / 10, "recall {}", found as f32 / 160.0);
+    }
+
+    #[test]
+    fn search_visits_every_reachable_node() {
+        let graph = GraphBuilder::new().m(4).m0(8).dims(16).levels(2).build();
+        // distinct vectors, unit_vec repeats itself
+        let mut state = 1u32;
+        let mut next = move || {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (state >> 8) as f32 / (1 << 24) as f32 - 0.5
+        };
+        for _ in 0..1024 {
+            let vec: Vec<f32> = (0..16).map(|_| next()).collect();
+            graph.index(&vec, 16);
+        }
+
+        let query = unit_vec(16, 5);
+        let quantized = QuantVecBox::new(graph.quantization, graph.dims, &query, &graph.allocator);
+        let entry = graph.descend(&quantized, 2048, 2048, None, false);
+
+        // walk level 0 from the same entry point
+        let mut reachable = BTreeSet::from([*entry]);
+        let mut stack = alloc::vec![entry];
+        while let Some(handle) = stack.pop() {
+            for neighbor in graph.nodes0_arena[handle].neighbors.read().neighbors() {
+                if reachable.insert(*neighbor.node()) {
+                    stack.push(neighbor.node());
+                }
+            }
+        }
+        reachable.remove(&0);
+
+        // with ef above the graph size no reachable node may be mistaken for a
+        // visited one (searches for as many results would scan instead)
+        for _ in 0..2 {
+            let results = graph.search_level0_adaptive(
+                &[entry],
+                &quantized,
+                2048,
+                2048,
+                2048,
+                false,
+                false,
+                None,
+                None,
+            );
+            assert_eq!(results.len(), reachable.len());
+        }
+    }
+
+    #[test]
+    fn mmr_trades_relevance_for_diversity() {
+        let graph = GraphBuilder::new()
+            .dims(16)
+            .quantization(Quantization::SignedByte)
+            .build();
+        // near-duplicates of the query, then a distinct but related vector
+        let mut query = alloc::vec![0.0; 16];
+        query[0] = 1.0;
+        for i in 0..4 {
+            let mut vec = query.clone();
+            vec[1] = 0.01 * i as f32;
+            graph.index(&vec, 16);
+        }
+        let mut other = query.clone();
+        other[0] = 0.6;
+        other[2] = 0.8;
+        let other = graph.index(&other, 16);
+
+        let plain = graph.search_with_params(&query, &SearchParams::new(16, 2));
+        assert!(plain.iter().all(|result| result.node != other));
+
+        let diverse = graph.search_with_params(&query, &SearchParams::new(16, 2).mmr(0.5));
+        assert_eq!(diverse.len(), 2);
+        assert_eq!(diverse[0].node, plain[0].node);
+        assert_eq!(diverse[1].node, other);
+
+        let relevant = graph.search_with_params(&query, &SearchParams::new(16, 2).mmr(1.0));
+        let nodes = |results: &[SearchResult]| results.iter().map(|r| r.node).collect::<Vec<_>>();
+        assert_eq!(nodes(&relevant), nodes(&plain));
+
+        let mut out = [SearchResult {
+            node: NodeId(0),
+            score: 0.0,
+        }; 2];
+        graph.search_into(&query, &SearchParams::new(16, 2).mmr(0.5), &mut out);
+        assert_eq!(nodes(&out), nodes(&diverse));
+    }
+
+    fn snapshot_graph(len: usize) -> Graph {
+        let graph = GraphBuilder::new()
+            .m(4)
+            .m0(8)
+            .dims(16)
+            .levels(2)
+            .quantization(Quantization::SignedByte)
+            .build();
+        let mut state = 7u32;
+        for _ in 0..len {
+            let vec: Vec<f32> = (0..16)
+                .map(|_| {
+                    state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                    (state >> 8) as f32 / (1 << 24) as f32 - 0.5
+                })
+                .collect();
+            graph.index(&vec, 16);
+        }
+        graph
+    }
+
+    #[test]
+    fn deserialized_graph_searches_and_inserts_alike() {
+        let graph = snapshot_graph(200);
+        let bytes = graph.serialize();
+        let loaded = Graph::deserialize(&bytes).unwrap();
+        assert_eq!(loaded.len(), graph.len());
+        assert_eq!(loaded.serialize(), bytes);
+
+        let ids = |results: &[SearchResult]| -> Vec<(u32, u32)> {
+            results
+                .iter()
+                .map(|result| (result.node.0, result.score.to_bits()))
+                .collect()
+        };
+        for i in 0..16 {
+            let query = unit_vec(16, i);
+            assert_eq!(
+                ids(&loaded.search(&query, 16, 5)),
+                ids(&graph.search(&query, 16, 5))
+            );
+        }
+
+        // the RNG picks the same levels for new nodes
+        for i in 0..32 {
+            assert_eq!(
+                loaded.index(&unit_vec(16, i), 16),
+                graph.index(&unit_vec(16, i), 16)
+            );
+        }
+        assert_eq!(loaded.serialize(), graph.serialize());
+    }
+
+    #[test]
+    #[cfg(feature = "mmap")]
+    fn mapped_graph_borrows_its_vectors() {
+        let graph = GraphBuilder::new()
+            .m(4)
+            .m0(8)
+            .dims(16)
+            .quantization(Quantization::SignedByte)
+            .chunk_size(16)
+            .build();
+        for i in 0..100 {
+            graph.index(&unit_vec(16, i), 16);
+        }
+        let bytes = graph.serialize_mapped();
+        // a mapping is page-aligned, a leaked `u32` buffer is at least aligned
+        // to `layout::ALIGN`
+        let words = Vec::leak(alloc::vec![0u32; bytes.len().div_ceil(4)]);
+        let buffer =
+            unsafe { core::slice::from_raw_parts_mut(words.as_mut_ptr().cast(), bytes.len()) };
+        buffer.copy_from_slice(&bytes);
+        let buffer: &'static [u8] = buffer;
+
+        let loaded = Graph::from_mapped(buffer).unwrap();
+        assert_eq!(loaded.serialize(), graph.serialize());
+        for i in 0..16 {
+            let query = unit_vec(16, i);
+            let (mapped, built) = (loaded.search(&query, 16, 5), graph.search(&query, 16, 5));
+            assert_eq!(mapped.len(), built.len());
+            assert!(
+                mapped
+                    .iter()
+                    .zip(&built)
+                    .all(|(a, b)| a.node == b.node && a.score == b.score)
+            );
+        }
+        assert_eq!(
+            loaded.try_index(&unit_vec(16, 0), 16),
+            Err(VectorDbError::ReadOnly)
+        );
+        assert_eq!(
+            loaded.update(NodeId(3), &unit_vec(16, 0), 16),
+            Err(VectorDbError::ReadOnly)
+        );
+        assert!(loaded.delete(NodeId(3)));
+        let copied = Graph::deserialize(&graph.serialize()).unwrap();
+        assert!(loaded.stats().arena_bytes < copied.stats().arena_bytes);
+
+        assert_eq!(
+            Graph::from_mapped(&buffer[1..]).err(),
+            Some(DeserializeError::Misaligned)
+        );
+        assert_eq!(
+            Graph::from_mapped(&buffer[..buffer.len() - 4]).err(),
+            Some(DeserializeError::Truncated)
+        );
+        assert_eq!(
+            Graph::from_mapped(Box::leak(graph.serialize())).err(),
+            Some(DeserializeError::NotASnapshot)
+        );
+    }
+
+    #[test]
+    fn deserialize_rejects_damaged_snapshots() {
+        let bytes = snapshot_graph(24).serialize();
+        assert_eq!(
+            Graph::deserialize(b"not a graph").err(),
+            Some(DeserializeError::NotASnapshot)
+        );
+        assert_eq!(
+            Graph::deserialize(&bytes[..bytes.len() - 1]).err(),
+            Some(DeserializeError::Truncated)
+        );
+
+        let mut version = bytes.to_vec();
+        version[4] += 1;
+        assert_eq!(
+            Graph::deserialize(&version).err(),
+            Some(DeserializeError::UnsupportedVersion(FORMAT_VERSION + 1))
+        );
+
+        // no flipped byte may load a graph whose handles escape the arenas
+        let mut damaged = bytes.to_vec();
+        for i in 0..damaged.len() {
+            damaged[i] ^= 0x81;
+            if let Ok(graph) = Graph::deserialize(&damaged) {
+                graph.search(&unit_vec(16, i as u32), 16, 5);
+            }
+            damaged[i] ^= 0x81;
+        }
+    }
+
+    #[test]
+    fn other_kernel_version_recomputes_scores() {
+        let graph = snapshot_graph(200);
+        let mut bytes = graph.serialize().into_vec();
+        bytes[8..12].copy_from_slice(&(KERNEL_VERSION + 1).to_le_bytes());
+
+        let loaded = Graph::deserialize(&bytes).unwrap();
+        // recomputing with the same kernels leaves every score as it was
+        assert_eq!(loaded.serialize()[12..], graph.serialize()[12..]);
+    }
+
+    #[test]
+    fn deleted_node_is_unlinked_and_its_slot_reused() {
+        let graph = GraphBuilder::new().m(4).m0(8).dims(16).levels(2).build();
+        for i in 0..64 {
+            graph.index(&unit_vec(16, i), 16);
+        }
+        let deleted = NodeId(5);
+        let vec_handle = deleted.to_vec_handle();
+        let node0 = graph.meta(vec_handle).top();
+        let node0 = (0..graph.meta(vec_handle).level())
+            .fold(node0, |node, _| graph.nodes_arena[node].child)
+            .cast::<Node0>();
+        let former: Vec<Node0Handle> = graph.nodes0_arena[node0]
+            .neighbors
+            .read()
+            .neighbors()
+            .iter()
+            .map(Neighbor0::node)
+            .collect();
+
+        assert!(graph.delete(deleted));
+        assert!(!graph.delete(deleted));
+        assert!(!graph.delete(NodeId(64)));
+
+        let results = graph.search(&unit_vec(16, 5), 64, 64);
+        assert!(results.iter().all(|result| result.node != deleted));
+        assert!(graph.distance(deleted, NodeId(0)).is_nan());
+
+        // its former neighbors dropped their links back to it
+        for neighbor in former {
+            let neighbors = graph.nodes0_arena[neighbor].neighbors.read();
+            assert!(neighbors.neighbors().iter().all(|n| n.node() != node0));
+        }
+
+        // a snapshot keeps the slot free
+        let loaded = Graph::deserialize(&graph.serialize()).unwrap();
+        assert_eq!(loaded.index(&unit_vec(16, 100), 16), deleted);
+
+        let id = graph.index(&unit_vec(16, 100), 16);
+        assert_eq!(id, deleted);
+        let mut exported = [0.0; 16];
+        assert_eq!(graph.export_vectors(id.0..id.0 + 1, &mut exported), 1);
+        assert_eq!(exported[..], unit_vec(16, 100)[..]);
+        let results = graph.search(&unit_vec(16, 100), 32, 4);
+        assert!(results.iter().any(|result| result.node == id));
+        assert_eq!(graph.len(), 64);
+    }
+
+    // Assert every link of every live node stores the score `link_score` gives.
+    fn check_stored_link_scores(graph: &Graph) {
+        let stored = |a: VecHandle, b: VecHandle| {
+            Neighbor0::new(
+                &graph.distance_metric,
+                Node0Handle::new(0),
+                graph.link_score(a, b),
+            )
+            .score(&graph.distance_metric)
+        };
+        for index in 0..graph.nodes_arena.len() as u32 {
+            let node = &graph.nodes_arena[NodeHandle::new(index)];
+            if graph.meta(node.vec).is_tombstoned() {
+                continue;
+            }
+            for neighbor in node.neighbors.read().neighbors() {
+                let other = graph.nodes_arena[neighbor.node()].vec;
+                let score = neighbor.score(&graph.distance_metric);
+                assert_eq!(
+                    score,
+                    stored(node.vec, other),
+                    "{:?} -> {:?}",
+                    node.vec,
+                    other
+                );
+            }
+        }
+        for index in 0..graph.nodes0_arena.len() as u32 {
+            let node = &graph.nodes0_arena[Node0Handle::new(index)];
+            if graph.meta(node.vec).is_tombstoned() {
+                continue;
+            }
+            for neighbor in node.neighbors.read().neighbors() {
+                let other = graph.nodes0_arena[neighbor.node()].vec;
+                let score = neighbor.score(&graph.distance_metric);
+                assert_eq!(
+                    score,
+                    stored(node.vec, other),
+                    "{:?} -> {:?}",
+                    node.vec,
+                    other
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn reused_slot_links_are_rescored() {
+        let graph = GraphBuilder::new()
+            .m(4)
+            .m0(8)
+            .dims(16)
+            .levels(2)
+            .metric(DistanceMetricKind::Euclidean)
+            .build();
+        for i in 0..64 {
+            graph.index(&unit_vec(16, i), 16);
+        }
+
+        // a node some other node links to without being linked back
+        let level0 = |id: NodeId| {
+            let meta = graph.meta(id.to_vec_handle());
+            (0..meta.level())
+                .fold(meta.top(), |node, _| graph.nodes_arena[node].child)
+                .cast::<Node0>()
+        };
+        let links = |node: Node0Handle| -> Vec<Node0Handle> {
+            graph.nodes0_arena[node]
+                .neighbors
+                .read()
+                .neighbors()
+                .iter()
+                .map(Neighbor0::node)
+                .collect()
+        };
+        let deleted = (0..64)
+            .map(NodeId)
+            .find(|&id| {
+                let node = level0(id);
+                (0..64).map(NodeId).any(|other| {
+                    let other = level0(other);
+                    links(other).contains(&node) && !links(node).contains(&other)
+                })
+            })
+            .unwrap();
+
+        assert!(graph.delete(deleted));
+        // far from every other vector, so the old scores are way off
+        assert_eq!(graph.index(&[5.0; 16], 16), deleted);
+        check_stored_link_scores(&graph);
+
+        graph.update(NodeId(7), &[-5.0; 16], 16).unwrap();
+        check_stored_link_scores(&graph);
+    }
+
+    #[test]
+    fn updated_node_keeps_its_id() {
+        let graph = GraphBuilder::new().m(4).m0(8).dims(16).levels(2).build();
+        for i in 0..64 {
+            graph.index(&unit_vec(16, i), 16);
+        }
+        let updated = NodeId(5);
+        graph.update(updated, &unit_vec(16, 100), 16).unwrap();
+        assert_eq!(
+            graph.update(NodeId(64), &unit_vec(16, 100), 16),
+            Err(VectorDbError::UnknownNode)
+        );
+        assert!(matches!(
+            graph.update(updated, &[0.0; 8], 16),
+            Err(VectorDbError::DimensionMismatch { .. })
+        ));
+        assert_eq!(graph.len(), 64);
+
+        let mut exported = [0.0; 16];
+        assert_eq!(graph.export_vectors(5..6, &mut exported), 1);
+        assert_eq!(exported[..], unit_vec(16, 100)[..]);
+        let results = graph.search(&unit_vec(16, 100), 32, 4);
+        assert!(results.iter().any(|result| result.node == updated));
+        // its slot isn't handed to the next insert
+        assert_eq!(graph.index(&unit_vec(16, 101), 16), NodeId(64));
+    }
+
+    #[test]
+    fn euclidean_search_finds_nearest() {
+        for quantization in [Quantization::SignedByte, Quantization::FullPrecisionFP] {
+            let graph = GraphBuilder::new()
+                .m(4)
+                .m0(8)
+                .dims(16)
+                .levels(2)
+                .quantization(quantization)
+                .metric(DistanceMetricKind::Euclidean)
+                .build();
+            for i in 0..64 {
+                graph.index(&unit_vec(16, i), 16);
+            }
+            let results = graph.search(&unit_vec(16, 20), 32, 4);
+            assert_eq!(results[0].node, NodeId(20));
+            assert_eq!(results[0].score, 0.0);
+            assert!(results.windows(2).all(|w| w[0].score <= w[1].score));
+        }
+    }
+
+    #[test]
+    fn binary_hamming_search_finds_nearest() {
+        let graph = GraphBuilder::new()
+            .m(4)
+            .m0(8)
+            .dims(100)
+            .levels(2)
+            .quantization(Quantization::Binary)
+            .metric(DistanceMetricKind::Hamming)
+            .build();
+        // signs from the bits of `i`, spread over the dimensions
+        let signs = |i: u32| -> Vec<f32> {
+            (0..100)
+                .map(|dim| if i >> (dim % 7) & 1 == 1 { 1.0 } else { -1.0 })
+                .collect()
+        };
+        for i in 0..64 {
+            graph.index(&signs(i), 16);
+        }
+        let results = graph.search(&signs(37), 32, 4);
+        assert_eq!(results[0].node, NodeId(37));
+        assert_eq!(results[0].score, 0.0);
+        // differs from 37 in one of the 7 bits, set in 15 dimensions
+        assert_eq!(graph.distance(NodeId(37), NodeId(36)), 15.0);
+
+        let loaded = Graph::deserialize(&graph.serialize()).unwrap();
+        assert_eq!(loaded.distance(NodeId(37), NodeId(36)), 15.0);
+        let mut exported = [0.0; 100];
+        assert_eq!(graph.export_vectors(37..38, &mut exported), 1);
+        assert_eq!(exported[..], signs(37)[..]);
+    }
+
+    #[test]
+    #[should_panic(expected = "the Hamming metric and binary quantization go together")]
+    fn hamming_needs_binary_quantization() {
+        GraphBuilder::new()
+            .dims(16)
+            .metric(DistanceMetricKind::Hamming)
+            .build();
+    }
+
+    #[test]
+    fn byte_vectors_are_stored_as_given() {
+        let graph = GraphBuilder::new()
+            .m(4)
+            .m0(8)
+            .dims(16)
+            .quantization(Quantization::UnsignedByte)
+            .metric(DistanceMetricKind::Euclidean)
+            .rerank(false)
+            .build();
+        let descriptors: Vec<Vec<u8>> = (0..32u8)
+            .map(|i| {
+                (0..16)
+                    .map(|j| i.wrapping_mul(37).wrapping_add(j * 11))
+                    .collect()
+            })
+            .collect();
+        for (i, bytes) in descriptors.iter().enumerate() {
+            let norm = (i % 2 == 0).then(|| {
+                let sum: f32 = bytes.iter().map(|&byte| (byte as f32).powi(2)).sum();
+                sum.sqrt()
+            });
+            assert_eq!(graph.index_bytes(bytes, norm, 16), NodeId(i as u32));
+        }
+
+        let vec = &graph.vec_arena[NodeId(5).to_vec_handle().handle_b()];
+        assert_eq!(vec.as_unsigned_byte(), &descriptors[5][..]);
+        let widened: Vec<f32> = descriptors[5].iter().map(|&b| b as f32 / 255.0).collect();
+        assert!((vec.mag - dot_product_f32(&widened, &widened)).abs() < 1e-4);
+        assert_eq!(graph.search(&widened, 16, 1)[0].node, NodeId(5));
+
+        assert_eq!(
+            graph.try_index_bytes(&[0; 8], None, 16),
+            Err(VectorDbError::DimensionMismatch {
+                expected: 16,
+                found: 8
+            })
+        );
+    }
+
+    #[test]
+    fn binary_descriptors_match_quantized_vectors() {
+        let build = || {
+            GraphBuilder::new()
+                .dims(70)
+                .quantization(Quantization::Binary)
+                .metric(DistanceMetricKind::Hamming)
+                .build()
+        };
+        let (from_bytes, from_floats) = (build(), build());
+        for i in 0..8u8 {
+            // stray bits past the 70th dimension are dropped
+            let bytes: Vec<u8> = (0..9).map(|j| i.wrapping_mul(91) ^ (j * 29)).collect();
+            let vec: Vec<f32> = (0..70)
+                .map(|k| {
+                    if bytes[k / 8] >> (k % 8) & 1 == 1 {
+                        1.0
+                    } else {
+                        -1.0
+                    }
+                })
+                .collect();
+            from_bytes.index_bytes(&bytes, None, 16);
+            from_floats.index(&vec, 16);
+        }
+        for i in 1..=8 {
+            let handle = VecHandle::new(i).handle_b();
+            assert_eq!(
+                from_bytes.vec_arena[handle].as_unsigned_byte(),
+                from_floats.vec_arena[handle].as_unsigned_byte()
+            );
+        }
+        assert!(from_bytes.try_index_bytes(&[0; 8], None, 16).is_err());
+    }
+
+    #[test]
+    fn near_duplicates_become_aliases() {
+        let graph = GraphBuilder::new()
+            .m(4)
+            .m0(8)
+            .dims(16)
+            .levels(2)
+            .metric(DistanceMetricKind::Euclidean)
+            .min_insert_distance(0.01)
+            .build();
+        for i in 0..32 {
+            assert_eq!(graph.index(&unit_vec(16, i), 16), NodeId(i));
+        }
+        let mut near = unit_vec(16, 7);
+        near[3] += 0.05;
+        assert_eq!(graph.index(&near, 16), NodeId(7));
+        assert_eq!(graph.aliased_inserts(), 1);
+        assert_eq!(graph.len(), 32);
+
+        near[3] += 0.1;
+        assert_eq!(graph.index(&near, 16), NodeId(32));
+        assert_eq!(graph.aliased_inserts(), 1);
+    }
+
+    #[test]
+    fn filtered_search_returns_passing_nodes_only() {
+        // dense enough that every node is reachable
+        let graph = GraphBuilder::new().m(8).m0(16).dims(16).levels(2).build();
+        for i in 0..64 {
+            graph.index(&unit_vec(16, i), 16);
+        }
+        let filter = |id: NodeId| id.0.is_multiple_of(8);
+        let query = unit_vec(16, 8);
+
+        let results = graph.search_filtered(&query, 8, 4, filter);
+        assert!(results.iter().all(|result| filter(result.node)));
+        let mut expected: Vec<f32> = (0..64)
+            .map(NodeId)
+            .filter(|id| filter(*id))
+            .map(|id| graph.distance_to(id, &query))
+            .collect();
+        expected.sort_by(|a, b| b.total_cmp(a));
+        let scores: Vec<f32> = results.iter().map(|result| result.score).collect();
+        assert_eq!(scores, expected[..4]);
+
+        assert!(graph.search_filtered(&query, 8, 4, |_| false).is_empty());
+    }
+
+    #[test]
+    fn heap_selection_matches_sorting() {
+        let mut state = 7u32;
+        let scores: Vec<(u32, f32)> = (0..5000)
+            .map(|i| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (i, (state >> 8) as f32 / 1024.0)
+            })
+            .collect();
+        for metric in [DistanceMetricKind::Cosine, DistanceMetricKind::Euclidean] {
+            let graph = GraphBuilder::new().dims(4).metric(metric).build();
+            let mut sorted = scores.clone();
+            sorted.sort_by(|a, b| graph.distance_metric.cmp_score(b.1, a.1));
+
+            // 5000 candidates take the heap for up to 4 results, and select
+            // in place for more
+            for top_k in [0, 1, 4, 5, 64] {
+                let mut results = scores.clone();
+                graph.select_top_k(&mut results, top_k);
+                assert_eq!(results, sorted[..top_k]);
+                let streamed = graph.collect_top_k(scores.iter().copied().filter(|_| true), top_k);
+                assert_eq!(streamed, sorted[..top_k]);
+            }
+        }
+    }
+
+    #[test]
+    fn result_order_holds_across_metrics() {
+        for metric in [DistanceMetricKind::Cosine, DistanceMetricKind::Euclidean] {
+            let graph = GraphBuilder::new()
+                .m(4)
+                .m0(8)
+                .dims(16)
+                .levels(2)
+                .metric(metric)
+                .result_cache(16)
+                .build();
+            for i in 0..64 {
+                graph.index(&unit_vec(16, i), 16);
+            }
+            let query = unit_vec(16, 3);
+            let best_first = graph.search(&query, 32, 8);
+
+            for (order, ordered) in [
+                (ResultOrder::Ascending, Ordering::Less),
+                (ResultOrder::Descending, Ordering::Greater),
+            ] {
+                // twice, the second time from the result cache
+                for _ in 0..2 {
+                    let params = SearchParams::new(32, 8).order(order);
+                    let results = graph.search_with_params(&query, &params);
+                    assert!(
+                        results.windows(2).all(
+                            |pair| pair[0].score.total_cmp(&pair[1].score) != ordered.reverse()
+                        )
+                    );
+                    let mut nodes: Vec<NodeId> = results.iter().map(|result| result.node).collect();
+                    let mut expected: Vec<NodeId> =
+                        best_first.iter().map(|result| result.node).collect();
+                    nodes.sort();
+                    expected.sort();
+                    assert_eq!(nodes, expected);
+
+                    let mut out = [results[0]; 8];
+                    let len = graph.search_into(&query, &params, &mut out);
+                    let scores: Vec<f32> = out[..len].iter().map(|result| result.score).collect();
+                    let expected: Vec<f32> = results.iter().map(|result| result.score).collect();
+                    assert_eq!(scores, expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn search_within_scores_only_the_candidates() {
+        let graph = GraphBuilder::new()
+            .m(4)
+            .m0(8)
+            .dims(16)
+            .levels(2)
+            .quantization(Quantization::SignedByte)
+            .metric(DistanceMetricKind::Euclidean)
+            .build();
+        for i in 0..64 {
+            graph.index(&unit_vec(16, i), 16);
+        }
+        let query = unit_vec(16, 5);
+        let candidates = [
+            NodeId(3),
+            NodeId(21),
+            NodeId(64),
+            NodeId(37),
+            NodeId(21),
+            NodeId(5),
+        ];
+
+        let results = graph.search_within(&candidates, &query, 3);
+        let ids: Vec<NodeId> = results.iter().map(|result| result.node).collect();
+        assert_eq!(ids, [NodeId(5), NodeId(37), NodeId(21)]);
+        // scored against the full-precision copies
+        let raw = |vec: &[f32]| unsafe { &*(vec as *const [f32] as *const RawVec) };
+        let vec = unit_vec(16, 37);
+        assert_eq!(
+            results[1].score,
+            graph
+                .distance_metric
+                .calculate_raw(raw(&query), 0.0, raw(&vec), 0.0)
+        );
+
+        assert_eq!(graph.search_within(&candidates, &query, 10).len(), 4);
+        assert!(graph.search_within(&[], &query, 10).is_empty());
+    }
+
+    #[test]
+    fn classify_votes_among_labeled_neighbors() {
+        let graph = GraphBuilder::new().m(4).m0(8).dims(16).levels(2).build();
+        for i in 0..64 {
+            graph.index(&unit_vec(16, i), 16);
+        }
+        // the dimension a node peaks in, in groups of 4, for the first 48 nodes
+        let label = |id: NodeId| (id.0 < 48).then_some((id.0 % 16 / 4) as u64);
+
+        for vote in [VoteKind::Majority, VoteKind::Weighted] {
+            let class = graph
+                .classify(&unit_vec(16, 3), 32, 4, vote, label)
+                .unwrap();
+            assert_eq!(class.label, 0);
+            assert_eq!(class.score, 1.0);
+            let class = graph
+                .classify(&unit_vec(16, 13), 32, 4, vote, label)
+                .unwrap();
+            assert_eq!(class.label, 3);
+        }
+
+        let majority = graph
+            .classify(&unit_vec(16, 4), 32, 16, VoteKind::Majority, label)
+            .unwrap();
+        assert!(majority.score > 0.0 && majority.score <= 1.0);
+        assert_eq!(
+            graph.classify(&unit_vec(16, 3), 32, 4, VoteKind::Majority, |_| None),
+            None
+        );
+    }
+
+    #[test]
+    fn scratch_budget_shrinks_ef() {
+        let graph = GraphBuilder::new().m(4).m0(8).dims(16).levels(2).build();
+        for i in 0..64 {
+            graph.index(&unit_vec(16, i), 16);
+        }
+        // a result entry, plus a queue entry and visited mark per neighbor
+        let per_node = 8 + 8 * (8 + 4);
+        let bounded = |params: SearchParams<'static>| params.max_scratch_bytes(4 * per_node);
+
+        let params = bounded(SearchParams::new(64, 10).adaptive(256));
+        assert!(graph.search_with_params(&unit_vec(16, 0), &params).len() <= 4);
+        let unbounded = graph.estimate_cost(&SearchParams::new(64, 10).adaptive(256));
+        assert!(graph.estimate_cost(&params).bytes_touched < unbounded.bytes_touched);
+
+        // too small a budget for a scan
+        let outcome =
+            graph.search_with_outcome(&unit_vec(16, 0), &bounded(SearchParams::new(16, 100)));
+        assert!(outcome.results.len() <= 4);
+        assert!(!outcome.exhausted);
+
+        // never below one node
+        let params = SearchParams::new(16, 2).max_scratch_bytes(0);
+        let results = graph.search_with_params(&unit_vec(16, 0), &params);
+        let expected = graph.search(&unit_vec(16, 0), 1, 2);
+        let ids = |results: &[SearchResult]| results.iter().map(|r| r.node).collect::<Vec<_>>();
+        assert_eq!(ids(&results), ids(&expected));
+    }
+
+    #[test]
+    fn top_k_beyond_len_returns_every_node() {
+        let graph = GraphBuilder::new().m(4).m0(8).dims(16).levels(2).build();
+        let outcome = graph.search_with_outcome(&unit_vec(16, 0), &SearchParams::new(1, 10));
+        assert!(outcome.results.is_empty());
+        assert!(outcome.exhausted);
+
+        for i in 0..5 {
+            graph.index(&unit_vec(16, i), 16);
+        }
+        // ef 1 would only visit the entry point
+        let outcome = graph.search_with_outcome(&unit_vec(16, 0), &SearchParams::new(1, 10));
+        let mut ids: Vec<u32> = outcome.results.iter().map(|r| r.node.0).collect();
+        ids.sort_unstable();
+        assert_eq!(ids, [0, 1, 2, 3, 4]);
+        assert!(outcome.exhausted);
+
+        // traversal doesn't reach every node of this graph
+        for i in 5..64 {
+            graph.index(&unit_vec(16, i), 16);
+        }
+        let outcome = graph.search_with_outcome(&unit_vec(16, 0), &SearchParams::new(16, 64));
+        assert_eq!(outcome.results.len(), 64);
+        assert!(!outcome.exhausted);
+        let outcome = graph.search_with_outcome(&unit_vec(16, 0), &SearchParams::new(16, 100));
+        assert_eq!(outcome.results.len(), 64);
+        assert!(outcome.exhausted);
+
+        for i in 64..100 {
+            graph.index(&unit_vec(16, i), 16);
+        }
+        let outcome = graph.search_with_outcome(&unit_vec(16, 0), &SearchParams::new(32, 10));
+        assert_eq!(outcome.results.len(), 10);
+        assert!(!outcome.exhausted);
+    }
+
+    #[test]
+    fn drift_report_flags_magnitude_shift() {
+        let graph = GraphBuilder::new().m(4).m0(8).dims(16).levels(2).build();
+        assert_eq!(graph.drift_report(), DriftReport::default());
+
+        for i in 0..DRIFT_BASELINE as u32 {
+            graph.index(&unit_vec(16, i), 16);
+        }
+        let report = graph.drift_report();
+        assert_eq!(report.inserts, DRIFT_BASELINE);
+        assert!(report.baseline_variance > 0.0);
+        assert!(!report.is_drifted(3.0));
+
+        // the embedding model starts producing vectors twice as long
+        for i in 0..1024 {
+            let vec: Vec<f32> = unit_vec(16, i).iter().map(|x| 2.0 * x).collect();
+            graph.index(&vec, 16);
+        }
+        let report = graph.drift_report();
+        assert_eq!(report.inserts, 2 * DRIFT_BASELINE);
+        assert!((report.recent_mean - 4.0 * report.baseline_mean).abs() < 0.1);
+        assert!(report.is_drifted(3.0));
+    }
+
+    #[test]
+    fn pairwise_matches_distance() {
+        let graph = GraphBuilder::new()
+            .dims(16)
+            .quantization(Quantization::SignedByte)
+            .build();
+        for i in 0..8 {
+            graph.index(&unit_vec(16, i), 16);
+        }
+
+        let ids = [NodeId(2), NodeId(5), NodeId(9), NodeId(0)];
+        let mut out = [0.0; 16];
+        graph.pairwise(&ids, &mut out);
+        for (i, a) in ids.iter().enumerate() {
+            for (j, b) in ids.iter().enumerate() {
+                let expected = graph.distance(*a, *b);
+                let actual = out[i * ids.len() + j];
+                assert!(actual == expected || (actual.is_nan() && expected.is_nan()));
+            }
+        }
+        assert!(out[2 * 4 + 1].is_nan());
+        assert_eq!(out[1], out[4]);
+    }
+
+    #[test]
+    fn cost_estimate_grows_with_ef_and_refills() {
+        let graph = GraphBuilder::new().m(4).m0(8).dims(16).levels(2).build();
+        for i in 0..256 {
+            graph.index(&unit_vec(16, i), 16);
+        }
+
+        let cost = |params: &SearchParams| graph.estimate_cost(params);
+        let small = cost(&SearchParams::new(8, 4));
+        let large = cost(&SearchParams::new(64, 4));
+        assert!(small.distance_computations > 0);
+        assert!(large.distance_computations > small.distance_computations);
+        assert!(large.bytes_touched > small.bytes_touched);
+        // level 0 scores at most every node once, plus rescoring
+        assert!(cost(&SearchParams::new(4096, 4)).distance_computations <= 3 * 257 + 32);
+
+        let adaptive = cost(&SearchParams::new(8, 4).adaptive(64));
+        assert!(adaptive.distance_computations > small.distance_computations);
+        let filtered = cost(&SearchParams::new(8, 4).post_filter(|_, _| FilterDecision::Accept));
+        assert!(filtered.distance_computations > small.distance_computations);
+    }
+
+    #[test]
+    fn stats_count_nodes_per_level() {
+        let graph = GraphBuilder::new().m(4).m0(8).dims(16).levels(2).build();
+        assert_eq!((graph.dims(), graph.levels()), (16, 2));
+        assert_eq!(graph.stats().nodes_per_level[..], [0, 0, 0]);
+
+        for i in 0..64 {
+            graph.index(&unit_vec(16, i), 16);
+        }
+        let stats = graph.stats();
+        assert_eq!(stats.nodes_per_level[0], 64);
+        assert!(stats.nodes_per_level[1] >= stats.nodes_per_level[2]);
+        assert!(stats.mean_neighbors[0] > 0.0 && stats.mean_neighbors[0] <= 8.0);
+        assert!(stats.mean_neighbors[1..].iter().all(|&mean| mean <= 4.0));
+        // at least one chunk of each kind of slot
+        let min_bytes = CHUNK_SIZE
+            * (QuantVec::size_aligned((Quantization::FullPrecisionFP, 16))
+                + Node0::size_aligned(8));
+        assert!(stats.arena_bytes >= min_bytes as u64);
+
+        graph.delete(NodeId(3));
+        assert_eq!(graph.stats().nodes_per_level[0], 63);
+    }
+
+    #[test]
+    fn tier_report_splits_by_accesses() {
+        let graph = GraphBuilder::new()
+            .m(4)
+            .m0(8)
+            .dims(16)
+            .levels(2)
+            .quantization(Quantization::SignedByte)
+            .build();
+        for i in 0..16 {
+            graph.index(&unit_vec(16, i), 16);
+        }
+
+        let query = unit_vec(16, 3);
+        for _ in 0..ACCESS_SAMPLE_RATE * 4 {
+            assert_eq!(graph.search(&query, 16, 1)[0].node, NodeId(3));
+        }
+        assert_eq!(graph.accesses(NodeId(3)), Some(ACCESS_SAMPLE_RATE * 4));
+        assert_eq!(graph.accesses(NodeId(4)), Some(0));
+        assert_eq!(graph.accesses(NodeId(16)), None);
+
+        assert_eq!(
+            graph.tier_report(ACCESS_SAMPLE_RATE * 4),
+            TierReport {
+                hot_nodes: 1,
+                cold_nodes: 15,
+                cold_raw_bytes: 15 * RawVec::size_aligned(16) as u64,
+            }
+        );
+        assert_eq!(graph.tier_report(0).cold_nodes, 0);
+    }
+
+    #[cfg(feature = "nightly")]
+    #[test]
+    fn f16_input_matches_f32() {
+        let graph = GraphBuilder::new().m(4).m0(8).dims(16).levels(2).build();
+        for i in 0..32 {
+            let vec: Vec<f16> = unit_vec(16, i).iter().map(|&x| x as f16).collect();
+            assert_eq!(graph.index_f16(&vec, 16), NodeId(i));
+        }
+
+        let query = unit_vec(16, 9);
+        let query_f16: Vec<f16> = query.iter().map(|&x| x as f16).collect();
+        let expected = graph.search(&query, 16, 4);
+        let results = graph.search_f16(&query_f16, 16, 4);
+        assert_eq!(results.len(), expected.len());
+        for (result, expected) in results.iter().zip(&expected) {
+            assert_eq!(result.node, expected.node);
+            assert!((result.score - expected.score).abs() < 1e-3);
+        }
+        assert_eq!(
+            graph.try_index_f16(&query_f16[..8], 16),
+            Err(VectorDbError::DimensionMismatch {
+                expected: 16,
+                found: 8
+            })
+        );
+    }
+
+    #[test]
+    fn bf16_input_matches_f32() {
+        // truncating to the upper half rounds towards zero
+        let bf16 =
+            |vec: &[f32]| -> Vec<u16> { vec.iter().map(|x| (x.to_bits() >> 16) as u16).collect() };
+        let graph = GraphBuilder::new().m(4).m0(8).dims(16).levels(2).build();
+        for i in 0..32 {
+            assert_eq!(graph.index_bf16(&bf16(&unit_vec(16, i)), 16), NodeId(i));
+        }
+        assert_eq!(widen_bf16(&[0x3f80, 0xc000, 0x3e80]), [1.0, -2.0, 0.25]);
+
+        let query = unit_vec(16, 9);
+        let expected = graph.search(&query, 16, 4);
+        let results = graph.search_bf16(&bf16(&query), 16, 4);
+        assert_eq!(results.len(), expected.len());
+        for (result, expected) in results.iter().zip(&expected) {
+            assert_eq!(result.node, expected.node);
+            assert!((result.score - expected.score).abs() < 1e-2);
+        }
+        assert_eq!(
+            graph.try_index_bf16(&bf16(&query)[..8], 16),
+            Err(VectorDbError::DimensionMismatch {
+                expected: 16,
+                found: 8
+            })
+        );
+    }
+
+    #[test]
+    fn search_furthest_finds_least_similar() {
+        let graph = GraphBuilder::new().m(4).m0(8).dims(16).levels(2).build();
+        let mut state = 7u32;
+        let mut next = move || {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (state >> 8) as f32 / (1 << 24) as f32 - 0.5
+        };
+        for _ in 0..256 {
+            let vec: Vec<f32> = (0..16).map(|_| next()).collect();
+            graph.index(&vec, 16);
+        }
+
+        let query = unit_vec(16, 5);
+        let all = graph.search_quantized(&query, 512, 256);
+        let furthest = graph.search_furthest(&query, 512, 8);
+        assert_eq!(furthest.len(), 8);
+        assert_eq!(furthest[0].node, all[all.len() - 1].node);
+        assert_eq!(furthest[0].score, all[all.len() - 1].score);
+        assert!(
+            furthest
+                .windows(2)
+                .all(|pair| pair[0].score <= pair[1].score)
+        );
+    }
+
+    #[test]
+    fn flat_graph_has_no_upper_levels() {
+        let graph = GraphBuilder::new().m0(16).dims(16).levels(0).build();
+        let mut state = 7u32;
+        let mut next = move || {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (state >> 8) as f32 / (1 << 24) as f32 - 0.5
+        };
+        let vecs: Vec<Vec<f32>> = (0..256)
+            .map(|_| (0..16).map(|_| next()).collect())
+            .collect();
+        for vec in &vecs {
+            graph.index(vec, 32);
+        }
+        assert_eq!(graph.nodes_arena.len(), 0);
+
+        let found = vecs
+            .iter()
+            .enumerate()
+            .filter(|(i, vec)| graph.search(vec, 32, 1)[0].node == NodeId(*i as u32))
+            .count();
+        assert!(found >= 240, "found {found}");
+
+        // entry points depend on the query only
+        let query = &vecs[17];
+        assert_eq!(
+            graph.search_quantized(query, 8, 4)[..]
+                .iter()
+                .map(|result| result.node)
+                .collect::<Vec<_>>(),
+            graph.search_quantized(query, 8, 4)[..]
+                .iter()
+                .map(|result| result.node)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn search_walks_past_the_entry_node() {
+        let graph = Graph::new(
+            4,
+            8,
+            16,
+            2,
+            Quantization::FullPrecisionFP,
+            DistanceMetricKind::Cosine,
+        );
+        for i in 0..32 {
+            graph.index(&unit_vec(16, i), 32);
+        }
+
+        // expanding only the entry node would stop at it and its m0 neighbors
+        let results = graph.search_quantized(&unit_vec(16, 5), 64, 64);
+        assert!(results.len() > 8 + 1);
+    }
+
+    #[test]
+    fn concurrent_inserts_and_searches() {
+        extern crate std;
+
+        let graph = Graph::new(
+            4,
+            8,
+            16,
+            2,
+            Quantization::FullPrecisionFP,
+            DistanceMetricKind::Cosine,
+        );
+
+        let mut ids: Vec<_> = std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..4)
+                .map(|t| {
+                    let graph = &graph;
+                    scope.spawn(move || {
+                        (0..64)
+                            .map(|i| {
+                                let id = graph.index(&unit_vec(16, t * 64 + i), 16);
+                                assert!(!graph.search(&unit_vec(16, i), 16, 4).is_empty());
+                                id
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            threads
+                .into_iter()
+                .flat_map(|thread| thread.join().unwrap())
+                .collect()
+        });
+
+        ids.sort_unstable();
+        ids.dedup();
+        assert_eq!(ids.len(), 256);
+    }
+
+    #[test]
+    fn builder_defaults() {
+        let graph = GraphBuilder::new().dims(16).build();
+        assert_eq!((graph.m, graph.m0, graph.levels), (16, 32, 4));
+
+        let id = graph.index(&unit_vec(16, 3), 16);
+        assert_eq!(graph.search(&unit_vec(16, 3), 16, 1)[0].node, id);
+    }
+
+    #[test]
+    #[should_panic(expected = "dims must be set")]
+    fn builder_needs_dims() {
+        GraphBuilder::new().build();
+    }
+
+    #[test]
+    fn rescore_mode_picks_the_scores() {
+        let graph = GraphBuilder::new()
+            .dims(16)
+            .quantization(Quantization::SignedByte)
+            .metric(DistanceMetricKind::Euclidean)
+            .build();
+        for i in 0..64 {
+            graph.index(&unit_vec(16, i), 32);
+        }
+        let query = unit_vec(16, 40);
+
+        let params = SearchParams::new(32, 4).rescore(RescoreMode::None);
+        let quantized = graph.search_with_params(&query, &params);
+        let expected = graph.search_quantized(&query, 32, 4);
+        assert_eq!(quantized.len(), 4);
+        for (result, expected) in quantized.iter().zip(&expected) {
+            assert_eq!((result.node, result.score), (expected.node, expected.score));
+        }
+
+        let params = SearchParams::new(32, 4).rescore(RescoreMode::FullPrecision { oversample: 1 });
+        let exact = graph.search_with_params(&query, &params);
+        let ids: Vec<_> = exact.iter().map(|result| result.node).collect();
+        let expected = graph.search_within(&ids, &query, 4);
+        for (result, expected) in exact.iter().zip(&expected) {
+            assert_eq!((result.node, result.score), (expected.node, expected.score));
+        }
+    }
+
+    #[test]
+    fn reranker_supplies_the_scores() {
+        // ranks candidates by id, lowest first
+        struct ById;
+        impl Reranker for ById {
+            fn rerank(&self, query: &[f32], candidates: &[NodeId], scores: &mut [f32]) {
+                assert_eq!(query.len(), 16);
+                for (id, score) in candidates.iter().zip(scores) {
+                    *score = -(id.0 as f32);
+                }
+            }
+        }
+
+        let graph = GraphBuilder::new()
+            .dims(16)
+            .quantization(Quantization::SignedByte)
+            .reranker(ById)
+            .build();
+        for i in 0..64 {
+            graph.index(&unit_vec(16, i), 32);
+        }
+
+        let results = graph.search(&unit_vec(16, 40), 32, 4);
+        assert_eq!(results.len(), 4);
+        for pair in results.windows(2) {
+            assert!(pair[0].node.0 < pair[1].node.0);
+        }
+        for result in &results[..] {
+            assert_eq!(result.score, -(result.node.0 as f32));
+        }
+
+        let mut out = [SearchResult {
+            node: NodeId(0),
+            score: 0.0,
+        }; 4];
+        graph.search_into(&unit_vec(16, 40), &SearchParams::new(32, 4), &mut out);
+        for (written, result) in out.iter().zip(&results) {
+            assert_eq!(written.node, result.node);
+        }
+    }
+
+    #[test]
+    fn seed_and_chunk_size_shape_the_build() {
+        let build = |seed, chunk_size| {
+            let graph = GraphBuilder::new()
+                .dims(16)
+                .seed(seed)
+                .chunk_size(chunk_size)
+                .build();
+            for i in 0..64 {
+                graph.index(&unit_vec(16, i), 32);
+            }
+            graph
+        };
+
+        let graph = build(7, 16);
+        assert_eq!(graph.serialize(), build(7, 16).serialize());
+        assert_ne!(graph.serialize(), build(8, 16).serialize());
+        // same levels and links, laid out in smaller chunks
+        let small_chunks = build(7, 4);
+        assert_eq!(graph.serialize(), small_chunks.serialize());
+        assert!(graph.stats().arena_bytes > small_chunks.stats().arena_bytes);
+        let ids = |graph: &Graph| {
+            let results = graph.search(&unit_vec(16, 3), 32, 4);
+            results.iter().map(|result| result.node).collect::<Vec<_>>()
+        };
+        assert_eq!(ids(&graph), ids(&small_chunks));
+    }
+
+    #[test]
+    fn fragmentation_report_counts_reusable_slots() {
+        let graph = GraphBuilder::new()
+            .dims(16)
+            .chunk_size(16)
+            .record_ingest(true)
+            .priors(true)
+            .build();
+        for i in 0..40 {
+            graph.index(&unit_vec(16, i), 16);
+        }
+        let report = graph.fragmentation_report();
+        // 41 slots with the root's, in 3 chunks
+        assert_eq!(report.vectors.chunks, 3);
+        assert_eq!(report.vectors.partial_chunks, 1);
+        assert_eq!(report.vectors.unused_slots, 7);
+        assert_eq!(report.vectors.tombstoned_slots, 0);
+        assert_eq!(report.expiry, None);
+        assert_eq!(report.ingest.unwrap().chunks, 3);
+        assert_eq!(report.priors.unwrap().chunks, 3);
+
+        for id in [3, 9, 27] {
+            assert!(graph.delete(NodeId(id)));
+        }
+        let report = graph.fragmentation_report();
+        assert_eq!(report.vectors.tombstoned_slots, 3);
+        assert_eq!(report.level0_nodes.tombstoned_slots, 3);
+        assert_eq!(report.ingest.unwrap().tombstoned_slots, 3);
+        assert_eq!(report.priors.unwrap().tombstoned_slots, 3);
+        assert_eq!(
+            report.vectors.reusable_bytes(),
+            10 * report.vectors.slot_bytes
+        );
+        assert!(report.reusable_bytes() <= graph.stats().arena_bytes);
+
+        graph.index(&unit_vec(16, 3), 16);
+        let after = graph.fragmentation_report();
+        assert_eq!(after.vectors.tombstoned_slots, 2);
+        assert_eq!(after.vectors.unused_slots, 7);
+    }
+
+    #[test]
+    fn priors_blend_into_scores() {
+        let nodes = |results: &[SearchResult]| results.iter().map(|r| r.node).collect::<Vec<_>>();
+        let graph = GraphBuilder::new().dims(16).priors(true).build();
+        for i in 0..16 {
+            graph.index(&unit_vec(16, i), 16);
+        }
+
+        let query = unit_vec(16, 3);
+        let plain = graph.search(&query, 32, 4);
+        let popular = plain[2];
+        assert_eq!(graph.prior(popular.node), Some(0.0));
+        graph.set_prior(popular.node, 2.0).unwrap();
+        assert_eq!(graph.prior(popular.node), Some(2.0));
+
+        // without a weight the prior is ignored
+        assert_eq!(graph.search(&query, 32, 4)[0].node, plain[0].node);
+        let params = SearchParams::new(32, 4).prior_weight(0.5);
+        let blended = graph.search_with_params(&query, &params);
+        assert_eq!(blended[0].node, popular.node);
+        assert_eq!(blended[0].score, popular.score + 1.0);
+        assert_eq!(blended[1].node, plain[0].node);
+        // every entry point blends alike
+        let mut out = [blended[0]; 4];
+        let len = graph.search_into(&query, &params, &mut out);
+        assert_eq!(nodes(&out[..len]), nodes(&blended));
+        let len = graph.search_with_context(&mut SearchContext::new(), &query, &params, &mut out);
+        assert_eq!(nodes(&out[..len]), nodes(&blended));
+        let shared = graph.search_batch_shared(&[&query], &params);
+        assert_eq!(nodes(&shared[0]), nodes(&blended));
+
+        assert!(graph.delete(popular.node));
+        assert_eq!(
+            graph.set_prior(popular.node, 1.0),
+            Err(VectorDbError::UnknownNode)
+        );
+        assert_eq!(graph.prior(popular.node), None);
+        let graph = GraphBuilder::new().dims(16).build();
+        let id = graph.index(&query, 16);
+        assert_eq!(graph.set_prior(id, 1.0), Err(VectorDbError::NoPriors));
+    }
+
+    #[cfg(feature = "f16-scores")]
+    #[test]
+    fn f16_scores_clamp_large_distances() {
+        let graph = GraphBuilder::new()
+            .m(4)
+            .m0(8)
+            .dims(16)
+            .metric(DistanceMetricKind::Euclidean)
+            .build();
+        // squared distances of around a million, far past the range of f16
+        for i in 0..32 {
+            let vec: Vec<f32> = unit_vec(16, i).iter().map(|x| x * 1000.0).collect();
+            graph.index(&vec, 16);
+        }
+
+        for index in 1..graph.nodes0_arena.len() as u32 {
+            let neighbors = graph.nodes0_arena[Node0Handle::new(index)].neighbors.read();
+            for neighbor in neighbors.neighbors() {
+                let score = neighbor.score(&graph.distance_metric);
+                assert!(score.is_finite() && score <= crate::node::MAX_STORED_SCORE);
+            }
+        }
+        let query: Vec<f32> = unit_vec(16, 7).iter().map(|x| x * 1000.0).collect();
+        assert_eq!(graph.search(&query, 32, 1)[0].node, NodeId(7));
+    }
+
+    #[test]
+    fn half_precision_indexes_similarity_metrics() {
+        for metric in [DistanceMetricKind::Cosine, DistanceMetricKind::DotProduct] {
+            let graph = GraphBuilder::new()
+                .dims(16)
+                .metric(metric)
+                .quantization(Quantization::HalfPrecisionFP)
+                .build();
+            for i in 0..16 {
+                graph.index(&unit_vec(16, i), 16);
+            }
+            let loaded = Graph::deserialize(&graph.serialize()).unwrap();
+            for graph in [&graph, &loaded] {
+                assert_eq!(graph.search(&unit_vec(16, 7), 32, 1)[0].node, NodeId(7));
+            }
+        }
+    }
+
+    #[test]
+    fn normalized_cosine_scores_dot_products() {
+        let normalized = |i| {
+            let vec = unit_vec(16, i);
+            let norm = dot_product_f32(&vec, &vec).sqrt();
+            vec.iter().map(|x| x / norm).collect::<Vec<f32>>()
+        };
+        let plain = GraphBuilder::new().dims(16).build();
+        let fast = GraphBuilder::new().dims(16).assume_normalized(true).build();
+        for i in 0..32 {
+            plain.index(&normalized(i), 16);
+            fast.index(&normalized(i), 16);
+        }
+
+        let query = normalized(5);
+        let expected = plain.search(&query, 32, 4);
+        let results = fast.search(&query, 32, 4);
+        for (result, expected) in results.iter().zip(&expected) {
+            assert_eq!(result.node, expected.node);
+            assert!((result.score - expected.score).abs() < 1e-5);
+        }
+
+        // a longer query scales the score instead of being divided out
+        let doubled: Vec<f32> = query.iter().map(|x| x * 2.0).collect();
+        let id = results[0].node;
+        assert!((fast.distance_to(id, &doubled) - 2.0).abs() < 1e-5);
+        assert!((plain.distance_to(id, &doubled) - 0.5).abs() < 1e-5);
+
+        let euclidean = GraphBuilder::new()
+            .dims(16)
+            .metric(DistanceMetricKind::Euclidean)
+            .assume_normalized(true);
+        assert_eq!(
+            euclidean.check(),
+            Err("assume_normalized needs the Cosine metric")
+        );
+    }
+
+    #[test]
+    fn l1_and_linf_graphs_rank_closest_first() {
+        for metric in [DistanceMetricKind::Manhattan, DistanceMetricKind::Chebyshev] {
+            let graph = GraphBuilder::new().dims(16).metric(metric).build();
+            for i in 0..16 {
+                graph.index(&unit_vec(16, i), 16);
+            }
+
+            let results = graph.search(&unit_vec(16, 3), 16, 4);
+            assert_eq!(results[0].node, NodeId(3), "{metric:?}");
+            assert_eq!(results[0].score, 0.0);
+            assert!(
+                results
+                    .windows(2)
+                    .all(|pair| pair[0].score <= pair[1].score)
+            );
+
+            let loaded = Graph::deserialize(&graph.serialize()).unwrap();
+            assert_eq!(loaded.search(&unit_vec(16, 3), 16, 1)[0].node, NodeId(3));
+        }
+    }
+
+    #[test]
+    fn range_search_returns_passing_nodes() {
+        let graph = GraphBuilder::new()
+            .dims(16)
+            .metric(DistanceMetricKind::Euclidean)
+            .build();
+        for i in 0..32 {
+            let mut vec = unit_vec(16, 0);
+            vec[1] = i as f32 / 8.0;
+            graph.index(&vec, 32);
+        }
+
+        let query = unit_vec(16, 0);
+        let all = graph.search(&query, 64, 32);
+        assert_eq!(all.len(), 32);
+        let threshold = all[5].score;
+        let nodes: Vec<NodeId> = all
+            .iter()
+            .filter(|result| result.score <= threshold)
+            .map(|result| result.node)
+            .collect();
+        let range = graph.search_range(&query, 64, threshold);
+        assert_eq!(
+            range.iter().map(|result| result.node).collect::<Vec<_>>(),
+            nodes
+        );
+        assert!(graph.search_range(&query, 64, -1.0).is_empty());
+        // a loose threshold is capped at ef
+        assert_eq!(graph.search_range(&query, 8, f32::MAX).len(), 8);
+    }
+
+    #[test]
+    fn banded_search_counts_every_candidate() {
+        let graph = GraphBuilder::new()
+            .dims(16)
+            .quantization(Quantization::FullPrecisionFP)
+            .build();
+        for i in 0..32 {
+            let mut vec = unit_vec(16, 0);
+            vec[1] = i as f32 / 8.0;
+            graph.index(&vec, 32);
+        }
+
+        let query = unit_vec(16, 0);
+        let scores: Vec<f32> = graph
+            .search_with_params(&query, &SearchParams::new(64, 32))
+            .iter()
+            .map(|result| result.score)
+            .collect();
+        assert_eq!(scores.len(), 32);
+        let params = SearchParams::new(64, 4);
+        let banded = graph.search_banded(&query, &params, &[0.9, 0.7]);
+        let expected = [
+            scores.iter().filter(|&&score| score >= 0.9).count() as u32,
+            scores
+                .iter()
+                .filter(|&&score| (0.7..0.9).contains(&score))
+                .count() as u32,
+            scores.iter().filter(|&&score| score < 0.7).count() as u32,
+        ];
+        assert_eq!(*banded.counts, expected);
+
+        let searched = graph.search_with_params(&query, &params);
+        assert_eq!(banded.results.len(), 4);
+        for (banded, searched) in banded.results.iter().zip(&searched) {
+            assert_eq!(banded.node, searched.node);
+            assert_eq!(banded.score, searched.score);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "best first")]
+    fn bands_must_be_ordered() {
+        let graph = GraphBuilder::new().dims(16).build();
+        graph.search_banded(&unit_vec(16, 0), &SearchParams::new(16, 4), &[0.5, 0.9]);
+    }
+
+    #[test]
+    fn builder_efs_drive_add_and_nearest() {
+        let build = || {
+            GraphBuilder::new()
+                .dims(16)
+                .ef_construction(24)
+                .ef_search(8)
+                .build()
+        };
+        let (added, indexed) = (build(), build());
+        for i in 0..64 {
+            assert_eq!(
+                added.add(&unit_vec(16, i)),
+                indexed.index(&unit_vec(16, i), 24)
+            );
+        }
+        assert_eq!(added.serialize(), indexed.serialize());
+
+        let ids = |results: Box<[SearchResult]>| {
+            results.iter().map(|result| result.node).collect::<Vec<_>>()
+        };
+        let query = unit_vec(16, 3);
+        assert_eq!(
+            ids(added.nearest(&query, 4)),
+            ids(added.search(&query, 8, 4))
+        );
+        // ef never drops below top_k
+        assert_eq!(
+            ids(added.nearest(&query, 20)),
+            ids(added.search(&query, 20, 20))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ef_construction must be positive")]
+    fn builder_needs_an_ef() {
+        GraphBuilder::new().dims(16).ef_construction(0).build();
+    }
+
+    #[test]
+    fn raw_chunks_follow_the_documented_layout() {
+        let graph = GraphBuilder::new()
+            .dims(16)
+            .quantization(Quantization::SignedByte)
+            .chunk_size(4)
+            .build();
+        for i in 0..10 {
+            graph.index(&unit_vec(16, i), 32);
+        }
+
+        let chunks = unsafe { graph.chunks_raw() };
+        let lens = |chunks: &[RawChunk]| chunks.iter().map(|chunk| chunk.len).collect::<Vec<_>>();
+        assert_eq!(lens(&chunks.quantized), [4, 4, 3]);
+        assert_eq!(lens(&chunks.full_precision), [4, 4, 3]);
+
+        // NodeId(5) sits in slot 6, the third of the second chunk
+        let vec = unit_vec(16, 5);
+        let quantized = chunks.quantized[1];
+        let slot = unsafe { quantized.ptr.add(2 * quantized.stride) };
+        let mag = unsafe { (slot.add(layout::MAGNITUDE_OFFSET) as *const f32).read() };
+        assert_eq!(mag, dot_product_f32(&vec, &vec));
+        let components = unsafe { slot.add(layout::COMPONENTS_OFFSET) as *const i8 };
+        for (i, dim) in vec.iter().enumerate() {
+            assert_eq!(unsafe { components.add(i).read() }, (dim * 127.0) as i8);
+        }
+        let full_precision = chunks.full_precision[1];
+        let slot = unsafe { full_precision.ptr.add(2 * full_precision.stride) as *const f32 };
+        assert_eq!(unsafe { core::slice::from_raw_parts(slot, 16) }, &vec[..]);
+    }
+
+    #[test]
+    #[should_panic(expected = "chunk size must be positive")]
+    fn builder_needs_chunks() {
+        GraphBuilder::new().dims(16).chunk_size(0).build();
+    }
+
+    #[test]
+    fn cache_evicts_least_recently_returned() {
+        let graph = GraphBuilder::new()
+            .m(4)
+            .m0(8)
+            .dims(16)
+            .levels(2)
+            .cache_budget(8)
+            .build();
+
+        // keep one node hot while the rest of the cache churns through vectors
+        // that never compete with it
+        let hot = unit_vec(16, 14);
+        for i in 0..8 {
+            if i == 3 {
+                graph.index(&hot, 16);
+            } else {
+                graph.index(&unit_vec(16, i), 16);
+            }
+        }
+
+        for i in 8..64 {
+            let results = graph.search_quantized(&hot, 16, 1);
+            assert_eq!(results[0].node, NodeId(3));
+            graph.index(&unit_vec(16, i % 12), 16);
+        }
+
+        assert_eq!(
+            &graph
+                .raw_vec(NodeId(3).to_vec_handle().handle_a())
+                .unwrap()
+                .vec,
+            &hot[..]
+        );
+    }
+
+    #[test]
+    fn evicted_slot_links_are_rescored() {
+        let graph = GraphBuilder::new()
+            .m(4)
+            .m0(8)
+            .dims(16)
+            .levels(2)
+            .metric(DistanceMetricKind::Euclidean)
+            .cache_budget(16)
+            .build();
+        // each round of 16 lands further out, far from the vectors it evicts
+        for i in 0..64 {
+            let scale = (1 + i / 16) as f32;
+            let vec: Vec<f32> = unit_vec(16, i).iter().map(|x| x * scale).collect();
+            graph.index(&vec, 16);
+        }
+        check_stored_link_scores(&graph);
+    }
+
+    #[cfg(feature = "allocator_api")]
+    #[test]
+    fn index_memory_comes_from_the_allocator() {
+        use alloc::{alloc::Global, sync::Arc};
+        use core::{
+            alloc::{AllocError, Allocator},
+            ptr::NonNull,
+            sync::atomic::AtomicUsize,
+        };
+
+        #[derive(Clone, Default)]
+        struct Counting(Arc<AtomicUsize>);
+
+        unsafe impl Allocator for Counting {
+            fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+                self.0.fetch_add(layout.size(), AtomicOrdering::Relaxed);
+                Global.allocate(layout)
+            }
+
+            unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+                self.0.fetch_sub(layout.size(), AtomicOrdering::Relaxed);
+                unsafe { Global.deallocate(ptr, layout) }
+            }
+        }
+
+        let counting = Counting::default();
+        let graph = GraphBuilder::new()
+            .dims(16)
+            .quantization(Quantization::SignedByte)
+            .allocator(counting.clone())
+            .build();
+        let empty = counting.0.load(AtomicOrdering::Relaxed);
+        assert!(empty > 0);
+
+        for i in 0..8 {
+            graph.index(&unit_vec(16, i), 16);
+        }
+        assert_eq!(graph.search(&unit_vec(16, 3), 16, 1)[0].node, NodeId(3));
+
+        drop(graph);
+        assert_eq!(counting.0.load(AtomicOrdering::Relaxed), 0);
+    }
+
+    #[cfg(all(feature = "debug-checks", debug_assertions))]
+    fn random_graph(metric: DistanceMetricKind) -> Graph {
+        let graph = GraphBuilder::new()
+            .m(4)
+            .m0(8)
+            .dims(16)
+            .levels(2)
+            .metric(metric)
+            .build();
+        let mut state = 1u32;
+        let mut next = move || {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (state >> 8) as f32 / (1 << 24) as f32 - 0.5
+        };
+        for _ in 0..256 {
+            let vec: Vec<f32> = (0..16).map(|_| next()).collect();
+            graph.index(&vec, 16);
+        }
+        graph
+    }
+
+    #[cfg(all(feature = "debug-checks", debug_assertions))]
+    #[test]
+    fn link_scores_match_full_precision() {
+        // every insert checks its links
+        random_graph(DistanceMetricKind::Cosine);
+        random_graph(DistanceMetricKind::DotProduct);
+        random_graph(DistanceMetricKind::Euclidean);
+        random_graph(DistanceMetricKind::Manhattan);
+        random_graph(DistanceMetricKind::Chebyshev);
+    }
+
+    #[cfg(all(feature = "debug-checks", debug_assertions))]
+    #[test]
+    #[should_panic(expected = "full precision gives")]
+    fn mismatched_link_score_is_caught() {
+        let graph = random_graph(DistanceMetricKind::Cosine);
+        let vec_handle = graph.nodes0_arena[Node0Handle::new(10)].vec;
+        {
+            let node = &graph.nodes0_arena[graph.meta(vec_handle).top().cast::<Node0>()];
+            let mut neighbors = node.neighbors.write();
+            let first = &neighbors.neighbors[0];
+            neighbors.neighbors[0] = Neighbor0::new(
+                &graph.distance_metric,
+                first.node(),
+                -first.score(&graph.distance_metric),
+            );
+        }
+        graph.check_link_scores(vec_handle);
+    }
+}
+
+// Run with `RUSTFLAGS="--cfg loom" cargo test --release --lib loom`.
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use loom::{model::Builder, sync::Arc, thread};
+
+    use super::*;
+
+    fn model(f: impl Fn() + Sync + Send + 'static) {
+        let mut builder = Builder::new();
+        builder.preemption_bound.get_or_insert(3);
+        builder.check(f);
+    }
+
+    fn build() -> Graph {
+        GraphBuilder::new()
+            .m(2)
+            .m0(2)
+            .dims(4)
+            .levels(2)
+            .quantization(Quantization::SignedByte)
+            .build()
+    }
+
+    // Every neighbor list is a valid prefix, free of self-links, and its cached
+    // lowest entry matches its contents.
+    fn check_neighbors(graph: &Graph) {
+        for i in 0..graph.nodes0_arena.len() as u32 {
+            let handle = Node0Handle::new(i);
+            let neighbors = graph.nodes0_arena[handle].neighbors.read();
+            let list = neighbors.neighbors();
+            assert!(list.iter().all(|neighbor| neighbor.node() != handle));
+
+            if neighbors.neighbors_full {
+                let lowest = &neighbors.neighbors[neighbors.lowest_index as usize];
+                assert_eq!(lowest.score(&graph.distance_metric), neighbors.lowest_score);
+                assert!(list.iter().all(|neighbor| {
+                    graph.distance_metric.cmp_score(
+                        neighbor.score(&graph.distance_metric),
+                        neighbors.lowest_score,
+                    ) != Ordering::Less
+                }));
+            } else {
+                assert_eq!(list.len(), neighbors.lowest_index as usize);
+            }
+        }
+    }
+
+    #[test]
+    fn concurrent_index() {
+        model(|| {
+            let graph = Arc::new(build());
+            graph.index(&[1.0, 0.2, 0.2, 0.0], 4);
+
+            let writer = {
+                let graph = graph.clone();
+                thread::spawn(move || graph.index(&[0.2, 1.0, 0.2, 0.0], 4))
+            };
+            let b = graph.index(&[0.2, 0.2, 1.0, 0.0], 4);
+            let a = writer.join().unwrap();
+
+            assert_ne!(a, b);
+            assert_eq!(graph.nodes0_arena.len(), 4);
+            check_neighbors(&graph);
+        });
+    }
+
+    #[test]
+    fn search_during_index() {
+        model(|| {
+            let graph = Arc::new(build());
+            graph.index(&[1.0, 0.2, 0.2, 0.0], 4);
+            graph.index(&[0.2, 1.0, 0.2, 0.0], 4);
+
+            let writer = {
+                let graph = graph.clone();
+                thread::spawn(move || graph.index(&[0.2, 0.2, 1.0, 0.0], 4))
+            };
+            // the node being inserted may or may not be visible yet, but the
+            // finished ones always are
+            let results = graph.search(&[1.0, 0.2, 0.2, 0.0], 4, 1);
+            assert_eq!(results[0].node, NodeId(0));
+            let c = writer.join().unwrap();
+
+            let results = graph.search(&[0.2, 0.2, 1.0, 0.0], 4, 1);
+            assert_eq!(results[0].node, c);
+            check_neighbors(&graph);
+        });
+    }
+
+    #[test]
+    fn scan_during_index() {
+        model(|| {
+            let graph = Arc::new(build());
+            graph.index(&[1.0, 0.2, 0.2, 0.0], 4);
+
+            let writer = {
+                let graph = graph.clone();
+                thread::spawn(move || graph.index(&[0.2, 1.0, 0.2, 0.0], 4))
+            };
+            // covers every slot, so the unpublished one is skipped, not read
+            let results = graph.search(&[1.0, 0.2, 0.2, 0.0], 4, 8);
+            assert!(matches!(results.len(), 1 | 2));
+            assert_eq!(results[0].node, NodeId(0));
+            writer.join().unwrap();
+
+            assert_eq!(graph.search(&[1.0, 0.2, 0.2, 0.0], 4, 8).len(), 2);
+        });
     }
 }
@@ -4,6 +4,7 @@ use core::{fmt, hash, marker::PhantomData, ops::Deref};
 
 use alloc::format;
 
+#[repr(transparent)]
 pub struct Handle<T: ?Sized> {
     index: u32,
     _marker: PhantomData<T>,
@@ -0,0 +1,62 @@
+//! Byte layout of the vector arenas, for readers outside the crate such as a
+//! GPU reranker copying whole chunks, see `Graph::chunks_raw`
+//!
+//! Chunks hold `GraphBuilder::chunk_size` slots of `stride` bytes each. Slot 0
+//! of the first chunk holds the synthetic root, an all-zero vector, and slot
+//! `n + 1` the vector of `NodeId(n)`. All values are native-endian.
+//!
+//! A quantized slot starts with the squared magnitude of the full-precision
+//! vector as an f32 at `MAGNITUDE_OFFSET`, followed at `COMPONENTS_OFFSET` by
+//! one component per dimension:
+//!
+//! - `SignedByte`: i8, the component times 127
+//! - `UnsignedByte`: u8, the component times 255
+//! - `HalfPrecisionFP`: f16 bits in a u16
+//! - `FullPrecisionFP`: f32
+//! - `Binary`: u64 words, bit `i % 64` of word `i / 64` set when component `i`
+//!   is positive; the words are only 4-byte aligned
+//!
+//! A full-precision slot holds `dims` f32s.
+
+use alloc::boxed::Box;
+
+use crate::{
+    arena::DynAlloc,
+    storage::{QuantVec, Quantization, RawVec},
+};
+
+/// Alignment of every slot, in bytes
+pub const ALIGN: usize = 4;
+/// Offset of the squared magnitude in a quantized slot
+pub const MAGNITUDE_OFFSET: usize = 0;
+/// Offset of the first component in a quantized slot
+pub const COMPONENTS_OFFSET: usize = 4;
+
+/// Bytes from one quantized slot to the next
+pub fn quantized_stride(quantization: Quantization, dims: u16) -> usize {
+    QuantVec::size_aligned((quantization, dims))
+}
+
+/// Bytes from one full-precision slot to the next
+pub fn full_precision_stride(dims: u16) -> usize {
+    RawVec::size_aligned(dims)
+}
+
+/// A chunk of vector slots, see `Graph::chunks_raw`
+#[derive(Debug, Clone, Copy)]
+pub struct RawChunk {
+    pub ptr: *const u8,
+    /// Slots in use, counted from the start of the chunk
+    pub len: usize,
+    /// Bytes from one slot to the next
+    pub stride: usize,
+}
+
+/// The vector chunks of a graph, in slot order
+#[derive(Debug, Clone)]
+pub struct VecChunks {
+    pub quantized: Box<[RawChunk]>,
+    /// Empty when the graph keeps no full-precision copies, see
+    /// `GraphBuilder::rerank`
+    pub full_precision: Box<[RawChunk]>,
+}
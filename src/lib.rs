@@ -1,24 +1,85 @@
-#![no_std]
-#![feature(ptr_metadata, f16, new_zeroed_alloc, portable_simd)]
+#![cfg_attr(not(any(feature = "std", loom)), no_std)]
+#![cfg_attr(feature = "nightly", feature(f16, portable_simd))]
+#![cfg_attr(feature = "allocator_api", feature(allocator_api))]
 
 extern crate alloc;
 
+mod allocator;
 mod arena;
-mod fixedset;
+mod builder;
+mod cache;
+mod cancel;
+#[cfg(feature = "checked")]
+mod checked;
+mod epoch;
+mod error;
 mod graph;
 mod handle;
+pub mod layout;
 mod mem_project;
 mod metric;
 mod node;
+mod params;
+mod pressure;
 mod random;
+mod rerank;
+mod router;
 mod rwlock;
+mod snapshot;
 mod storage;
+mod sync;
 mod util;
+mod visited;
 
-pub use graph::{Graph, InternalSearchResult};
-pub use mem_project::mem_project;
-pub use metric::DistanceMetricKind;
-pub use storage::Quantization;
+pub use builder::GraphBuilder;
+pub use cancel::CancellationToken;
+#[cfg(feature = "checked")]
+pub use checked::CheckedGraph;
+pub use error::{DeserializeError, VectorDbError};
+pub use graph::{
+    ArenaUsage, BandedResults, ClippingStats, CostEstimate, DriftReport, FragmentationReport,
+    Graph, GraphStats, Ingest, InternalSearchResult, LabelScore, SearchContext, SearchOutcome,
+    SearchProvenance, SearchResult, SearchTrace, TierReport, VoteKind,
+};
+pub use mem_project::{MemProjectOptions, mem_project, mem_project_with};
+pub use metric::{DistanceMetricKind, KERNEL_VERSION};
+pub use node::{NeighborOrder, NeighborSelection};
+pub use params::{FilterDecision, MAX_TOP_K, RescoreMode, ResultOrder, SearchParams};
+pub use pressure::MemoryPressureHook;
+pub use rerank::Reranker;
+pub use router::Router;
+pub use storage::{QuantVec, Quantization};
+pub use visited::VisitedSet;
 
+use node::VecHandle;
+
+/// Identifies an indexed vector
+///
+/// Ids are handed out in insertion order: the n-th successful insert into a graph
+/// gets `NodeId(n - 1)`, so ids are dense and increase with every insert.
+/// Concurrent inserts get distinct ids in the order they reach allocation. The
+/// exceptions are slot reuse, by cache mode evictions or after
+/// `Graph::purge_expired` or `Graph::delete`, where a new vector takes over an
+/// existing id, and inserts aliased to a near-duplicate (see
+/// `GraphBuilder::min_insert_distance`), which return the existing node's id.
+#[repr(transparent)]
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub struct NodeId(pub u32);
+
+// Vector slot 0 holds the synthetic root, so ids are one less than the handles
+// of the slots they name. Every conversion between the two goes through here.
+impl NodeId {
+    #[inline]
+    pub(crate) fn from_vec_handle(handle: VecHandle) -> Self {
+        debug_assert_ne!(*handle, 0, "the root has no node id");
+        Self(*handle - 1)
+    }
+
+    // The id must name a slot of the graph, see `Graph::live_vec` for ids that
+    // come from outside.
+    #[inline]
+    pub(crate) fn to_vec_handle(self) -> VecHandle {
+        debug_assert_ne!(self.0, u32::MAX, "no slot past the last handle");
+        VecHandle::new(self.0 + 1)
+    }
+}
@@ -1,7 +1,10 @@
 use crate::{
-    Quantization,
-    arena::DynAlloc,
-    node::{Node, Node0},
+    Graph, Quantization,
+    arena::{DynAlloc, FreeLink},
+    cache::ResultCache,
+    graph::{CHUNK_SIZE, LEVEL_FACTOR},
+    node::{Node, Node0, NodeExpiry, NodeIngest, NodeMeta, NodePrior},
+    storage::{QuantVec, RawVec},
 };
 
 pub fn len_to_cap(mut x: u64) -> u64 {
@@ -18,6 +21,109 @@ pub fn len_to_cap(mut x: u64) -> u64 {
     x + 1
 }
 
+/// The settings of a graph to project the memory of, see `mem_project_with`
+///
+/// Mirrors the `GraphBuilder` options that change what a graph allocates, with
+/// the same defaults.
+#[derive(Debug, Clone, Copy)]
+pub struct MemProjectOptions {
+    m: u16,
+    m0: u16,
+    dims: u16,
+    levels: u8,
+    quantization: Quantization,
+    rerank: bool,
+    expiry: bool,
+    record_ingest: bool,
+    priors: bool,
+    deletes: bool,
+    result_cache: Option<(usize, u16)>,
+    chunk_size: usize,
+}
+
+impl MemProjectOptions {
+    pub fn new(dims: u16) -> Self {
+        Self {
+            m: 16,
+            m0: 32,
+            dims,
+            levels: 4,
+            quantization: Quantization::FullPrecisionFP,
+            rerank: true,
+            expiry: false,
+            record_ingest: false,
+            priors: false,
+            deletes: false,
+            result_cache: None,
+            chunk_size: CHUNK_SIZE,
+        }
+    }
+
+    pub fn m(mut self, m: u16) -> Self {
+        self.m = m;
+        self
+    }
+
+    pub fn m0(mut self, m0: u16) -> Self {
+        self.m0 = m0;
+        self
+    }
+
+    pub fn levels(mut self, levels: u8) -> Self {
+        self.levels = levels;
+        self
+    }
+
+    pub fn quantization(mut self, quantization: Quantization) -> Self {
+        self.quantization = quantization;
+        self
+    }
+
+    /// Whether full-precision copies are kept, see `GraphBuilder::rerank`
+    pub fn rerank(mut self, rerank: bool) -> Self {
+        self.rerank = rerank;
+        self
+    }
+
+    /// Whether the graph has a clock, which keeps an expiry time per node,
+    /// see `GraphBuilder::clock`
+    pub fn expiry(mut self, enabled: bool) -> Self {
+        self.expiry = enabled;
+        self
+    }
+
+    pub fn record_ingest(mut self, enabled: bool) -> Self {
+        self.record_ingest = enabled;
+        self
+    }
+
+    pub fn priors(mut self, enabled: bool) -> Self {
+        self.priors = enabled;
+        self
+    }
+
+    /// Whether nodes get deleted or purged, queueing their slots for reuse,
+    /// counted as if every slot was
+    pub fn deletes(mut self, enabled: bool) -> Self {
+        self.deletes = enabled;
+        self
+    }
+
+    /// A result cache of `capacity` entries, each holding a query and `top_k`
+    /// results, see `GraphBuilder::result_cache`
+    pub fn result_cache(mut self, capacity: usize, top_k: u16) -> Self {
+        self.result_cache = Some((capacity, top_k));
+        self
+    }
+
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+}
+
+/// Projected bytes of a graph of `dataset_size` vectors, with the default
+/// options of `MemProjectOptions` besides the ones given
 pub fn mem_project(
     m: u16,
     m0: u16,
@@ -26,24 +132,57 @@ pub fn mem_project(
     quantization: Quantization,
     dataset_size: u32,
 ) -> u64 {
-    let graph_size_bytes = 232;
-    let chunk_size = 1024;
-    let node0_size = Node0::size_aligned(m0) as u64;
-    let node_size = Node::size_aligned(m) as u64;
-
-    let raw_vec_size = dims as u64 * 4;
-    let quant_vec_size = quantization.size() as u64 * dims as u64;
-    let vec_size = raw_vec_size + quant_vec_size;
+    let options = MemProjectOptions::new(dims)
+        .m(m)
+        .m0(m0)
+        .levels(levels)
+        .quantization(quantization);
+    mem_project_with(&options, dataset_size)
+}
+
+/// Projected bytes of a graph of `dataset_size` vectors built with `options`
+///
+/// Counts whole arena chunks, as the graph allocates them, and the expected
+/// number of upper-level nodes. Searches' scratch space and recorded traces
+/// are not counted.
+pub fn mem_project_with(options: &MemProjectOptions, dataset_size: u32) -> u64 {
+    let graph_size_bytes = size_of::<Graph>() as u64;
+    let chunk_size = options.chunk_size.max(1) as u64;
+    let node0_size = Node0::size_aligned(options.m0) as u64;
+    let node_size = Node::size_aligned(options.m) as u64;
+
+    // full precision graphs don't keep a separate raw copy
+    let raw_dims = match options.quantization {
+        Quantization::FullPrecisionFP => 0,
+        _ if !options.rerank => 0,
+        _ => options.dims,
+    };
+    let optional_size = |enabled: bool, size: usize| if enabled { size as u64 } else { 0 };
+    let vec_size = RawVec::size_aligned(raw_dims) as u64
+        + QuantVec::size_aligned((options.quantization, options.dims)) as u64
+        + NodeMeta::size_aligned(()) as u64
+        + optional_size(options.expiry, NodeExpiry::size_aligned(()))
+        + optional_size(options.record_ingest, NodeIngest::size_aligned(()))
+        + optional_size(options.priors, NodePrior::size_aligned(()))
+        + optional_size(options.deletes, FreeLink::size_aligned(()));
+    // one chunk list each for the raw and quantized vectors and the metadata,
+    // plus one per optional arena
+    let vec_chunk_lists = 3
+        + options.expiry as u64
+        + options.record_ingest as u64
+        + options.priors as u64
+        + options.deletes as u64;
     let mut node_arena_size = 0.0;
 
-    for level in 1..=levels {
-        let multiplier = 0.4f64.powi(level as i32);
+    for level in 1..=options.levels {
+        let multiplier = LEVEL_FACTOR.powi(level as i32);
         node_arena_size += multiplier * dataset_size as f64;
     }
 
-    let node0_arena_len = dataset_size as u64;
-    let node_arena_len = node_arena_size as u64;
-    let vec_arena_len = dataset_size as u64;
+    // slot 0 and one node per level hold the synthetic root
+    let node0_arena_len = dataset_size as u64 + 1;
+    let node_arena_len = node_arena_size as u64 + options.levels as u64;
+    let vec_arena_len = dataset_size as u64 + 1;
 
     let node0_arena_vec_len = node0_arena_len.div_ceil(chunk_size);
     let node_arena_vec_len = node_arena_len.div_ceil(chunk_size);
@@ -53,11 +192,59 @@ pub fn mem_project(
     let node_arena_vec_cap = len_to_cap(node_arena_vec_len);
     let vec_arena_vec_cap = len_to_cap(vec_arena_vec_len);
 
-    let chunk_size = size_of::<usize>() as u64;
+    let chunk_ptr_size = size_of::<usize>() as u64;
+    let chunk_bytes = |chunks: u64, slot_size: u64| chunks * chunk_size * slot_size;
+
+    let node0_arena_heap_size =
+        (node0_arena_vec_cap * chunk_ptr_size) + chunk_bytes(node0_arena_vec_len, node0_size);
+    let node_arena_heap_size =
+        (node_arena_vec_cap * chunk_ptr_size) + chunk_bytes(node_arena_vec_len, node_size);
+    let vec_arena_heap_size = (vec_chunk_lists * vec_arena_vec_cap * chunk_ptr_size)
+        + chunk_bytes(vec_arena_vec_len, vec_size);
+    let result_cache_size = options.result_cache.map_or(0, |(capacity, top_k)| {
+        capacity.max(1) as u64 * ResultCache::entry_bytes(options.dims, top_k) as u64
+    });
+
+    graph_size_bytes
+        + node0_arena_heap_size
+        + node_arena_heap_size
+        + vec_arena_heap_size
+        + result_cache_size
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn options_add_their_subsystems() {
+        let base = MemProjectOptions::new(128).quantization(Quantization::SignedByte);
+        let bare = mem_project_with(&base, 100_000);
+        assert_eq!(
+            mem_project(16, 32, 128, 4, Quantization::SignedByte, 100_000),
+            bare
+        );
 
-    let node0_arena_heap_size = (node0_arena_vec_cap * chunk_size) + (node0_arena_len * node0_size);
-    let node_arena_heap_size = (node_arena_vec_cap * chunk_size) + (node_arena_len * node_size);
-    let vec_arena_heap_size = (vec_arena_vec_cap * chunk_size) + (vec_arena_len * vec_size);
+        // dropping the full-precision copies saves about 512 bytes a vector
+        let no_copies = mem_project_with(&base.rerank(false), 100_000);
+        assert!(bare - no_copies >= 100_000 * 512);
 
-    graph_size_bytes + node0_arena_heap_size + node_arena_heap_size + vec_arena_heap_size
+        // whole chunks of a few bytes a slot, and one more chunk list
+        for (options, slot_bytes) in [
+            (base.expiry(true), 8),
+            (base.record_ingest(true), 8),
+            (base.priors(true), 4),
+            (base.deletes(true), 4),
+        ] {
+            let extra = mem_project_with(&options, 100_000) - bare;
+            let chunks = 100_001u64.div_ceil(1024);
+            assert_eq!(
+                extra,
+                chunks * 1024 * slot_bytes + chunks.next_power_of_two() * 8
+            );
+        }
+
+        let cached = mem_project_with(&base.result_cache(64, 10), 100_000);
+        assert!(cached - bare >= 64 * (128 * 4 + 10 * 8));
+    }
 }
@@ -1,28 +1,66 @@
-use core::{
-    cmp::Ordering,
-    f32,
-    simd::{Simd, num::SimdFloat},
+#[cfg(feature = "nightly")]
+use core::simd::Simd;
+use core::{cmp::Ordering, f32};
+
+use alloc::boxed::Box;
+
+use crate::{
+    storage::{QuantVec, Quantization, RawVec, binary_word},
+    util::f16_from_bits,
 };
 
-use crate::storage::{QuantVec, Quantization, RawVec};
+/// Version of the scoring kernels, bumped whenever a change alters the scores
+/// they return
+///
+/// Neighbor lists cache scores, which only compare with scores computed under
+/// the same version. Snapshots should record it, and loaders should call
+/// `Graph::recompute_scores` when it differs. The `lanes-8` and `unroll-2`
+/// features sum in another order under the same version, so call it as well
+/// when loading a snapshot written by a build with other kernel features.
+pub const KERNEL_VERSION: u32 = 1;
 
+/// How nodes are scored against each other
+///
+/// Euclidean, Hamming, Manhattan and Chebyshev are the distance metrics,
+/// scoring closer vectors lower. Cosine and DotProduct score them higher.
 #[derive(Debug, Clone, Copy)]
 #[repr(u8)]
 pub enum DistanceMetricKind {
     Cosine,
+    /// Squared L2 distance, lower is better
     Euclidean,
     Hamming,
     DotProduct,
+    /// L1 distance, the sum of the absolute differences, lower is better
+    Manhattan,
+    /// L∞ distance, the largest absolute difference, lower is better
+    Chebyshev,
 }
 
 pub struct DistanceMetric {
     kind: DistanceMetricKind,
     quantization: Quantization,
+    normalized: bool,
 }
 
 impl DistanceMetric {
     pub fn new(kind: DistanceMetricKind, quantization: Quantization) -> Self {
-        Self { kind, quantization }
+        Self {
+            kind,
+            quantization,
+            normalized: false,
+        }
+    }
+
+    // Score Cosine as the plain dot product, for unit-norm vectors, see
+    // `GraphBuilder::assume_normalized`.
+    pub(crate) fn assume_normalized(mut self, normalized: bool) -> Self {
+        self.normalized = normalized;
+        self
+    }
+
+    pub fn kind(&self) -> DistanceMetricKind {
+        self.kind
     }
 
     pub fn calculate(&self, a: &QuantVec, b: &QuantVec) -> f32 {
@@ -32,25 +70,60 @@ impl DistanceMetric {
         match (self.quantization, self.kind) {
             (SignedByte, Cosine) => {
                 let dot_product = dot_product_i8(a.as_signed_byte(), b.as_signed_byte());
-                cosine_similarity_from_dot_procut(dot_product, a.mag, b.mag)
+                self.cosine(dot_product, a.mag, b.mag)
             }
             (UnsignedByte, Cosine) => {
                 let dot_product = dot_product_u8(a.as_unsigned_byte(), b.as_unsigned_byte());
-                cosine_similarity_from_dot_procut(dot_product, a.mag, b.mag)
+                self.cosine(dot_product, a.mag, b.mag)
+            }
+            (HalfPrecisionFP, Cosine) => {
+                let dot_product =
+                    dot_product_f16(a.as_half_precision_bits(), b.as_half_precision_bits());
+                self.cosine(dot_product, a.mag, b.mag)
             }
             (FullPrecisionFP, Cosine) => {
                 let dot_product =
                     dot_product_f32(a.as_full_precision_fp(), b.as_full_precision_fp());
-                cosine_similarity_from_dot_procut(dot_product, a.mag, b.mag)
+                self.cosine(dot_product, a.mag, b.mag)
             }
             (SignedByte, DotProduct) => dot_product_i8(a.as_signed_byte(), b.as_signed_byte()),
             (UnsignedByte, DotProduct) => {
                 dot_product_u8(a.as_unsigned_byte(), b.as_unsigned_byte())
             }
+            (HalfPrecisionFP, DotProduct) => {
+                dot_product_f16(a.as_half_precision_bits(), b.as_half_precision_bits())
+            }
             (FullPrecisionFP, DotProduct) => {
                 dot_product_f32(a.as_full_precision_fp(), b.as_full_precision_fp())
             }
-            _ => todo!(),
+            (SignedByte, Euclidean) => squared_euclidean_i8(a.as_signed_byte(), b.as_signed_byte()),
+            (UnsignedByte, Euclidean) => {
+                squared_euclidean_u8(a.as_unsigned_byte(), b.as_unsigned_byte())
+            }
+            (HalfPrecisionFP, Euclidean) => {
+                squared_euclidean_f16(a.as_half_precision_bits(), b.as_half_precision_bits())
+            }
+            (FullPrecisionFP, Euclidean) => {
+                squared_euclidean_f32(a.as_full_precision_fp(), b.as_full_precision_fp())
+            }
+            (SignedByte, Manhattan) => manhattan_i8(a.as_signed_byte(), b.as_signed_byte()),
+            (UnsignedByte, Manhattan) => manhattan_u8(a.as_unsigned_byte(), b.as_unsigned_byte()),
+            (HalfPrecisionFP, Manhattan) => {
+                manhattan_f16(a.as_half_precision_bits(), b.as_half_precision_bits())
+            }
+            (FullPrecisionFP, Manhattan) => {
+                manhattan_f32(a.as_full_precision_fp(), b.as_full_precision_fp())
+            }
+            (SignedByte, Chebyshev) => chebyshev_i8(a.as_signed_byte(), b.as_signed_byte()),
+            (UnsignedByte, Chebyshev) => chebyshev_u8(a.as_unsigned_byte(), b.as_unsigned_byte()),
+            (HalfPrecisionFP, Chebyshev) => {
+                chebyshev_f16(a.as_half_precision_bits(), b.as_half_precision_bits())
+            }
+            (FullPrecisionFP, Chebyshev) => {
+                chebyshev_f32(a.as_full_precision_fp(), b.as_full_precision_fp())
+            }
+            (Binary, Hamming) => hamming_binary(a.as_binary(), b.as_binary()),
+            _ => unreachable!("rejected by GraphBuilder::check"),
         }
     }
 
@@ -59,10 +132,78 @@ impl DistanceMetric {
         match self.kind {
             Cosine => {
                 let dot_product = dot_product_f32(&a.vec, &b.vec);
-                cosine_similarity_from_dot_procut(dot_product, mag_a, mag_b)
+                self.cosine(dot_product, mag_a, mag_b)
             }
             DotProduct => dot_product_f32(&a.vec, &b.vec),
-            _ => todo!(),
+            Euclidean => squared_euclidean_f32(&a.vec, &b.vec),
+            Hamming => hamming_f32(&a.vec, &b.vec),
+            Manhattan => manhattan_f32(&a.vec, &b.vec),
+            Chebyshev => chebyshev_f32(&a.vec, &b.vec),
+        }
+    }
+
+    // Per-dimension terms of `calculate_raw`, summing to the score up to
+    // rounding, or `None` for metrics that don't decompose into a sum of
+    // products.
+    pub fn contributions_raw(
+        &self,
+        a: &RawVec,
+        mag_a: f32,
+        b: &RawVec,
+        mag_b: f32,
+    ) -> Option<Box<[f32]>> {
+        use DistanceMetricKind::*;
+        let scale = match self.kind {
+            Cosine => self.cosine(1.0, mag_a, mag_b),
+            DotProduct => 1.0,
+            Euclidean | Hamming | Manhattan | Chebyshev => return None,
+        };
+        Some(
+            a.vec
+                .iter()
+                .zip(&b.vec)
+                .map(|(a, b)| a * b * scale)
+                .collect(),
+        )
+    }
+
+    // How far `calculate` on the quantized vectors may drift from
+    // `calculate_raw`, as `(scale, bound)`: the quantized score divided by
+    // `scale` is within `bound` of the raw one, provided no component was
+    // clamped. `None` where no bound is known.
+    #[cfg(feature = "debug-checks")]
+    pub fn quantization_error(
+        &self,
+        a: &RawVec,
+        mag_a: f32,
+        b: &RawVec,
+        mag_b: f32,
+    ) -> Option<(f32, f32)> {
+        use DistanceMetricKind::*;
+        use Quantization::*;
+        // the encoders' step and the divisor the byte kernels normalize by
+        let (step, divisor) = match self.quantization {
+            SignedByte => (127.0, 16384.0),
+            UnsignedByte => (255.0, 65025.0),
+            FullPrecisionFP => return Some((1.0, 0.0)),
+            HalfPrecisionFP | Binary => return None,
+        };
+        let norm = match self.kind {
+            Cosine => self.cosine(1.0, mag_a, mag_b).abs(),
+            DotProduct => 1.0,
+            Euclidean | Hamming | Manhattan | Chebyshev => return None,
+        };
+        // quantization truncates, so each component is off by less than a step
+        let l1 = |v: &RawVec| v.vec.iter().map(|x| x.abs()).sum::<f32>();
+        let bound = (l1(a) + l1(b)) / step + a.vec.len() as f32 / (step * step);
+        Some((step * step / divisor, bound * norm))
+    }
+
+    fn cosine(&self, dot_product: f32, mag_a: f32, mag_b: f32) -> f32 {
+        if self.normalized {
+            dot_product
+        } else {
+            cosine_similarity_from_dot_procut(dot_product, mag_a, mag_b)
         }
     }
 
@@ -73,6 +214,7 @@ impl DistanceMetric {
             Euclidean => b.total_cmp(&a),
             Hamming => b.total_cmp(&a),
             DotProduct => a.total_cmp(&b),
+            Manhattan | Chebyshev => b.total_cmp(&a),
         }
     }
 
@@ -83,30 +225,265 @@ impl DistanceMetric {
             Euclidean => 0.0,
             Hamming => 0.0,
             DotProduct => f32::INFINITY,
+            Manhattan | Chebyshev => 0.0,
         }
     }
 }
 
+// Lanes of the f32 kernels: 16 (512 bits), or 8 (256 bits) with `lanes-8`.
+#[cfg(not(feature = "lanes-8"))]
 const LANES: usize = 16;
+#[cfg(feature = "lanes-8")]
+const LANES: usize = 8;
+// Independent sums the f32 kernels keep: 1, or 2 with `unroll-2`, which
+// overlaps the latency of their adds.
+#[cfg(not(feature = "unroll-2"))]
+const UNROLL: usize = 1;
+#[cfg(feature = "unroll-2")]
+const UNROLL: usize = 2;
 
+#[cfg(feature = "nightly")]
 pub(crate) fn dot_product_f32(a: &[f32], b: &[f32]) -> f32 {
     debug_assert_eq!(a.len(), b.len());
     let len = a.len();
-    let mut sum = Simd::<f32, LANES>::splat(0.0);
+    let mut sums = [Simd::<f32, LANES>::splat(0.0); UNROLL];
+    let mut i = 0;
+    while i + LANES * UNROLL <= len {
+        for sum in &mut sums {
+            let a_chunk = Simd::from_slice(&a[i..]);
+            let b_chunk = Simd::from_slice(&b[i..]);
+            *sum += a_chunk * b_chunk;
+            i += LANES;
+        }
+    }
+    let mut total = reduce_sum(fold_sums(sums));
+    for j in i..len {
+        total += a[j] * b[j];
+    }
+    total
+}
+
+// Without portable SIMD the lanes live in arrays, left to the
+// autovectorizer, and are summed in the documented order.
+#[cfg(not(feature = "nightly"))]
+pub(crate) fn dot_product_f32(a: &[f32], b: &[f32]) -> f32 {
+    debug_assert_eq!(a.len(), b.len());
+    let len = a.len();
+    let mut sums = [[0.0f32; LANES]; UNROLL];
+    let mut i = 0;
+    while i + LANES * UNROLL <= len {
+        for lanes in &mut sums {
+            for (lane, sum) in lanes.iter_mut().enumerate() {
+                *sum += a[i + lane] * b[i + lane];
+            }
+            i += LANES;
+        }
+    }
+    let mut total = reduce_lanes(fold_lanes(sums));
+    for j in i..len {
+        total += a[j] * b[j];
+    }
+    total
+}
+
+#[cfg(feature = "nightly")]
+pub(crate) fn squared_euclidean_f32(a: &[f32], b: &[f32]) -> f32 {
+    debug_assert_eq!(a.len(), b.len());
+    let len = a.len();
+    let mut sums = [Simd::<f32, LANES>::splat(0.0); UNROLL];
+    let mut i = 0;
+    while i + LANES * UNROLL <= len {
+        for sum in &mut sums {
+            let diff = Simd::<f32, LANES>::from_slice(&a[i..]) - Simd::from_slice(&b[i..]);
+            *sum += diff * diff;
+            i += LANES;
+        }
+    }
+    let mut total = reduce_sum(fold_sums(sums));
+    for j in i..len {
+        let diff = a[j] - b[j];
+        total += diff * diff;
+    }
+    total
+}
+
+#[cfg(not(feature = "nightly"))]
+pub(crate) fn squared_euclidean_f32(a: &[f32], b: &[f32]) -> f32 {
+    debug_assert_eq!(a.len(), b.len());
+    let len = a.len();
+    let mut sums = [[0.0f32; LANES]; UNROLL];
+    let mut i = 0;
+    while i + LANES * UNROLL <= len {
+        for lanes in &mut sums {
+            for (lane, sum) in lanes.iter_mut().enumerate() {
+                let diff = a[i + lane] - b[i + lane];
+                *sum += diff * diff;
+            }
+            i += LANES;
+        }
+    }
+    let mut total = reduce_lanes(fold_lanes(sums));
+    for j in i..len {
+        let diff = a[j] - b[j];
+        total += diff * diff;
+    }
+    total
+}
+
+#[cfg(feature = "nightly")]
+pub(crate) fn manhattan_f32(a: &[f32], b: &[f32]) -> f32 {
+    use core::simd::num::SimdFloat;
+    debug_assert_eq!(a.len(), b.len());
+    let len = a.len();
+    let mut sums = [Simd::<f32, LANES>::splat(0.0); UNROLL];
+    let mut i = 0;
+    while i + LANES * UNROLL <= len {
+        for sum in &mut sums {
+            let diff = Simd::<f32, LANES>::from_slice(&a[i..]) - Simd::from_slice(&b[i..]);
+            *sum += diff.abs();
+            i += LANES;
+        }
+    }
+    let mut total = reduce_sum(fold_sums(sums));
+    for j in i..len {
+        total += (a[j] - b[j]).abs();
+    }
+    total
+}
+
+#[cfg(not(feature = "nightly"))]
+pub(crate) fn manhattan_f32(a: &[f32], b: &[f32]) -> f32 {
+    debug_assert_eq!(a.len(), b.len());
+    let len = a.len();
+    let mut sums = [[0.0f32; LANES]; UNROLL];
+    let mut i = 0;
+    while i + LANES * UNROLL <= len {
+        for lanes in &mut sums {
+            for (lane, sum) in lanes.iter_mut().enumerate() {
+                *sum += (a[i + lane] - b[i + lane]).abs();
+            }
+            i += LANES;
+        }
+    }
+    let mut total = reduce_lanes(fold_lanes(sums));
+    for j in i..len {
+        total += (a[j] - b[j]).abs();
+    }
+    total
+}
+
+// A maximum doesn't depend on the order it's taken in, so the lanes reduce
+// the same way with or without `reproducible`.
+#[cfg(feature = "nightly")]
+pub(crate) fn chebyshev_f32(a: &[f32], b: &[f32]) -> f32 {
+    use core::simd::num::SimdFloat;
+    debug_assert_eq!(a.len(), b.len());
+    let len = a.len();
+    let mut max = Simd::<f32, LANES>::splat(0.0);
     let mut i = 0;
     while i + LANES <= len {
-        let a_chunk = Simd::from_slice(&a[i..]);
-        let b_chunk = Simd::from_slice(&b[i..]);
-        sum += a_chunk * b_chunk;
+        let diff = Simd::<f32, LANES>::from_slice(&a[i..]) - Simd::from_slice(&b[i..]);
+        max = max.simd_max(diff.abs());
         i += LANES;
     }
-    let mut total = sum.reduce_sum();
+    let mut total = max.reduce_max();
     for j in i..len {
-        total += a[j] * b[j];
+        total = total.max((a[j] - b[j]).abs());
     }
     total
 }
 
+#[cfg(not(feature = "nightly"))]
+pub(crate) fn chebyshev_f32(a: &[f32], b: &[f32]) -> f32 {
+    debug_assert_eq!(a.len(), b.len());
+    a.iter()
+        .zip(b)
+        .fold(0.0f32, |max, (a, b)| max.max((a - b).abs()))
+}
+
+// The independent sums added lane by lane, first to last.
+#[cfg(feature = "nightly")]
+#[inline]
+fn fold_sums(sums: [Simd<f32, LANES>; UNROLL]) -> Simd<f32, LANES> {
+    let mut sum = sums[0];
+    for other in &sums[1..] {
+        sum += other;
+    }
+    sum
+}
+
+#[cfg(not(feature = "nightly"))]
+#[inline]
+fn fold_lanes(sums: [[f32; LANES]; UNROLL]) -> [f32; LANES] {
+    let mut lanes = sums[0];
+    for other in &sums[1..] {
+        for (lane, other) in lanes.iter_mut().zip(other) {
+            *lane += other;
+        }
+    }
+    lanes
+}
+
+#[cfg(all(feature = "nightly", not(feature = "reproducible")))]
+#[inline]
+fn reduce_sum(sum: Simd<f32, LANES>) -> f32 {
+    use core::simd::num::SimdFloat;
+    sum.reduce_sum()
+}
+
+#[cfg(all(feature = "nightly", feature = "reproducible"))]
+#[inline]
+fn reduce_sum(sum: Simd<f32, LANES>) -> f32 {
+    reduce_lanes(sum.to_array())
+}
+
+// Fixed-order tree reduction, so scores are bit-identical across platforms
+// built with the same lane width and unroll factor: lane `i` is added to lane
+// `i + LANES / 2`, then the first half folds the same way, down to a distance
+// of 1. The remainder past the last full round of chunks is then added
+// sequentially by the caller.
+#[cfg(any(feature = "reproducible", not(feature = "nightly")))]
+#[inline]
+fn reduce_lanes(mut lanes: [f32; LANES]) -> f32 {
+    let mut width = LANES / 2;
+    while width > 0 {
+        for i in 0..width {
+            lanes[i] += lanes[i + width];
+        }
+        width /= 2;
+    }
+    lanes[0]
+}
+
+// Lanes of the byte dot products: products are 16 bits wide, twice as many as
+// f32 lanes fit the same registers. Their sums are exact integers, so unlike
+// the f32 kernels the lane count doesn't change any score.
+#[cfg(feature = "nightly")]
+const BYTE_LANES: usize = LANES * 2;
+
+// Products of two u8 fit a u16, and are widened to u32 to be summed.
+#[cfg(feature = "nightly")]
+pub fn dot_product_u8(a: &[u8], b: &[u8]) -> f32 {
+    use core::simd::num::SimdUint;
+
+    debug_assert_eq!(a.len(), b.len());
+    let len = a.len();
+    let mut sums = Simd::<u32, BYTE_LANES>::splat(0);
+    let mut i = 0;
+    while i + BYTE_LANES <= len {
+        let a_chunk = Simd::<u8, BYTE_LANES>::from_slice(&a[i..]).cast::<u16>();
+        let b_chunk = Simd::<u8, BYTE_LANES>::from_slice(&b[i..]).cast::<u16>();
+        sums += (a_chunk * b_chunk).cast::<u32>();
+        i += BYTE_LANES;
+    }
+    let mut sum = sums.reduce_sum();
+    for j in i..len {
+        sum += a[j] as u32 * b[j] as u32;
+    }
+    sum as f32 / (65025.0)
+}
+
+#[cfg(not(feature = "nightly"))]
 pub fn dot_product_u8(a: &[u8], b: &[u8]) -> f32 {
     debug_assert_eq!(a.len(), b.len());
     let mut sum: u32 = 0;
@@ -116,6 +493,30 @@ pub fn dot_product_u8(a: &[u8], b: &[u8]) -> f32 {
     sum as f32 / (65025.0)
 }
 
+// Products of two i8 fit an i16, at most 128 * 128, and are widened to i32 to
+// be summed.
+#[cfg(feature = "nightly")]
+pub fn dot_product_i8(a: &[i8], b: &[i8]) -> f32 {
+    use core::simd::num::SimdInt;
+
+    debug_assert_eq!(a.len(), b.len());
+    let len = a.len();
+    let mut sums = Simd::<i32, BYTE_LANES>::splat(0);
+    let mut i = 0;
+    while i + BYTE_LANES <= len {
+        let a_chunk = Simd::<i8, BYTE_LANES>::from_slice(&a[i..]).cast::<i16>();
+        let b_chunk = Simd::<i8, BYTE_LANES>::from_slice(&b[i..]).cast::<i16>();
+        sums += (a_chunk * b_chunk).cast::<i32>();
+        i += BYTE_LANES;
+    }
+    let mut sum = sums.reduce_sum();
+    for j in i..len {
+        sum += a[j] as i32 * b[j] as i32;
+    }
+    sum as f32 / (16384.0)
+}
+
+#[cfg(not(feature = "nightly"))]
 pub fn dot_product_i8(a: &[i8], b: &[i8]) -> f32 {
     debug_assert_eq!(a.len(), b.len());
     let mut sum: i32 = 0;
@@ -125,6 +526,122 @@ pub fn dot_product_i8(a: &[i8], b: &[i8]) -> f32 {
     sum as f32 / (16384.0)
 }
 
+// The byte kernels scale by the encoders' step, so scores approximate the raw
+// distance.
+pub fn squared_euclidean_u8(a: &[u8], b: &[u8]) -> f32 {
+    debug_assert_eq!(a.len(), b.len());
+    let mut sum: u32 = 0;
+    for i in 0..a.len() {
+        let diff = a[i].abs_diff(b[i]) as u32;
+        sum += diff * diff;
+    }
+    sum as f32 / 65025.0
+}
+
+pub fn squared_euclidean_i8(a: &[i8], b: &[i8]) -> f32 {
+    debug_assert_eq!(a.len(), b.len());
+    let mut sum: u32 = 0;
+    for i in 0..a.len() {
+        let diff = a[i].abs_diff(b[i]) as u32;
+        sum += diff * diff;
+    }
+    sum as f32 / 16129.0
+}
+
+pub fn manhattan_u8(a: &[u8], b: &[u8]) -> f32 {
+    debug_assert_eq!(a.len(), b.len());
+    let mut sum: u32 = 0;
+    for i in 0..a.len() {
+        sum += a[i].abs_diff(b[i]) as u32;
+    }
+    sum as f32 / 255.0
+}
+
+pub fn manhattan_i8(a: &[i8], b: &[i8]) -> f32 {
+    debug_assert_eq!(a.len(), b.len());
+    let mut sum: u32 = 0;
+    for i in 0..a.len() {
+        sum += a[i].abs_diff(b[i]) as u32;
+    }
+    sum as f32 / 127.0
+}
+
+pub fn chebyshev_u8(a: &[u8], b: &[u8]) -> f32 {
+    debug_assert_eq!(a.len(), b.len());
+    let mut max = 0;
+    for i in 0..a.len() {
+        max = max.max(a[i].abs_diff(b[i]));
+    }
+    max as f32 / 255.0
+}
+
+pub fn chebyshev_i8(a: &[i8], b: &[i8]) -> f32 {
+    debug_assert_eq!(a.len(), b.len());
+    let mut max = 0;
+    for i in 0..a.len() {
+        max = max.max(a[i].abs_diff(b[i]));
+    }
+    max as f32 / 127.0
+}
+
+// Over the bits of f16 components, widened one at a time.
+pub fn dot_product_f16(a: &[u16], b: &[u16]) -> f32 {
+    debug_assert_eq!(a.len(), b.len());
+    let mut sum = 0.0;
+    for i in 0..a.len() {
+        sum += f16_from_bits(a[i]) * f16_from_bits(b[i]);
+    }
+    sum
+}
+
+pub fn squared_euclidean_f16(a: &[u16], b: &[u16]) -> f32 {
+    debug_assert_eq!(a.len(), b.len());
+    let mut sum = 0.0;
+    for i in 0..a.len() {
+        let diff = f16_from_bits(a[i]) - f16_from_bits(b[i]);
+        sum += diff * diff;
+    }
+    sum
+}
+
+pub fn manhattan_f16(a: &[u16], b: &[u16]) -> f32 {
+    debug_assert_eq!(a.len(), b.len());
+    let mut sum = 0.0;
+    for i in 0..a.len() {
+        sum += (f16_from_bits(a[i]) - f16_from_bits(b[i])).abs();
+    }
+    sum
+}
+
+pub fn chebyshev_f16(a: &[u16], b: &[u16]) -> f32 {
+    debug_assert_eq!(a.len(), b.len());
+    let mut max = 0.0f32;
+    for i in 0..a.len() {
+        max = max.max((f16_from_bits(a[i]) - f16_from_bits(b[i])).abs());
+    }
+    max
+}
+
+// Number of differing bits between two binary quantized vectors.
+pub fn hamming_binary(a: &[u8], b: &[u8]) -> f32 {
+    debug_assert_eq!(a.len(), b.len());
+    let mut sum = 0;
+    for i in 0..a.len() / 8 {
+        sum += (binary_word(a, i) ^ binary_word(b, i)).count_ones();
+    }
+    sum as f32
+}
+
+// The binary quantized score of two raw vectors: the number of components
+// positive in only one of them.
+pub fn hamming_f32(a: &[f32], b: &[f32]) -> f32 {
+    debug_assert_eq!(a.len(), b.len());
+    a.iter()
+        .zip(b)
+        .filter(|(a, b)| (**a > 0.0) != (**b > 0.0))
+        .count() as f32
+}
+
 pub fn cosine_similarity_from_dot_procut(dot_product: f32, mag_a: f32, mag_b: f32) -> f32 {
     let denominator = mag_a * mag_b;
 
@@ -134,3 +651,221 @@ pub fn cosine_similarity_from_dot_procut(dot_product: f32, mag_a: f32, mag_b: f3
         dot_product / denominator
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::QuantVecBox;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn only_sums_of_products_have_contributions() {
+        let a = [1.0f32, 2.0, 0.0];
+        let b = [0.5f32, 1.0, 3.0];
+        let (a, b) = unsafe {
+            (
+                core::mem::transmute::<&[f32], &RawVec>(&a),
+                core::mem::transmute::<&[f32], &RawVec>(&b),
+            )
+        };
+        let contributions = |kind| {
+            DistanceMetric::new(kind, Quantization::FullPrecisionFP)
+                .contributions_raw(a, 5.0, b, 10.25)
+        };
+
+        assert_eq!(
+            contributions(DistanceMetricKind::DotProduct).as_deref(),
+            Some(&[0.5, 2.0, 0.0][..])
+        );
+        assert!(contributions(DistanceMetricKind::Cosine).is_some());
+        assert!(contributions(DistanceMetricKind::Euclidean).is_none());
+        assert!(contributions(DistanceMetricKind::Hamming).is_none());
+        assert!(contributions(DistanceMetricKind::Manhattan).is_none());
+        assert!(contributions(DistanceMetricKind::Chebyshev).is_none());
+    }
+
+    #[cfg(feature = "reproducible")]
+    #[test]
+    fn dot_product_follows_documented_order() {
+        let a: Vec<f32> = (0..37).map(|i| 1.0 / (i as f32 + 1.0)).collect();
+        let b: Vec<f32> = (0..37).map(|i| (i as f32).sin()).collect();
+
+        // chunk `c` of each full round goes to sum `c % UNROLL`
+        let chunks = 37 / (LANES * UNROLL) * UNROLL;
+        let mut sums = [[0.0f32; LANES]; UNROLL];
+        for chunk in 0..chunks {
+            for (lane, sum) in sums[chunk % UNROLL].iter_mut().enumerate() {
+                let j = chunk * LANES + lane;
+                *sum += a[j] * b[j];
+            }
+        }
+        let mut lanes = sums[0];
+        for other in &sums[1..] {
+            for (lane, other) in lanes.iter_mut().zip(other) {
+                *lane += other;
+            }
+        }
+        let mut width = LANES / 2;
+        while width > 0 {
+            for i in 0..width {
+                lanes[i] += lanes[i + width];
+            }
+            width /= 2;
+        }
+        let mut expected = lanes[0];
+        for j in chunks * LANES..37 {
+            expected += a[j] * b[j];
+        }
+
+        assert_eq!(dot_product_f32(&a, &b).to_bits(), expected.to_bits());
+    }
+
+    #[test]
+    fn byte_dot_products_match_scalar() {
+        // long enough for full chunks and a remainder, with the extreme
+        // products that must not overflow
+        let a: Vec<u8> = (0..203).map(|i| (i * 37 % 256) as u8).collect();
+        let b: Vec<u8> = (0..203)
+            .map(|i| {
+                if i % 9 == 0 {
+                    255
+                } else {
+                    (i * 11 % 256) as u8
+                }
+            })
+            .collect();
+        let expected: u32 = a.iter().zip(&b).map(|(&a, &b)| a as u32 * b as u32).sum();
+        assert_eq!(dot_product_u8(&a, &b), expected as f32 / 65025.0);
+        assert_eq!(dot_product_u8(&[255; 203], &[255; 203]), 203.0);
+
+        let a: Vec<i8> = a.iter().map(|&x| x as i8).collect();
+        let b: Vec<i8> = b.iter().map(|&x| (x as i8).saturating_sub(1)).collect();
+        let expected: i32 = a.iter().zip(&b).map(|(&a, &b)| a as i32 * b as i32).sum();
+        assert_eq!(dot_product_i8(&a, &b), expected as f32 / 16384.0);
+        assert_eq!(dot_product_i8(&[i8::MIN; 203], &[i8::MIN; 203]), 203.0);
+    }
+
+    #[test]
+    fn euclidean_matches_scalar() {
+        let a: Vec<f32> = (0..37).map(|i| i as f32 / 40.0).collect();
+        let b: Vec<f32> = (0..37).map(|i| 1.0 - i as f32 / 20.0).collect();
+        let expected: f32 = a.iter().zip(&b).map(|(a, b)| (a - b) * (a - b)).sum();
+        assert!((squared_euclidean_f32(&a, &b) - expected).abs() < 1e-4);
+
+        let metric =
+            |quantization| DistanceMetric::new(DistanceMetricKind::Euclidean, quantization);
+        let allocator = crate::allocator::IndexAllocator::default();
+        for quantization in [
+            Quantization::SignedByte,
+            Quantization::UnsignedByte,
+            Quantization::HalfPrecisionFP,
+            Quantization::FullPrecisionFP,
+        ] {
+            let quantize = |v: &[f32]| QuantVecBox::new(quantization, 37, v, &allocator);
+            let score = metric(quantization).calculate(&quantize(&a), &quantize(&b));
+            // u8 clamps the negative components of `b` to 0
+            let expected = match quantization {
+                Quantization::UnsignedByte => a
+                    .iter()
+                    .zip(&b)
+                    .map(|(a, b)| (a - b.max(0.0)) * (a - b.max(0.0)))
+                    .sum(),
+                _ => expected,
+            };
+            // the byte encoders truncate
+            assert!(
+                (score - expected).abs() < 0.02 * expected,
+                "{quantization:?}: {score} vs {expected}"
+            );
+            assert_eq!(
+                metric(quantization).calculate(&quantize(&a), &quantize(&a)),
+                0.0
+            );
+        }
+    }
+
+    #[test]
+    fn half_precision_scores_similarities() {
+        let a: Vec<f32> = (0..37).map(|i| i as f32 / 40.0).collect();
+        let b: Vec<f32> = (0..37).map(|i| 1.0 - i as f32 / 20.0).collect();
+        let allocator = crate::allocator::IndexAllocator::default();
+        let quantize =
+            |v: &[f32]| QuantVecBox::new(Quantization::HalfPrecisionFP, 37, v, &allocator);
+        let raw = |v: &[f32]| unsafe { core::mem::transmute::<&[f32], &RawVec>(v) };
+        let (mag_a, mag_b) = (dot_product_f32(&a, &a), dot_product_f32(&b, &b));
+
+        for kind in [DistanceMetricKind::Cosine, DistanceMetricKind::DotProduct] {
+            let half = DistanceMetric::new(kind, Quantization::HalfPrecisionFP);
+            let full = DistanceMetric::new(kind, Quantization::FullPrecisionFP);
+            let score = half.calculate(&quantize(&a), &quantize(&b));
+            let expected = full.calculate_raw(raw(&a), mag_a, raw(&b), mag_b);
+            assert!(
+                (score - expected).abs() < 1e-3 * expected.abs(),
+                "{kind:?}: {score} vs {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn l1_and_linf_match_scalar() {
+        let a: Vec<f32> = (0..37).map(|i| i as f32 / 40.0).collect();
+        let b: Vec<f32> = (0..37).map(|i| (i as f32 / 5.0).sin().abs()).collect();
+        let l1: f32 = a.iter().zip(&b).map(|(a, b)| (a - b).abs()).sum();
+        let linf = a
+            .iter()
+            .zip(&b)
+            .fold(0.0f32, |max, (a, b)| max.max((a - b).abs()));
+        assert!((manhattan_f32(&a, &b) - l1).abs() < 1e-4);
+        assert_eq!(chebyshev_f32(&a, &b), linf);
+
+        let allocator = crate::allocator::IndexAllocator::default();
+        for (kind, expected) in [
+            (DistanceMetricKind::Manhattan, l1),
+            (DistanceMetricKind::Chebyshev, linf),
+        ] {
+            for quantization in [
+                Quantization::SignedByte,
+                Quantization::UnsignedByte,
+                Quantization::HalfPrecisionFP,
+                Quantization::FullPrecisionFP,
+            ] {
+                let metric = DistanceMetric::new(kind, quantization);
+                let quantize = |v: &[f32]| QuantVecBox::new(quantization, 37, v, &allocator);
+                let score = metric.calculate(&quantize(&a), &quantize(&b));
+                // the byte encoders truncate, by less than a step per component
+                assert!(
+                    (score - expected).abs() < 0.02 * expected,
+                    "{kind:?} {quantization:?}: {score} vs {expected}"
+                );
+                assert_eq!(metric.calculate(&quantize(&a), &quantize(&a)), 0.0);
+                assert_eq!(metric.cmp_score(0.5, 1.0), Ordering::Greater);
+            }
+        }
+    }
+
+    #[test]
+    fn hamming_counts_sign_changes() {
+        let a: Vec<f32> = (0..100)
+            .map(|i| if i % 3 == 0 { 1.0 } else { -1.0 })
+            .collect();
+        let b: Vec<f32> = (0..100)
+            .map(|i| if i % 5 == 0 { 0.5 } else { -0.5 })
+            .collect();
+        let expected = (0..100).filter(|i| (i % 3 == 0) != (i % 5 == 0)).count() as f32;
+        assert_eq!(hamming_f32(&a, &b), expected);
+
+        let allocator = crate::allocator::IndexAllocator::default();
+        let quantize = |v: &[f32]| QuantVecBox::new(Quantization::Binary, 100, v, &allocator);
+        let metric = DistanceMetric::new(DistanceMetricKind::Hamming, Quantization::Binary);
+        assert_eq!(metric.calculate(&quantize(&a), &quantize(&b)), expected);
+        assert_eq!(metric.calculate(&quantize(&a), &quantize(&a)), 0.0);
+    }
+
+    #[test]
+    fn dot_product_matches_scalar() {
+        let a: Vec<f32> = (0..37).map(|i| i as f32).collect();
+        let b: Vec<f32> = (0..37).map(|i| 2.0 - i as f32 / 8.0).collect();
+        let expected: f32 = a.iter().zip(&b).map(|(a, b)| a * b).sum();
+        assert_eq!(dot_product_f32(&a, &b), expected);
+    }
+}
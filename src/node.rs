@@ -1,4 +1,8 @@
-use core::cmp::Ordering;
+use core::{
+    cmp::Ordering,
+    ptr,
+    sync::atomic::{AtomicU32, AtomicU64, Ordering as AtomicOrdering},
+};
 
 use crate::{
     arena::DynAlloc,
@@ -11,6 +15,20 @@ use crate::{
 pub type VecHandle = DoubleHandle<RawVec, QuantVec>;
 pub type NodeHandle = Handle<Node>;
 pub type Node0Handle = Handle<Node0>;
+pub type NodeMetaHandle = Handle<NodeMeta>;
+pub type NodeExpiryHandle = Handle<NodeExpiry>;
+pub type NodeIngestHandle = Handle<NodeIngest>;
+pub type NodePriorHandle = Handle<NodePrior>;
+
+// The node has been fully linked and `top` is valid.
+pub(crate) const META_READY: u32 = 0b01;
+// The node is excluded from search results (e.g. while its slot is being reused).
+pub(crate) const META_TOMBSTONE: u32 = 0b10;
+// The node's top level is stored in the 8 bits above this shift.
+pub(crate) const META_LEVEL_SHIFT: u32 = 8;
+// The number of components clamped when quantizing the vector, saturating at
+// u16::MAX, is stored in the bits above this shift.
+pub(crate) const META_CLIPPED_SHIFT: u32 = 16;
 
 #[repr(C, align(4))]
 pub struct Node {
@@ -25,9 +43,36 @@ pub struct Node0 {
     pub(crate) neighbors: RwLock<Neighbors0>,
 }
 
+// Fits in the padding byte of the neighbors header. Zeroed memory reads as
+// `Unordered`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum NeighborOrder {
+    /// Insertion order, as left by indexing
+    Unordered,
+    /// Descending score, most similar neighbor first
+    ByScore,
+    /// Ascending handle, for memory locality while traversing
+    ByHandle,
+}
+
+/// How inserts choose a node's neighbors among the candidates they find
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NeighborSelection {
+    /// The `m` best-scoring candidates
+    #[default]
+    Simple,
+    /// The HNSW heuristic (Algorithm 4 of the paper): best first, keep a
+    /// candidate only if it scores better against the new node than against
+    /// every neighbor kept so far, spreading links out instead of into one
+    /// dense cluster
+    Heuristic,
+}
+
 #[repr(C, align(4))]
 pub struct Neighbors {
     pub(crate) neighbors_full: bool,
+    pub(crate) order: NeighborOrder,
     pub(crate) lowest_index: u16,
     pub(crate) lowest_score: f32,
     pub(crate) neighbors: [Neighbor],
@@ -36,11 +81,108 @@ pub struct Neighbors {
 #[repr(C, align(4))]
 pub struct Neighbors0 {
     pub(crate) neighbors_full: bool,
+    pub(crate) order: NeighborOrder,
     pub(crate) lowest_index: u16,
     pub(crate) lowest_score: f32,
     pub(crate) neighbors: [Neighbor0],
 }
 
+// Per-vector bookkeeping, stored at the same index as the vector it describes.
+#[repr(C, align(4))]
+pub struct NodeMeta {
+    pub(crate) flags: AtomicU32,
+    pub(crate) top: AtomicU32,
+    pub(crate) last_access: AtomicU32,
+    // Times searches returned the node, counted for a sample of searches only.
+    pub(crate) accesses: AtomicU32,
+}
+
+// Clock tick at which a node stops being returned by searches, 0 for never.
+// Stored at the same index as the vector, only in graphs with a clock.
+#[repr(C, align(8))]
+pub struct NodeExpiry {
+    pub(crate) at: AtomicU64,
+}
+
+// Clock time of the insert that wrote a slot and the source it was tagged
+// with. Stored at the same index as the vector, only in graphs that record
+// ingests.
+#[repr(C, align(4))]
+pub struct NodeIngest {
+    pub(crate) at: AtomicU32,
+    pub(crate) source: AtomicU32,
+}
+
+// Static ranking prior of a node as f32 bits, 0.0 until set. Stored at the
+// same index as the vector, only in graphs that keep priors.
+#[repr(C, align(4))]
+pub struct NodePrior {
+    bits: AtomicU32,
+}
+
+impl NodeMeta {
+    pub fn is_ready(&self) -> bool {
+        self.flags.load(AtomicOrdering::Acquire) & META_READY != 0
+    }
+
+    // SeqCst pairs with `Epoch`: a reader that pinned after a writer's flip is
+    // guaranteed to observe the tombstone set before that flip.
+    pub fn is_tombstoned(&self) -> bool {
+        self.flags.load(AtomicOrdering::SeqCst) & META_TOMBSTONE != 0
+    }
+
+    /// Set the tombstone bit, returning `false` if it was already set
+    pub fn try_tombstone(&self) -> bool {
+        self.flags.fetch_or(META_TOMBSTONE, AtomicOrdering::SeqCst) & META_TOMBSTONE == 0
+    }
+
+    pub fn clear_tombstone(&self) {
+        self.flags
+            .fetch_and(!META_TOMBSTONE, AtomicOrdering::SeqCst);
+    }
+
+    pub fn level(&self) -> u8 {
+        (self.flags.load(AtomicOrdering::Acquire) >> META_LEVEL_SHIFT) as u8
+    }
+
+    pub fn top(&self) -> NodeHandle {
+        NodeHandle::new(self.top.load(AtomicOrdering::Acquire))
+    }
+
+    pub fn clipped(&self) -> u16 {
+        (self.flags.load(AtomicOrdering::Acquire) >> META_CLIPPED_SHIFT) as u16
+    }
+
+    pub fn set_clipped(&self, clipped: u16) {
+        let _ = self
+            .flags
+            .fetch_update(AtomicOrdering::SeqCst, AtomicOrdering::SeqCst, |flags| {
+                Some(
+                    (flags & !(u32::MAX << META_CLIPPED_SHIFT))
+                        | ((clipped as u32) << META_CLIPPED_SHIFT),
+                )
+            });
+    }
+
+    pub fn record_access(&self) {
+        let _ = self.accesses.fetch_update(
+            AtomicOrdering::Relaxed,
+            AtomicOrdering::Relaxed,
+            |accesses| accesses.checked_add(1),
+        );
+    }
+
+    pub fn publish(&self, top: NodeHandle, level: u8, clipped: u16) {
+        self.top.store(*top, AtomicOrdering::Release);
+        self.flags.store(
+            META_READY
+                | ((level as u32) << META_LEVEL_SHIFT)
+                | ((clipped as u32) << META_CLIPPED_SHIFT),
+            AtomicOrdering::Release,
+        );
+    }
+}
+
 impl Neighbors {
     pub fn neighbors(&self) -> &[Neighbor] {
         if self.neighbors_full {
@@ -50,6 +192,36 @@ impl Neighbors {
         }
     }
 
+    // Inserting neighbors afterwards resets the order to `Unordered`.
+    pub fn sort(&mut self, distance_metric: &DistanceMetric, order: NeighborOrder) {
+        let len = self.neighbors().len();
+        let neighbors = &mut self.neighbors[..len];
+        match order {
+            NeighborOrder::Unordered => {}
+            NeighborOrder::ByScore => neighbors.sort_unstable_by(|a, b| {
+                distance_metric.cmp_score(b.score(distance_metric), a.score(distance_metric))
+            }),
+            NeighborOrder::ByHandle => neighbors.sort_unstable_by_key(|neighbor| *neighbor.node()),
+        }
+        if self.neighbors_full {
+            self.recompute_lowest_index(distance_metric);
+        }
+        self.order = order;
+    }
+
+    // Replace every score with `score(node)`. A by-score order is kept up to date.
+    pub fn rescore(&mut self, distance_metric: &DistanceMetric, score: impl Fn(NodeHandle) -> f32) {
+        let len = self.neighbors().len();
+        for neighbor in &mut self.neighbors[..len] {
+            neighbor.score = store_score(distance_metric, score(neighbor.node()));
+        }
+        if self.order == NeighborOrder::ByScore {
+            self.sort(distance_metric, NeighborOrder::ByScore);
+        } else if self.neighbors_full {
+            self.recompute_lowest_index(distance_metric);
+        }
+    }
+
     pub fn insert_neighbor(
         &mut self,
         distance_metric: &DistanceMetric,
@@ -57,14 +229,20 @@ impl Neighbors {
         score: f32,
     ) {
         if self.neighbors_full {
-            if distance_metric.cmp_score(score, self.neighbors[self.lowest_index as usize].score)
-                == Ordering::Greater
+            if distance_metric.cmp_score(
+                score,
+                self.neighbors[self.lowest_index as usize].score(distance_metric),
+            ) == Ordering::Greater
             {
-                self.neighbors[self.lowest_index as usize] = Neighbor { node, score };
+                self.neighbors[self.lowest_index as usize] =
+                    Neighbor::new(distance_metric, node, score);
+                self.order = NeighborOrder::Unordered;
                 self.recompute_lowest_index(distance_metric);
             }
         } else {
-            self.neighbors[self.lowest_index as usize] = Neighbor { node, score };
+            self.neighbors[self.lowest_index as usize] =
+                Neighbor::new(distance_metric, node, score);
+            self.order = NeighborOrder::Unordered;
             self.lowest_index += 1;
             if self.lowest_index as usize == self.neighbors.len() {
                 self.neighbors_full = true;
@@ -73,14 +251,63 @@ impl Neighbors {
         }
     }
 
+    // Like `insert_neighbor`, but refreshes the score if `node` is already linked.
+    pub fn upsert_neighbor(
+        &mut self,
+        distance_metric: &DistanceMetric,
+        node: NodeHandle,
+        score: f32,
+    ) {
+        if !self.refresh_neighbor(distance_metric, node, score) {
+            self.insert_neighbor(distance_metric, node, score);
+        }
+    }
+
+    // Replace the score of the link to `node`, returning whether there was one.
+    pub fn refresh_neighbor(
+        &mut self,
+        distance_metric: &DistanceMetric,
+        node: NodeHandle,
+        score: f32,
+    ) -> bool {
+        let len = self.neighbors().len();
+        let Some(neighbor) = self.neighbors[..len].iter_mut().find(|n| n.node() == node) else {
+            return false;
+        };
+        neighbor.score = store_score(distance_metric, score);
+        if self.order == NeighborOrder::ByScore {
+            self.order = NeighborOrder::Unordered;
+        }
+        if self.neighbors_full {
+            self.recompute_lowest_index(distance_metric);
+        }
+        true
+    }
+
+    // Drop the link to `node`, returning whether there was one. The last
+    // neighbor takes its place, so the list becomes `Unordered`.
+    pub fn remove_neighbor(&mut self, node: NodeHandle) -> bool {
+        let len = self.neighbors().len();
+        let Some(index) = self.neighbors[..len].iter().position(|n| n.node() == node) else {
+            return false;
+        };
+        self.neighbors.swap(index, len - 1);
+        self.neighbors_full = false;
+        self.lowest_index = len as u16 - 1;
+        self.order = NeighborOrder::Unordered;
+        true
+    }
+
     fn recompute_lowest_index(&mut self, distance_metric: &DistanceMetric) {
         let mut lowest_index = 0;
         let mut lowest_score = distance_metric.max_value();
 
         for i in 0..(self.neighbors.len() as u16) {
             let neighbor = &self.neighbors[i as usize];
-            if distance_metric.cmp_score(neighbor.score, lowest_score) == Ordering::Less {
-                lowest_score = neighbor.score;
+            if distance_metric.cmp_score(neighbor.score(distance_metric), lowest_score)
+                == Ordering::Less
+            {
+                lowest_score = neighbor.score(distance_metric);
                 lowest_index = i;
             }
         }
@@ -99,6 +326,40 @@ impl Neighbors0 {
         }
     }
 
+    // Inserting neighbors afterwards resets the order to `Unordered`.
+    pub fn sort(&mut self, distance_metric: &DistanceMetric, order: NeighborOrder) {
+        let len = self.neighbors().len();
+        let neighbors = &mut self.neighbors[..len];
+        match order {
+            NeighborOrder::Unordered => {}
+            NeighborOrder::ByScore => neighbors.sort_unstable_by(|a, b| {
+                distance_metric.cmp_score(b.score(distance_metric), a.score(distance_metric))
+            }),
+            NeighborOrder::ByHandle => neighbors.sort_unstable_by_key(|neighbor| *neighbor.node()),
+        }
+        if self.neighbors_full {
+            self.recompute_lowest_index(distance_metric);
+        }
+        self.order = order;
+    }
+
+    // Replace every score with `score(node)`. A by-score order is kept up to date.
+    pub fn rescore(
+        &mut self,
+        distance_metric: &DistanceMetric,
+        score: impl Fn(Node0Handle) -> f32,
+    ) {
+        let len = self.neighbors().len();
+        for neighbor in &mut self.neighbors[..len] {
+            neighbor.score = store_score(distance_metric, score(neighbor.node()));
+        }
+        if self.order == NeighborOrder::ByScore {
+            self.sort(distance_metric, NeighborOrder::ByScore);
+        } else if self.neighbors_full {
+            self.recompute_lowest_index(distance_metric);
+        }
+    }
+
     pub fn insert_neighbor(
         &mut self,
         distance_metric: &DistanceMetric,
@@ -106,14 +367,20 @@ impl Neighbors0 {
         score: f32,
     ) {
         if self.neighbors_full {
-            if distance_metric.cmp_score(score, self.neighbors[self.lowest_index as usize].score)
-                == Ordering::Greater
+            if distance_metric.cmp_score(
+                score,
+                self.neighbors[self.lowest_index as usize].score(distance_metric),
+            ) == Ordering::Greater
             {
-                self.neighbors[self.lowest_index as usize] = Neighbor0 { node, score };
+                self.neighbors[self.lowest_index as usize] =
+                    Neighbor0::new(distance_metric, node, score);
+                self.order = NeighborOrder::Unordered;
                 self.recompute_lowest_index(distance_metric);
             }
         } else {
-            self.neighbors[self.lowest_index as usize] = Neighbor0 { node, score };
+            self.neighbors[self.lowest_index as usize] =
+                Neighbor0::new(distance_metric, node, score);
+            self.order = NeighborOrder::Unordered;
             self.lowest_index += 1;
             if self.lowest_index as usize == self.neighbors.len() {
                 self.neighbors_full = true;
@@ -122,14 +389,63 @@ impl Neighbors0 {
         }
     }
 
+    // Like `insert_neighbor`, but refreshes the score if `node` is already linked.
+    pub fn upsert_neighbor(
+        &mut self,
+        distance_metric: &DistanceMetric,
+        node: Node0Handle,
+        score: f32,
+    ) {
+        if !self.refresh_neighbor(distance_metric, node, score) {
+            self.insert_neighbor(distance_metric, node, score);
+        }
+    }
+
+    // Replace the score of the link to `node`, returning whether there was one.
+    pub fn refresh_neighbor(
+        &mut self,
+        distance_metric: &DistanceMetric,
+        node: Node0Handle,
+        score: f32,
+    ) -> bool {
+        let len = self.neighbors().len();
+        let Some(neighbor) = self.neighbors[..len].iter_mut().find(|n| n.node() == node) else {
+            return false;
+        };
+        neighbor.score = store_score(distance_metric, score);
+        if self.order == NeighborOrder::ByScore {
+            self.order = NeighborOrder::Unordered;
+        }
+        if self.neighbors_full {
+            self.recompute_lowest_index(distance_metric);
+        }
+        true
+    }
+
+    // Drop the link to `node`, returning whether there was one. The last
+    // neighbor takes its place, so the list becomes `Unordered`.
+    pub fn remove_neighbor(&mut self, node: Node0Handle) -> bool {
+        let len = self.neighbors().len();
+        let Some(index) = self.neighbors[..len].iter().position(|n| n.node() == node) else {
+            return false;
+        };
+        self.neighbors.swap(index, len - 1);
+        self.neighbors_full = false;
+        self.lowest_index = len as u16 - 1;
+        self.order = NeighborOrder::Unordered;
+        true
+    }
+
     fn recompute_lowest_index(&mut self, distance_metric: &DistanceMetric) {
         let mut lowest_index = 0;
         let mut lowest_score = distance_metric.max_value();
 
         for i in 0..(self.neighbors.len() as u16) {
             let neighbor = &self.neighbors[i as usize];
-            if distance_metric.cmp_score(neighbor.score, lowest_score) == Ordering::Less {
-                lowest_score = neighbor.score;
+            if distance_metric.cmp_score(neighbor.score(distance_metric), lowest_score)
+                == Ordering::Less
+            {
+                lowest_score = neighbor.score(distance_metric);
                 lowest_index = i;
             }
         }
@@ -139,16 +455,112 @@ impl Neighbors0 {
     }
 }
 
-#[repr(C, align(4))]
+// With the `f16-scores` feature neighbor scores are stored in 16 bits, shrinking
+// each neighbor entry from 8 to 6 bytes: as f16 bits, widened back to f32 when
+// compared, except Hamming distances, which count at most `dims` bits and are
+// stored exactly as a u16. f16 tops out at `MAX_STORED_SCORE`, and larger
+// scores, such as squared distances or dot products of unnormalized vectors,
+// are clamped to it rather than stored as infinities, so links past it tie
+// when lists are pruned.
+#[cfg(not(feature = "f16-scores"))]
+pub(crate) type StoredScore = f32;
+#[cfg(feature = "f16-scores")]
+pub(crate) type StoredScore = u16;
+
+#[cfg(feature = "f16-scores")]
+pub(crate) const MAX_STORED_SCORE: f32 = f16::MAX as f32;
+
+#[cfg(not(feature = "f16-scores"))]
+#[inline]
+fn store_score(_distance_metric: &DistanceMetric, score: f32) -> StoredScore {
+    score
+}
+
+#[cfg(feature = "f16-scores")]
+#[inline]
+fn store_score(distance_metric: &DistanceMetric, score: f32) -> StoredScore {
+    match distance_metric.kind() {
+        crate::DistanceMetricKind::Hamming => score as u16,
+        _ => (score.clamp(-MAX_STORED_SCORE, MAX_STORED_SCORE) as f16).to_bits(),
+    }
+}
+
+#[cfg(not(feature = "f16-scores"))]
+#[inline]
+fn load_score(_distance_metric: &DistanceMetric, score: StoredScore) -> f32 {
+    score
+}
+
+#[cfg(feature = "f16-scores")]
+#[inline]
+fn load_score(distance_metric: &DistanceMetric, score: StoredScore) -> f32 {
+    match distance_metric.kind() {
+        crate::DistanceMetricKind::Hamming => score as f32,
+        _ => f16::from_bits(score) as f32,
+    }
+}
+
+#[cfg_attr(not(feature = "f16-scores"), repr(C, align(4)))]
+#[cfg_attr(feature = "f16-scores", repr(C, packed(2)))]
 pub struct Neighbor {
-    pub node: NodeHandle,
-    pub score: f32,
+    node: NodeHandle,
+    score: StoredScore,
 }
 
-#[repr(C, align(4))]
+#[cfg_attr(not(feature = "f16-scores"), repr(C, align(4)))]
+#[cfg_attr(feature = "f16-scores", repr(C, packed(2)))]
 pub struct Neighbor0 {
-    pub node: Node0Handle,
-    pub score: f32,
+    node: Node0Handle,
+    score: StoredScore,
+}
+
+// Neighbor lists take `8 + len * size_of::<Neighbor>()` bytes in the arenas,
+// and level 0 lists the same.
+const _: () = {
+    let expected = if cfg!(feature = "f16-scores") { 6 } else { 8 };
+    assert!(size_of::<Neighbor>() == expected && size_of::<Neighbor0>() == expected);
+    assert!(core::mem::offset_of!(Neighbor, score) == 4);
+    assert!(core::mem::offset_of!(Neighbor0, score) == 4);
+};
+
+impl Neighbor {
+    #[inline]
+    pub fn new(distance_metric: &DistanceMetric, node: NodeHandle, score: f32) -> Self {
+        Self {
+            node,
+            score: store_score(distance_metric, score),
+        }
+    }
+
+    #[inline]
+    pub fn node(&self) -> NodeHandle {
+        self.node
+    }
+
+    #[inline]
+    pub fn score(&self, distance_metric: &DistanceMetric) -> f32 {
+        load_score(distance_metric, self.score)
+    }
+}
+
+impl Neighbor0 {
+    #[inline]
+    pub fn new(distance_metric: &DistanceMetric, node: Node0Handle, score: f32) -> Self {
+        Self {
+            node,
+            score: store_score(distance_metric, score),
+        }
+    }
+
+    #[inline]
+    pub fn node(&self) -> Node0Handle {
+        self.node
+    }
+
+    #[inline]
+    pub fn score(&self, distance_metric: &DistanceMetric) -> f32 {
+        load_score(distance_metric, self.score)
+    }
 }
 
 impl DynAlloc for Node {
@@ -161,8 +573,8 @@ impl DynAlloc for Node {
         12 + Neighbors::size_aligned(metadata)
     }
 
-    fn ptr_metadata(len: u16) -> <Self as core::ptr::Pointee>::Metadata {
-        len as usize
+    fn from_raw_parts(ptr: *mut u8, len: u16) -> *mut Self {
+        ptr::slice_from_raw_parts_mut(ptr, len as usize) as *mut Self
     }
 
     unsafe fn new_at(ptr: *mut u8, len: u16, (vec, child): Self::Args) {
@@ -185,8 +597,8 @@ impl DynAlloc for Node0 {
         8 + Neighbors0::size_aligned(metadata)
     }
 
-    fn ptr_metadata(len: u16) -> <Self as core::ptr::Pointee>::Metadata {
-        len as usize
+    fn from_raw_parts(ptr: *mut u8, len: u16) -> *mut Self {
+        ptr::slice_from_raw_parts_mut(ptr, len as usize) as *mut Self
     }
 
     unsafe fn new_at(ptr: *mut u8, len: u16, vec: Self::Args) {
@@ -198,6 +610,118 @@ impl DynAlloc for Node0 {
     }
 }
 
+impl DynAlloc for NodeMeta {
+    type Metadata = ();
+    type Args = u32;
+
+    const ALIGN: usize = 4;
+
+    fn size(_metadata: ()) -> usize {
+        16
+    }
+
+    fn from_raw_parts(ptr: *mut u8, _metadata: ()) -> *mut Self {
+        ptr.cast()
+    }
+
+    // Node metadata lives in a zeroed arena and may be read concurrently by
+    // eviction sampling, so it is initialized through atomic stores.
+    unsafe fn new_at(ptr: *mut u8, _metadata: (), last_access: Self::Args) {
+        let meta = unsafe { &*(ptr as *const NodeMeta) };
+        meta.last_access.store(last_access, AtomicOrdering::Relaxed);
+        meta.accesses.store(0, AtomicOrdering::Relaxed);
+        meta.top.store(u32::MAX, AtomicOrdering::Relaxed);
+        meta.flags.store(0, AtomicOrdering::Release);
+    }
+}
+
+impl NodeExpiry {
+    pub fn is_expired(&self, now: u64) -> bool {
+        let at = self.at.load(AtomicOrdering::Relaxed);
+        at != 0 && at <= now
+    }
+}
+
+impl DynAlloc for NodeExpiry {
+    type Metadata = ();
+    type Args = u64;
+
+    const ALIGN: usize = 8;
+
+    fn size(_metadata: ()) -> usize {
+        8
+    }
+
+    fn from_raw_parts(ptr: *mut u8, _metadata: ()) -> *mut Self {
+        ptr.cast()
+    }
+
+    // Zeroed arena, like the node metadata.
+    unsafe fn new_at(ptr: *mut u8, _metadata: (), at: Self::Args) {
+        let expiry = unsafe { &*(ptr as *const NodeExpiry) };
+        expiry.at.store(at, AtomicOrdering::Relaxed);
+    }
+}
+
+impl NodeIngest {
+    pub fn set(&self, at: u32, source: u32) {
+        self.at.store(at, AtomicOrdering::Relaxed);
+        self.source.store(source, AtomicOrdering::Relaxed);
+    }
+}
+
+impl DynAlloc for NodeIngest {
+    type Metadata = ();
+    type Args = (u32, u32);
+
+    const ALIGN: usize = 4;
+
+    fn size(_metadata: ()) -> usize {
+        8
+    }
+
+    fn from_raw_parts(ptr: *mut u8, _metadata: ()) -> *mut Self {
+        ptr.cast()
+    }
+
+    // Zeroed arena, like the node metadata.
+    unsafe fn new_at(ptr: *mut u8, _metadata: (), (at, source): Self::Args) {
+        let ingest = unsafe { &*(ptr as *const NodeIngest) };
+        ingest.set(at, source);
+    }
+}
+
+impl NodePrior {
+    pub fn get(&self) -> f32 {
+        f32::from_bits(self.bits.load(AtomicOrdering::Relaxed))
+    }
+
+    pub fn set(&self, prior: f32) {
+        self.bits.store(prior.to_bits(), AtomicOrdering::Relaxed);
+    }
+}
+
+impl DynAlloc for NodePrior {
+    type Metadata = ();
+    type Args = f32;
+
+    const ALIGN: usize = 4;
+
+    fn size(_metadata: ()) -> usize {
+        4
+    }
+
+    fn from_raw_parts(ptr: *mut u8, _metadata: ()) -> *mut Self {
+        ptr.cast()
+    }
+
+    // Zeroed arena, like the node metadata.
+    unsafe fn new_at(ptr: *mut u8, _metadata: (), prior: Self::Args) {
+        let node_prior = unsafe { &*(ptr as *const NodePrior) };
+        node_prior.set(prior);
+    }
+}
+
 impl DynAlloc for Neighbors {
     type Metadata = u16;
     type Args = ();
@@ -205,11 +729,11 @@ impl DynAlloc for Neighbors {
     const ALIGN: usize = 4;
 
     fn size(len: u16) -> usize {
-        8 + (len as usize) * 8
+        8 + (len as usize) * size_of::<Neighbor>()
     }
 
-    fn ptr_metadata(len: u16) -> <Self as core::ptr::Pointee>::Metadata {
-        len as usize
+    fn from_raw_parts(ptr: *mut u8, len: u16) -> *mut Self {
+        ptr::slice_from_raw_parts_mut(ptr, len as usize) as *mut Self
     }
 
     unsafe fn new_at(ptr: *mut u8, len: u16, _args: ()) {
@@ -226,11 +750,11 @@ impl DynAlloc for Neighbors0 {
     const ALIGN: usize = 4;
 
     fn size(len: u16) -> usize {
-        8 + (len as usize) * 8
+        8 + (len as usize) * size_of::<Neighbor0>()
     }
 
-    fn ptr_metadata(len: u16) -> <Self as core::ptr::Pointee>::Metadata {
-        len as usize
+    fn from_raw_parts(ptr: *mut u8, len: u16) -> *mut Self {
+        ptr::slice_from_raw_parts_mut(ptr, len as usize) as *mut Self
     }
 
     unsafe fn new_at(ptr: *mut u8, metadata: Self::Metadata, _args: ()) {
@@ -243,12 +767,13 @@ impl DynAlloc for Neighbors0 {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::arena::Arena;
+    use crate::{allocator::IndexAllocator, arena::Arena};
+    use alloc::vec::Vec;
 
     #[test]
     fn test_node_allocation() {
         let metadata: u16 = 5; // Number of neighbors
-        let arena = Arena::<Node>::new(16, metadata);
+        let arena = Arena::<Node>::new(16, metadata, IndexAllocator::default());
         let dummy_vec_handle = VecHandle::invalid();
         let dummy_child_handle = NodeHandle::invalid();
 
@@ -267,16 +792,20 @@ mod tests {
         assert_eq!(neighbors.lowest_score, 0.0);
         assert_eq!(neighbors.neighbors.len(), metadata as usize);
 
+        let metric = DistanceMetric::new(
+            crate::DistanceMetricKind::Cosine,
+            crate::Quantization::FullPrecisionFP,
+        );
         for neighbor in &neighbors.neighbors {
-            assert_eq!(*neighbor.node, 0);
-            assert_eq!(neighbor.score, 0.0);
+            assert_eq!(*neighbor.node(), 0);
+            assert_eq!(neighbor.score(&metric), 0.0);
         }
     }
 
     #[test]
     fn test_node0_allocation() {
         let metadata: u16 = 3; // Number of neighbors
-        let arena = Arena::<Node0>::new(16, metadata);
+        let arena = Arena::<Node0>::new(16, metadata, IndexAllocator::default());
         let dummy_vec_handle = VecHandle::invalid();
 
         // Allocate a Node0
@@ -293,16 +822,74 @@ mod tests {
         assert_eq!(neighbors.lowest_score, 0.0);
         assert_eq!(neighbors.neighbors.len(), metadata as usize);
 
+        let metric = DistanceMetric::new(
+            crate::DistanceMetricKind::Cosine,
+            crate::Quantization::FullPrecisionFP,
+        );
         for neighbor in &neighbors.neighbors {
-            assert_eq!(*neighbor.node, 0);
-            assert_eq!(neighbor.score, 0.0);
+            assert_eq!(*neighbor.node(), 0);
+            assert_eq!(neighbor.score(&metric), 0.0);
         }
     }
 
+    #[test]
+    fn test_neighbor_size() {
+        let expected = if cfg!(feature = "f16-scores") { 6 } else { 8 };
+        assert_eq!(size_of::<Neighbor>(), expected);
+        assert_eq!(size_of::<Neighbor0>(), expected);
+        assert_eq!(Neighbors0::size(64), 8 + 64 * expected);
+    }
+
+    #[test]
+    fn test_hamming_scores_are_exact() {
+        let metric = DistanceMetric::new(
+            crate::DistanceMetricKind::Hamming,
+            crate::Quantization::Binary,
+        );
+        // past 2048 an f16 can't hold every whole number
+        for distance in [0.0, 2049.0, 4097.0, 65535.0] {
+            let neighbor = Neighbor0::new(&metric, Node0Handle::new(0), distance);
+            assert_eq!(neighbor.score(&metric), distance);
+        }
+    }
+
+    #[test]
+    fn test_neighbor_sort() {
+        let metric = DistanceMetric::new(
+            crate::DistanceMetricKind::DotProduct,
+            crate::Quantization::FullPrecisionFP,
+        );
+        let arena = Arena::<Node0>::new(16, 4, IndexAllocator::default());
+        let node = &arena[arena.alloc(VecHandle::invalid())];
+        let mut neighbors = node.neighbors.write();
+
+        for (handle, score) in [(7, 0.5), (3, 0.875), (5, 0.125), (1, 0.625)] {
+            neighbors.insert_neighbor(&metric, Node0Handle::new(handle), score);
+        }
+
+        neighbors.sort(&metric, NeighborOrder::ByScore);
+        assert_eq!(neighbors.order, NeighborOrder::ByScore);
+        let scores: Vec<f32> = neighbors
+            .neighbors()
+            .iter()
+            .map(|n| n.score(&metric))
+            .collect();
+        assert_eq!(scores, [0.875, 0.625, 0.5, 0.125]);
+        assert_eq!(neighbors.lowest_index, 3);
+
+        neighbors.sort(&metric, NeighborOrder::ByHandle);
+        let handles: Vec<u32> = neighbors.neighbors().iter().map(|n| *n.node()).collect();
+        assert_eq!(handles, [1, 3, 5, 7]);
+        assert_eq!(neighbors.lowest_score, 0.125);
+
+        neighbors.insert_neighbor(&metric, Node0Handle::new(9), 0.8);
+        assert_eq!(neighbors.order, NeighborOrder::Unordered);
+    }
+
     #[test]
     fn test_clear_arena() {
         let metadata: u16 = 2;
-        let mut arena = Arena::<Node>::new(16, metadata);
+        let mut arena = Arena::<Node>::new(16, metadata, IndexAllocator::default());
         let dummy_vec_handle = VecHandle::invalid();
         let dummy_child_handle = NodeHandle::invalid();
 
@@ -0,0 +1,207 @@
+use alloc::boxed::Box;
+
+use crate::NodeId;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterDecision {
+    Accept,
+    Reject,
+    /// Reject this result and end the search with the results accepted so far
+    Stop,
+}
+
+/// How a search rescores the candidates of its quantized traversal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RescoreMode {
+    /// Return the top_k candidates with their quantized scores
+    None,
+    /// Rescore `oversample` times top_k candidates against the full-precision
+    /// copies, where kept, and return the best top_k
+    FullPrecision { oversample: u8 },
+}
+
+impl Default for RescoreMode {
+    fn default() -> Self {
+        Self::FullPrecision { oversample: 8 }
+    }
+}
+
+/// The order a search returns its results in
+///
+/// Scores of the similarity metrics (cosine, dot product) rank higher ones
+/// first, those of the distance metrics lower ones, so `BestFirst` means
+/// descending scores for the former and ascending for the latter. Pick
+/// `Ascending` or `Descending` to get the same order whatever the metric.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResultOrder {
+    /// Best result first, in the order the metric ranks scores
+    #[default]
+    BestFirst,
+    /// Lowest score first, e.g. the nearest by a distance
+    Ascending,
+    /// Highest score first, e.g. the most similar by a similarity
+    Descending,
+}
+
+impl ResultOrder {
+    // Reorder `results`, given best first. Equal scores keep their order.
+    pub(crate) fn apply<T>(self, results: &mut [T], score: impl Fn(&T) -> f32) {
+        match self {
+            Self::BestFirst => {}
+            Self::Ascending => results.sort_by(|a, b| score(a).total_cmp(&score(b))),
+            Self::Descending => results.sort_by(|a, b| score(b).total_cmp(&score(a))),
+        }
+    }
+}
+
+/// The most results a search can return
+pub const MAX_TOP_K: u16 = 8191;
+
+pub(crate) type GroupKeyFn<'a> = Box<dyn Fn(NodeId) -> u64 + 'a>;
+
+pub struct SearchParams<'a> {
+    pub(crate) ef: u16,
+    pub(crate) top_k: u16,
+    pub(crate) post_filter: Option<Box<dyn Fn(NodeId, f32) -> FilterDecision + 'a>>,
+    pub(crate) group_limit: Option<(GroupKeyFn<'a>, u16)>,
+    pub(crate) work_budget: Option<u16>,
+    pub(crate) adaptive_max_ef: Option<u16>,
+    pub(crate) mmr_lambda: Option<f32>,
+    pub(crate) prior_weight: Option<f32>,
+    pub(crate) max_scratch_bytes: Option<usize>,
+    pub(crate) rescore: RescoreMode,
+    pub(crate) order: ResultOrder,
+}
+
+impl<'a> SearchParams<'a> {
+    pub fn new(ef: u16, top_k: u16) -> Self {
+        Self {
+            ef,
+            top_k,
+            post_filter: None,
+            group_limit: None,
+            work_budget: None,
+            adaptive_max_ef: None,
+            mmr_lambda: None,
+            prior_weight: None,
+            max_scratch_bytes: None,
+            rescore: RescoreMode::default(),
+            order: ResultOrder::default(),
+        }
+    }
+
+    /// Filter results during final selection, best first
+    ///
+    /// If fewer than `top_k` results are accepted, the search is repeated with a
+    /// doubled ef and only the new candidates are offered to the filter, until
+    /// enough are accepted or the work budget is exhausted.
+    pub fn post_filter(mut self, filter: impl Fn(NodeId, f32) -> FilterDecision + 'a) -> Self {
+        self.post_filter = Some(Box::new(filter));
+        self
+    }
+
+    /// Return at most `k_per_group` results sharing the key given by `extractor`,
+    /// e.g. the document or source a vector came from
+    ///
+    /// Results are offered best first, so each group keeps the best ones seen.
+    /// Applied after the post-filter, with the same refill when too few results
+    /// are left.
+    pub fn max_per_group(
+        mut self,
+        extractor: impl Fn(NodeId) -> u64 + 'a,
+        k_per_group: u16,
+    ) -> Self {
+        self.group_limit = Some((Box::new(extractor), k_per_group));
+        self
+    }
+
+    /// How to rescore the candidates, `RescoreMode::FullPrecision` with an
+    /// oversampling of 8 by default
+    ///
+    /// Refilling searches (post-filter, group limit) rescore ef candidates a
+    /// round whatever the oversampling. With `RescoreMode::None`, no reranker
+    /// is called either.
+    pub fn rescore(mut self, mode: RescoreMode) -> Self {
+        self.rescore = mode;
+        self
+    }
+
+    /// The order of the results, `ResultOrder::BestFirst` by default
+    ///
+    /// Applied to the `top_k` results once they're selected, after any MMR
+    /// picking, so it never changes which results are returned.
+    pub fn order(mut self, order: ResultOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    // Number of candidates to rescore, top_k when scores are kept quantized.
+    pub(crate) fn candidates(&self) -> u16 {
+        match self.rescore {
+            RescoreMode::None => self.top_k,
+            RescoreMode::FullPrecision { oversample } => {
+                self.top_k.saturating_mul(oversample.max(1) as u16)
+            }
+        }
+    }
+
+    // Whether final selection may drop results and needs to refill.
+    pub(crate) fn refills(&self) -> bool {
+        self.post_filter.is_some() || self.group_limit.is_some()
+    }
+
+    /// Treat ef as a starting point and double it until the results stop changing
+    /// between rounds or `max_ef` is reached
+    ///
+    /// Each round continues the previous one instead of starting over. Ignored
+    /// when a post-filter or group limit is set, which grows ef on its own.
+    pub fn adaptive(mut self, max_ef: u16) -> Self {
+        self.adaptive_max_ef = Some(max_ef);
+        self
+    }
+
+    /// Select the results by maximal marginal relevance, trading relevance for
+    /// diversity
+    ///
+    /// Results are picked one at a time from the candidates, each maximizing
+    /// `lambda` times its score minus `1 - lambda` times its score against the
+    /// closest result picked before it, computed from the stored vectors. A
+    /// `lambda` of 1 gives the plain top_k, lower values favor diversity.
+    /// Results come in the order they were picked. Ignored when a post-filter
+    /// or group limit is set.
+    pub fn mmr(mut self, lambda: f32) -> Self {
+        assert!((0.0..=1.0).contains(&lambda), "lambda must be in [0, 1]");
+        self.mmr_lambda = Some(lambda);
+        self
+    }
+
+    /// Rank results by their score plus `weight` times their node's prior, see
+    /// `Graph::set_prior`, and return that blended score
+    ///
+    /// Blended into the rescored candidates, so with `RescoreMode::None` only
+    /// the top_k found by score are reordered. The scores of the distance
+    /// metrics rank lower ones higher, so favor high priors there with a
+    /// negative weight. Ignored by graphs without priors.
+    pub fn prior_weight(mut self, weight: f32) -> Self {
+        self.prior_weight = Some(weight);
+        self
+    }
+
+    /// Bound the working memory of the search to about `bytes`, lowering ef, the
+    /// adaptive limit and the work budget until it fits
+    ///
+    /// Each node expanded at level 0 takes a result entry, plus a queue entry
+    /// and a visited mark for each of up to m0 neighbors. At least one node is
+    /// always expanded. When `top_k` covers the graph but a scan wouldn't fit,
+    /// the graph is traversed instead.
+    pub fn max_scratch_bytes(mut self, bytes: usize) -> Self {
+        self.max_scratch_bytes = Some(bytes);
+        self
+    }
+
+    /// Largest ef the post-filter refill may grow to (defaults to 8 * ef)
+    pub fn work_budget(mut self, max_ef: u16) -> Self {
+        self.work_budget = Some(max_ef);
+        self
+    }
+}
@@ -0,0 +1,9 @@
+/// Consulted by the graph before an insert allocates new arena chunks
+///
+/// Returning `false` vetoes the insert, which then fails with
+/// `VectorDbError::OutOfBudget` without allocating anything. Searches never
+/// allocate chunks and are unaffected.
+pub trait MemoryPressureHook: Send + Sync {
+    /// `bytes` is the total size of the chunks the insert is about to allocate
+    fn allow_alloc(&self, bytes: usize) -> bool;
+}
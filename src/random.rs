@@ -31,6 +31,11 @@ impl AtomicRng {
     pub const fn new(seed: u64) -> Self {
         Self(AtomicU64::new(seed))
     }
+
+    // Seeding a new generator with the state continues the same sequence.
+    pub fn state(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
 }
 
 impl ThreadSafeRng for AtomicRng {
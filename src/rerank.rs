@@ -0,0 +1,16 @@
+use crate::NodeId;
+
+/// Supplies the exact scores of search candidates in place of the graph's own
+/// full-precision rescoring, e.g. from a GPU holding the chunks exposed by
+/// `Graph::chunks_raw`
+///
+/// Without one, candidates are rescored on the CPU against the full-precision
+/// copies, where kept.
+pub trait Reranker: Send + Sync {
+    /// `scores` holds the quantized score of each of `candidates`, to be
+    /// overwritten with exact ones under the graph's metric
+    ///
+    /// Scores left unchanged rank as they are. Candidates come in no particular
+    /// order and the graph picks the best ones by the new scores.
+    fn rerank(&self, query: &[f32], candidates: &[NodeId], scores: &mut [f32]);
+}
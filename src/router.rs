@@ -0,0 +1,98 @@
+use alloc::{boxed::Box, collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+use parking_lot::RwLock;
+
+use crate::{Graph, NodeId, SearchParams, SearchResult, VectorDbError};
+
+/// Serves several graphs side by side, one per embedding model tag
+///
+/// Vectors from different models live in incompatible spaces, so each tag maps to
+/// its own graph. Graphs can be added and retired while searches are running; a
+/// search that already started keeps its graph alive until it finishes.
+pub struct Router {
+    graphs: RwLock<BTreeMap<String, Arc<Graph>>>,
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Self {
+            graphs: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    /// Serve `graph` under `tag`, returning the graph it replaces, if any
+    pub fn insert(&self, tag: &str, graph: Graph) -> Option<Arc<Graph>> {
+        self.graphs.write().insert(tag.into(), Arc::new(graph))
+    }
+
+    /// Stop serving `tag`, returning its graph
+    pub fn retire(&self, tag: &str) -> Option<Arc<Graph>> {
+        self.graphs.write().remove(tag)
+    }
+
+    pub fn get(&self, tag: &str) -> Option<Arc<Graph>> {
+        self.graphs.read().get(tag).cloned()
+    }
+
+    /// Tags currently served, in order
+    pub fn tags(&self) -> Vec<String> {
+        self.graphs.read().keys().cloned().collect()
+    }
+
+    pub fn index(&self, tag: &str, vec: &[f32], ef: u16) -> Result<NodeId, VectorDbError> {
+        let graph = self.get(tag).ok_or(VectorDbError::UnknownModel)?;
+        graph.try_index(vec, ef)
+    }
+
+    pub fn search(
+        &self,
+        tag: &str,
+        query: &[f32],
+        params: &SearchParams,
+    ) -> Result<Box<[SearchResult]>, VectorDbError> {
+        let graph = self.get(tag).ok_or(VectorDbError::UnknownModel)?;
+        graph.try_search(query, params)
+    }
+}
+
+impl Default for Router {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GraphBuilder;
+
+    #[test]
+    fn routes_by_tag() {
+        let router = Router::new();
+        router.insert("v1", GraphBuilder::new().dims(4).build());
+        router.insert("v2", GraphBuilder::new().dims(8).build());
+
+        let id = router.index("v2", &[0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0], 16);
+        assert_eq!(id, Ok(NodeId(0)));
+        assert_eq!(
+            router.index("v1", &[1.0; 8], 16),
+            Err(VectorDbError::DimensionMismatch {
+                expected: 4,
+                found: 8
+            })
+        );
+
+        let params = SearchParams::new(16, 1);
+        let results = router
+            .search("v2", &[0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0], &params)
+            .unwrap();
+        assert_eq!(results[0].node, NodeId(0));
+
+        let retired = router.retire("v1").unwrap();
+        assert_eq!(Arc::strong_count(&retired), 1);
+        assert_eq!(router.tags(), ["v2"]);
+        assert_eq!(
+            router.search("v1", &[1.0; 4], &params).unwrap_err(),
+            VectorDbError::UnknownModel
+        );
+    }
+}
@@ -0,0 +1,60 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use parking_lot::lock_api;
+
+const WRITER: u32 = u32::MAX;
+
+/// Spinning reader-writer lock used under `--cfg loom`.
+///
+/// Neighbor locks are initialized in place inside arena chunks, which rules out
+/// loom's own lock. The state stays a plain atomic with the same layout, and
+/// every acquisition yields to the loom scheduler first so the model explores
+/// contention on it.
+pub struct RawRwLock {
+    state: AtomicU32,
+}
+
+unsafe impl lock_api::RawRwLock for RawRwLock {
+    const INIT: RawRwLock = RawRwLock {
+        state: AtomicU32::new(0),
+    };
+
+    type GuardMarker = lock_api::GuardNoSend;
+
+    fn lock_shared(&self) {
+        loom::thread::yield_now();
+        while !self.try_lock_shared() {
+            loom::thread::yield_now();
+        }
+    }
+
+    fn try_lock_shared(&self) -> bool {
+        let state = self.state.load(Ordering::Relaxed);
+        state != WRITER
+            && self
+                .state
+                .compare_exchange(state, state + 1, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+    }
+
+    unsafe fn unlock_shared(&self) {
+        self.state.fetch_sub(1, Ordering::Release);
+    }
+
+    fn lock_exclusive(&self) {
+        loom::thread::yield_now();
+        while !self.try_lock_exclusive() {
+            loom::thread::yield_now();
+        }
+    }
+
+    fn try_lock_exclusive(&self) -> bool {
+        self.state
+            .compare_exchange(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    unsafe fn unlock_exclusive(&self) {
+        self.state.store(0, Ordering::Release);
+    }
+}
@@ -1,4 +1,18 @@
+#[cfg(loom)]
+pub mod loom_rwlock;
+#[cfg(not(loom))]
 pub mod raw_mutex;
+#[cfg(not(loom))]
 pub mod raw_rwlock;
 
+#[cfg(not(loom))]
 pub type RwLock<T> = parking_lot::lock_api::RwLock<raw_rwlock::RawRwLock, T>;
+#[cfg(loom)]
+pub type RwLock<T> = parking_lot::lock_api::RwLock<loom_rwlock::RawRwLock, T>;
+
+#[cfg(not(loom))]
+pub type RwLockReadGuard<'a, T> =
+    parking_lot::lock_api::RwLockReadGuard<'a, raw_rwlock::RawRwLock, T>;
+#[cfg(loom)]
+pub type RwLockReadGuard<'a, T> =
+    parking_lot::lock_api::RwLockReadGuard<'a, loom_rwlock::RawRwLock, T>;
@@ -0,0 +1,137 @@
+// Byte encoding of `Graph::serialize`: little-endian integers and floats,
+// written and read in the order the graph lays them out.
+
+use alloc::{boxed::Box, vec::Vec};
+
+use crate::error::DeserializeError;
+
+pub(crate) const MAGIC: [u8; 4] = *b"VDBG";
+// Bumped whenever the layout of a snapshot changes.
+pub(crate) const FORMAT_VERSION: u32 = 1;
+
+// A mapped snapshot, see `Graph::serialize_mapped`: this magic, the format
+// version, `BYTE_ORDER` in native byte order and the length of a snapshot
+// without vectors, followed by that snapshot and, from the next multiple of
+// `MAPPED_ALIGN`, the vector slots in native layout.
+#[cfg(feature = "mmap")]
+pub(crate) const MAPPED_MAGIC: [u8; 4] = *b"VDBM";
+#[cfg(feature = "mmap")]
+pub(crate) const BYTE_ORDER: u32 = 0x0102_0304;
+#[cfg(feature = "mmap")]
+pub(crate) const MAPPED_ALIGN: usize = 64;
+
+pub(crate) struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    pub fn new() -> Self {
+        Self { bytes: Vec::new() }
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub fn f32(&mut self, value: f32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    // Native-endian components of `size` bytes each, as stored in a quantized
+    // vector.
+    pub fn components(&mut self, bytes: &[u8], size: usize) {
+        for component in bytes.chunks_exact(size) {
+            if cfg!(target_endian = "big") {
+                self.bytes.extend(component.iter().rev());
+            } else {
+                self.bytes(component);
+            }
+        }
+    }
+
+    pub fn finish(self) -> Box<[u8]> {
+        self.bytes.into_boxed_slice()
+    }
+}
+
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], DeserializeError> {
+        let (bytes, rest) = self
+            .bytes
+            .split_at_checked(len)
+            .ok_or(DeserializeError::Truncated)?;
+        self.bytes = rest;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], DeserializeError> {
+        Ok(self.bytes(N)?.try_into().unwrap())
+    }
+
+    pub fn u8(&mut self) -> Result<u8, DeserializeError> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, DeserializeError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(DeserializeError::Corrupt),
+        }
+    }
+
+    pub fn u16(&mut self) -> Result<u16, DeserializeError> {
+        self.array().map(u16::from_le_bytes)
+    }
+
+    pub fn u32(&mut self) -> Result<u32, DeserializeError> {
+        self.array().map(u32::from_le_bytes)
+    }
+
+    pub fn u64(&mut self) -> Result<u64, DeserializeError> {
+        self.array().map(u64::from_le_bytes)
+    }
+
+    pub fn f32(&mut self) -> Result<f32, DeserializeError> {
+        self.array().map(f32::from_le_bytes)
+    }
+
+    // The inverse of `Writer::components`, into `out`.
+    pub fn components(&mut self, out: &mut [u8], size: usize) -> Result<(), DeserializeError> {
+        out.copy_from_slice(self.bytes(out.len())?);
+        if cfg!(target_endian = "big") {
+            for component in out.chunks_exact_mut(size) {
+                component.reverse();
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+}
@@ -1,6 +1,16 @@
-use core::ptr::{self, Pointee};
+use core::{
+    alloc::Layout,
+    ops::Deref,
+    ptr::{self, NonNull},
+    slice,
+};
 
-use crate::{arena::DynAlloc, metric::dot_product_f32};
+use crate::{
+    allocator::IndexAllocator,
+    arena::DynAlloc,
+    metric::dot_product_f32,
+    util::{f16_bits, f16_from_bits},
+};
 
 #[derive(Debug, Clone, Copy)]
 #[repr(u8)]
@@ -9,19 +19,54 @@ pub enum Quantization {
     UnsignedByte,
     HalfPrecisionFP,
     FullPrecisionFP,
+    /// One bit per dimension, set for positive components, packed into u64
+    /// words. Only scored by `Hamming`.
+    Binary,
 }
 
 impl Quantization {
+    // Bytes per stored component, or per word of bits.
     #[inline]
     pub(crate) fn size(&self) -> usize {
         match self {
             Self::SignedByte | Self::UnsignedByte => 1,
             Self::HalfPrecisionFP => 2,
             Self::FullPrecisionFP => 4,
+            Self::Binary => 8,
+        }
+    }
+
+    // Bytes taken by `dims` encoded components.
+    #[inline]
+    pub(crate) fn encoded_len(&self, dims: u16) -> usize {
+        match self {
+            Self::Binary => (dims as usize).div_ceil(64) * 8,
+            _ => dims as usize * self.size(),
+        }
+    }
+
+    /// Number of components of `vec` that fall outside the encodable range and
+    /// get clamped when quantizing
+    pub fn clipped_components(&self, vec: &[f32]) -> u32 {
+        let out_of_range = |scale: f32, min: f32, max: f32| {
+            vec.iter()
+                .filter(|dim| {
+                    let scaled = *dim * scale;
+                    scaled < min || scaled > max
+                })
+                .count() as u32
+        };
+
+        match self {
+            Self::SignedByte => out_of_range(127.0, -128.0, 127.0),
+            Self::UnsignedByte => out_of_range(255.0, 0.0, 255.0),
+            Self::HalfPrecisionFP | Self::FullPrecisionFP | Self::Binary => 0,
         }
     }
 }
 
+/// A quantized vector as a graph stores it, see `layout` for the encodings
+/// and `Graph::get_quantized`
 #[repr(C, align(4))]
 pub struct QuantVec {
     pub(crate) mag: f32,
@@ -33,26 +78,117 @@ pub struct RawVec {
     pub(crate) vec: [f32],
 }
 
+// A heap-allocated QuantVec outside of any arena, used to quantize queries.
+pub struct QuantVecBox {
+    ptr: NonNull<u8>,
+    metadata: (Quantization, u16),
+    allocator: IndexAllocator,
+}
+
+impl QuantVecBox {
+    pub fn new(
+        quantization: Quantization,
+        dims: u16,
+        vec: &[f32],
+        allocator: &IndexAllocator,
+    ) -> Self {
+        // reading `dims` components out of a shorter slice would be out of bounds
+        assert_eq!(vec.len(), dims as usize, "query dimension mismatch");
+        let metadata = (quantization, dims);
+        // never zero-sized, the magnitude is always stored
+        let ptr = allocator.alloc(Self::layout(metadata), false);
+        unsafe {
+            QuantVec::new_at(ptr.as_ptr(), metadata, QuantArgs::Raw(vec.as_ptr()));
+        }
+        Self {
+            ptr,
+            metadata,
+            allocator: allocator.clone(),
+        }
+    }
+
+    // Whether `vec` encodes into this box's shape, see `set`.
+    pub fn fits(&self, quantization: Quantization, dims: u16) -> bool {
+        self.metadata.0 as u8 == quantization as u8 && self.metadata.1 == dims
+    }
+
+    // Encode `vec` in place of the current vector.
+    pub fn set(&mut self, vec: &[f32]) {
+        assert_eq!(
+            vec.len(),
+            self.metadata.1 as usize,
+            "query dimension mismatch"
+        );
+        unsafe {
+            QuantVec::new_at(
+                self.ptr.as_ptr(),
+                self.metadata,
+                QuantArgs::Raw(vec.as_ptr()),
+            );
+        }
+    }
+
+    fn layout(metadata: (Quantization, u16)) -> Layout {
+        unsafe {
+            Layout::from_size_align_unchecked(QuantVec::size_aligned(metadata), QuantVec::ALIGN)
+        }
+    }
+}
+
+impl Deref for QuantVecBox {
+    type Target = QuantVec;
+
+    fn deref(&self) -> &QuantVec {
+        unsafe { &*QuantVec::from_raw_parts(self.ptr.as_ptr(), self.metadata) }
+    }
+}
+
+impl Drop for QuantVecBox {
+    fn drop(&mut self) {
+        unsafe {
+            self.allocator
+                .dealloc(self.ptr, Self::layout(self.metadata));
+        }
+    }
+}
+
+// What a quantized vector is built from: the full-precision vector, or its
+// components already encoded, in native byte order, along with its squared
+// magnitude.
+#[derive(Clone, Copy)]
+pub enum QuantArgs {
+    Raw(*const f32),
+    Encoded(*const u8, f32),
+}
+
 impl DynAlloc for QuantVec {
     type Metadata = (Quantization, u16);
-    type Args = *const f32;
+    type Args = QuantArgs;
 
     const ALIGN: usize = 4;
 
     #[inline]
     fn size((quantization, len): Self::Metadata) -> usize {
-        let multiplier = quantization.size();
-        4 + len as usize * multiplier
+        4 + quantization.encoded_len(len)
     }
 
     #[inline]
-    fn ptr_metadata((quantization, len): Self::Metadata) -> <Self as Pointee>::Metadata {
-        let multiplier = quantization.size();
-        len as usize * multiplier
+    fn from_raw_parts(ptr: *mut u8, (quantization, len): Self::Metadata) -> *mut Self {
+        ptr::slice_from_raw_parts_mut(ptr, quantization.encoded_len(len)) as *mut Self
     }
 
-    unsafe fn new_at(ptr: *mut u8, (quantization, len): Self::Metadata, raw_vec_ptr: Self::Args) {
-        let raw_vec_ref: &[f32] = unsafe { &*ptr::from_raw_parts(raw_vec_ptr, len as usize) };
+    unsafe fn new_at(ptr: *mut u8, (quantization, len): Self::Metadata, args: Self::Args) {
+        let raw_vec_ptr = match args {
+            QuantArgs::Raw(raw_vec_ptr) => raw_vec_ptr,
+            QuantArgs::Encoded(encoded, mag) => {
+                unsafe {
+                    (ptr as *mut f32).write(mag);
+                    ptr::copy_nonoverlapping(encoded, ptr.add(4), quantization.encoded_len(len));
+                }
+                return;
+            }
+        };
+        let raw_vec_ref = unsafe { slice::from_raw_parts(raw_vec_ptr, len as usize) };
         let mag = dot_product_f32(raw_vec_ref, raw_vec_ref);
         unsafe {
             (ptr as *mut f32).write(mag);
@@ -79,10 +215,10 @@ impl DynAlloc for QuantVec {
                 }
             }
             Quantization::HalfPrecisionFP => {
-                let vec_ptr = vec_ptr as *mut f16;
+                let vec_ptr = vec_ptr as *mut u16;
                 for (i, dim) in raw_vec_ref.iter().enumerate() {
                     unsafe {
-                        vec_ptr.add(i).write(*dim as f16);
+                        vec_ptr.add(i).write(f16_bits(*dim));
                     }
                 }
             }
@@ -92,6 +228,19 @@ impl DynAlloc for QuantVec {
                     ptr::copy_nonoverlapping(raw_vec_ptr, vec_ptr, len as usize);
                 }
             }
+            Quantization::Binary => {
+                // only 4-byte aligned, past the magnitude
+                let vec_ptr = vec_ptr as *mut u64;
+                for (i, dims) in raw_vec_ref.chunks(64).enumerate() {
+                    let word = dims
+                        .iter()
+                        .enumerate()
+                        .fold(0u64, |word, (bit, dim)| word | ((*dim > 0.0) as u64) << bit);
+                    unsafe {
+                        vec_ptr.add(i).write_unaligned(word);
+                    }
+                }
+            }
         }
     }
 }
@@ -108,8 +257,8 @@ impl DynAlloc for RawVec {
     }
 
     #[inline]
-    fn ptr_metadata(len: Self::Metadata) -> <Self as Pointee>::Metadata {
-        len as usize
+    fn from_raw_parts(ptr: *mut u8, len: Self::Metadata) -> *mut Self {
+        ptr::slice_from_raw_parts_mut(ptr as *mut f32, len as usize) as *mut Self
     }
 
     unsafe fn new_at(ptr: *mut u8, metadata: Self::Metadata, args: Self::Args) {
@@ -120,6 +269,11 @@ impl DynAlloc for RawVec {
 }
 
 impl QuantVec {
+    /// Squared magnitude of the full-precision vector
+    pub fn magnitude(&self) -> f32 {
+        self.mag
+    }
+
     pub fn as_signed_byte(&self) -> &[i8] {
         unsafe { &*(&self.vec as *const [u8] as *const [i8]) }
     }
@@ -128,12 +282,56 @@ impl QuantVec {
         &self.vec
     }
 
-    #[allow(unused)]
-    pub fn as_half_precision_fp(&self) -> &[f16] {
-        unsafe { &*ptr::from_raw_parts(&self.vec as *const [u8] as *const f16, self.vec.len() / 2) }
+    // The encoded components, to restore a vector from a snapshot.
+    pub(crate) fn bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.vec
+    }
+
+    /// The bits of the f16 components, which builds on stable have no type for
+    pub fn as_half_precision_bits(&self) -> &[u16] {
+        unsafe { slice::from_raw_parts(self.vec.as_ptr() as *const u16, self.vec.len() / 2) }
+    }
+
+    /// Native-endian u64 words of bits, which may not be 8-byte aligned
+    pub fn as_binary(&self) -> &[u8] {
+        &self.vec
     }
 
     pub fn as_full_precision_fp(&self) -> &[f32] {
-        unsafe { &*ptr::from_raw_parts(&self.vec as *const [u8] as *const f32, self.vec.len() / 4) }
+        unsafe { slice::from_raw_parts(self.vec.as_ptr() as *const f32, self.vec.len() / 4) }
     }
+
+    /// Approximate the vector that was quantized, up to clamping and rounding
+    pub fn dequantize_into(&self, quantization: Quantization, out: &mut [f32]) {
+        match quantization {
+            Quantization::SignedByte => {
+                for (out, dim) in out.iter_mut().zip(self.as_signed_byte()) {
+                    *out = *dim as f32 / 127.0;
+                }
+            }
+            Quantization::UnsignedByte => {
+                for (out, dim) in out.iter_mut().zip(self.as_unsigned_byte()) {
+                    *out = *dim as f32 / 255.0;
+                }
+            }
+            Quantization::HalfPrecisionFP => {
+                for (out, dim) in out.iter_mut().zip(self.as_half_precision_bits()) {
+                    *out = f16_from_bits(*dim);
+                }
+            }
+            Quantization::FullPrecisionFP => out.copy_from_slice(self.as_full_precision_fp()),
+            Quantization::Binary => {
+                for (i, out) in out.iter_mut().enumerate() {
+                    let word = binary_word(self.as_binary(), i / 64);
+                    *out = if word >> (i % 64) & 1 == 1 { 1.0 } else { -1.0 };
+                }
+            }
+        }
+    }
+}
+
+// The `index`th word of a binary quantized vector.
+#[inline]
+pub(crate) fn binary_word(bits: &[u8], index: usize) -> u64 {
+    u64::from_ne_bytes(bits[index * 8..index * 8 + 8].try_into().unwrap())
 }
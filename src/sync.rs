@@ -0,0 +1,74 @@
+// Synchronization primitives shared by the graph and its arenas.
+//
+// Under `--cfg loom` they are replaced by loom's models so the loom tests can
+// explore their interleavings. Atomics that live inside arena memory (node
+// metadata, the neighbor lock state) are initialized in place and stay on
+// `core`.
+
+#[cfg(not(loom))]
+pub(crate) use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+#[cfg(not(loom))]
+pub(crate) use parking_lot::{RwLock, RwLockWriteGuard};
+
+#[cfg(loom)]
+pub(crate) use self::loom_lock::{RwLock, RwLockWriteGuard};
+#[cfg(loom)]
+pub(crate) use loom::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+#[cfg(loom)]
+mod loom_lock {
+    use core::ops::{Deref, DerefMut};
+
+    // The subset of the parking_lot API the crate uses, on top of loom's lock.
+    pub struct RwLock<T>(loom::sync::RwLock<T>);
+
+    pub struct RwLockWriteGuard<'a, T> {
+        lock: &'a RwLock<T>,
+        guard: loom::sync::RwLockWriteGuard<'a, T>,
+    }
+
+    impl<T> RwLock<T> {
+        pub fn new(value: T) -> Self {
+            Self(loom::sync::RwLock::new(value))
+        }
+
+        pub fn read(&self) -> loom::sync::RwLockReadGuard<'_, T> {
+            self.0.read().unwrap()
+        }
+
+        pub fn write(&self) -> RwLockWriteGuard<'_, T> {
+            RwLockWriteGuard {
+                lock: self,
+                guard: self.0.write().unwrap(),
+            }
+        }
+
+        pub fn get_mut(&mut self) -> &mut T {
+            self.0.get_mut().unwrap()
+        }
+    }
+
+    impl<'a, T> RwLockWriteGuard<'a, T> {
+        // loom has no atomic downgrade, so another writer may run in between.
+        // Arena chunk lists only ever grow, which keeps this sound for them.
+        pub fn downgrade(this: Self) -> loom::sync::RwLockReadGuard<'a, T> {
+            let lock = this.lock;
+            drop(this);
+            lock.read()
+        }
+    }
+
+    impl<T> Deref for RwLockWriteGuard<'_, T> {
+        type Target = T;
+
+        fn deref(&self) -> &T {
+            &self.guard
+        }
+    }
+
+    impl<T> DerefMut for RwLockWriteGuard<'_, T> {
+        fn deref_mut(&mut self) -> &mut T {
+            &mut self.guard
+        }
+    }
+}
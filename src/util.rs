@@ -2,7 +2,7 @@ use core::alloc::Layout;
 use core::{mem, ptr};
 
 use alloc::alloc::dealloc;
-use alloc::boxed::Box;
+use alloc::{boxed::Box, vec::Vec};
 
 // SAFETY:
 // - T and U must have identical size and alignment (checked by debug_assertions)
@@ -58,3 +58,140 @@ impl Drop for Guard {
         unsafe { dealloc(self.data, self.layout) };
     }
 }
+
+// f16 input converted for the f32 insert and search paths.
+#[cfg(feature = "nightly")]
+pub fn widen_f16(vec: &[f16]) -> Vec<f32> {
+    vec.iter().map(|&x| x as f32).collect()
+}
+
+// bf16 input, given as bits, converted for the f32 insert and search paths.
+// A bf16 is the upper half of an f32, so each component widens with a shift.
+// The kernels only score f32, so this is a full copy of the vector.
+pub fn widen_bf16(vec: &[u16]) -> Vec<f32> {
+    vec.iter()
+        .map(|&bits| f32::from_bits((bits as u32) << 16))
+        .collect()
+}
+
+// Bits of `value` rounded to the nearest f16.
+#[inline]
+pub fn f16_bits(value: f32) -> u16 {
+    #[cfg(feature = "nightly")]
+    {
+        (value as f16).to_bits()
+    }
+    #[cfg(not(feature = "nightly"))]
+    {
+        f32_to_f16_bits(value)
+    }
+}
+
+#[inline]
+pub fn f16_from_bits(bits: u16) -> f32 {
+    #[cfg(feature = "nightly")]
+    {
+        f16::from_bits(bits) as f32
+    }
+    #[cfg(not(feature = "nightly"))]
+    {
+        f16_bits_to_f32(bits)
+    }
+}
+
+// Software f16 conversions for stable builds, rounding to nearest even like
+// the `as` casts they stand in for.
+#[cfg(any(test, not(feature = "nightly")))]
+fn f32_to_f16_bits(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exp = ((bits >> 23) & 0xff) as i32;
+    let man = bits & 0x7f_ffff;
+
+    if exp == 0xff {
+        // infinity, or a quiet NaN keeping the top of the payload
+        let nan = if man != 0 {
+            0x200 | (man >> 13) as u16
+        } else {
+            0
+        };
+        return sign | 0x7c00 | nan;
+    }
+
+    let exp = exp - 127 + 15;
+    if exp >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if exp <= 0 {
+        // subnormal, or too small for one
+        if exp < -10 {
+            return sign;
+        }
+        let man = man | 0x80_0000;
+        let shift = (14 - exp) as u32;
+        let half = man >> shift;
+        let rem = man & ((1 << shift) - 1);
+        let halfway = 1 << (shift - 1);
+        let round_up = rem > halfway || (rem == halfway && half & 1 == 1);
+        return sign | (half + round_up as u32) as u16;
+    }
+
+    let half = ((exp as u32) << 10) | (man >> 13);
+    let rem = man & 0x1fff;
+    let round_up = rem > 0x1000 || (rem == 0x1000 && half & 1 == 1);
+    // a carry out of the mantissa bumps the exponent, up to infinity
+    sign | (half + round_up as u32) as u16
+}
+
+#[cfg(any(test, not(feature = "nightly")))]
+fn f16_bits_to_f32(bits: u16) -> f32 {
+    let sign = ((bits & 0x8000) as u32) << 16;
+    let exp = ((bits >> 10) & 0x1f) as u32;
+    let man = (bits & 0x3ff) as u32;
+
+    let bits = match exp {
+        0 if man == 0 => sign,
+        0 => {
+            // subnormal, normalized for f32
+            let shift = man.leading_zeros() - 21;
+            sign | ((113 - shift) << 23) | (((man << shift) & 0x3ff) << 13)
+        }
+        0x1f => sign | 0x7f80_0000 | (man << 13),
+        _ => sign | ((exp + 112) << 23) | (man << 13),
+    };
+    f32::from_bits(bits)
+}
+
+#[cfg(all(test, feature = "nightly"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn software_f16_matches_casts() {
+        for bits in 0..=u16::MAX {
+            let expected = f16::from_bits(bits) as f32;
+            let actual = f16_bits_to_f32(bits);
+            assert!(
+                actual.to_bits() == expected.to_bits() || (actual.is_nan() && expected.is_nan()),
+                "{bits:#06x}"
+            );
+        }
+
+        // every exponent, with mantissas around the rounding boundaries
+        for high in 0..=0x1ffu32 {
+            for low in [
+                0, 1, 0xfff, 0x1000, 0x1001, 0x1fff, 0x2000, 0x3000, 0x7f_ffff,
+            ] {
+                for man in [low, low | 0x2000, low | 0x40_0000] {
+                    let value = f32::from_bits((high << 23) | (man & 0x7f_ffff));
+                    let expected = (value as f16).to_bits();
+                    let actual = f32_to_f16_bits(value);
+                    assert!(
+                        actual == expected || (value.is_nan() && actual & 0x7fff > 0x7c00),
+                        "{value:e}: {actual:#06x} vs {expected:#06x}"
+                    );
+                }
+            }
+        }
+    }
+}
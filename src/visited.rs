@@ -0,0 +1,101 @@
+use alloc::vec::Vec;
+
+/// Set of visited `u32` ids, e.g. node handles during a graph traversal
+///
+/// Each slot stores the generation in which its id was last inserted, so
+/// `clear` only bumps the current generation instead of touching the slots.
+/// Membership is exact. Memory grows with the largest id inserted, so ids
+/// should be dense.
+pub struct VisitedSet {
+    stamps: Vec<u32>,
+    generation: u32,
+}
+
+impl VisitedSet {
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    /// Create a set that holds ids below `capacity` without growing
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            stamps: alloc::vec![0; capacity],
+            generation: 1,
+        }
+    }
+
+    /// Insert `id`, returning `true` if it wasn't in the set yet
+    #[inline]
+    pub fn insert(&mut self, id: u32) -> bool {
+        let index = id as usize;
+        if index >= self.stamps.len() {
+            self.stamps.resize(index + 1, 0);
+        }
+
+        let stamp = &mut self.stamps[index];
+        let inserted = *stamp != self.generation;
+        *stamp = self.generation;
+        inserted
+    }
+
+    #[inline]
+    pub fn contains(&self, id: u32) -> bool {
+        self.stamps.get(id as usize) == Some(&self.generation)
+    }
+
+    /// Remove every id, in O(1) except once every `u32::MAX` clears
+    pub fn clear(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+        if self.generation == 0 {
+            // stamps from 2^32 generations ago would alias, so reset them
+            self.stamps.fill(0);
+            self.generation = 1;
+        }
+    }
+
+    /// Number of ids the set holds without growing
+    pub fn capacity(&self) -> usize {
+        self.stamps.len()
+    }
+}
+
+impl Default for VisitedSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_and_clear() {
+        let mut set = VisitedSet::with_capacity(4);
+        assert!(set.insert(1));
+        assert!(!set.insert(1));
+        assert!(set.insert(100));
+        assert!(set.contains(1));
+        assert!(set.contains(100));
+        assert!(!set.contains(2));
+        assert!(!set.contains(1000));
+        assert_eq!(set.capacity(), 101);
+
+        set.clear();
+        assert!(!set.contains(1));
+        assert!(!set.contains(100));
+        assert!(set.insert(1));
+    }
+
+    #[test]
+    fn generation_wraparound() {
+        let mut set = VisitedSet::with_capacity(2);
+        set.insert(0);
+        set.generation = u32::MAX;
+        set.insert(1);
+        set.clear();
+        assert_eq!(set.generation, 1);
+        assert!(!set.contains(0));
+        assert!(!set.contains(1));
+    }
+}
@@ -0,0 +1,143 @@
+// Golden files: small snapshots and the results of searching them, committed
+// under tests/golden. Loading a snapshot catches changes to the format, and the
+// expected results catch changes to scoring. After an intended change, rewrite
+// the files with `VECTOR_DB_BLESS=1 cargo test --test golden` and review the
+// diff.
+
+use std::{env, fmt::Write, fs, path::PathBuf};
+
+use vector_db::{DistanceMetricKind, Graph, GraphBuilder, Quantization};
+
+const DIMS: u16 = 16;
+const QUERIES: u32 = 8;
+const TOP_K: u16 = 5;
+
+struct Corpus {
+    name: &'static str,
+    quantization: Quantization,
+    metric: DistanceMetricKind,
+    levels: u8,
+}
+
+const CORPORA: [Corpus; 4] = [
+    Corpus {
+        name: "cosine_fp",
+        quantization: Quantization::FullPrecisionFP,
+        metric: DistanceMetricKind::Cosine,
+        levels: 2,
+    },
+    Corpus {
+        name: "cosine_i8",
+        quantization: Quantization::SignedByte,
+        metric: DistanceMetricKind::Cosine,
+        levels: 2,
+    },
+    Corpus {
+        name: "dot_u8",
+        quantization: Quantization::UnsignedByte,
+        metric: DistanceMetricKind::DotProduct,
+        levels: 2,
+    },
+    Corpus {
+        name: "flat_i8",
+        quantization: Quantization::SignedByte,
+        metric: DistanceMetricKind::Cosine,
+        levels: 0,
+    },
+];
+
+// Components in [0, 1), which every quantization encodes without clamping.
+fn vectors(seed: u32, count: u32) -> Vec<Vec<f32>> {
+    let mut state = seed;
+    (0..count)
+        .map(|_| {
+            (0..DIMS)
+                .map(|_| {
+                    state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                    (state >> 8) as f32 / (1 << 24) as f32
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn build(corpus: &Corpus) -> Graph {
+    let graph = GraphBuilder::new()
+        .m(4)
+        .m0(8)
+        .dims(DIMS)
+        .levels(corpus.levels)
+        .quantization(corpus.quantization)
+        .metric(corpus.metric)
+        .build();
+    for vec in vectors(1, 96) {
+        graph.index(&vec, 16);
+    }
+    graph
+}
+
+// One line per result: query, rank, node and score.
+fn search(graph: &Graph) -> String {
+    let mut out = String::new();
+    for (query, vec) in vectors(2, QUERIES).iter().enumerate() {
+        for (rank, result) in graph.search(vec, 32, TOP_K).iter().enumerate() {
+            writeln!(out, "{query} {rank} {} {:?}", result.node.0, result.score).unwrap();
+        }
+    }
+    out
+}
+
+fn path(name: &str, extension: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(name)
+        .with_extension(extension)
+}
+
+#[test]
+fn golden_snapshots() {
+    if env::var_os("VECTOR_DB_BLESS").is_some() {
+        for corpus in &CORPORA {
+            let graph = build(corpus);
+            fs::write(path(corpus.name, "bin"), graph.serialize()).unwrap();
+            fs::write(path(corpus.name, "txt"), search(&graph)).unwrap();
+        }
+        return;
+    }
+
+    for corpus in &CORPORA {
+        let bytes = fs::read(path(corpus.name, "bin")).unwrap();
+        let graph =
+            Graph::deserialize(&bytes).unwrap_or_else(|err| panic!("{}: {err}", corpus.name));
+        // neighbor scores are rounded when stored as f16
+        #[cfg(not(feature = "f16-scores"))]
+        assert!(
+            graph.serialize()[..] == bytes[..],
+            "{}: snapshot changed after a reload",
+            corpus.name
+        );
+
+        let expected = fs::read_to_string(path(corpus.name, "txt")).unwrap();
+        let actual = search(&graph);
+        assert_eq!(
+            actual.lines().count(),
+            expected.lines().count(),
+            "{}: result count changed",
+            corpus.name
+        );
+        for (actual, expected) in actual.lines().zip(expected.lines()) {
+            let (actual_id, actual_score) = actual.rsplit_once(' ').unwrap();
+            let (expected_id, expected_score) = expected.rsplit_once(' ').unwrap();
+            let actual_score: f32 = actual_score.parse().unwrap();
+            let expected_score: f32 = expected_score.parse().unwrap();
+            // the kernels may sum in another order without the nightly feature
+            assert!(
+                actual_id == expected_id
+                    && (actual_score - expected_score).abs()
+                        <= 1e-5 * expected_score.abs().max(1.0),
+                "{}: expected `{expected}`, got `{actual}`",
+                corpus.name
+            );
+        }
+    }
+}